@@ -0,0 +1,1023 @@
+//! Opt-in authentication for the generated service: register, login,
+//! refresh, change password and change email endpoints on the table
+//! holding accounts, argon2 password hashes,
+//! JWT access and refresh tokens, and an `AuthUser` extractor that every
+//! route not configured as public requires. Roles from `[auth.roles]` decide
+//! what each user may do per table, and the data layer keeps users who
+//! don't see all rows to the ones whose foreign key points at them.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use convert_case::{Case, Casing};
+use proc_macro2::TokenStream;
+use quote::quote;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::base_structs::{create_type_map, Endpoint, HttpMethod, Row, PASSWORD_HASH_COLUMN};
+use crate::codegen::{ident, rust_type, write_module, TableModule};
+use crate::gen_toml::merge_dependencies;
+use crate::openapi::{object_schema, schema_ref, Operation};
+use crate::schema::Col;
+
+/// Module the auth handlers are written to, next to the table modules.
+pub const AUTH_MODULE: &str = "auth";
+
+/// The column accounts sign in with.
+const EMAIL_COLUMN: &str = "email";
+
+/// Passwords shorter than this are turned away at registration.
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// The role new accounts get when `[auth] default_role` isn't set.
+const DEFAULT_ROLE: &str = "member";
+
+/// The users column holding each account's role when `[auth] role_column` isn't set.
+const ROLE_COLUMN: &str = "role";
+
+/// Something a role may do to the rows of a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Read,
+    Create,
+    Update,
+    Delete,
+}
+
+impl Action {
+    pub const ALL: [Action; 4] = [Action::Read, Action::Create, Action::Update, Action::Delete];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Read => "read",
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Delete => "delete",
+        }
+    }
+}
+
+/// `[auth.roles.<name>]` in auto-rust.toml.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoleConfig {
+    /// Sees and changes every user's rows, not only the caller's own.
+    pub all_rows: bool,
+    /// What the role may do per table, e.g. `posts = ["read", "create"]`.
+    /// `*` covers the tables that aren't listed; without it they are off limits.
+    pub tables: BTreeMap<String, Vec<Action>>,
+}
+
+/// `[auth]` in auto-rust.toml.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// The table accounts live in, by default the first one with `email`
+    /// and `password_hash` columns.
+    pub table: Option<String>,
+    /// Routes anyone may call without a token, by handler name or path,
+    /// e.g. `get_posts` or `/get_posts`. A trailing `*` matches by prefix,
+    /// so `get_*` opens every read. Every other route needs an access token.
+    pub public: Vec<String>,
+    /// The text column of the accounts table holding each user's role,
+    /// `role` by default. Without one every user has the default role.
+    pub role_column: Option<String>,
+    /// The role accounts get when they register, `member` by default.
+    pub default_role: Option<String>,
+    /// Roles by name. Without any, `admin` may do anything to every row,
+    /// `member` anything to their own rows and `readonly` only read them.
+    pub roles: BTreeMap<String, RoleConfig>,
+}
+
+impl AuthConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == AuthConfig::default()
+    }
+
+    pub fn is_public(&self, endpoint: &Endpoint) -> bool {
+        self.public.iter().any(|pattern| {
+            [endpoint.handler.as_str(), endpoint.path.as_str()].iter().any(|name| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+        })
+    }
+
+    /// The table accounts live in, if `rows` has one.
+    pub fn users_table<'a>(&self, rows: &'a [Row]) -> Option<&'a Row> {
+        let has_col = |row: &Row, name: &str| row.cols.iter().any(|col| col.name == name);
+        match &self.table {
+            Some(table) => rows.iter().find(|row| &row.name == table),
+            None => rows.iter().find(|row| has_col(row, EMAIL_COLUMN) && has_col(row, PASSWORD_HASH_COLUMN)),
+        }
+    }
+
+    pub fn default_role(&self) -> &str {
+        self.default_role.as_deref().unwrap_or(DEFAULT_ROLE)
+    }
+
+    /// The configured roles, or admin, member and readonly when there are none.
+    pub fn roles(&self) -> BTreeMap<String, RoleConfig> {
+        if !self.roles.is_empty() {
+            return self.roles.clone();
+        }
+        let role = |all_rows: bool, actions: &[Action]| RoleConfig {
+            all_rows,
+            tables: BTreeMap::from([("*".to_string(), actions.to_vec())]),
+        };
+        BTreeMap::from([
+            ("admin".to_string(), role(true, &Action::ALL)),
+            ("member".to_string(), role(false, &Action::ALL)),
+            ("readonly".to_string(), role(false, &[Action::Read])),
+        ])
+    }
+}
+
+/// Auth for one service: the config and the table accounts live in. The
+/// generated data layer checks each call against the caller's role and
+/// keeps non-admins to the rows that belong to them.
+pub struct Authz<'a> {
+    pub config: &'a AuthConfig,
+    pub users: &'a Row,
+    /// The users column holding the role, if there is one.
+    role_col: Option<&'a Col>,
+}
+
+impl<'a> Authz<'a> {
+    /// Auth for `rows`, or `None`, with a warning, when there is no table to
+    /// build accounts on.
+    pub fn new(config: &'a AuthConfig, rows: &'a [Row]) -> Option<Authz<'a>> {
+        let Some(users) = config.users_table(rows) else {
+            eprintln!(
+                "Warning: no table with {} and {} columns, skipping auth. Set [auth] table in auto-rust.toml to pick one.",
+                EMAIL_COLUMN, PASSWORD_HASH_COLUMN
+            );
+            return None;
+        };
+        let type_map = create_type_map();
+        let pk_count = users.cols.iter().filter(|col| col.primary_key).count();
+        let (Some(_), Some(email), Some(password_hash)) = (
+            users.primary_key().filter(|_| pk_count == 1),
+            users.cols.iter().find(|col| col.name == EMAIL_COLUMN),
+            users.cols.iter().find(|col| col.name == PASSWORD_HASH_COLUMN),
+        ) else {
+            eprintln!(
+                "Warning: auth needs {} to have a single column primary key and {} and {} columns, skipping auth.",
+                users.name, EMAIL_COLUMN, PASSWORD_HASH_COLUMN
+            );
+            return None;
+        };
+        if email.rust_type(&type_map) != "String" || password_hash.rust_type(&type_map) != "String" {
+            eprintln!("Warning: {}.{} and {}.{} must be text columns, skipping auth.", users.name, EMAIL_COLUMN, users.name, PASSWORD_HASH_COLUMN);
+            return None;
+        }
+
+        let role_name = config.role_column.as_deref().unwrap_or(ROLE_COLUMN);
+        let role_col = users.cols.iter().find(|col| col.name == role_name);
+        match role_col {
+            Some(col) if col.rust_type(&type_map) != "String" => {
+                eprintln!("Warning: {}.{} is not a text column, every user gets the {} role.", users.name, role_name, config.default_role());
+            }
+            None if config.role_column.is_some() => {
+                eprintln!("Warning: {} has no {} column, every user gets the {} role.", users.name, role_name, config.default_role());
+            }
+            _ => {}
+        }
+        let roles = config.roles();
+        if !roles.contains_key(config.default_role()) {
+            eprintln!("Warning: the default role {} is not one of [auth.roles], new accounts can't do anything.", config.default_role());
+        }
+        for (name, role) in &roles {
+            for table in role.tables.keys().filter(|table| *table != "*" && !rows.iter().any(|row| &row.name == *table)) {
+                eprintln!("Warning: role {} lists {}, which is not a table.", name, table);
+            }
+        }
+
+        Some(Authz {
+            config,
+            users,
+            role_col: role_col.filter(|col| col.rust_type(&type_map) == "String"),
+        })
+    }
+
+    /// The column of `row` naming the user a row belongs to: the primary
+    /// key of the accounts table itself, and on other tables the first
+    /// foreign key to it. Rows of tables without one are shared by everyone
+    /// their role lets read them.
+    pub fn owner_col<'r>(&self, row: &'r Row) -> Option<&'r Col> {
+        let type_map = create_type_map();
+        let pk = self.users.primary_key()?;
+        if row.name == self.users.name {
+            return row.primary_key();
+        }
+        row.relations.iter()
+            .find(|rel| rel.ref_table == self.users.name && rel.ref_columns == [pk.name.clone()] && rel.columns.len() == 1)
+            .and_then(|rel| row.cols.iter().find(|col| col.name == rel.columns[0]))
+            // the caller's id is put straight into the column
+            .filter(|col| col.rust_type(&type_map) == pk.rust_type(&type_map))
+    }
+
+    /// Turns the call away with 403 unless the caller's role may `action`
+    /// the rows of `row`. Calls without a user, from public routes, pass.
+    pub fn authorize(&self, row: &Row, action: Action) -> TokenStream {
+        let (table, action) = (&row.name, action.as_str());
+        quote! {
+            if let Some(user) = &user {
+                user.authorize(#table, #action)?;
+            }
+        }
+    }
+
+    /// Sets the columns a caller limited to their own rows can't choose
+    /// in `payload`: the owner column to their id and, on the accounts
+    /// table, the role column to the role they already have. Credentials
+    /// are not in any payload, see `is_credential`. `wrap` puts a
+    /// value into the payload field's type, e.g. `Some(value)`. With
+    /// `only_sent` a field is only set when the payload has it, for PATCH.
+    pub fn pin_fields(
+        &self,
+        row: &Row,
+        fields: &[&Col],
+        only_sent: bool,
+        wrap: impl Fn(&Col, TokenStream) -> TokenStream,
+    ) -> Option<TokenStream> {
+        let pin = |col: &Col, value: TokenStream| {
+            let (name, value) = (ident(&col.name), wrap(col, value));
+            match only_sent {
+                true => quote! {
+                    if payload.#name.is_some() {
+                        payload.#name = #value;
+                    }
+                },
+                false => quote! { payload.#name = #value; },
+            }
+        };
+        let owner = self.owner_col(row)
+            .filter(|owner| fields.iter().any(|col| col.name == owner.name))
+            .map(|owner| pin(owner, quote! { owner }));
+        let role = self.role_col
+            .filter(|role| row.name == self.users.name && fields.iter().any(|col| col.name == role.name))
+            .map(|role| pin(role, quote! { user.role.clone() }));
+        match (owner, role) {
+            (None, None) => None,
+            (owner, None) => Some(quote! {
+                if let Some(owner) = crate::auth::owner_scope(&user) {
+                    #owner
+                }
+            }),
+            (owner, Some(role)) => {
+                let scope = match owner {
+                    Some(_) => quote! { let Some(owner) = crate::auth::owner_scope(&user) },
+                    None => quote! { crate::auth::owner_scope(&user).is_some() },
+                };
+                Some(quote! {
+                    if #scope {
+                        let user = user.as_ref().expect("a scoped call has a user");
+                        #owner
+                        #role
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Whether `col` of `row` is kept out of the generic table handlers: not
+/// returned, filtered or sorted by, and not in the payloads. That is the
+/// accounts table's password hash, and only when the service has auth;
+/// without it `password_hash` is a column like any other.
+pub fn is_hidden(authz: Option<&Authz>, row: &Row, col: &Col) -> bool {
+    authz.is_some_and(|authz| row.name == authz.users.name && col.name == PASSWORD_HASH_COLUMN)
+}
+
+/// Whether `col` of `row` is a credential: the accounts table's email and
+/// password hash, when the service has auth. They only change through
+/// /auth, which asks for the current password first, so the generic
+/// insert, PUT and PATCH handlers don't take them, whatever the role.
+pub fn is_credential(authz: Option<&Authz>, row: &Row, col: &Col) -> bool {
+    authz.is_some_and(|authz| row.name == authz.users.name && [EMAIL_COLUMN, PASSWORD_HASH_COLUMN].contains(&col.name.as_str()))
+}
+
+/// The `user` argument handlers and data functions take when the service
+/// has auth. Public routes get `None` unless the caller sends a token.
+pub fn user_param(authz: Option<&Authz>) -> Option<TokenStream> {
+    authz.map(|_| quote! { user: Option<crate::auth::AuthUser>, })
+}
+
+/// Passes `user` on from a handler to its data function.
+pub fn user_arg(authz: Option<&Authz>) -> Option<TokenStream> {
+    authz.map(|_| quote! { user, })
+}
+
+/// Adds the 403 a role check can answer with to `endpoint`'s docs.
+pub fn with_forbidden(mut endpoint: Endpoint, authz: Option<&Authz>) -> Endpoint {
+    if authz.is_some() {
+        endpoint.operation.errors.push(403);
+        endpoint.operation.errors.sort();
+    }
+    endpoint
+}
+
+/// Writes `src/auth.rs` and the auth handlers, adds the crates they use to
+/// Cargo.toml and marks every endpoint in `endpoints` that isn't public as
+/// protected. The auth endpoints are appended.
+pub fn add_auth(project_dir: &Path, authz: &Authz, endpoints: &mut Vec<Endpoint>) -> Result<(), io::Error> {
+    for endpoint in endpoints.iter_mut() {
+        endpoint.protected = !authz.config.is_public(endpoint);
+    }
+
+    let src_dir = project_dir.join("src");
+    add_auth_helpers(authz, &src_dir.join("auth.rs"))?;
+    endpoints.extend(add_auth_funcs(authz, &src_dir)?);
+
+    let deps = r#"
+argon2 = { version = "0.5", features = ["std"] }
+jsonwebtoken = "9"
+"#;
+    merge_dependencies(&project_dir.join("Cargo.toml"), "dependencies", deps)?;
+    Ok(())
+}
+
+/// Writes `src/auth.rs`: tokens, password hashing, the `AuthUser` extractor
+/// and the role checks.
+fn add_auth_helpers(authz: &Authz, file_path: &Path) -> Result<(), io::Error> {
+    let pk = authz.users.primary_key().expect("Authz::new checked the primary key");
+    let pk_type = rust_type(&pk.rust_type(&create_type_map()))?;
+    let default_role = authz.config.default_role();
+
+    let roles = authz.config.roles();
+    let all_rows = roles.iter().filter(|(_, role)| role.all_rows).map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+    let all_rows = match all_rows.as_slice() {
+        [] => quote! { false },
+        names => quote! { matches!(self.role.as_str(), #(#names)|*) },
+    };
+    // a table's own entry wins over the role's `*`
+    let role_arms = roles.iter().flat_map(|(name, role)| {
+        let mut tables = role.tables.iter().collect::<Vec<_>>();
+        tables.sort_by_key(|(table, _)| *table == "*");
+        tables.into_iter().map(move |(table, actions)| {
+            let actions = actions.iter().map(|action| action.as_str());
+            let table = match table.as_str() {
+                "*" => quote! { _ },
+                table => quote! { #table },
+            };
+            quote! { (#name, #table) => &[#(#actions),*], }
+        })
+    });
+
+    write_module(file_path, quote! {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        use argon2::password_hash::rand_core::OsRng;
+        use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+        use argon2::Argon2;
+        use axum::extract::FromRequestParts;
+        use axum::http::request::Parts;
+        use jsonwebtoken::{DecodingKey, EncodingKey, Validation};
+
+        /// Access tokens are short lived; the refresh token gets new ones.
+        pub const ACCESS_TOKEN_SECONDS: u64 = 15 * 60;
+        pub const REFRESH_TOKEN_SECONDS: u64 = 30 * 24 * 60 * 60;
+
+        /// The role accounts get when they register.
+        pub const DEFAULT_ROLE: &str = #default_role;
+
+        #[derive(Debug, Serialize, Deserialize)]
+        pub struct Claims {
+            /// The user's primary key.
+            pub sub: String,
+            pub role: String,
+            /// "access" or "refresh", so one can't stand in for the other.
+            pub kind: String,
+            pub exp: u64,
+        }
+
+        /// The signed in user, from the `Authorization: Bearer` access token.
+        /// Routes that aren't public require it, and handlers can take it as an
+        /// argument to know who is calling.
+        #[derive(Debug, Clone)]
+        pub struct AuthUser {
+            pub id: #pk_type,
+            pub role: String,
+        }
+
+        impl AuthUser {
+            /// Whether the role sees and changes every user's rows, not only the caller's own.
+            pub fn all_rows(&self) -> bool {
+                #all_rows
+            }
+
+            /// Turns the call away unless the role may `action` (read, create,
+            /// update or delete) the rows of `table`.
+            pub fn authorize(&self, table: &str, action: &str) -> Result<(), AppError> {
+                let allowed: &[&str] = match (self.role.as_str(), table) {
+                    #(#role_arms)*
+                    _ => &[],
+                };
+                if allowed.contains(&action) {
+                    Ok(())
+                } else {
+                    Err(AppError::Forbidden(format!("The {} role may not {} {}", self.role, action, table)))
+                }
+            }
+        }
+
+        /// The user id the caller's rows must belong to, or `None` when every
+        /// row is open to them: on public routes called without a token and
+        /// for roles that see all rows.
+        pub fn owner_scope(user: &Option<AuthUser>) -> Option<#pk_type> {
+            user.as_ref().filter(|user| !user.all_rows()).map(|user| user.id.clone())
+        }
+
+        #[axum::async_trait]
+        impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+            type Rejection = AppError;
+
+            async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+                let token = parts.headers.get(header::AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                    .ok_or(AppError::Unauthorized("Missing bearer token".to_string()))?;
+                verify_token(token, "access")
+            }
+        }
+
+        /// Tokens are signed with the JWT_SECRET env var.
+        fn jwt_secret() -> Result<String, AppError> {
+            env::var("JWT_SECRET").map_err(|_| AppError::Internal("JWT_SECRET is not set".to_string()))
+        }
+
+        fn issue_token(id: &str, role: &str, kind: &str, seconds: u64) -> Result<String, AppError> {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            let claims = Claims { sub: id.to_string(), role: role.to_string(), kind: kind.to_string(), exp: now + seconds };
+            jsonwebtoken::encode(&jsonwebtoken::Header::default(), &claims, &EncodingKey::from_secret(jwt_secret()?.as_bytes()))
+                .map_err(|e| AppError::Internal(format!("Could not sign token: {}", e)))
+        }
+
+        /// A new access and refresh token for the user `id` with `role`.
+        pub fn issue_tokens(id: &#pk_type, role: &str) -> Result<Value, AppError> {
+            let id = id.to_string();
+            Ok(json!({
+                "access_token": issue_token(&id, role, "access", ACCESS_TOKEN_SECONDS)?,
+                "refresh_token": issue_token(&id, role, "refresh", REFRESH_TOKEN_SECONDS)?,
+                "token_type": "Bearer",
+                "expires_in": ACCESS_TOKEN_SECONDS,
+            }))
+        }
+
+        /// Checks a token's signature, expiry and kind, and returns the user in it.
+        pub fn verify_token(token: &str, kind: &str) -> Result<AuthUser, AppError> {
+            let unauthorized = |message: &str| AppError::Unauthorized(message.to_string());
+            let token = jsonwebtoken::decode::<Claims>(token, &DecodingKey::from_secret(jwt_secret()?.as_bytes()), &Validation::default())
+                .map_err(|_| unauthorized("Invalid or expired token"))?;
+            if token.claims.kind != kind {
+                return Err(unauthorized("Wrong kind of token"));
+            }
+            let id = token.claims.sub.parse().map_err(|_| unauthorized("Invalid token"))?;
+            Ok(AuthUser { id, role: token.claims.role })
+        }
+
+        pub fn hash_password(password: &str) -> Result<String, AppError> {
+            let salt = SaltString::generate(&mut OsRng);
+            Argon2::default().hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|e| AppError::Internal(format!("Could not hash password: {}", e)))
+        }
+
+        pub fn verify_password(password: &str, hash: &str) -> bool {
+            PasswordHash::new(hash).is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        }
+    })
+}
+
+/// Writes the register, login, refresh and me handlers for the accounts in `authz.users`.
+fn add_auth_funcs(authz: &Authz, src_dir: &Path) -> Result<Vec<Endpoint>, io::Error> {
+    let users = authz.users;
+    let type_map = create_type_map();
+    let table = &users.name;
+    let user_struct = ident(&table.to_case(Case::Pascal));
+    let pk = users.primary_key().expect("Authz::new checked the primary key");
+    let pk_name = ident(&pk.name);
+    let soft_delete = match users.live_condition(None) {
+        Some(live) => format!(" AND {}", live),
+        None => String::new(),
+    };
+
+    // new accounts always get the default role, whatever they send
+    let role_name = authz.role_col.map(|col| col.name.as_str());
+    let role = match authz.role_col {
+        Some(col) if col.is_nullable() => {
+            let name = ident(&col.name);
+            quote! { user.#name.as_deref().unwrap_or(crate::auth::DEFAULT_ROLE) }
+        }
+        Some(col) => {
+            let name = ident(&col.name);
+            quote! { &user.#name }
+        }
+        None => quote! { crate::auth::DEFAULT_ROLE },
+    };
+    let role_bind = role_name.map(|_| quote! { .bind(crate::auth::DEFAULT_ROLE) });
+
+    // registering fills in the columns an insert can't do without
+    let extra_cols: Vec<_> = users.cols.iter()
+        .filter(|col| col.name != EMAIL_COLUMN && col.name != PASSWORD_HASH_COLUMN && Some(col.name.as_str()) != role_name)
+        .filter(|col| !col.auto_gen && !col.is_nullable())
+        .collect();
+    let extra_fields = extra_cols.iter().map(|col| {
+        let (name, field_type) = (ident(&col.name), rust_type(&col.rust_type(&type_map))?);
+        Ok(quote! { pub #name: #field_type })
+    }).collect::<Result<Vec<_>, io::Error>>()?;
+    let extra_binds = extra_cols.iter().map(|col| {
+        let name = ident(&col.name);
+        quote! { .bind(payload.#name) }
+    });
+    let columns = [EMAIL_COLUMN, PASSWORD_HASH_COLUMN].into_iter()
+        .chain(role_name)
+        .chain(extra_cols.iter().map(|col| col.name.as_str()))
+        .collect::<Vec<_>>();
+    let placeholders = (1..=columns.len()).map(|i| format!("${}", i)).collect::<Vec<_>>();
+    let insert = format!("INSERT INTO {} ({}) VALUES ({}) RETURNING *", table, columns.join(", "), placeholders.join(", "));
+    let by_email = format!("SELECT * FROM {} WHERE {} = $1{}", table, EMAIL_COLUMN, soft_delete);
+    let by_pk = format!("SELECT * FROM {} WHERE {} = $1{}", table, pk.name, soft_delete);
+    let set_email = format!(
+        "UPDATE {} SET {} = $1 WHERE {} = $2{} RETURNING *",
+        table, EMAIL_COLUMN, pk.name, soft_delete
+    );
+    let set_password = format!(
+        "UPDATE {} SET {} = $1 WHERE {} = $2{} RETURNING *",
+        table, PASSWORD_HASH_COLUMN, pk.name, soft_delete
+    );
+    let password_hash = ident(PASSWORD_HASH_COLUMN);
+    let stored_hash = match users.cols.iter().any(|col| col.name == PASSWORD_HASH_COLUMN && col.is_nullable()) {
+        true => quote! { user.#password_hash.as_deref().unwrap_or_default() },
+        false => quote! { &user.#password_hash },
+    };
+    let min_password_length = proc_macro2::Literal::usize_unsuffixed(MIN_PASSWORD_LENGTH);
+    let taken = format!("That {} is already registered", EMAIL_COLUMN);
+
+    let mut module = TableModule::default();
+    module.models.extend(quote! {
+        #[derive(Debug, Deserialize)]
+        pub struct RegisterPayload {
+            pub email: String,
+            pub password: String,
+            #(#extra_fields),*
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct LoginPayload {
+            pub email: String,
+            pub password: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct RefreshPayload {
+            pub refresh_token: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct PasswordPayload {
+            pub current_password: String,
+            pub new_password: String,
+        }
+
+        #[derive(Debug, Deserialize)]
+        pub struct EmailPayload {
+            pub current_password: String,
+            pub new_email: String,
+        }
+    });
+    module.handlers.extend(quote! {
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn auth_register(
+            extract::State(pool): extract::State<PgPool>,
+            Json(payload): Json<RegisterPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = data_auth_register(extract::State(pool), Json(payload)).await;
+            result
+        }
+
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn auth_login(
+            extract::State(pool): extract::State<PgPool>,
+            Json(payload): Json<LoginPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = data_auth_login(extract::State(pool), Json(payload)).await;
+            result
+        }
+
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn auth_refresh(
+            extract::State(pool): extract::State<PgPool>,
+            Json(payload): Json<RefreshPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = data_auth_refresh(extract::State(pool), Json(payload)).await;
+            result
+        }
+
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn auth_me(
+            extract::State(pool): extract::State<PgPool>,
+            user: crate::auth::AuthUser,
+        ) -> Result<Json<Value>, AppError> {
+            let result = data_auth_me(extract::State(pool), user).await;
+            result
+        }
+
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn auth_password(
+            extract::State(pool): extract::State<PgPool>,
+            user: crate::auth::AuthUser,
+            Json(payload): Json<PasswordPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = data_auth_password(extract::State(pool), user, Json(payload)).await;
+            result
+        }
+
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn auth_email(
+            extract::State(pool): extract::State<PgPool>,
+            user: crate::auth::AuthUser,
+            Json(payload): Json<EmailPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = data_auth_email(extract::State(pool), user, Json(payload)).await;
+            result
+        }
+    });
+    module.data.extend(quote! {
+        /// Passwords shorter than this are turned away.
+        const MIN_PASSWORD_LENGTH: usize = #min_password_length;
+
+        /// Turns away a new password that is too short.
+        fn check_password(password: &str) -> Result<(), AppError> {
+            if password.chars().count() < MIN_PASSWORD_LENGTH {
+                return Err(AppError::BadRequest(format!("password must be at least {} characters", MIN_PASSWORD_LENGTH)));
+            }
+            Ok(())
+        }
+
+        /// Data layer: creates the account with a hashed password and signs it in
+        pub async fn data_auth_register(
+            extract::State(pool): extract::State<PgPool>,
+            Json(payload): Json<RegisterPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            if !payload.email.contains('@') {
+                return Err(AppError::BadRequest("email is not an email address".to_string()));
+            }
+            check_password(&payload.password)?;
+            let password_hash = crate::auth::hash_password(&payload.password)?;
+            let user = sqlx::query_as::<_, #user_struct>(#insert)
+                .bind(payload.email)
+                .bind(password_hash)
+                #role_bind
+                #(#extra_binds)*
+                .fetch_one(&pool)
+                .await
+                .map_err(|e| match e.as_database_error().is_some_and(|e| e.is_unique_violation()) {
+                    true => AppError::Conflict(#taken.to_string()),
+                    false => AppError::from(e),
+                })?;
+
+            let mut tokens = crate::auth::issue_tokens(&user.#pk_name, #role)?;
+            tokens["user"] = json!(user);
+            Ok(Json(tokens))
+        }
+
+        /// Data layer: checks the password and signs the account in
+        pub async fn data_auth_login(
+            extract::State(pool): extract::State<PgPool>,
+            Json(payload): Json<LoginPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            let user = sqlx::query_as::<_, #user_struct>(#by_email)
+                .bind(&payload.email)
+                .fetch_optional(&pool)
+                .await?;
+            // an unknown email and a wrong password look the same to the caller
+            let user = user
+                .filter(|user| crate::auth::verify_password(&payload.password, #stored_hash))
+                .ok_or(AppError::Unauthorized("Invalid email or password".to_string()))?;
+
+            let mut tokens = crate::auth::issue_tokens(&user.#pk_name, #role)?;
+            tokens["user"] = json!(user);
+            Ok(Json(tokens))
+        }
+
+        /// Data layer: trades a refresh token for new tokens, if the account is
+        /// still there. The new tokens carry the account's current role.
+        pub async fn data_auth_refresh(
+            extract::State(pool): extract::State<PgPool>,
+            Json(payload): Json<RefreshPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            let token = crate::auth::verify_token(&payload.refresh_token, "refresh")?;
+            let user = sqlx::query_as::<_, #user_struct>(#by_pk)
+                .bind(&token.id)
+                .fetch_optional(&pool)
+                .await?
+                .ok_or(AppError::Unauthorized("The account no longer exists".to_string()))?;
+            Ok(Json(crate::auth::issue_tokens(&user.#pk_name, #role)?))
+        }
+
+        /// Data layer: looks up the signed in account
+        pub async fn data_auth_me(
+            extract::State(pool): extract::State<PgPool>,
+            user: crate::auth::AuthUser,
+        ) -> Result<Json<Value>, AppError> {
+            let user = sqlx::query_as::<_, #user_struct>(#by_pk)
+                .bind(&user.id)
+                .fetch_optional(&pool)
+                .await?
+                .ok_or(AppError::Unauthorized("The account no longer exists".to_string()))?;
+            Ok(Json(json!({ "payload": user })))
+        }
+
+        /// Data layer: checks the current password and stores a hash of the new one
+        pub async fn data_auth_password(
+            extract::State(pool): extract::State<PgPool>,
+            user: crate::auth::AuthUser,
+            Json(payload): Json<PasswordPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            let id = user.id;
+            let user = sqlx::query_as::<_, #user_struct>(#by_pk)
+                .bind(&id)
+                .fetch_optional(&pool)
+                .await?
+                .ok_or(AppError::Unauthorized("The account no longer exists".to_string()))?;
+            // a stolen access token alone can't lock the owner out
+            if !crate::auth::verify_password(&payload.current_password, #stored_hash) {
+                return Err(AppError::Forbidden("current_password is wrong".to_string()));
+            }
+            check_password(&payload.new_password)?;
+            let password_hash = crate::auth::hash_password(&payload.new_password)?;
+            let user = sqlx::query_as::<_, #user_struct>(#set_password)
+                .bind(password_hash)
+                .bind(&id)
+                .fetch_optional(&pool)
+                .await?
+                .ok_or(AppError::Unauthorized("The account no longer exists".to_string()))?;
+            Ok(Json(json!({ "payload": user })))
+        }
+
+        /// Data layer: checks the current password and moves the account to a new email
+        pub async fn data_auth_email(
+            extract::State(pool): extract::State<PgPool>,
+            user: crate::auth::AuthUser,
+            Json(payload): Json<EmailPayload>,
+        ) -> Result<Json<Value>, AppError> {
+            if !payload.new_email.contains('@') {
+                return Err(AppError::BadRequest("new_email is not an email address".to_string()));
+            }
+            let id = user.id;
+            let user = sqlx::query_as::<_, #user_struct>(#by_pk)
+                .bind(&id)
+                .fetch_optional(&pool)
+                .await?
+                .ok_or(AppError::Unauthorized("The account no longer exists".to_string()))?;
+            if !crate::auth::verify_password(&payload.current_password, #stored_hash) {
+                return Err(AppError::Forbidden("current_password is wrong".to_string()));
+            }
+            let user = sqlx::query_as::<_, #user_struct>(#set_email)
+                .bind(payload.new_email)
+                .bind(&id)
+                .fetch_optional(&pool)
+                .await
+                .map_err(|e| match e.as_database_error().is_some_and(|e| e.is_unique_violation()) {
+                    true => AppError::Conflict(#taken.to_string()),
+                    false => AppError::from(e),
+                })?
+                .ok_or(AppError::Unauthorized("The account no longer exists".to_string()))?;
+            Ok(Json(json!({ "payload": user })))
+        }
+    });
+    // unlike a table's data module this one uses none of the other data
+    // modules, so it doesn't glob import them
+    let file_name = format!("{}.rs", AUTH_MODULE);
+    let TableModule { models, handlers, data } = module;
+    write_module(&src_dir.join("models").join(&file_name), models)?;
+    write_module(&src_dir.join("handlers").join(&file_name), quote! {
+        use crate::data::*;
+        use crate::models::*;
+
+        #handlers
+    })?;
+    write_module(&src_dir.join("data").join(&file_name), quote! {
+        use crate::models::*;
+
+        #data
+    })?;
+
+    let tokens = json!({
+        "type": "object",
+        "properties": {
+            "access_token": {"type": "string"},
+            "refresh_token": {"type": "string"},
+            "token_type": {"const": "Bearer"},
+            "expires_in": {"type": "integer", "description": "seconds the access token is valid for"},
+            "user": schema_ref(&table.to_case(Case::Pascal)),
+        },
+        "required": ["access_token", "refresh_token", "token_type", "expires_in"],
+    });
+    let credentials = |cols: &[&Col]| {
+        let mut schema = object_schema(users, cols, |_| false);
+        schema["properties"]["password"] = json!({"type": "string", "minLength": MIN_PASSWORD_LENGTH});
+        schema["required"].as_array_mut().expect("object_schema lists the required fields").push(json!("password"));
+        schema
+    };
+    let email_col = users.cols.iter().find(|col| col.name == EMAIL_COLUMN).expect("Authz::new checked the email column");
+    let endpoint = |method: HttpMethod, action: &str, operation: Operation| Endpoint {
+        method,
+        path: format!("/{}/{}", AUTH_MODULE, action),
+        handler: format!("auth_{}", action),
+        protected: false,
+        operation: Operation { tag: Some(AUTH_MODULE.to_string()), ..operation },
+    };
+    Ok(vec![
+        endpoint(HttpMethod::Post, "register", Operation {
+            summary: format!("Create an account in {} and sign in", table),
+            body: Some(credentials(&[&[email_col], extra_cols.as_slice()].concat())),
+            response: tokens.clone(),
+            errors: vec![400, 409, 422, 500],
+            ..Operation::default()
+        }),
+        endpoint(HttpMethod::Post, "login", Operation {
+            summary: "Sign in with email and password".to_string(),
+            body: Some(credentials(&[email_col])),
+            response: tokens.clone(),
+            errors: vec![401, 422, 500],
+            ..Operation::default()
+        }),
+        endpoint(HttpMethod::Post, "refresh", Operation {
+            summary: "Trade a refresh token for new tokens".to_string(),
+            body: Some(json!({
+                "type": "object",
+                "properties": {"refresh_token": {"type": "string"}},
+                "required": ["refresh_token"],
+            })),
+            response: tokens,
+            errors: vec![401, 422, 500],
+            ..Operation::default()
+        }),
+        Endpoint {
+            protected: true,
+            ..endpoint(HttpMethod::Get, "me", Operation {
+                summary: "The signed in account".to_string(),
+                response: json!({
+                    "type": "object",
+                    "properties": {"payload": schema_ref(&table.to_case(Case::Pascal))},
+                    "required": ["payload"],
+                }),
+                errors: vec![401, 500],
+                ..Operation::default()
+            })
+        },
+        Endpoint {
+            protected: true,
+            ..endpoint(HttpMethod::Post, "password", Operation {
+                summary: "Change the signed in account's password".to_string(),
+                description: Some("The current password is asked for again, a wrong one is turned away with 403.".to_string()),
+                body: Some(json!({
+                    "type": "object",
+                    "properties": {
+                        "current_password": {"type": "string"},
+                        "new_password": {"type": "string", "minLength": MIN_PASSWORD_LENGTH},
+                    },
+                    "required": ["current_password", "new_password"],
+                })),
+                response: json!({
+                    "type": "object",
+                    "properties": {"payload": schema_ref(&table.to_case(Case::Pascal))},
+                    "required": ["payload"],
+                }),
+                errors: vec![400, 401, 403, 422, 500],
+                ..Operation::default()
+            })
+        },
+        Endpoint {
+            protected: true,
+            ..endpoint(HttpMethod::Post, "email", Operation {
+                summary: "Change the signed in account's email".to_string(),
+                description: Some("The current password is asked for again, a wrong one is turned away with 403.".to_string()),
+                body: Some(json!({
+                    "type": "object",
+                    "properties": {
+                        "current_password": {"type": "string"},
+                        "new_email": {"type": "string", "format": "email"},
+                    },
+                    "required": ["current_password", "new_email"],
+                })),
+                response: json!({
+                    "type": "object",
+                    "properties": {"payload": schema_ref(&table.to_case(Case::Pascal))},
+                    "required": ["payload"],
+                }),
+                errors: vec![400, 401, 403, 409, 422, 500],
+                ..Operation::default()
+            })
+        },
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_structs::HttpMethod;
+    use crate::schema::parse_rows;
+
+    const SCHEMA: &str = "CREATE TABLE accounts (id SERIAL PRIMARY KEY, email TEXT NOT NULL UNIQUE, password_hash TEXT NOT NULL, role TEXT);
+        CREATE TABLE posts (id SERIAL PRIMARY KEY, author INT REFERENCES accounts(id), title TEXT, password_hash TEXT);
+        CREATE TABLE tags (id SERIAL PRIMARY KEY, name TEXT);";
+
+    fn col<'a>(row: &'a Row, name: &str) -> &'a Col {
+        row.cols.iter().find(|col| col.name == name).unwrap()
+    }
+
+    fn config(public: &[&str]) -> AuthConfig {
+        AuthConfig { public: public.iter().map(|pattern| pattern.to_string()).collect(), ..AuthConfig::default() }
+    }
+
+    #[test]
+    fn public_routes_match_by_handler_path_or_prefix() {
+        let config = config(&["get_posts", "/get_tags/:id", "get_users_*"]);
+        assert!(config.is_public(&Endpoint::new(HttpMethod::Get, "get_posts")));
+        assert!(config.is_public(&Endpoint::with_path_param(HttpMethod::Get, "get_tags", "id")));
+        assert!(config.is_public(&Endpoint::new(HttpMethod::Get, "get_users_by_email")));
+        assert!(!config.is_public(&Endpoint::new(HttpMethod::Get, "get_posts_count")));
+        assert!(!config.is_public(&Endpoint::new(HttpMethod::Get, "get_tags")));
+        assert!(!config.is_public(&Endpoint::new(HttpMethod::Post, "add_posts")));
+        assert!(!AuthConfig::default().is_public(&Endpoint::new(HttpMethod::Get, "get_posts")));
+    }
+
+    #[test]
+    fn users_table_is_found_or_named() {
+        let rows = parse_rows(SCHEMA).unwrap();
+        assert_eq!(AuthConfig::default().users_table(&rows).unwrap().name, "accounts");
+        let named = AuthConfig { table: Some("tags".to_string()), ..AuthConfig::default() };
+        assert_eq!(named.users_table(&rows).unwrap().name, "tags");
+        // tags has neither credential column
+        assert!(Authz::new(&named, &rows).is_none());
+    }
+
+    #[test]
+    fn only_the_accounts_password_hash_is_hidden() {
+        let rows = parse_rows(SCHEMA).unwrap();
+        let config = AuthConfig::default();
+        let authz = Authz::new(&config, &rows);
+        assert!(authz.is_some());
+        let (accounts, posts) = (&rows[0], &rows[1]);
+        assert!(is_hidden(authz.as_ref(), accounts, col(accounts, "password_hash")));
+        assert!(!is_hidden(authz.as_ref(), accounts, col(accounts, "email")));
+        assert!(!is_hidden(authz.as_ref(), posts, col(posts, "password_hash")));
+        assert!(!is_hidden(None, accounts, col(accounts, "password_hash")));
+    }
+
+    #[test]
+    fn default_roles() {
+        let roles = AuthConfig::default().roles();
+        assert_eq!(roles.keys().collect::<Vec<_>>(), ["admin", "member", "readonly"]);
+        assert!(roles["admin"].all_rows);
+        assert!(!roles["member"].all_rows);
+        assert_eq!(roles["member"].tables["*"], Action::ALL);
+        assert_eq!(roles["readonly"].tables["*"], [Action::Read]);
+        assert_eq!(AuthConfig::default().default_role(), "member");
+    }
+
+    #[test]
+    fn configured_roles_replace_the_defaults() {
+        let config: AuthConfig = toml::from_str(
+            "default_role = \"reader\"\n[roles.reader]\ntables = { posts = [\"read\"], \"*\" = [] }\n",
+        ).unwrap();
+        let roles = config.roles();
+        assert_eq!(roles.keys().collect::<Vec<_>>(), ["reader"]);
+        assert_eq!(roles["reader"].tables["posts"], [Action::Read]);
+        assert!(roles["reader"].tables["*"].is_empty());
+        assert_eq!(config.default_role(), "reader");
+        assert!(toml::from_str::<AuthConfig>("[roles.reader]\ntables = { posts = [\"write\"] }\n").is_err());
+    }
+
+    #[test]
+    fn rows_belong_through_the_key_to_accounts() {
+        let rows = parse_rows(SCHEMA).unwrap();
+        let config = AuthConfig::default();
+        let authz = Authz::new(&config, &rows).unwrap();
+        assert_eq!(authz.owner_col(&rows[0]).unwrap().name, "id");
+        assert_eq!(authz.owner_col(&rows[1]).unwrap().name, "author");
+        assert!(authz.owner_col(&rows[2]).is_none());
+    }
+
+    #[test]
+    fn credentials_are_the_accounts_email_and_password_hash() {
+        let rows = parse_rows(SCHEMA).unwrap();
+        let config = AuthConfig::default();
+        let authz = Authz::new(&config, &rows);
+        let (accounts, posts) = (&rows[0], &rows[1]);
+        let credentials: Vec<&str> = accounts.cols.iter()
+            .filter(|col| is_credential(authz.as_ref(), accounts, col))
+            .map(|col| col.name.as_str())
+            .collect();
+        assert_eq!(credentials, ["email", "password_hash"]);
+        assert!(!is_credential(authz.as_ref(), posts, col(posts, "password_hash")));
+        assert!(!is_credential(None, accounts, col(accounts, "email")));
+    }
+}
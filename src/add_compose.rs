@@ -1,26 +1,40 @@
-use std::{fs::OpenOptions, io::Write, fs::File};
-
-
-pub fn add_compose(path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    print!("{}\n", path);
-let compose = format!(
-"
-
-version: '3.8'
-
-services:
-  nginx:
+use crate::cli::Component;
+use crate::{manifest, vfs};
+
+
+/// Writes `docker-compose.yaml`, and `nginx/nginx.conf` with nginx, for
+/// `components`. Returns the names of the compose services.
+pub fn add_compose(project_dir: &std::path::Path, components: &[Component]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    println!("{}", project_dir.display());
+    let minio = components.contains(&Component::Minio);
+    let react = components.contains(&Component::React);
+    let fastapi = components.contains(&Component::Fastapi);
+    let nginx = components.contains(&Component::Nginx);
+    let auth = components.contains(&Component::Auth);
+
+    let mut services = String::new();
+
+    if nginx {
+        // nginx waits for whatever it proxies to
+        let depends_on = ["app"].into_iter()
+            .chain(react.then_some("frontend"))
+            .chain(fastapi.then_some("python"))
+            .map(|service| format!("      - {}\n", service))
+            .collect::<String>();
+        services.push_str(&format!(
+"  nginx:
     image: nginx:alpine
     ports:
       - \"3002:80\"
     volumes:
       - ./nginx/nginx.conf:/etc/nginx/nginx.conf:ro
     depends_on:
-      - app
-      - frontend
-      - python
+{depends_on}
+"));
+    }
 
-  db:
+    services.push_str(
+"  db:
     image: postgres:15-alpine
     environment:
       POSTGRES_USER: dbuser
@@ -36,7 +50,11 @@ services:
       timeout: 5s
       retries: 10
 
-  minio:
+");
+
+    if minio {
+        services.push_str(
+"  minio:
     image: minio/minio:latest
     command: server /data --console-address \":9001\"
     environment:
@@ -52,7 +70,7 @@ services:
       interval: 30s
       timeout: 20s
       retries: 3
-  
+
   createbuckets:
     image: minio/mc
     depends_on:
@@ -67,7 +85,13 @@ services:
       exit 0;
       \"
 
-  app:
+");
+    }
+
+    // tokens are signed with it, so set a real secret before deploying
+    let jwt_secret = if auth { "      JWT_SECRET: \"change-me\"\n" } else { "" };
+    services.push_str(&format!(
+"  app:
     build:
       context: .
       dockerfile: Dockerfile
@@ -75,7 +99,7 @@ services:
       DATABASE_URL: \"postgres://dbuser:p@db:5432/data\"
       DATABASE_CONNECT_TIMEOUT: \"30\"
       SQLX_OFFLINE: \"true\"
-    depends_on:
+{jwt_secret}    depends_on:
       db:
         condition: service_healthy
     restart: on-failure
@@ -86,14 +110,22 @@ services:
       retries: 5
       start_period: 30s
 
+"));
 
-  frontend:
+    if react {
+        services.push_str(
+"  frontend:
     build:
       context: ./frontend
     depends_on:
       - app
 
-  python:
+");
+    }
+
+    if fastapi {
+        services.push_str(
+"  python:
     build:
       context: ./fastapi-template
     depends_on:
@@ -107,66 +139,81 @@ services:
       retries: 5
       start_period: 30s
 
+");
+    }
+
+    // every service starts with its name, indented once
+    let names = services.lines()
+        .filter_map(|line| line.strip_prefix("  ")?.strip_suffix(':'))
+        .filter(|name| !name.starts_with(' '))
+        .map(String::from)
+        .collect();
+
+    let minio_volume = if minio { "  minio_data:\n" } else { "" };
+    let compose = format!(
+"
+
+version: '3.8'
+
+services:
+{services}
 volumes:
   postgres_data:
-  minio_data:
-
+{minio_volume}
 
 "
 );
   // Create the directory if it doesn't exist
   //std::fs::create_dir_all(path)?;
-  
-  let compose_path = format!("../{}/docker-compose.yaml", path);
-  let mut file = File::create(&compose_path)?;
-  file.write_all(compose.as_bytes())?;
-  
-  println!("compose created at {}", compose_path);
 
-  // nginx 
+  let compose_path = project_dir.join("docker-compose.yaml");
+  manifest::write_generated(&compose_path, compose)?;
 
-  let nginx = format!(
-    "events {{}}
+  println!("compose created at {}", compose_path.display());
 
-http {{
-    server {{
-        listen 80;
+  if !nginx {
+    return Ok(names);
+  }
 
-        location / {{
-            proxy_pass http://frontend:3000;
-            proxy_set_header Host $host;
-            proxy_set_header X-Real-IP $remote_addr;
-            proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
-            proxy_set_header X-Forwarded-Proto $scheme;
-        }}
+  // nginx
 
-        location /api/ {{
-            proxy_pass http://app:8081/;
+  let location = |path: &str, upstream: &str| format!(
+"
+        location {path} {{
+            proxy_pass {upstream};
             proxy_set_header Host $host;
             proxy_set_header X-Real-IP $remote_addr;
             proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
             proxy_set_header X-Forwarded-Proto $scheme;
         }}
+");
+  let mut locations = String::new();
+  if react {
+    locations.push_str(&location("/", "http://frontend:3000"));
+  }
+  locations.push_str(&location("/api/", "http://app:8081/"));
+  if fastapi {
+    locations.push_str(&location("/python/", "http://python:8003/"));
+  }
 
-        location /python/ {{
-            proxy_pass http://python:8003/;
-            proxy_set_header Host $host;
-            proxy_set_header X-Real-IP $remote_addr;
-            proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
-            proxy_set_header X-Forwarded-Proto $scheme;
-        }}
-    }}
+  let nginx = format!(
+    "events {{}}
+
+http {{
+    server {{
+        listen 80;
+{locations}    }}
 }}"
   );
 
-  let nginx_path = format!("../{}/nginx/nginx.conf", path);
-  std::fs::create_dir_all(format!("../{}/nginx", path))?;
-  let mut file = File::create(&nginx_path)?;
-  file.write_all(nginx.as_bytes())?;
-  
-  println!("nginx created at {}", nginx_path);
+  let nginx_dir = project_dir.join("nginx");
+  vfs::create_dir_all(&nginx_dir)?;
+  let nginx_path = nginx_dir.join("nginx.conf");
+  manifest::write_generated(&nginx_path, nginx)?;
+
+  println!("nginx created at {}", nginx_path.display());
 
-  Ok(())
+  Ok(names)
 }
 
 // docker build -t pangolin-testing .
@@ -1,18 +1,21 @@
-use fs_extra::dir::{copy, CopyOptions};
 use std::path::Path;
-pub fn add_fastapi(path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let from = Path::new("fastapi-template");
-    let destination = format!("../{}", path);
-    let to = Path::new(&destination);
 
-    let options = CopyOptions::new(); // Use default options
+use crate::vfs;
 
-    println!("Attempting to copy directory from {:?} to {:?}", from, to);
+pub fn add_fastapi(project_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // the template ships with this repo, wherever the generator is run from
+    let from = Path::new(env!("CARGO_MANIFEST_DIR")).join("fastapi-template");
+    let to = project_dir;
 
-    // This function handles creating destination folders and copying all files/subdirectories
+    if vfs::exists(to.join("fastapi-template")) {
+        println!("{:?} already has the fastapi template, leaving it as it is", to);
+        return Ok(());
+    }
 
+    println!("Attempting to copy directory from {:?} to {:?}", from, to);
 
-    copy(from, to, &options).map_err(|e| Box::<dyn std::error::Error>::from(e))?;
+    // This function handles creating destination folders and copying all files/subdirectories
+    vfs::copy_dir(from, to)?;
     println!("✅ Directory copied successfully!");
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::io;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::codegen::{rust_type, write_module};
+use crate::schema::Col;
+
+/// Rust types that parse from a query string value and bind directly. Any
+/// other column, or one whose SQL type isn't in the type map and only falls
+/// back to `String`, is bound as text and cast to its SQL type by postgres.
+const BINDABLE_TYPES: &[&str] = &[
+    "bool",
+    "i16",
+    "i32",
+    "i64",
+    "f32",
+    "f64",
+    "rust_decimal::Decimal",
+    "String",
+    "uuid::Uuid",
+    "chrono::DateTime<Utc>",
+    "chrono::NaiveDateTime",
+    "chrono::NaiveDate",
+    "chrono::NaiveTime",
+];
+
+/// The `push_filter` call for one column of `table`, e.g.
+/// `push_filter::<i32>(query, "users.age", filter, None)`.
+pub fn push_filter_call(col: &Col, table: &str, type_map: &HashMap<String, String>) -> Result<TokenStream, io::Error> {
+    let column = format!("{}.{}", table, col.name);
+    // arrays are bound as text, e.g. `{a,b}`, and cast to the array type
+    let bind_type = Some(col.rust_type(type_map))
+        .filter(|t| type_map.contains_key(&col.col_type) && BINDABLE_TYPES.contains(&t.as_str()));
+    match bind_type {
+        Some(bind_type) => {
+            let bind_type = rust_type(&bind_type)?;
+            Ok(quote! { push_filter::<#bind_type>(query, #column, filter, None) })
+        }
+        None => {
+            // with its length, a BIT(8) value isn't cut down to one bit
+            let cast = col.declared_cast_type();
+            Ok(quote! { push_filter::<String>(query, #column, filter, Some(#cast)) })
+        }
+    }
+}
+
+/// Writes the filter grammar shared by every generated list endpoint:
+/// `column=value`, `column[op]=value` and the code that binds them.
+pub fn add_filter_helpers(file_path: &std::path::Path) -> Result<(), io::Error> {
+    let helpers = quote! {
+        /// Operators accepted as `column[op]=value` on list endpoints. A bare
+        /// `column=value` is `eq`.
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum FilterOp {
+            Eq,
+            Ne,
+            Gt,
+            Gte,
+            Lt,
+            Lte,
+            Like,
+            Ilike,
+            In,
+            NotIn,
+            Is,
+            Between,
+        }
+
+        impl FilterOp {
+            fn parse(op: &str) -> Option<FilterOp> {
+                match op {
+                    "eq" => Some(FilterOp::Eq),
+                    "ne" | "neq" => Some(FilterOp::Ne),
+                    "gt" => Some(FilterOp::Gt),
+                    "gte" => Some(FilterOp::Gte),
+                    "lt" => Some(FilterOp::Lt),
+                    "lte" => Some(FilterOp::Lte),
+                    "like" => Some(FilterOp::Like),
+                    "ilike" => Some(FilterOp::Ilike),
+                    "in" => Some(FilterOp::In),
+                    "nin" | "not_in" => Some(FilterOp::NotIn),
+                    "is" => Some(FilterOp::Is),
+                    "between" => Some(FilterOp::Between),
+                    _ => None,
+                }
+            }
+
+            fn sql(self) -> &'static str {
+                match self {
+                    FilterOp::Eq => "=",
+                    FilterOp::Ne => "<>",
+                    FilterOp::Gt => ">",
+                    FilterOp::Gte => ">=",
+                    FilterOp::Lt => "<",
+                    FilterOp::Lte => "<=",
+                    FilterOp::Like => "LIKE",
+                    FilterOp::Ilike => "ILIKE",
+                    FilterOp::In => "IN",
+                    FilterOp::NotIn => "NOT IN",
+                    FilterOp::Is => "IS",
+                    FilterOp::Between => "BETWEEN",
+                }
+            }
+        }
+
+        pub struct Filter {
+            pub column: String,
+            pub op: FilterOp,
+            pub value: String,
+        }
+
+        /// Splits `age[gte]=18` style query parameters into filters. Column names
+        /// are checked later against the table being queried.
+        pub fn parse_filters(params: &HashMap<String, String>) -> Result<Vec<Filter>, AppError> {
+            params.iter().map(|(key, value)| {
+                let (column, op) = match key.split_once('[') {
+                    Some((column, rest)) => {
+                        let op = rest.strip_suffix(']')
+                            .and_then(FilterOp::parse)
+                            .ok_or(AppError::BadRequest(format!("Invalid filter operator: {}", key)))?;
+                        (column, op)
+                    }
+                    None => (key.as_str(), FilterOp::Eq),
+                };
+                Ok(Filter { column: column.to_string(), op, value: value.clone() })
+            }).collect()
+        }
+
+        pub fn parse_filter_value<T: std::str::FromStr>(filter: &Filter, value: &str) -> Result<T, AppError> {
+            value.parse::<T>()
+                .map_err(|_| AppError::BadRequest(format!("Invalid value for {}: {}", filter.column, value)))
+        }
+
+        /// Binds a value, wrapped in a CAST when the column has no rust type to bind with.
+        pub fn push_filter_value<T>(query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>, value: T, cast: Option<&str>)
+        where
+            T: for<'q> sqlx::Encode<'q, sqlx::Postgres> + sqlx::Type<sqlx::Postgres> + Send + 'static,
+        {
+            match cast {
+                Some(sql_type) => {
+                    query.push("CAST(");
+                    query.push_bind(value);
+                    query.push(format!(" AS {})", sql_type));
+                }
+                None => {
+                    query.push_bind(value);
+                }
+            }
+        }
+
+        /// Appends the condition for one filter. Values are parsed as `T`, the rust
+        /// type of the column, so a bad integer or uuid is a 400 rather than a
+        /// database error. `column` is the table qualified column the filter was
+        /// checked against.
+        pub fn push_filter<T>(
+            query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+            column: &str,
+            filter: &Filter,
+            cast: Option<&str>,
+        ) -> Result<(), AppError>
+        where
+            T: std::str::FromStr + for<'q> sqlx::Encode<'q, sqlx::Postgres> + sqlx::Type<sqlx::Postgres> + Send + 'static,
+        {
+            match filter.op {
+                FilterOp::Is => {
+                    let test = match filter.value.to_lowercase().as_str() {
+                        "null" => "NULL",
+                        "not_null" | "notnull" => "NOT NULL",
+                        "true" => "TRUE",
+                        "false" => "FALSE",
+                        _ => return Err(AppError::BadRequest(
+                            format!("{}[is] must be null, not_null, true or false", filter.column),
+                        )),
+                    };
+                    query.push(format!("{} IS {}", column, test));
+                }
+                // pattern matching works on the text form of any column
+                FilterOp::Like | FilterOp::Ilike => {
+                    query.push(format!("CAST({} AS TEXT) {} ", column, filter.op.sql()));
+                    query.push_bind(filter.value.clone());
+                }
+                FilterOp::In | FilterOp::NotIn => {
+                    let values = filter.value.split(',')
+                        .map(|value| parse_filter_value::<T>(filter, value))
+                        .collect::<Result<Vec<T>, _>>()?;
+                    query.push(format!("{} {} (", column, filter.op.sql()));
+                    for (i, value) in values.into_iter().enumerate() {
+                        if i > 0 {
+                            query.push(", ");
+                        }
+                        push_filter_value(query, value, cast);
+                    }
+                    query.push(")");
+                }
+                FilterOp::Between => {
+                    let (low, high) = filter.value.split_once(',').ok_or(AppError::BadRequest(
+                        format!("{}[between] takes two values: low,high", filter.column),
+                    ))?;
+                    let (low, high) = (parse_filter_value::<T>(filter, low)?, parse_filter_value::<T>(filter, high)?);
+                    query.push(format!("{} BETWEEN ", column));
+                    push_filter_value(query, low, cast);
+                    query.push(" AND ");
+                    push_filter_value(query, high, cast);
+                }
+                FilterOp::Eq | FilterOp::Ne | FilterOp::Gt | FilterOp::Gte | FilterOp::Lt | FilterOp::Lte => {
+                    let value = parse_filter_value::<T>(filter, &filter.value)?;
+                    query.push(format!("{} {} ", column, filter.op.sql()));
+                    push_filter_value(query, value, cast);
+                }
+            }
+            Ok(())
+        }
+    };
+
+    write_module(file_path, helpers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_type_map;
+    use crate::schema::parse_rows;
+
+    fn calls(sql: &str) -> Vec<String> {
+        let type_map = create_type_map();
+        let row = parse_rows(sql).unwrap().remove(0);
+        row.cols.iter()
+            .map(|col| push_filter_call(col, &row.name, &type_map).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parseable_types_bind_directly() {
+        assert_eq!(calls("CREATE TABLE t (id INT, big BIGINT, name TEXT, ok BOOLEAN, born DATE, price NUMERIC(10,2));"), vec![
+            "push_filter :: < i32 > (query , \"t.id\" , filter , None)",
+            "push_filter :: < i64 > (query , \"t.big\" , filter , None)",
+            "push_filter :: < String > (query , \"t.name\" , filter , None)",
+            "push_filter :: < bool > (query , \"t.ok\" , filter , None)",
+            "push_filter :: < chrono :: NaiveDate > (query , \"t.born\" , filter , None)",
+            "push_filter :: < rust_decimal :: Decimal > (query , \"t.price\" , filter , None)",
+        ]);
+    }
+
+    #[test]
+    fn other_types_are_cast_from_text() {
+        assert_eq!(calls("CREATE TABLE t (span INTERVAL, tags TEXT[], meta JSONB, flags BIT(8), codes CHAR(3)[]);"), vec![
+            "push_filter :: < String > (query , \"t.span\" , filter , Some (\"INTERVAL\"))",
+            "push_filter :: < String > (query , \"t.tags\" , filter , Some (\"TEXT[]\"))",
+            "push_filter :: < String > (query , \"t.meta\" , filter , Some (\"JSONB\"))",
+            "push_filter :: < String > (query , \"t.flags\" , filter , Some (\"BIT(8)\"))",
+            "push_filter :: < String > (query , \"t.codes\" , filter , Some (\"CHAR(3)[]\"))",
+        ]);
+    }
+
+    #[test]
+    fn helpers_cover_every_operator() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("filters.rs");
+        add_filter_helpers(&file_path).unwrap();
+        let source = std::fs::read_to_string(&file_path).unwrap();
+        for op in ["eq", "ne", "neq", "gt", "gte", "lt", "lte", "like", "ilike", "in", "nin", "not_in", "is", "between"] {
+            assert!(source.contains(&format!("\"{}\"", op)), "{} is not parsed", op);
+        }
+        assert!(source.contains("\"Invalid filter operator: {}\""));
+    }
+}
@@ -1,299 +1,1006 @@
-use std::fs::OpenOptions;
 use std::io;
-use std::io::BufWriter;
-use std::io::Write;
-use std::collections::HashMap;
 
-
-use crate::schema::Col;
 use convert_case::{Case, Casing};
+use quote::quote;
+
+use crate::add_auth::{is_credential, is_hidden, user_arg, user_param, with_forbidden, Action, Authz};
+use crate::add_filters::push_filter_call;
+use crate::add_joins::{expansions, join_struct};
+use crate::add_validation::{validate_impl, PayloadKind};
 use crate::base_structs;
+use crate::base_structs::{deleted_value, is_creation_stamp, Endpoint, HttpMethod};
+use crate::codegen::{ident, rust_type, TableModule};
 use crate::create_type_map;
+use crate::openapi;
 use crate::schema;
 
 
+/// The columns of `row` its list endpoint filters and sorts by. Hidden
+/// columns are left out, a filter or cursor on them would give their
+/// values away.
+pub fn query_cols<'a>(row: &'a base_structs::Row, authz: Option<&Authz>) -> Vec<&'a schema::Col> {
+    row.cols.iter().filter(|col| !is_hidden(authz, row, col)).collect()
+}
+
+/// The columns of `row` callers may set through the insert, PUT and PATCH
+/// payloads. Credentials are only written by /auth, which hashes passwords
+/// and asks for the current one, and the soft delete column only by DELETE,
+/// so a deleted row can't be brought back by writing it.
+pub fn payload_cols<'a>(row: &'a base_structs::Row, authz: Option<&Authz>) -> Vec<&'a schema::Col> {
+    row.cols.iter()
+        .filter(|col| col.name != row.soft_delete_column && !is_credential(authz, row, col))
+        .collect()
+}
+
 pub fn add_get_all_func(
     row: &base_structs::Row,
-    file_path: &std::path::Path,
-) -> Result<String, io::Error> {
-    // Ensure parent directories exist
-    if let Some(parent) = file_path.parent() {
-        std::fs::create_dir_all(parent)?;
-    }
-    
-    let row_name = row.name.clone();
-    let func_name = format!("get_{}", row.name.clone());
-    let struct_name = row.name.clone().to_case(Case::Pascal);
-
-    // Generate the JSON fields for the response
-    let cols: String = row.cols.iter()
-        .map(|col| format!("\t\"{}\": elemint.{}, \n", col.name, col.name))
-        .collect::<String>()
-        .trim_end_matches(", \n")
-        .to_string();
-    
-    // API layer function - calls data layer and can add business logic
-    let api_func = format!(r###"
-
-#[derive(Deserialize)]
-struct {row_name}QueryParams {{
-    order_by: Option<String>,
-    direction: Option<String>, // "asc" or "desc"
-    #[serde(flatten)]
-    filters: HashMap<String, String>,
-}}
-
-
-pub async fn {func_name}(
-    extract::State(pool): extract::State<PgPool>,
-    Query(query_params): Query<{row_name}QueryParams>,
-) -> Result<Json<Value>, (StatusCode, String)> {{
-    // Call data function from data module 
-    // Other business logic can also be handled here 
-    let result = data_{func_name}(extract::State(pool), axum::extract::Query(query_params)).await;
-    result
-}}
-"###);
-// owned_str.push_str(borrows_str)
-    // Data layer function - handles database operations
-    let data_func = format!(r###"
-
-
-pub async fn data_{func_name}(
-    extract::State(pool): extract::State<PgPool>,
-    query_params: axum::extract::Query<{row_name}QueryParams>,
-) -> Result<Json<Value>, (StatusCode, String)> {{
-    let mut query = "SELECT * FROM {row_name}".to_owned();
-    let mut sql_params: Vec<String> = Vec::new();
-    let mut param_index = 1;
-    
-    // Handle filters
-    if !query_params.filters.is_empty() {{
-        let mut where_conditions: Vec<String> = Vec::new();
-        
-        for (field, value) in &query_params.filters {{
-            // Skip ordering parameters
-            if field == "order_by" || field == "direction" {{
-                continue;
-            }}
-            
-            // Validate field name to prevent SQL injection
-            if field.chars().all(|c| c.is_alphanumeric() || c == '_') {{
-                where_conditions.push(format!("{{}} = ${{}}", field, param_index));
-                sql_params.push(value.clone());
-                param_index += 1;
-            }} else {{
-                return Err((StatusCode::BAD_REQUEST, format!("Invalid field name: {{}}", field)));
-            }}
-        }}
-        
-        if !where_conditions.is_empty() {{
-            query.push_str(&(" WHERE ".to_owned() + &where_conditions.join(" AND ")));
-        }}
-    }}
-    
-    // Validate and apply ordering if provided
-    if let Some(order_by) = &query_params.order_by {{
-        // Validate order_by column name to prevent SQL injection
-        // Only allow alphanumeric characters and underscores
-        if order_by.chars().all(|c| c.is_alphanumeric() || c == '_') {{
-            // Validate direction parameter
-            let direction = match &query_params.direction {{
+    rows: &[base_structs::Row],
+    authz: Option<&Authz>,
+    module: &mut TableModule,
+) -> Result<Endpoint, io::Error> {
+    let type_map = create_type_map();
+    let table = &row.name;
+    let func_name = format!("get_{}", row.name);
+    let func = ident(&func_name);
+    let data_func = ident(&format!("data_{}", func_name));
+    let struct_name = ident(&row.name.to_case(Case::Pascal));
+    let params_struct = ident(&format!("{}QueryParams", row.name));
+    let column_type_fn = ident(&format!("{}_column_type", row.name));
+    let conditions_fn = ident(&format!("push_{}_conditions", row.name));
+    let cols = query_cols(row, authz);
+
+    // soft deleted rows are never listed
+    let (where_start, soft_delete_filter) = match row.live_condition(Some(table)) {
+        Some(live) => {
+            let condition = format!(" WHERE {}", live);
+            (true, Some(quote! { query.push(#condition); }))
+        }
+        None => (false, None),
+    };
+
+    // Keyset pagination needs a unique tie breaker, so it is only offered
+    // for tables with a single column primary key. Without one, pages are
+    // ordered by the first column and only limit/offset is available.
+    let pk_count = row.cols.iter().filter(|col| col.primary_key).count();
+    let pk = row.primary_key().filter(|_| pk_count == 1);
+    let default_order = pk.or(cols.first().copied()).map(|col| col.name.clone()).unwrap_or_default();
+    // the primary key breaks ties so rows never repeat or go missing between pages.
+    // Columns are qualified with the table name because ?expand joins another table.
+    let order_clause = match pk {
+        Some(pk) => {
+            let order = format!(" ORDER BY {0}.{{}} {{}}, {0}.{1} {{}} LIMIT {{}} OFFSET {{}}", table, pk.name);
+            quote! { query.push(format!(#order, order_by, direction, direction, limit + 1, offset)); }
+        }
+        None => {
+            let order = format!(" ORDER BY {}.{{}} {{}} LIMIT {{}} OFFSET {{}}", table);
+            quote! { query.push(format!(#order, order_by, direction, limit + 1, offset)); }
+        }
+    };
+    // only the keyset condition needs to know whether a WHERE was written
+    let has_where = pk.map(|_| quote! { let has_where = });
+    let keyset = match pk {
+        Some(pk) => {
+            let column = format!("{}.{{}}", table);
+            let key = format!("{}.{}", table, pk.name);
+            let key_type = pk.declared_cast_type();
+            quote! {
+                if let Some(cursor) = &query_params.cursor {
+                    let cursor = decode_cursor(cursor)
+                        .filter(|c| c.column == order_by)
+                        .ok_or(AppError::BadRequest("Invalid cursor for this order_by".to_string()))?;
+                    let column_type = #column_type_fn(&order_by).unwrap_or("TEXT");
+                    query.push(if has_where { " AND " } else { " WHERE " });
+                    push_keyset_condition(
+                        &mut query,
+                        &format!(#column, order_by),
+                        column_type,
+                        #key,
+                        #key_type,
+                        direction == "DESC",
+                        &cursor,
+                    );
+                }
+            }
+        }
+        None => quote! {
+            if query_params.cursor.is_some() {
+                return Err(AppError::BadRequest("This table does not support cursor pagination, use offset".to_string()));
+            }
+        },
+    };
+    let next_cursor = match pk {
+        Some(pk) => {
+            let key = &pk.name;
+            quote! {
+                let next_cursor = if has_more {
+                    elemints.last().map(|(last, _)| {
+                        let last = serde_json::to_value(last).unwrap_or(Value::Null);
+                        encode_cursor(&PageCursor {
+                            column: order_by.clone(),
+                            value: json_text(&last[order_by.as_str()]),
+                            key: json_text(&last[#key]).unwrap_or_default(),
+                        })
+                    })
+                } else {
+                    None
+                };
+            }
+        }
+        None => quote! { let next_cursor: Option<String> = None; },
+    };
+
+    // Column names mapped to the type their text parameters are cast to
+    let column_types = cols.iter().map(|col| {
+        let (name, cast) = (&col.name, col.declared_cast_type());
+        quote! { #name => Some(#cast), }
+    });
+    // Each column filters with its own rust type
+    let column_filters = cols.iter().map(|col| {
+        let name = &col.name;
+        let call = push_filter_call(col, table, &type_map)?;
+        Ok(quote! { #name => #call?, })
+    }).collect::<Result<Vec<_>, io::Error>>()?;
+
+    // ?expand=<name> joins in the row a foreign key points at, one
+    // FromRow struct per join shape
+    let expansions = expansions(row, rows, authz);
+    let join_structs = expansions.iter()
+        .map(|expansion| join_struct(row, expansion))
+        .collect::<Result<Vec<_>, io::Error>>()?;
+    // an expanded table is read too, so the caller's role has to allow it
+    let expand_selects = expansions.iter().map(|expansion| {
+        let (name, select) = (&expansion.name, expansion.select(row));
+        match expansion.authorize(authz) {
+            Some(authorize) => quote! {
+                Some(#name) => {
+                    #authorize
+                    #select
+                }
+            },
+            None => quote! { Some(#name) => #select, },
+        }
+    });
+    let expand_owners = expansions.iter().filter_map(|expansion| expansion.owner_condition(authz));
+    let expand_fetches = expansions.iter().map(|expansion| {
+        let name = &expansion.name;
+        let join_struct = ident(&expansion.struct_name(row));
+        let json_fn = ident(&format!("{}_json", expansion.name));
+        quote! {
+            Some(#name) => query.build_query_as::<#join_struct>().fetch_all(&pool).await
+                .map(|rows| rows.into_iter().map(|row| {
+                    let expanded = row.#json_fn();
+                    (row.base, Some((#name, expanded)))
+                }).collect()),
+        }
+    });
+    let (user_param, user_arg) = (user_param(authz), user_arg(authz));
+    let authorize = authz.map(|authz| authz.authorize(row, Action::Read));
+    // callers that only see their own rows get a condition on the owner
+    // column on top of their filters
+    let owner = authz.and_then(|authz| authz.owner_col(row));
+    let owner_param = owner.map(|owner| {
+        let owner_type = rust_type(&owner.rust_type(&type_map))?;
+        Ok::<_, io::Error>(quote! { owner: Option<#owner_type>, })
+    }).transpose()?;
+    let owner_condition = owner.map(|owner| {
+        let column = format!("{}.{} = ", table, owner.name);
+        quote! {
+            if let Some(owner) = owner {
+                query.push(if has_where { " AND " } else { " WHERE " });
+                has_where = true;
+                query.push(#column);
+                query.push_bind(owner);
+            }
+        }
+    });
+    let owner_scope = owner.map(|_| quote! { let owner = crate::auth::owner_scope(&user); });
+    let (count_owner, page_owner) = match owner {
+        Some(_) => (Some(quote! { owner.clone(), }), Some(quote! { owner, })),
+        None => (None, None),
+    };
+    let plain_select = format!("SELECT {0}.* FROM {0}", table);
+    let count_select = format!("SELECT COUNT(*) FROM {}", table);
+    let where_doc = format!(
+        " Appends the WHERE clause shared by the {0} count and page queries.\n Filters on anything but a {0} column are rejected. Returns whether\n a WHERE was written.",
+        table
+    );
+
+    module.models.extend(quote! {
+        #[derive(Deserialize)]
+        pub struct #params_struct {
+            pub order_by: Option<String>,
+            /// "asc" or "desc"
+            pub direction: Option<String>,
+            /// pagination values are read as strings because serde(flatten)
+            /// passes every query value through as text
+            pub limit: Option<String>,
+            pub offset: Option<String>,
+            pub cursor: Option<String>,
+            /// a foreign key to embed, e.g. `expand=user` for a `user_id` column
+            pub expand: Option<String>,
+            /// everything else is a column filter such as `age[gte]=18`
+            #[serde(flatten)]
+            pub filters: HashMap<String, String>,
+        }
+
+        #(#join_structs)*
+    });
+    module.handlers.extend(quote! {
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn #func(
+            extract::State(pool): extract::State<PgPool>,
+            #user_param
+            Query(query_params): Query<#params_struct>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = #data_func(extract::State(pool), axum::extract::Query(query_params), #user_arg).await;
+            result
+        }
+    });
+    module.data.extend(quote! {
+        fn #column_type_fn(column: &str) -> Option<&'static str> {
+            match column {
+                #(#column_types)*
+                _ => None,
+            }
+        }
+
+        #[doc = #where_doc]
+        fn #conditions_fn(
+            query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+            filters: &[Filter],
+            #owner_param
+        ) -> Result<bool, AppError> {
+            let mut has_where = #where_start;
+            #soft_delete_filter
+            #owner_condition
+            for filter in filters {
+                query.push(if has_where { " AND " } else { " WHERE " });
+                has_where = true;
+                match filter.column.as_str() {
+                    #(#column_filters)*
+                    _ => return Err(AppError::BadRequest(format!("Unknown filter column: {}", filter.column))),
+                }
+            }
+            Ok(has_where)
+        }
+
+        /// Data layer: runs the count and page queries
+        pub async fn #data_func(
+            extract::State(pool): extract::State<PgPool>,
+            query_params: axum::extract::Query<#params_struct>,
+            #user_param
+        ) -> Result<Json<Value>, AppError> {
+            #authorize
+            #owner_scope
+            let filters = parse_filters(&query_params.filters)?;
+
+            // Page size is capped by MAX_PAGE_SIZE
+            let max_page_size = crate::config::max_page_size();
+            let limit = parse_param::<i64>("limit", &query_params.limit)?
+                .unwrap_or(max_page_size)
+                .clamp(1, max_page_size);
+            let offset = parse_param::<i64>("offset", &query_params.offset)?.unwrap_or(0);
+            if offset < 0 {
+                return Err(AppError::BadRequest("offset must not be negative".to_string()));
+            }
+            if query_params.cursor.is_some() && offset > 0 {
+                return Err(AppError::BadRequest("Use either cursor or offset, not both".to_string()));
+            }
+
+            // Validate ordering against the table's columns to prevent SQL injection
+            let order_by = query_params.order_by.clone().unwrap_or_else(|| #default_order.to_string());
+            if #column_type_fn(&order_by).is_none() {
+                return Err(AppError::BadRequest("Invalid order_by parameter".to_string()));
+            }
+            let direction = match &query_params.direction {
                 Some(dir) if dir.to_lowercase() == "desc" => "DESC",
                 _ => "ASC",
-            }};
-            
-            query.push_str(&format!(" ORDER BY {{}} {{}}", *order_by, direction));
-        }} else {{
-            return Err((StatusCode::BAD_REQUEST, "Invalid order_by parameter".to_string()));
-        }}
-    }}
-
-    // Execute query with parameters
-    let mut query_builder = sqlx::query_as::<_, {struct_name}>(&query);
-    for param in &sql_params {{
-        query_builder = query_builder.bind(param);
-    }}
-
-    let elemints: Vec<{struct_name}> = query_builder.fetch_all(&pool).await.map_err(|e| {{
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {{}}", e))
-    }})?;
-
-    let res_json: Vec<Value> = elemints.into_iter().map(|elemint| {{
-        json!({{
-    {cols}
-        }})
-    }}).collect();
-
-    Ok(Json(json!({{ "payload": res_json }})))
-}}
-"###);
-
-    // Write both functions to the same file
-    let mut file = OpenOptions::new()
-        .write(true)
-        .append(true)
-        .create(true)
-        .open(file_path)?;
-
-    // Write API function first, then data function
-    file.write_all(api_func.as_bytes())?;
-    file.write_all(data_func.as_bytes())?;
-
-    Ok(func_name.to_string())
+            };
+
+            // total_count covers every page, so count before the cursor narrows it
+            let mut count_query = sqlx::QueryBuilder::<sqlx::Postgres>::new(#count_select);
+            #conditions_fn(&mut count_query, &filters, #count_owner)?;
+            let total_count = count_query.build_query_scalar::<i64>().fetch_one(&pool).await?;
+
+            let select = match query_params.expand.as_deref() {
+                None => #plain_select,
+                #(#expand_selects)*
+                Some(other) => return Err(AppError::BadRequest(format!("Unknown expand: {}", other))),
+            };
+            let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(select);
+            #(#expand_owners)*
+            #has_where #conditions_fn(&mut query, &filters, #page_owner)?;
+            #keyset
+            // one extra row tells us whether there is another page
+            #order_clause
+
+            // each row comes with the json of the row it expands, if any
+            let fetched: Result<Vec<(#struct_name, Option<(&str, Value)>)>, sqlx::Error> = match query_params.expand.as_deref() {
+                #(#expand_fetches)*
+                _ => query.build_query_as::<#struct_name>().fetch_all(&pool).await
+                    .map(|rows| rows.into_iter().map(|row| (row, None)).collect()),
+            };
+            let mut elemints = fetched?;
+            let has_more = elemints.len() as i64 > limit;
+            elemints.truncate(limit as usize);
+            #next_cursor
+
+            let res_json: Vec<Value> = elemints.into_iter().map(|(elemint, expanded)| {
+                let mut res = serde_json::to_value(&elemint).unwrap_or(Value::Null);
+                if let Some((name, value)) = expanded {
+                    res[name] = value;
+                }
+                res
+            }).collect();
+
+            Ok(Json(json!({
+                "payload": res_json,
+                "next_cursor": next_cursor,
+                "has_more": has_more,
+                "total_count": total_count,
+                "limit": limit,
+                "offset": offset,
+            })))
+        }
+    });
+
+    let expansion_names: Vec<String> = expansions.iter().map(|expansion| expansion.name.clone()).collect();
+    let endpoint = Endpoint::new(HttpMethod::Get, &func_name).with_operation(openapi::list_operation(row, &cols, &expansion_names));
+    Ok(with_forbidden(endpoint, authz))
 }
 
-pub fn add_insert_func(row: &base_structs::Row, file_path: &std::path::Path) -> Result<String, io::Error> {
-    // Ensure parent directories exist
-    if let Some(parent) = file_path.parent() {
-        std::fs::create_dir_all(parent)?;
+pub fn add_insert_func(
+    row: &base_structs::Row,
+    authz: Option<&Authz>,
+    module: &mut TableModule,
+) -> Result<Option<Endpoint>, io::Error> {
+    // accounts come from /auth/register, which hashes the password
+    if authz.is_some_and(|authz| row.name == authz.users.name) {
+        return Ok(None);
     }
-    
-    let funk_name = format!("add_{}", row.name.clone());
-    let struct_name = row.name.clone().to_case(Case::Pascal);
-    let table_name = row.name.clone();
-    let cols_list = row.cols.iter()
-    .filter(|col| !col.auto_gen )
-    .map(|col| { // filter based on if auto generated 
-            col.name.clone()
-    }).collect::<Vec<_>>();
-    
-
-    let cols: String = cols_list.iter().map(|col| format!("{}, ", col).to_string()).collect::<String>()
-        .trim_end_matches(", ").to_string();
-    let bind_fields = cols_list.iter().enumerate().map(|(i, col)| 
-        format!("\t\t.bind(payload.{})", cols_list[i]))
-        .collect::<Vec<_>>().join("\n");
-    let fields = cols_list.iter().enumerate().map(|(i, col)| format!("${}, ", i + 1)).collect::<String>()
-        .trim_end_matches(", ").to_string();
-    
-    // API layer function - calls data layer and can add business logic
-    let api_func = format!(r###"
-pub async fn {funk_name}(
-    extract::State(pool): extract::State<PgPool>,
-    Json(payload): Json<{struct_name}>,
-) -> Json<Value> {{
-    // Call data function from data module 
-    // Other business logic can also be handled here 
-    let result = data_{funk_name}(extract::State(pool), Json(payload)).await;
-    result
-}}
-"###);
-
-    // Data layer function - handles database operations
-    let data_func = format!(r###"
-pub async fn data_{funk_name}(
-    extract::State(pool): extract::State<PgPool>,
-    Json(payload): Json<{struct_name}>,
-) -> Json<Value> {{
-    let query = "INSERT INTO {table_name} ({cols}) VALUES ({fields}) RETURNING *";
-    
-    let q = sqlx::query_as::<_, {struct_name}>(&query)
-{bind_fields};
-    
-    let result = q.fetch_one(&pool).await;
-
-    match result {{
-        Ok(value) => Json(json!({{"res": "success", "data": value}})),
-        Err(e) => Json(json!({{"res": format!("error: {{}}", e)}}))
-    }}
-}}
-"###);
-
-    // Write both functions to the same file
-    let mut file = OpenOptions::new()
-        .write(true)
-        .append(true)
-        .create(true)
-        .open(file_path)?;
-
-    // Write API function first, then data function
-    file.write_all(api_func.as_bytes())?;
-    file.write_all(data_func.as_bytes())?;
-
-    Ok(funk_name.to_string())
+    let type_map = create_type_map();
+    let funk_name = format!("add_{}", row.name);
+    let func = ident(&funk_name);
+    let data_func = ident(&format!("data_{}", funk_name));
+    let struct_name = ident(&row.name.to_case(Case::Pascal));
+    let payload_struct = ident(&format!("New{}", row.name.to_case(Case::Pascal)));
+
+    // columns without a default are always sent, columns with one are only
+    // sent when the caller supplies a value so postgres can fill in the rest
+    let fields = payload_cols(row, authz);
+    let (defaulted, required): (Vec<&schema::Col>, Vec<&schema::Col>) = fields.iter()
+        .partition(|col| col.auto_gen);
+
+    // Insert payload struct - every field is optional so validate() can
+    // report a missing required column along with the other bad fields
+    let payload_fields = fields.iter().map(|col| {
+        let name = ident(&col.name);
+        let field_type = rust_type(&col.rust_type(&type_map))?;
+        Ok(quote! { #[serde(default)] pub #name: Option<#field_type> })
+    }).collect::<Result<Vec<_>, io::Error>>()?;
+    let validate = validate_impl(row, &payload_struct, &fields, PayloadKind::Full, &type_map);
+
+    let columns_mut = (!defaulted.is_empty()).then(|| quote! { mut });
+    let required_cols = required.iter().map(|col| &col.name);
+    let push_defaulted_cols = defaulted.iter().map(|col| {
+        let (field, name) = (ident(&col.name), &col.name);
+        quote! {
+            if payload.#field.is_some() {
+                columns.push(#name);
+            }
+        }
+    });
+    let bind_fields = required.iter().map(|col| {
+        let field = ident(&col.name);
+        quote! { values.push_bind(payload.#field); }
+    }).chain(defaulted.iter().map(|col| {
+        let field = ident(&col.name);
+        quote! {
+            if let Some(value) = payload.#field {
+                values.push_bind(value);
+            }
+        }
+    }));
+
+    // every column has a default, so the caller may send nothing at all
+    let default_values = required.is_empty().then(|| {
+        let insert_defaults = format!("INSERT INTO {} DEFAULT VALUES RETURNING *", row.name);
+        quote! {
+            if columns.is_empty() {
+                query = sqlx::QueryBuilder::new(#insert_defaults);
+                let elemint = query.build_query_as::<#struct_name>().fetch_one(&pool).await?;
+                return Ok(Json(json!({ "payload": elemint })));
+            }
+        }
+    });
+    let insert_into = format!("INSERT INTO {} (", row.name);
+
+    let (user_param, user_arg) = (user_param(authz), user_arg(authz));
+    let authorize = authz.map(|authz| authz.authorize(row, Action::Create));
+    // rows belong to whoever creates them
+    let pin = authz.and_then(|authz| authz.pin_fields(row, &fields, false, |_, value| quote! { Some(#value) }));
+    let payload_mut = pin.as_ref().map(|_| quote! { mut });
+
+    module.models.extend(quote! {
+        #[derive(Debug, Deserialize)]
+        pub struct #payload_struct {
+            #(#payload_fields),*
+        }
+
+        #validate
+    });
+    module.handlers.extend(quote! {
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn #func(
+            extract::State(pool): extract::State<PgPool>,
+            #user_param
+            Json(payload): Json<#payload_struct>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = #data_func(extract::State(pool), Json(payload), #user_arg).await;
+            result
+        }
+    });
+    module.data.extend(quote! {
+        /// Data layer: inserts the row and returns it
+        pub async fn #data_func(
+            extract::State(pool): extract::State<PgPool>,
+            Json(#payload_mut payload): Json<#payload_struct>,
+            #user_param
+        ) -> Result<Json<Value>, AppError> {
+            #authorize
+            #pin
+            payload.validate()?;
+            let #columns_mut columns: Vec<&str> = vec![#(#required_cols),*];
+            #(#push_defaulted_cols)*
+
+            let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(#insert_into);
+            #default_values
+            query.push(columns.join(", "));
+            query.push(") VALUES (");
+            let mut values = query.separated(", ");
+            #(#bind_fields)*
+            values.push_unseparated(") RETURNING *");
+
+            let elemint = query.build_query_as::<#struct_name>().fetch_one(&pool).await?;
+            Ok(Json(json!({ "payload": elemint })))
+        }
+    });
+
+    let endpoint = Endpoint::new(HttpMethod::Post, &funk_name).with_operation(openapi::insert_operation(row, &fields));
+    Ok(Some(with_forbidden(endpoint, authz)))
 }
 
 
 
 
-pub fn add_get_one_func(row: &base_structs::Row, col: &schema::Col, file_path: &std::path::Path) -> Result<String, io::Error> {
+pub fn add_get_one_func(
+    row: &base_structs::Row,
+    col: &schema::Col,
+    authz: Option<&Authz>,
+    module: &mut TableModule,
+) -> Result<Endpoint, io::Error> {
     let type_map = create_type_map();
-    let row_name = row.name.clone();
-    let col_name = col.name.clone();
-    let end_index = col.col_type.find('(').unwrap_or(col.col_type.len());
-    let col_type = match type_map.get(&col.col_type[..end_index]) {
-        Some(t) => t.to_string(),
-        None => {
-            eprintln!("Warning: Type '{}' not found in type map for column '{}'. Defaulting to String.", col.col_type, col_name);
-            "f64".to_string() // Default to String if type not found
+    let col_name = ident(&col.name);
+    let col_type = rust_type(&col.rust_type(&type_map))?;
+    let col_type = match col.is_nullable() {
+        true => quote! { Option<#col_type> },
+        false => quote! { #col_type },
+    };
+    let func_name = format!("get_one_{}{}", row.name, col.name);
+    let func = ident(&func_name);
+    let data_func = ident(&format!("data_{}", func_name));
+    let query_struct = ident(&format!("{}{}Query", row.name, col.name));
+    let struct_name = ident(&row.name.to_case(Case::Pascal));
+    let soft_delete_filter = match row.live_condition(None) {
+        Some(live) => format!(" AND {}", live),
+        None => String::new(),
+    };
+    // other users' rows are not found, unless the caller sees all rows
+    let owner = authz.and_then(|authz| authz.owner_col(row));
+    let owner_filter = match owner {
+        Some(owner) => format!(" AND ($2 IS NULL OR {} = $2)", owner.name),
+        None => String::new(),
+    };
+    let owner_bind = owner.map(|_| quote! { .bind(crate::auth::owner_scope(&user)) });
+    let query = format!("SELECT * FROM {} WHERE {} = $1{}{}", row.name, col.name, soft_delete_filter, owner_filter);
+    let (user_param, user_arg) = (user_param(authz), user_arg(authz));
+    let authorize = authz.map(|authz| authz.authorize(row, Action::Read));
+    let not_found = format!("No record found with {} = the value", col.name);
+
+    module.models.extend(quote! {
+        #[derive(Debug, Deserialize)]
+        pub struct #query_struct {
+            pub #col_name: #col_type,
+        }
+    });
+    module.handlers.extend(quote! {
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn #func(
+            extract::State(pool): extract::State<PgPool>,
+            #user_param
+            match_val: Query<#query_struct>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = #data_func(extract::State(pool), match_val, #user_arg).await;
+            result
+        }
+    });
+    module.data.extend(quote! {
+        /// Data layer: looks up the first matching row
+        pub async fn #data_func(
+            extract::State(pool): extract::State<PgPool>,
+            match_val: Query<#query_struct>,
+            #user_param
+        ) -> Result<Json<Value>, AppError> {
+            #authorize
+            let q = sqlx::query_as::<_, #struct_name>(#query).bind(match_val.#col_name.clone()) #owner_bind;
+
+            let elemint = q.fetch_optional(&pool).await?;
+
+            match elemint {
+                Some(elemint) => Ok(Json(json!({ "payload": elemint }))),
+                None => Err(AppError::NotFound(#not_found.to_string())),
+            }
+        }
+    });
+
+    let endpoint = Endpoint::new(HttpMethod::Get, &func_name).with_operation(openapi::get_one_operation(row, col));
+    Ok(with_forbidden(endpoint, authz))
+}
+
+pub fn add_update_func(
+    row: &base_structs::Row,
+    authz: Option<&Authz>,
+    module: &mut TableModule,
+) -> Result<Vec<Endpoint>, io::Error> {
+    let pk_count = row.cols.iter().filter(|col| col.primary_key).count();
+    let pk = match row.primary_key() {
+        Some(pk) if pk_count == 1 => pk,
+        _ => {
+            eprintln!("Warning: table '{}' does not have a single column primary key, skipping update endpoints.", row.name);
+            return Ok(Vec::new());
         }
     };
-    let func_name = format!("get_one_{}{}", row.name.clone(), col_name.clone());
-    let struct_name = row.name.clone().to_case(Case::Pascal);
-    let cols: String = row.cols.iter().map(|col| format!("\t\"{}\": elemint.{}, \n", 
-        col.name, col.name)
-        .to_string()).collect::<String>()
-        .trim_end_matches(", ").to_string();
-
-    // Query struct definition
-    let query_struct = format!(r###"
-#[derive(Debug, Deserialize)]
-struct {row_name}{col_name}Query {{
-    {col_name}: {col_type},
-}}
-"###);
-
-    // API layer function - calls data layer and can add business logic
-    let api_func = format!(r###"
-pub async fn {func_name}(
-    extract::State(pool): extract::State<PgPool>,
-    match_val: Query<{row_name}{col_name}Query>,
-) -> Result<Json<Value>, (StatusCode, String)> {{
-    // Call data function from data module 
-    // Other business logic can also be handled here 
-    let result = data_{func_name}(extract::State(pool), match_val).await;
-    result
-}}
-"###);
-
-    // Data layer function - handles database operations
-    let data_func = format!(r###"
-pub async fn data_{func_name}(
-    extract::State(pool): extract::State<PgPool>,
-    match_val: Query<{row_name}{col_name}Query>,
-) -> Result<Json<Value>, (StatusCode, String)> {{
-    let query = format!("SELECT * FROM {row_name} WHERE {col_name} = $1");
-    let q = sqlx::query_as::<_, {struct_name}>(&query).bind(match_val.{col_name}.clone());
-
-    let elemint = q.fetch_optional(&pool).await.map_err(|e| {{
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("Database err{{}}", e))
-    }})?;
-
-    match elemint {{
-        Some(elemint) => Ok(Json(json!({{
-            "payload": {{
-                {cols}
-            }}
-        }}))),
-        None => Err((StatusCode::NOT_FOUND, format!("No record found with {col_name} = the value"))),
-    }}
-}}
-
-
-"###);
-    // Write all parts to the same file
-    let mut file = OpenOptions::new()
-        .write(true)
-        .append(true)
-        .create(true)
-        .open(file_path)?;
-
-    // Write query struct, API function, then data function
-    file.write_all(query_struct.as_bytes())?;
-    file.write_all(api_func.as_bytes())?;
-    file.write_all(data_func.as_bytes())?;
-
-    Ok(func_name.to_string())
+    // the creation time stays what insert made it
+    let fields: Vec<&schema::Col> = payload_cols(row, authz).into_iter()
+        .filter(|col| !col.primary_key && !is_creation_stamp(col))
+        .collect();
+    if fields.is_empty() {
+        eprintln!("Warning: table '{}' has no writable columns besides its primary key, skipping update endpoints.", row.name);
+        return Ok(Vec::new());
+    }
+
+    let type_map = create_type_map();
+    let update_name = format!("update_{}", row.name);
+    let patch_name = format!("patch_{}", row.name);
+    let (update_func, data_update_func) = (ident(&update_name), ident(&format!("data_{}", update_name)));
+    let (patch_func, data_patch_func) = (ident(&patch_name), ident(&format!("data_{}", patch_name)));
+    let struct_name = ident(&row.name.to_case(Case::Pascal));
+    let update_struct = ident(&format!("Update{}", row.name.to_case(Case::Pascal)));
+    let patch_struct = ident(&format!("Patch{}", row.name.to_case(Case::Pascal)));
+    let pk_name = ident(&pk.name);
+    let pk_type = rust_type(&pk.rust_type(&type_map))?;
+    let update_table = format!("UPDATE {} SET ", row.name);
+    let where_pk = format!(" WHERE {} = ", pk.name);
+    let not_found = format!("No record found with {} = the value", pk.name);
+    // soft deleted rows are gone, so they can't be changed either
+    let live_only = row.live_condition(None).map(|live| {
+        let and_live = format!(" AND {}", live);
+        quote! { query.push(#and_live); }
+    });
+
+    let (user_param, user_arg) = (user_param(authz), user_arg(authz));
+    let authorize = authz.map(|authz| authz.authorize(row, Action::Update));
+    // other users' rows are not found, and their own can't be handed to someone else
+    let owner_filter = authz.and_then(|authz| authz.owner_col(row)).map(|owner| {
+        let and_owner = format!(" AND {} = ", owner.name);
+        quote! {
+            if let Some(owner) = crate::auth::owner_scope(&user) {
+                query.push(#and_owner);
+                query.push_bind(owner);
+            }
+        }
+    });
+    let update_pin = authz.and_then(|authz| authz.pin_fields(row, &fields, false, |_, value| quote! { Some(#value) }));
+    let patch_pin = authz.and_then(|authz| {
+        authz.pin_fields(row, &fields, true, |col, value| match col.is_nullable() {
+            true => quote! { Some(Some(#value)) },
+            false => quote! { Some(#value) },
+        })
+    });
+    let (update_mut, patch_mut) = (update_pin.as_ref().map(|_| quote! { mut }), patch_pin.as_ref().map(|_| quote! { mut }));
+
+    // PUT replaces the whole row: omitted nullable columns become NULL and
+    // omitted defaulted columns go back to their default. Required columns
+    // are optional here too, validate() reports them missing
+    let update_fields = fields.iter().map(|col| {
+        let name = ident(&col.name);
+        let field_type = rust_type(&col.rust_type(&type_map))?;
+        Ok(quote! { #[serde(default)] pub #name: Option<#field_type> })
+    }).collect::<Result<Vec<_>, io::Error>>()?;
+    let update_sets = fields.iter().map(|col| {
+        let field = ident(&col.name);
+        let set = format!("{} = ", col.name);
+        if col.auto_gen {
+            let set_default = format!("{} = DEFAULT", col.name);
+            quote! {
+                match payload.#field {
+                    Some(value) => {
+                        fields.push(#set);
+                        fields.push_bind_unseparated(value);
+                    }
+                    None => {
+                        fields.push(#set_default);
+                    }
+                }
+            }
+        } else {
+            quote! {
+                fields.push(#set);
+                fields.push_bind_unseparated(payload.#field);
+            }
+        }
+    });
+
+    // PATCH only touches the columns that were sent. Nullable columns use a
+    // double Option so an explicit null can be told apart from a missing key
+    let patch_fields = fields.iter().map(|col| {
+        let name = ident(&col.name);
+        let field_type = rust_type(&col.rust_type(&type_map))?;
+        if col.is_nullable() {
+            Ok(quote! {
+                #[serde(default, deserialize_with = "crate::models::double_option")]
+                pub #name: Option<Option<#field_type>>
+            })
+        } else {
+            Ok(quote! { #[serde(default)] pub #name: Option<#field_type> })
+        }
+    }).collect::<Result<Vec<_>, io::Error>>()?;
+    let patch_sets = fields.iter().map(|col| {
+        let field = ident(&col.name);
+        let set = format!("{} = ", col.name);
+        quote! {
+            if let Some(value) = payload.#field {
+                fields.push(#set);
+                fields.push_bind_unseparated(value);
+                supplied += 1;
+            }
+        }
+    });
+
+    let validate_update = validate_impl(row, &update_struct, &fields, PayloadKind::Full, &type_map);
+    let validate_patch = validate_impl(row, &patch_struct, &fields, PayloadKind::Partial, &type_map);
+
+    module.models.extend(quote! {
+        #[derive(Debug, Deserialize)]
+        pub struct #update_struct {
+            #(#update_fields),*
+        }
+
+        #validate_update
+
+        #[derive(Debug, Deserialize)]
+        pub struct #patch_struct {
+            #(#patch_fields),*
+        }
+
+        #validate_patch
+    });
+    module.handlers.extend(quote! {
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn #update_func(
+            extract::State(pool): extract::State<PgPool>,
+            #user_param
+            Path(#pk_name): Path<#pk_type>,
+            Json(payload): Json<#update_struct>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = #data_update_func(extract::State(pool), Path(#pk_name), Json(payload), #user_arg).await;
+            result
+        }
+
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn #patch_func(
+            extract::State(pool): extract::State<PgPool>,
+            #user_param
+            Path(#pk_name): Path<#pk_type>,
+            Json(payload): Json<#patch_struct>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = #data_patch_func(extract::State(pool), Path(#pk_name), Json(payload), #user_arg).await;
+            result
+        }
+    });
+    module.data.extend(quote! {
+        /// Data layer: replaces every column of the row
+        pub async fn #data_update_func(
+            extract::State(pool): extract::State<PgPool>,
+            Path(#pk_name): Path<#pk_type>,
+            Json(#update_mut payload): Json<#update_struct>,
+            #user_param
+        ) -> Result<Json<Value>, AppError> {
+            #authorize
+            #update_pin
+            payload.validate()?;
+            let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(#update_table);
+            let mut fields = query.separated(", ");
+            #(#update_sets)*
+
+            query.push(#where_pk);
+            query.push_bind(#pk_name);
+            #live_only
+            #owner_filter
+            query.push(" RETURNING *");
+
+            let elemint = query.build_query_as::<#struct_name>().fetch_optional(&pool).await?;
+
+            match elemint {
+                Some(elemint) => Ok(Json(json!({ "payload": elemint }))),
+                None => Err(AppError::NotFound(#not_found.to_string())),
+            }
+        }
+
+        /// Data layer: updates only the columns in the payload
+        pub async fn #data_patch_func(
+            extract::State(pool): extract::State<PgPool>,
+            Path(#pk_name): Path<#pk_type>,
+            Json(#patch_mut payload): Json<#patch_struct>,
+            #user_param
+        ) -> Result<Json<Value>, AppError> {
+            #authorize
+            #patch_pin
+            payload.validate()?;
+            let mut query = sqlx::QueryBuilder::<sqlx::Postgres>::new(#update_table);
+            let mut fields = query.separated(", ");
+            let mut supplied = 0;
+            #(#patch_sets)*
+
+            if supplied == 0 {
+                return Err(AppError::BadRequest("No fields to update".to_string()));
+            }
+
+            query.push(#where_pk);
+            query.push_bind(#pk_name);
+            #live_only
+            #owner_filter
+            query.push(" RETURNING *");
+
+            let elemint = query.build_query_as::<#struct_name>().fetch_optional(&pool).await?;
+
+            match elemint {
+                Some(elemint) => Ok(Json(json!({ "payload": elemint }))),
+                None => Err(AppError::NotFound(#not_found.to_string())),
+            }
+        }
+    });
+
+    Ok(vec![
+        with_forbidden(
+            Endpoint::with_path_param(HttpMethod::Put, &update_name, &pk.name)
+                .with_operation(openapi::update_operation(row, pk, &fields)),
+            authz,
+        ),
+        with_forbidden(
+            Endpoint::with_path_param(HttpMethod::Patch, &patch_name, &pk.name)
+                .with_operation(openapi::patch_operation(row, pk, &fields)),
+            authz,
+        ),
+    ])
+}
+
+pub fn add_delete_func(
+    row: &base_structs::Row,
+    authz: Option<&Authz>,
+    module: &mut TableModule,
+) -> Result<Option<Endpoint>, io::Error> {
+    let pk_count = row.cols.iter().filter(|col| col.primary_key).count();
+    let pk = match row.primary_key() {
+        Some(pk) if pk_count == 1 => pk,
+        _ => {
+            eprintln!("Warning: table '{}' does not have a single column primary key, skipping delete endpoint.", row.name);
+            return Ok(None);
+        }
+    };
+
+    let type_map = create_type_map();
+    let table_name = &row.name;
+    let func_name = format!("delete_{}", row.name);
+    let func = ident(&func_name);
+    let data_func = ident(&format!("data_{}", func_name));
+    let struct_name = ident(&row.name.to_case(Case::Pascal));
+    let pk_name = ident(&pk.name);
+    let pk_type = rust_type(&pk.rust_type(&type_map))?;
+    let not_found = format!("No record found with {} = the value", pk.name);
+
+    // other users' rows are not found, unless the caller sees all rows
+    let owner = authz.and_then(|authz| authz.owner_col(row));
+    let owner_filter = match owner {
+        Some(owner) => format!(" AND ($2 IS NULL OR {} = $2)", owner.name),
+        None => String::new(),
+    };
+    let owner_bind = owner.map(|_| quote! { .bind(crate::auth::owner_scope(&user)) });
+    let (user_param, user_arg) = (user_param(authz), user_arg(authz));
+    let authorize = authz.map(|authz| authz.authorize(row, Action::Delete));
+
+    // tables with a soft delete column keep the row and stamp it instead
+    let soft_delete = row.soft_delete_col().zip(row.live_condition(None));
+    let query = match soft_delete {
+        Some((col, live)) => {
+            let deleted = deleted_value(col).ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}.{} is {}, a soft delete column must be a timestamp, date or boolean", table_name, col.name, col.col_type),
+            ))?;
+            format!(
+                "UPDATE {table_name} SET {} = {} WHERE {} = $1 AND {live}{owner_filter} RETURNING *",
+                col.name, deleted, pk.name
+            )
+        }
+        None => format!("DELETE FROM {table_name} WHERE {} = $1{owner_filter} RETURNING *", pk.name),
+    };
+
+    module.handlers.extend(quote! {
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn #func(
+            extract::State(pool): extract::State<PgPool>,
+            #user_param
+            Path(#pk_name): Path<#pk_type>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = #data_func(extract::State(pool), Path(#pk_name), #user_arg).await;
+            result
+        }
+    });
+    module.data.extend(quote! {
+        /// Data layer: deletes the row and returns it
+        pub async fn #data_func(
+            extract::State(pool): extract::State<PgPool>,
+            Path(#pk_name): Path<#pk_type>,
+            #user_param
+        ) -> Result<Json<Value>, AppError> {
+            #authorize
+            let q = sqlx::query_as::<_, #struct_name>(#query).bind(#pk_name) #owner_bind;
+
+            let elemint = q.fetch_optional(&pool).await?;
+
+            match elemint {
+                Some(elemint) => Ok(Json(json!({ "payload": elemint }))),
+                None => Err(AppError::NotFound(#not_found.to_string())),
+            }
+        }
+    });
+
+    Ok(Some(with_forbidden(
+        Endpoint::with_path_param(HttpMethod::Delete, &func_name, &pk.name)
+            .with_operation(openapi::delete_operation(row, pk)),
+        authz,
+    )))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::render;
+    use crate::schema::parse_rows;
+
+    fn list_data(sql: &str) -> String {
+        let rows = parse_rows(sql).unwrap();
+        let mut module = TableModule::default();
+        add_get_all_func(&rows[0], &rows, None, &mut module).unwrap();
+        render(module.data).unwrap()
+    }
+
+    #[test]
+    fn single_key_tables_page_by_keyset() {
+        let data = list_data("CREATE TABLE runs (id BIGSERIAL PRIMARY KEY, started TIMESTAMPTZ);");
+        assert!(data.contains("ORDER BY runs.{} {}, runs.id {} LIMIT {} OFFSET {}"));
+        assert!(data.contains("push_keyset_condition("));
+        assert!(data.contains("\"runs.id\",\n"));
+        assert!(data.contains("\"BIGINT\",\n"));
+        assert!(data.contains("key: json_text(&last[\"id\"]).unwrap_or_default()"));
+        assert!(data.contains("\"started\" => Some(\"TIMESTAMPTZ\")"));
+    }
+
+    #[test]
+    fn cursors_are_cast_to_the_declared_length() {
+        // CAST(.. AS CHAR) would keep only the first character of the cursor
+        let data = list_data("CREATE TABLE codes (code CHAR(3) PRIMARY KEY, flags BIT(8), price NUMERIC(10, 2));");
+        assert!(data.contains("\"codes.code\",\n"));
+        assert!(data.contains("\"CHAR(3)\",\n"));
+        assert!(data.contains("\"code\" => Some(\"CHAR(3)\")"));
+        assert!(data.contains("\"flags\" => Some(\"BIT(8)\")"));
+        assert!(data.contains("\"price\" => Some(\"NUMERIC(10, 2)\")"));
+    }
+
+    #[test]
+    fn composite_key_tables_only_page_by_offset() {
+        let data = list_data("CREATE TABLE members (team INT, person INT, PRIMARY KEY (team, person));");
+        assert!(data.contains("ORDER BY members.{} {} LIMIT {} OFFSET {}"));
+        assert!(!data.contains("push_keyset_condition"));
+        assert!(data.contains("This table does not support cursor pagination, use offset"));
+        assert!(data.contains("let next_cursor: Option<String> = None;"));
+    }
+
+    #[test]
+    fn soft_deleted_rows_are_not_listed() {
+        let data = list_data("CREATE TABLE notes (id INT PRIMARY KEY, deleted_at TIMESTAMPTZ);");
+        assert!(data.contains("query.push(\" WHERE notes.deleted_at IS NULL\");"));
+    }
+
+    fn delete_data(sql: &str) -> String {
+        let rows = parse_rows(sql).unwrap();
+        let mut module = TableModule::default();
+        add_delete_func(&rows[0], None, &mut module).unwrap();
+        render(module.data).unwrap()
+    }
+
+    #[test]
+    fn soft_delete_sets_what_the_column_holds() {
+        let stamped = delete_data("CREATE TABLE notes (id INT PRIMARY KEY, deleted_at TIMESTAMPTZ);");
+        assert!(stamped.contains("\"UPDATE notes SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL RETURNING *\""));
+
+        let mut rows = parse_rows("CREATE TABLE notes (id INT PRIMARY KEY, is_deleted BOOLEAN NOT NULL DEFAULT false);").unwrap();
+        rows[0].soft_delete_column = "is_deleted".to_string();
+        let mut module = TableModule::default();
+        add_delete_func(&rows[0], None, &mut module).unwrap();
+        add_get_all_func(&rows[0], &rows, None, &mut module).unwrap();
+        let flagged = render(module.data).unwrap();
+        assert!(flagged.contains("\"UPDATE notes SET is_deleted = true WHERE id = $1 AND is_deleted IS NOT TRUE RETURNING *\""));
+        assert!(flagged.contains("query.push(\" WHERE notes.is_deleted IS NOT TRUE\");"));
+
+        assert!(delete_data("CREATE TABLE notes (id INT PRIMARY KEY);").contains("\"DELETE FROM notes WHERE id = $1 RETURNING *\""));
+    }
+
+    #[test]
+    fn a_soft_delete_column_that_cant_be_set_is_refused() {
+        let mut rows = parse_rows("CREATE TABLE notes (id INT PRIMARY KEY, removed TEXT);").unwrap();
+        rows[0].soft_delete_column = "removed".to_string();
+        let error = add_delete_func(&rows[0], None, &mut TableModule::default()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "notes.removed is TEXT, a soft delete column must be a timestamp, date or boolean");
+    }
+
+    #[test]
+    fn updates_leave_the_creation_time_alone() {
+        let rows = parse_rows(
+            "CREATE TABLE notes (id INT PRIMARY KEY, body TEXT, created_at TIMESTAMPTZ NOT NULL DEFAULT now(), due DATE DEFAULT now());",
+        ).unwrap();
+        let mut module = TableModule::default();
+        add_update_func(&rows[0], None, &mut module).unwrap();
+        let (models, data) = (render(module.models).unwrap(), render(module.data).unwrap());
+        assert!(!models.contains("created_at"));
+        assert!(!data.contains("created_at"));
+        assert!(data.contains("fields.push(\"due = DEFAULT\");"));
+    }
+
+    fn one_line(code: proc_macro2::TokenStream) -> String {
+        render(code).unwrap().split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn patch_only_sets_the_fields_sent() {
+        let rows = parse_rows(
+            "CREATE TABLE notes (id INT PRIMARY KEY, title TEXT NOT NULL, body TEXT, pinned BOOL NOT NULL DEFAULT false);",
+        ).unwrap();
+        let mut module = TableModule::default();
+        let endpoints = add_update_func(&rows[0], None, &mut module).unwrap();
+        let routes: Vec<(&str, &str)> = endpoints.iter().map(|e| (e.method.as_str(), e.path.as_str())).collect();
+        assert_eq!(routes, [("PUT", "/update_notes/:id"), ("PATCH", "/patch_notes/:id")]);
+
+        let models = one_line(module.models);
+        // a missing key is None, an explicit null on a nullable column Some(None)
+        assert!(models.contains("pub struct PatchNotes { #[serde(default)] pub title: Option<String>, \
+            #[serde(default, deserialize_with = \"crate::models::double_option\")] pub body: Option<Option<String>>, \
+            #[serde(default)] pub pinned: Option<bool>, }"));
+
+        let data = one_line(module.data);
+        let patch = &data[data.find("pub async fn data_patch_notes").unwrap()..];
+        for col in ["title", "body", "pinned"] {
+            assert!(patch.contains(&format!(
+                "if let Some(value) = payload.{col} {{ fields.push(\"{col} = \"); fields.push_bind_unseparated(value); supplied += 1; }}"
+            )), "{} is set when it was left out", col);
+        }
+        assert!(patch.contains("if supplied == 0 { return Err(AppError::BadRequest(\"No fields to update\".to_string())); }"));
+
+        // PUT replaces the row: left out columns are cleared or defaulted
+        let put = &data[..data.find("pub async fn data_patch_notes").unwrap()];
+        assert!(put.contains("fields.push(\"body = \"); fields.push_bind_unseparated(payload.body);"));
+        assert!(put.contains("None => { fields.push(\"pinned = DEFAULT\"); }"));
+        assert!(put.contains("query.push(\" RETURNING *\");"));
+    }
+}
@@ -0,0 +1,395 @@
+use std::io;
+
+use convert_case::{Case, Casing};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::add_auth::{is_hidden, user_arg, user_param, with_forbidden, Action, Authz};
+use crate::base_structs::{create_type_map, Endpoint, HasMany, HttpMethod, Row};
+use crate::add_functions::query_cols;
+use crate::codegen::{ident, rust_type, TableModule};
+use crate::schema::Col;
+use crate::openapi;
+
+/// A foreign key that a list endpoint can embed with `?expand={name}`.
+pub struct Expansion<'a> {
+    /// Name used in `?expand=` and as the key of the embedded object:
+    /// `user` for a `user_id` column.
+    pub name: String,
+    /// The foreign key column on the listed table.
+    pub column: String,
+    /// The referenced table.
+    pub target: &'a Row,
+    /// The column on `target` that the foreign key points at.
+    pub ref_column: String,
+    /// The columns of `target` that are embedded. Hidden columns are left
+    /// out, the same as when the row is serialized.
+    pub cols: Vec<&'a Col>,
+}
+
+impl Expansion<'_> {
+    /// The FromRow struct for this join shape, e.g. `RunsWithUser`.
+    pub fn struct_name(&self, row: &Row) -> String {
+        format!("{}With{}", row.name.to_case(Case::Pascal), self.name.to_case(Case::Pascal))
+    }
+
+    fn joined_cols(&self) -> impl Iterator<Item = &Col> + Clone {
+        self.cols.iter().copied()
+    }
+
+    /// The joined table is aliased so a table can reference itself.
+    fn alias(&self) -> String {
+        format!("{}_join", self.name)
+    }
+
+    /// The SELECT used when listing `row` with this expansion. Joined columns
+    /// are prefixed with `{name}__` so they can't clash with the row's own.
+    pub fn select(&self, row: &Row) -> String {
+        let alias = self.alias();
+        let joined_cols = self.joined_cols()
+            .map(|col| format!(", {}.{} AS {}__{}", alias, col.name, self.name, col.name))
+            .collect::<String>();
+        // a soft deleted row is gone, so it is not embedded either
+        let soft_delete = match self.target.live_condition(Some(&alias)) {
+            Some(live) => format!(" AND {}", live),
+            None => String::new(),
+        };
+        format!(
+            "SELECT {table}.*{joined_cols} FROM {table} LEFT JOIN {target} AS {alias} ON {table}.{column} = {alias}.{ref_column}{soft_delete}",
+            table = row.name,
+            target = self.target.name,
+            column = self.column,
+            ref_column = self.ref_column,
+        )
+    }
+
+    /// Turns the expansion away with 403 unless the caller's role may read
+    /// the joined table.
+    pub fn authorize(&self, authz: Option<&Authz>) -> Option<TokenStream> {
+        authz.map(|authz| authz.authorize(self.target, Action::Read))
+    }
+
+    /// Narrows the join to the joined rows the caller owns, so other users'
+    /// rows come back as null. Pushed right after `select`, which ends in
+    /// the join condition.
+    pub fn owner_condition(&self, authz: Option<&Authz>) -> Option<TokenStream> {
+        let owner = authz?.owner_col(self.target)?;
+        let name = &self.name;
+        let and_owner = format!(" AND {}.{} = ", self.alias(), owner.name);
+        Some(quote! {
+            if query_params.expand.as_deref() == Some(#name)
+                && let Some(owner) = crate::auth::owner_scope(&user)
+            {
+                query.push(#and_owner);
+                query.push_bind(owner);
+            }
+        })
+    }
+}
+
+/// The single column foreign keys of `row` whose tables are in `rows`.
+pub fn expansions<'a>(row: &Row, rows: &'a [Row], authz: Option<&Authz>) -> Vec<Expansion<'a>> {
+    row.relations.iter()
+        .filter(|rel| rel.columns.len() == 1 && rel.ref_columns.len() == 1)
+        .filter_map(|rel| {
+            let target = rows.iter().find(|r| r.name == rel.ref_table)?;
+            let column = rel.columns[0].clone();
+            // `user_id` expands as `user`, unless that is already a column
+            let name = match column.strip_suffix("_id") {
+                Some(name) if !name.is_empty() && row.cols.iter().all(|c| c.name != name) => name.to_string(),
+                _ => format!("{}_ref", column),
+            };
+            let cols = target.cols.iter().filter(|col| !is_hidden(authz, target, col)).collect();
+            Some(Expansion { name, column, target, ref_column: rel.ref_columns[0].clone(), cols })
+        })
+        .collect()
+}
+
+/// The FromRow struct for listing `row` joined with `expansion`, and a
+/// method turning the joined columns back into one json object.
+pub fn join_struct(row: &Row, expansion: &Expansion) -> Result<TokenStream, io::Error> {
+    let type_map = create_type_map();
+    let struct_name = ident(&expansion.struct_name(row));
+    let row_struct = ident(&row.name.to_case(Case::Pascal));
+    let name = &expansion.name;
+    let json_fn = ident(&format!("{}_json", name));
+    let ref_field = ident(&format!("{}_{}", name, expansion.ref_column));
+
+    // every joined column is optional because the join is a LEFT JOIN
+    let fields = expansion.joined_cols().map(|col| {
+        let alias = format!("{}__{}", name, col.name);
+        let field = ident(&format!("{}_{}", name, col.name));
+        let rust_type = rust_type(&col.rust_type(&type_map))?;
+        Ok(quote! {
+            #[sqlx(rename = #alias)]
+            #field: Option<#rust_type>
+        })
+    }).collect::<Result<Vec<_>, io::Error>>()?;
+    let json_cols = expansion.joined_cols();
+    let json_keys = json_cols.clone().map(|col| &col.name);
+    let json_fields = json_cols.map(|col| ident(&format!("{}_{}", name, col.name)));
+
+    let struct_doc = format!(
+        " A {} row with the {} row its {} points at, for `?expand={}`.",
+        row.name, expansion.target.name, expansion.column, name
+    );
+    let json_doc = format!(" The joined {} row, or null when there was none to join.", expansion.target.name);
+
+    Ok(quote! {
+        #[doc = #struct_doc]
+        #[derive(Debug, FromRow)]
+        pub struct #struct_name {
+            #[sqlx(flatten)]
+            pub base: #row_struct,
+            #(#fields),*
+        }
+
+        impl #struct_name {
+            #[doc = #json_doc]
+            pub fn #json_fn(&self) -> Value {
+                if self.#ref_field.is_none() {
+                    return Value::Null;
+                }
+                json!({
+                    #(#json_keys: self.#json_fields),*
+                })
+            }
+        }
+    })
+}
+
+/// Writes `GET /{parent}/:id/{child}`, listing the child rows whose foreign
+/// key points at one parent row. It takes the same query parameters as the
+/// child's own list endpoint. `suffix` tells apart several foreign keys from
+/// one child table to the same parent.
+pub fn add_nested_list_func(
+    parent: &Row,
+    child: &HasMany,
+    rows: &[Row],
+    suffix: Option<&str>,
+    authz: Option<&Authz>,
+    module: &mut TableModule,
+) -> Result<Option<Endpoint>, io::Error> {
+    let (fk_name, ref_name) = match (child.columns.as_slice(), child.ref_columns.as_slice()) {
+        ([fk], [ref_col]) => (fk, ref_col),
+        _ => {
+            eprintln!(
+                "Warning: skipping /{}/:id/{} because the foreign key has more than one column",
+                parent.name, child.table
+            );
+            return Ok(None);
+        }
+    };
+    let Some(ref_col) = parent.cols.iter().find(|col| &col.name == ref_name) else {
+        return Ok(None);
+    };
+    let Some(child_row) = rows.iter().find(|row| row.name == child.table) else {
+        return Ok(None);
+    };
+
+    let type_map = create_type_map();
+    let ref_type = rust_type(&ref_col.rust_type(&type_map))?;
+    let ref_ident = ident(ref_name);
+    let parent_name = &parent.name;
+    let child_name = &child.table;
+    let child_path = match suffix {
+        Some(suffix) => format!("{}_by_{}", child_name, suffix),
+        None => child_name.clone(),
+    };
+    let func_name = format!("get_{}_{}", parent_name, child_path);
+    let func = ident(&func_name);
+    let data_func = ident(&format!("data_{}", func_name));
+    let child_list = ident(&format!("data_get_{}", child_name));
+    let params_struct = ident(&format!("{}QueryParams", child_name));
+    let soft_delete = match parent.live_condition(None) {
+        Some(live) => format!(" AND {}", live),
+        None => String::new(),
+    };
+    // a parent row owned by someone else doesn't exist for the caller
+    let owner = authz.and_then(|authz| authz.owner_col(parent));
+    let owner_filter = match owner {
+        Some(owner) => format!(" AND ($2 IS NULL OR {} = $2)", owner.name),
+        None => String::new(),
+    };
+    let owner_bind = owner.map(|_| quote! { .bind(crate::auth::owner_scope(&user)) });
+    let exists = format!("SELECT EXISTS(SELECT 1 FROM {} WHERE {} = $1{}{})", parent_name, ref_name, soft_delete, owner_filter);
+    let not_found = format!("No {} record found with {} = {{}}", parent_name, ref_name);
+    // the role check comes before the lookup, so callers who may not read
+    // these tables can't learn which parent rows exist
+    let authorize = authz.map(|authz| {
+        let (parent, child) = (authz.authorize(parent, Action::Read), authz.authorize(child_row, Action::Read));
+        quote! { #parent #child }
+    });
+    // the child's list checks which rows are the caller's
+    let (user_param, user_arg) = (user_param(authz), user_arg(authz));
+
+    module.handlers.extend(quote! {
+        /// API layer: calls the data layer, other business logic can be added here
+        pub async fn #func(
+            extract::State(pool): extract::State<PgPool>,
+            #user_param
+            Path(#ref_ident): Path<#ref_type>,
+            Query(query_params): Query<#params_struct>,
+        ) -> Result<Json<Value>, AppError> {
+            let result = #data_func(extract::State(pool), Path(#ref_ident), axum::extract::Query(query_params), #user_arg).await;
+            result
+        }
+    });
+    module.data.extend(quote! {
+        /// Data layer: checks the caller may see the parent, then lists its children
+        pub async fn #data_func(
+            extract::State(pool): extract::State<PgPool>,
+            Path(#ref_ident): Path<#ref_type>,
+            axum::extract::Query(mut query_params): axum::extract::Query<#params_struct>,
+            #user_param
+        ) -> Result<Json<Value>, AppError> {
+            #authorize
+            let exists = sqlx::query_scalar::<_, bool>(#exists)
+                .bind(&#ref_ident)
+                #owner_bind
+                .fetch_one(&pool)
+                .await?;
+            if !exists {
+                return Err(AppError::NotFound(format!(#not_found, #ref_ident)));
+            }
+
+            // the path picks the parent, whatever the query string says
+            query_params.filters.insert(#fk_name.to_string(), #ref_ident.to_string());
+            #child_list(extract::State(pool), axum::extract::Query(query_params), #user_arg).await
+        }
+    });
+
+    let expansion_names: Vec<String> = expansions(child_row, rows, authz).iter().map(|expansion| expansion.name.clone()).collect();
+    Ok(Some(with_forbidden(Endpoint {
+        method: HttpMethod::Get,
+        path: format!("/{}/:id/{}", parent_name, child_path),
+        handler: func_name,
+        protected: false,
+        operation: openapi::nested_list_operation(parent, ref_col, child_row, &query_cols(child_row, authz), &expansion_names),
+    }, authz)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::add_auth::AuthConfig;
+    use crate::add_functions::add_get_all_func;
+    use crate::codegen::render;
+    use crate::schema::parse_rows;
+
+    const SCHEMA: &str = "CREATE TABLE users (id SERIAL PRIMARY KEY, email TEXT NOT NULL, password_hash TEXT NOT NULL);
+        CREATE TABLE posts (id SERIAL PRIMARY KEY, user_id INT NOT NULL REFERENCES users(id), title TEXT);
+        CREATE TABLE comments (id SERIAL PRIMARY KEY, post_id INT REFERENCES posts(id), user_id INT REFERENCES users(id));";
+
+    #[test]
+    fn expanding_checks_the_joined_table() {
+        let rows = parse_rows(SCHEMA).unwrap();
+        let config = AuthConfig::default();
+        let authz = Authz::new(&config, &rows);
+        let mut module = TableModule::default();
+        add_get_all_func(&rows[2], &rows, authz.as_ref(), &mut module).unwrap();
+        let data = render(module.data).unwrap();
+
+        let post_arm = data.find("Some(\"post\") => {").unwrap();
+        let authorize = data[post_arm..].find("user.authorize(\"posts\", \"read\")?;").unwrap();
+        assert!(authorize < data[post_arm..].find("\"SELECT comments.*").unwrap());
+        assert!(data.contains("user.authorize(\"users\", \"read\")?;"));
+        // the joined rows are narrowed to the caller's own
+        assert!(data.contains("query.push(\" AND post_join.user_id = \");"));
+        assert!(data.contains("query.push(\" AND user_join.id = \");"));
+    }
+
+    #[test]
+    fn nested_lists_check_roles_before_looking_up_the_parent() {
+        let rows = parse_rows(SCHEMA).unwrap();
+        let config = AuthConfig::default();
+        let authz = Authz::new(&config, &rows);
+        let child = rows[1].has_many(&rows).remove(0);
+        let mut module = TableModule::default();
+        add_nested_list_func(&rows[1], &child, &rows, None, authz.as_ref(), &mut module).unwrap();
+        let data = render(module.data).unwrap();
+
+        let exists = data.find("SELECT EXISTS(SELECT 1 FROM posts WHERE id = $1 AND ($2 IS NULL OR user_id = $2))").unwrap();
+        assert!(data.find("user.authorize(\"posts\", \"read\")?;").unwrap() < exists);
+        assert!(data.find("user.authorize(\"comments\", \"read\")?;").unwrap() < exists);
+        assert!(data.contains(".bind(crate::auth::owner_scope(&user))"));
+    }
+
+    #[test]
+    fn foreign_keys_expand_under_the_key_without_id() {
+        let rows = parse_rows(
+            "CREATE TABLE users (id SERIAL PRIMARY KEY, name TEXT, deleted_at TIMESTAMPTZ);
+            CREATE TABLE runs (
+                id SERIAL PRIMARY KEY,
+                user_id INT REFERENCES users(id),
+                coach_id INT REFERENCES users(id),
+                coach TEXT,
+                parent_id INT REFERENCES runs(id)
+            );",
+        ).unwrap();
+        let expansions = expansions(&rows[1], &rows, None);
+        let names: Vec<&str> = expansions.iter().map(|expansion| expansion.name.as_str()).collect();
+        // `coach` is already a column
+        assert_eq!(names, ["user", "coach_id_ref", "parent"]);
+        assert_eq!(expansions[0].struct_name(&rows[1]), "RunsWithUser");
+
+        assert_eq!(
+            expansions[0].select(&rows[1]),
+            "SELECT runs.*, user_join.id AS user__id, user_join.name AS user__name, user_join.deleted_at AS user__deleted_at \
+            FROM runs LEFT JOIN users AS user_join ON runs.user_id = user_join.id AND user_join.deleted_at IS NULL"
+        );
+        // a table can join itself
+        assert!(expansions[2].select(&rows[1]).contains("LEFT JOIN runs AS parent_join ON runs.parent_id = parent_join.id"));
+    }
+
+    #[test]
+    fn joined_columns_are_optional_and_embedded_as_one_object() {
+        let rows = parse_rows(SCHEMA).unwrap();
+        let expansion = expansions(&rows[1], &rows, None).remove(0);
+        let code = render(join_struct(&rows[1], &expansion).unwrap()).unwrap();
+        assert!(code.contains("pub struct PostsWithUser {\n    #[sqlx(flatten)]\n    pub base: Posts,"));
+        assert!(code.contains("#[sqlx(rename = \"user__email\")]\n    user_email: Option<String>,"));
+        assert!(code.contains("if self.user_id.is_none() {\n            return Value::Null;"));
+        // the json! body is printed as the tokens were written
+        let json: String = code[code.find("json!(").unwrap()..].split_whitespace().collect();
+        assert!(json.starts_with("json!({\"id\":self.user_id,\"email\":self.user_email,"));
+        // without auth the password hash is a column like any other
+        assert!(json.contains("\"password_hash\":self.user_password_hash}"));
+    }
+
+    #[test]
+    fn nested_lists_filter_the_child_list_by_the_parent() {
+        let rows = parse_rows(SCHEMA).unwrap();
+        let children = rows[0].has_many(&rows);
+        assert_eq!(children.len(), 2);
+        let mut module = TableModule::default();
+        let endpoint = add_nested_list_func(&rows[0], &children[1], &rows, Some("user_id"), None, &mut module)
+            .unwrap()
+            .unwrap();
+        assert_eq!(endpoint.path, "/users/:id/comments_by_user_id");
+        assert_eq!(endpoint.handler, "get_users_comments_by_user_id");
+
+        let data = render(module.data).unwrap();
+        assert!(data.contains("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)"));
+        assert!(data.contains("query_params.filters.insert(\"user_id\".to_string(), id.to_string());"));
+        assert!(data.contains("data_get_comments(extract::State(pool), axum::extract::Query(query_params))"));
+        assert!(!data.contains("authorize"));
+    }
+
+    #[test]
+    fn lists_join_the_expanded_row_by_name() {
+        let rows = parse_rows(SCHEMA).unwrap();
+        let mut module = TableModule::default();
+        add_get_all_func(&rows[2], &rows, None, &mut module).unwrap();
+        let models = render(module.models).unwrap();
+        assert!(models.contains("pub struct CommentsWithPost {"));
+        assert!(models.contains("pub struct CommentsWithUser {"));
+        // the arms are laid out by length, so compare them on one line
+        let data = render(module.data).unwrap().split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(data.contains("Some(\"post\") => { \"SELECT comments.*, post_join.id AS post__id, post_join.user_id AS post__user_id, post_join.title AS post__title FROM comments LEFT JOIN posts AS post_join ON comments.post_id = post_join.id\" }"));
+        assert!(data.contains("Some(other) => { return Err(AppError::BadRequest(format!(\"Unknown expand: {}\", other))); }"));
+        assert!(data.contains("Some(\"user\") => { query .build_query_as::<CommentsWithUser>()"));
+        assert!(data.contains("let expanded = row.user_json(); (row.base, Some((\"user\", expanded)))"));
+        assert!(data.contains("res[name] = value;"));
+    }
+}
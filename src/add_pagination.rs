@@ -0,0 +1,112 @@
+use std::io;
+
+use quote::quote;
+
+use crate::codegen::write_module;
+
+/// Page size used when the generated service has no MAX_PAGE_SIZE env var,
+/// written into its config.rs.
+pub const DEFAULT_MAX_PAGE_SIZE: i64 = 100;
+
+/// Writes the helpers shared by every generated list endpoint: query
+/// parameter parsing, cursor encoding and the keyset WHERE condition.
+pub fn add_pagination_helpers(file_path: &std::path::Path) -> Result<(), io::Error> {
+    let helpers = quote! {
+        /// Parses an optional query parameter, answering 400 if it is malformed.
+        pub fn parse_param<T: std::str::FromStr>(name: &str, value: &Option<String>) -> Result<Option<T>, AppError> {
+            match value {
+                Some(v) => v.parse::<T>()
+                    .map(Some)
+                    .map_err(|_| AppError::BadRequest(format!("Invalid {} parameter: {}", name, v))),
+                None => Ok(None),
+            }
+        }
+
+        /// Where the previous page stopped: the sort column, its value on the last
+        /// row (None when it was NULL) and the primary key of the last row.
+        #[derive(Serialize, Deserialize)]
+        pub struct PageCursor {
+            pub column: String,
+            pub value: Option<String>,
+            pub key: String,
+        }
+
+        /// Cursors are hex encoded json so they are opaque and url safe.
+        pub fn encode_cursor(cursor: &PageCursor) -> String {
+            serde_json::to_vec(cursor)
+                .unwrap_or_default()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect()
+        }
+
+        pub fn decode_cursor(cursor: &str) -> Option<PageCursor> {
+            if cursor.len() % 2 != 0 || !cursor.is_ascii() {
+                return None;
+            }
+            let bytes = (0..cursor.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).ok())
+                .collect::<Option<Vec<u8>>>()?;
+            serde_json::from_slice(&bytes).ok()
+        }
+
+        /// The text form of a column value, as postgres would accept it in a cast.
+        pub fn json_text(value: &Value) -> Option<String> {
+            match value {
+                Value::Null => None,
+                Value::String(s) => Some(s.clone()),
+                // array columns, in postgres' literal form: {"a","b"}
+                Value::Array(values) => {
+                    let elements: Vec<String> = values.iter().map(|value| match (value, json_text(value)) {
+                        (_, None) => "NULL".to_string(),
+                        (Value::Array(_), Some(text)) => text,
+                        (_, Some(text)) => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+                    }).collect();
+                    Some(format!("{{{}}}", elements.join(",")))
+                }
+                other => Some(other.to_string()),
+            }
+        }
+
+        /// Appends the condition that continues a keyset page after `cursor`. Rows
+        /// are ordered by (column, key), and postgres puts NULLs last when ascending
+        /// and first when descending, so a NULL sort value needs its own comparison.
+        /// Cursor values are bound as text and cast on the postgres side.
+        pub fn push_keyset_condition(
+            query: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+            column: &str,
+            column_type: &str,
+            key: &str,
+            key_type: &str,
+            descending: bool,
+            cursor: &PageCursor,
+        ) {
+            let op = if descending { "<" } else { ">" };
+            match &cursor.value {
+                Some(value) => {
+                    query.push(format!("(({column}, {key}) {op} (CAST("));
+                    query.push_bind(value.clone());
+                    query.push(format!(" AS {column_type}), CAST("));
+                    query.push_bind(cursor.key.clone());
+                    query.push(format!(" AS {key_type}))"));
+                    if !descending {
+                        query.push(format!(" OR {column} IS NULL"));
+                    }
+                    query.push(")");
+                }
+                None => {
+                    query.push(format!("(({column} IS NULL AND {key} {op} CAST("));
+                    query.push_bind(cursor.key.clone());
+                    query.push(format!(" AS {key_type}))"));
+                    if descending {
+                        query.push(format!(" OR {column} IS NOT NULL"));
+                    }
+                    query.push(")");
+                }
+            }
+        }
+    };
+
+    write_module(file_path, helpers)
+}
@@ -1,40 +1,45 @@
-use std::{fs::OpenOptions, io};
-use std::io::Write;
-
-
-pub fn add_python_func(file_path: &std::path::Path) -> Result<(), io::Error> {
-let python_func = format!(r###"
-
-
-async fn python() -> Result<Json<Value>, (StatusCode, String)> {{
-    // Call the Python FastAPI service
-    let client = reqwest::Client::new();
-    let res = client
-        .get("http://python:8003/chat")  // Use service name and correct port
-        .send()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Request failed: {{}}", e)))?;
-
-    if res.status().is_client_error() || res.status().is_server_error() {{
-        return Err((StatusCode::BAD_REQUEST, format!("Error from Python service: {{}}", res.status())));
-    }}
-
-    let json_response: Value = res
-        .json()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to parse JSON: {{}}", e)))?;
-
-    Ok(Json(json!({{"payload": json_response}})))
-}}
-
-"###);
-    let mut file = OpenOptions::new()
-        .write(true) // Enable writing to the file.
-        .append(true) // Set the append mode.  Crucially, this makes it append.
-        .create(true) // Create the file if it doesn't exist.
-        .open(file_path)?; // Open the file, returning a Result.
-
-
-    file.write_all(python_func.as_bytes())?;
-    Ok(())
-}
\ No newline at end of file
+use std::io;
+
+use quote::quote;
+use serde_json::json;
+
+use crate::base_structs::{Endpoint, HttpMethod};
+use crate::codegen::write_module;
+use crate::openapi::Operation;
+
+
+pub fn add_python_func(file_path: &std::path::Path) -> Result<Endpoint, io::Error> {
+    let python_func = quote! {
+        /// Calls the Python FastAPI service
+        pub async fn python() -> Result<Json<Value>, AppError> {
+            // Use service name and correct port
+            let client = reqwest::Client::new();
+            let res = client
+                .get("http://python:8003/chat")
+                .send()
+                .await
+                .map_err(|e| AppError::Internal(format!("Request failed: {}", e)))?;
+
+            if res.status().is_client_error() || res.status().is_server_error() {
+                return Err(AppError::BadRequest(format!("Error from Python service: {}", res.status())));
+            }
+
+            let json_response: Value = res
+                .json()
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to parse JSON: {}", e)))?;
+
+            Ok(Json(json!({"payload": json_response})))
+        }
+    };
+
+    write_module(file_path, python_func)?;
+
+    Ok(Endpoint::new(HttpMethod::Get, "python").with_operation(Operation {
+        summary: "Call the Python FastAPI service".to_string(),
+        tag: Some("python".to_string()),
+        response: json!({"type": "object", "properties": {"payload": {}}, "required": ["payload"]}),
+        errors: vec![400, 500],
+        ..Operation::default()
+    }))
+}
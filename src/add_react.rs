@@ -1,25 +1,21 @@
-use std::process::{Command};
 use std::io::{self};
 use std::path::Path;
 
+use crate::vfs;
+
 pub fn create_react_app<P: AsRef<Path>>(directory: P) -> io::Result<()> {
     let dir = directory.as_ref();
-    println!("Creating a new React application in '{}' directory...", dir.display());
-    
-    if !dir.exists() {
-        std::fs::create_dir_all(dir)?;
+    // the template is the user's to change once it is copied
+    if vfs::exists(dir.join("frontend")) {
+        println!("'{}' already has a frontend, leaving it as it is", dir.display());
+        return Ok(());
     }
+    println!("Creating a new React application in '{}' directory...", dir.display());
 
-    let status = Command::new("cp")
-    .arg("-r")
-    .arg("./frontend")
-    .arg(dir.display().to_string())
-    .status()?;
-    if status.success() {
-        println!("\n✅ Successfully created React application in '{}'!", dir.display());
-    } else {
-        eprintln!("\n❌ Failed to create React application in '{}'", dir.display());
+    match vfs::copy_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("frontend"), dir) {
+        Ok(()) => println!("\n✅ Successfully created React application in '{}'!", dir.display()),
+        Err(e) => eprintln!("\n❌ Failed to create React application in '{}': {}", dir.display(), e),
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,7 +1,22 @@
-use std::fs;
 use std::io;
 use std::path::Path;
 
+use crate::base_structs::Row;
+use crate::manifest;
+use crate::vfs as fs;
+
+/// Writes the integration test scaffolding for `rows` under `tests/` and
+/// adds the dev-dependencies it needs.
+pub fn add_tests(project_root: &Path, rows: &[Row]) -> Result<(), io::Error> {
+    let table_names: Vec<String> = rows.iter().map(|row| row.name.clone()).collect();
+    create_test_directory_structure(project_root)?;
+    add_test_dependencies_to_cargo_toml(project_root)?;
+    generate_database_test_utilities(project_root, &table_names)?;
+    generate_crud_tests(project_root, &table_names)?;
+    generate_query_param_tests(project_root, rows)?;
+    generate_error_handling_tests(project_root, &table_names)
+}
+
 pub fn create_test_directory_structure(project_root: &Path) -> Result<(), io::Error> {
     // Create the tests directory
     let tests_dir = project_root.join("tests");
@@ -63,13 +78,16 @@ async fn test_health_endpoint() {
 }
 "###;
     
-    fs::write(&integration_test_file, integration_test_content)?;
+    manifest::write_generated(&integration_test_file, integration_test_content)?;
     
     // Create a test utilities module
     let utils_file = tests_dir.join("test_utils.rs");
     let utils_content = r###"//! Test utilities for database setup, data seeding, and cleanup
 
-use sqlx::{PgPool, PgPoolOptions};
+// every test file pulls this in with `mod test_utils;` and uses only part of it
+#![allow(dead_code)]
+
+use sqlx::postgres::{PgPool, PgPoolOptions};
 use std::env;
 use std::process::Command;
 use tokio::time::{sleep, Duration};
@@ -128,7 +146,7 @@ pub async fn setup_test_database() -> Result<PgPool, Box<dyn std::error::Error>>
     let pool = create_test_database_pool().await?;
     
     // Run migrations
-    sqlx::migrate!("../migrations")
+    sqlx::migrate!("./migrations")
         .run(&pool)
         .await
         .map_err(|e| format!("Failed to run migrations: {}", e))?;
@@ -139,6 +157,12 @@ pub async fn setup_test_database() -> Result<PgPool, Box<dyn std::error::Error>>
     Ok(pool)
 }
 
+/// Start the API on the default test port, it is stopped when the
+/// returned server is dropped
+pub async fn start_test_server() -> Result<TestServer, Box<dyn std::error::Error>> {
+    TestServer::new().await
+}
+
 /// Test server management utilities
 pub struct TestServer {
     child: Option<tokio::process::Child>,
@@ -192,7 +216,7 @@ impl Drop for TestServer {
 }
 "###;
     
-    fs::write(&utils_file, utils_content)?;
+    manifest::write_generated(&utils_file, utils_content)?;
     
     // Create a CRUD tests module
     let crud_tests_file = tests_dir.join("crud_tests.rs");
@@ -210,7 +234,7 @@ fn create_test_client() -> reqwest::Client {
 // This file will be auto-generated with tests for each table's CRUD operations
 "###;
     
-    fs::write(&crud_tests_file, crud_tests_content)?;
+    manifest::write_generated(&crud_tests_file, crud_tests_content)?;
     
     // Create a query parameter tests module
     let query_tests_file = tests_dir.join("query_param_tests.rs");
@@ -228,7 +252,7 @@ fn create_test_client() -> reqwest::Client {
 // This file will be auto-generated with tests for ordering, filtering, etc.
 "###;
     
-    fs::write(&query_tests_file, query_tests_content)?;
+    manifest::write_generated(&query_tests_file, query_tests_content)?;
     
     // Create an error handling tests module
     let error_tests_file = tests_dir.join("error_handling_tests.rs");
@@ -246,29 +270,14 @@ fn create_test_client() -> reqwest::Client {
 // This file will be auto-generated with tests for various error conditions
 "###;
     
-    fs::write(&error_tests_file, error_tests_content)?;
+    manifest::write_generated(&error_tests_file, error_tests_content)?;
     
-    // Create a mod.rs file to organize tests
+    // Every file in tests/ is its own crate that declares `mod test_utils;`,
+    // so a tests/mod.rs pulling them all in again doesn't compile
     let mod_file = tests_dir.join("mod.rs");
-    let mod_content = r###"//! Test module organization
-
-// Test utilities
-pub mod test_utils;
-
-// CRUD operation tests
-mod crud_tests;
-
-// Query parameter tests
-mod query_param_tests;
-
-// Error handling tests
-mod error_handling_tests;
-
-// Integration tests
-mod api_integration_tests;
-"###;
-    
-    fs::write(&mod_file, mod_content)?;
+    if fs::exists(&mod_file) {
+        manifest::remove_generated(&mod_file)?;
+    }
     
     Ok(())
 }
@@ -276,33 +285,16 @@ mod api_integration_tests;
 pub fn add_test_dependencies_to_cargo_toml(project_root: &Path) -> Result<(), io::Error> {
     let cargo_toml_path = project_root.join("Cargo.toml");
     
-    // Read the existing Cargo.toml
-    let mut content = fs::read_to_string(&cargo_toml_path)?;
-    
-    // Add test dependencies to the [dev-dependencies] section
+    // Add test dependencies to the [dev-dependencies] section, leaving
+    // ones the project already has alone
     let dev_dependencies = r###"
-[dev-dependencies]
 tokio-test = "0.4"
 reqwest = { version = "0.11", features = ["json"] }
 serde_json = "1.0"
 sqlx = { version = "0.7", features = ["runtime-tokio-rustls", "postgres", "uuid", "chrono", "json"] }
 "###;
     
-    // If there's no [dev-dependencies] section, add it
-    if !content.contains("[dev-dependencies]") {
-        content.push_str(dev_dependencies);
-    } else {
-        // If there is a [dev-dependencies] section, add our dependencies to it
-        // This is a simple approach - in a real implementation, you'd want to be more careful
-        // about merging dependencies
-        content = content.replace("[dev-dependencies]", "[dev-dependencies]");
-        if !content.contains("sqlx = { version = \"0.7\"") {
-            content = content.replace("[dev-dependencies]", &format!("[dev-dependencies]{}", dev_dependencies.lines().skip(4).collect::<Vec<_>>().join("\n")));
-        }
-    }
-    
-    // Write the updated Cargo.toml
-    fs::write(&cargo_toml_path, content)?;
+    crate::gen_toml::merge_dependencies(&cargo_toml_path, "dev-dependencies", dev_dependencies)?;
     
     Ok(())
 }
@@ -320,23 +312,23 @@ pub fn generate_database_test_utilities(project_root: &Path, table_names: &[Stri
     let cleanup_function = generate_cleanup_function(table_names);
     
     // Replace the placeholder functions with generated ones
-    if let Some(start) = content.find("/// Seed the database with test data") {
-        if let Some(end) = content[start..].find("/// Clean up test data") {
-            let before_seed = &content[..start];
-            let after_seed = &content[start + end..];
-            content = format!("{}{}{}", before_seed, seed_function, after_seed);
-        }
+    if let Some(start) = content.find("/// Seed the database with test data")
+        && let Some(end) = content[start..].find("/// Clean up test data")
+    {
+        let before_seed = &content[..start];
+        let after_seed = &content[start + end..];
+        content = format!("{}{}{}", before_seed, seed_function, after_seed);
     }
     
-    if let Some(start) = content.find("/// Clean up test data") {
-        if let Some(end) = content[start..].find("/// Set up a test database with migrations") {
-            let before_cleanup = &content[..start];
-            let after_cleanup = &content[start + end..];
-            content = format!("{}{}{}", before_cleanup, cleanup_function, after_cleanup);
-        }
+    if let Some(start) = content.find("/// Clean up test data")
+        && let Some(end) = content[start..].find("/// Set up a test database with migrations")
+    {
+        let before_cleanup = &content[..start];
+        let after_cleanup = &content[start + end..];
+        content = format!("{}{}{}", before_cleanup, cleanup_function, after_cleanup);
     }
     
-    fs::write(&utils_file, content)?;
+    manifest::write_generated(&utils_file, content)?;
     
     Ok(())
 }
@@ -398,7 +390,7 @@ pub fn generate_crud_tests(project_root: &Path, table_names: &[String]) -> Resul
 
 use reqwest;
 use serde_json::Value;
-use crate::test_utils;
+mod test_utils;
 
 // Helper function to create a test client
 fn create_test_client() -> reqwest::Client {
@@ -409,18 +401,15 @@ fn create_test_client() -> reqwest::Client {
     
     // Generate tests for each table
     for table_name in table_names {
-        let pascal_case_name = to_pascal_case(table_name);
-        let kebab_case_name = to_kebab_case(table_name);
-        
-        test_content.push_str(&generate_table_crud_tests(table_name, &pascal_case_name, &kebab_case_name));
+        test_content.push_str(&generate_table_crud_tests(table_name));
     }
     
-    fs::write(&crud_tests_file, test_content)?;
+    manifest::write_generated(&crud_tests_file, test_content)?;
     
     Ok(())
 }
 
-fn generate_table_crud_tests(table_name: &str, pascal_case_name: &str, kebab_case_name: &str) -> String {
+fn generate_table_crud_tests(table_name: &str) -> String {
     format!(
         r###"
 #[tokio::test]
@@ -461,13 +450,13 @@ async fn test_{}_crud_operations() {{
 
 "###,
         table_name.to_lowercase(),
-        kebab_case_name,
-        kebab_case_name
+        table_name,
+        table_name
     )
 }
 
 /// Generate query parameter tests for the API endpoints
-pub fn generate_query_param_tests(project_root: &Path, table_names: &[String]) -> Result<(), io::Error> {
+pub fn generate_query_param_tests(project_root: &Path, rows: &[Row]) -> Result<(), io::Error> {
     let tests_dir = project_root.join("tests");
     let query_tests_file = tests_dir.join("query_param_tests.rs");
     
@@ -475,7 +464,7 @@ pub fn generate_query_param_tests(project_root: &Path, table_names: &[String]) -
 
 use reqwest;
 use serde_json::Value;
-use crate::test_utils;
+mod test_utils;
 
 // Helper function to create a test client
 fn create_test_client() -> reqwest::Client {
@@ -484,19 +473,19 @@ fn create_test_client() -> reqwest::Client {
 
 "###);
     
-    // Generate tests for each table
-    for table_name in table_names {
-        let kebab_case_name = to_kebab_case(table_name);
+    // Generate tests for each table that has a column to order and filter by
+    for row in rows {
+        let Some(first_col) = row.cols.first() else { continue };
         
-        test_content.push_str(&generate_table_query_tests(table_name, &kebab_case_name));
+        test_content.push_str(&generate_table_query_tests(&row.name, &first_col.name));
     }
     
-    fs::write(&query_tests_file, test_content)?;
+    manifest::write_generated(&query_tests_file, test_content)?;
     
     Ok(())
 }
 
-fn generate_table_query_tests(table_name: &str, kebab_case_name: &str) -> String {
+fn generate_table_query_tests(table_name: &str, first_col: &str) -> String {
     format!(
         r###"#[tokio::test]
 async fn test_{}_ordering() {{
@@ -507,7 +496,7 @@ async fn test_{}_ordering() {{
     
     // Test ordering by a column (ascending)
     let order_asc_response = client
-        .get(&format!("{{}}/get_{{}}?order_by=id&direction=asc", base_url, "{}"))
+        .get(&format!("{{}}/get_{{}}?order_by={}&direction=asc", base_url, "{}"))
         .send()
         .await
         .expect("Failed to send order asc request");
@@ -516,7 +505,7 @@ async fn test_{}_ordering() {{
     
     // Test ordering by a column (descending)
     let order_desc_response = client
-        .get(&format!("{{}}/get_{{}}?order_by=id&direction=desc", base_url, "{}"))
+        .get(&format!("{{}}/get_{{}}?order_by={}&direction=desc", base_url, "{}"))
         .send()
         .await
         .expect("Failed to send order desc request");
@@ -527,6 +516,52 @@ async fn test_{}_ordering() {{
     // This would require seeding the database with known data and checking the order
 }}
 
+#[tokio::test]
+async fn test_{}_pagination() {{
+    // Setup
+    let _server_handle = test_utils::start_test_server().await.expect("Failed to start test server");
+    let client = create_test_client();
+    let base_url = "http://localhost:8082";
+    
+    // Test limit/offset pagination
+    let page_response = client
+        .get(&format!("{{}}/get_{{}}?limit=1&offset=0", base_url, "{}"))
+        .send()
+        .await
+        .expect("Failed to send paginated request");
+    
+    assert_eq!(page_response.status(), 200);
+    
+    let page: Value = page_response
+        .json()
+        .await
+        .expect("Failed to parse paginated response");
+    
+    assert!(page["payload"].as_array().map(|rows| rows.len() <= 1).unwrap_or(false));
+    assert!(page.get("total_count").is_some());
+    assert!(page.get("next_cursor").is_some());
+    
+    // Test following the cursor to the next page
+    if let Some(cursor) = page["next_cursor"].as_str() {{
+        let next_response = client
+            .get(&format!("{{}}/get_{{}}?limit=1&cursor={{}}", base_url, "{}", cursor))
+            .send()
+            .await
+            .expect("Failed to send cursor request");
+        
+        assert_eq!(next_response.status(), 200);
+    }}
+    
+    // Test that a malformed cursor is rejected
+    let invalid_cursor_response = client
+        .get(&format!("{{}}/get_{{}}?cursor=not-a-cursor", base_url, "{}"))
+        .send()
+        .await
+        .expect("Failed to send invalid cursor request");
+    
+    assert_eq!(invalid_cursor_response.status(), 400);
+}}
+
 #[tokio::test]
 async fn test_{}_invalid_order_by() {{
     // Setup
@@ -545,12 +580,58 @@ async fn test_{}_invalid_order_by() {{
     assert_eq!(invalid_order_response.status(), 400);
 }}
 
+#[tokio::test]
+async fn test_{}_invalid_filters() {{
+    // Setup
+    let _server_handle = test_utils::start_test_server().await.expect("Failed to start test server");
+    let client = create_test_client();
+    let base_url = "http://localhost:8082";
+    
+    // Filters on columns outside the table are rejected
+    let unknown_column_response = client
+        .get(&format!("{{}}/get_{{}}?not_a_column=1", base_url, "{}"))
+        .send()
+        .await
+        .expect("Failed to send unknown column request");
+    
+    assert_eq!(unknown_column_response.status(), 400);
+    
+    // So are operators outside the filter grammar
+    let unknown_operator_response = client
+        .get(&format!("{{}}/get_{{}}?{}[regex]=1", base_url, "{}"))
+        .send()
+        .await
+        .expect("Failed to send unknown operator request");
+    
+    assert_eq!(unknown_operator_response.status(), 400);
+    
+    // Only foreign keys can be expanded
+    let unknown_expand_response = client
+        .get(&format!("{{}}/get_{{}}?expand=not_a_relation", base_url, "{}"))
+        .send()
+        .await
+        .expect("Failed to send unknown expand request");
+    
+    assert_eq!(unknown_expand_response.status(), 400);
+}}
+
 "###,
         table_name.to_lowercase(),
-        kebab_case_name,
-        kebab_case_name,
+        first_col,
+        table_name,
+        first_col,
+        table_name,
+        table_name.to_lowercase(),
+        table_name,
+        table_name,
+        table_name,
         table_name.to_lowercase(),
-        kebab_case_name
+        table_name,
+        table_name.to_lowercase(),
+        table_name,
+        first_col,
+        table_name,
+        table_name
     )
 }
 
@@ -563,7 +644,7 @@ pub fn generate_error_handling_tests(project_root: &Path, table_names: &[String]
 
 use reqwest;
 use serde_json::Value;
-use crate::test_utils;
+mod test_utils;
 
 // Helper function to create a test client
 fn create_test_client() -> reqwest::Client {
@@ -574,17 +655,15 @@ fn create_test_client() -> reqwest::Client {
     
     // Generate tests for each table
     for table_name in table_names {
-        let kebab_case_name = to_kebab_case(table_name);
-        
-        test_content.push_str(&generate_table_error_tests(table_name, &kebab_case_name));
+        test_content.push_str(&generate_table_error_tests(table_name));
     }
     
-    fs::write(&error_tests_file, test_content)?;
+    manifest::write_generated(&error_tests_file, test_content)?;
     
     Ok(())
 }
 
-fn generate_table_error_tests(table_name: &str, kebab_case_name: &str) -> String {
+fn generate_table_error_tests(table_name: &str) -> String {
     format!(
         r###"#[tokio::test]
 async fn test_{}_database_error_handling() {{
@@ -640,24 +719,36 @@ async fn test_{}_missing_required_fields() {{
 "###,
         table_name.to_lowercase(),
         table_name.to_lowercase(),
-        kebab_case_name,
+        table_name,
         table_name.to_lowercase(),
-        kebab_case_name
+        table_name
     )
 }
 
-fn to_pascal_case(s: &str) -> String {
-    s.split('_')
-        .map(|word| {
-            let mut chars = word.chars();
-            match chars.next() {
-                None => String::new(),
-                Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
-            }
-        })
-        .collect()
-}
-
-fn to_kebab_case(s: &str) -> String {
-    s.replace("_", "-")
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::parse_rows;
+
+    #[test]
+    fn test_files_compile_on_their_own_and_filter_real_columns() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Cargo.toml"), "[package]\nname = \"service\"\n").unwrap();
+        std::fs::create_dir(dir.path().join("tests")).unwrap();
+        std::fs::write(dir.path().join("tests/mod.rs"), "pub mod test_utils;\n").unwrap();
+        let rows = parse_rows("CREATE TABLE run_notes (note_id SERIAL PRIMARY KEY, body TEXT);").unwrap();
+
+        add_tests(dir.path(), &rows).unwrap();
+
+        // every file in tests/ is its own crate, a tests/mod.rs would declare them twice
+        assert!(!dir.path().join("tests/mod.rs").exists());
+        let query_tests = std::fs::read_to_string(dir.path().join("tests/query_param_tests.rs")).unwrap();
+        assert!(query_tests.contains("\nmod test_utils;\n"));
+        assert!(!query_tests.contains("use crate::test_utils"));
+        assert!(query_tests.contains(r#"format!("{}/get_{}?note_id[regex]=1", base_url, "run_notes")"#));
+        assert!(query_tests.contains(r#"format!("{}/get_{}?order_by=note_id&direction=asc", base_url, "run_notes")"#));
+        let utils = std::fs::read_to_string(dir.path().join("tests/test_utils.rs")).unwrap();
+        assert!(utils.contains("pub async fn start_test_server()"));
+    }
 }
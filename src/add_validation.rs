@@ -0,0 +1,682 @@
+//! Payload validation derived from the schema: VARCHAR lengths, NOT NULL,
+//! simple CHECK constraints and email or url column names become rules,
+//! and each insert, PUT and PATCH payload gets a `validate()` that reports
+//! every field breaking one of them before the database is queried.
+//! CHECKs too complex to read here are still enforced by postgres.
+
+use std::collections::HashMap;
+
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
+
+use crate::base_structs::Row;
+use crate::codegen::ident;
+use crate::ddl_lexer::{tokenize, Token, TokenKind};
+use crate::schema::Col;
+
+const INTEGER_TYPES: &[&str] = &["i16", "i32", "i64"];
+const FLOAT_TYPES: &[&str] = &["f32", "f64"];
+
+/// A literal a CHECK compares a column with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckValue {
+    Number(String),
+    Text(String),
+}
+
+impl CheckValue {
+    fn as_str(&self) -> &str {
+        match self {
+            CheckValue::Number(n) | CheckValue::Text(n) => n,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl CompareOp {
+    fn parse(op: &str) -> Option<CompareOp> {
+        match op {
+            "=" => Some(CompareOp::Eq),
+            "<>" | "!=" => Some(CompareOp::Ne),
+            ">" => Some(CompareOp::Gt),
+            ">=" => Some(CompareOp::Gte),
+            "<" => Some(CompareOp::Lt),
+            "<=" => Some(CompareOp::Lte),
+            _ => None,
+        }
+    }
+
+    /// The same comparison with its sides swapped: `0 <= age` is `age >= 0`.
+    fn flip(self) -> CompareOp {
+        match self {
+            CompareOp::Gt => CompareOp::Lt,
+            CompareOp::Gte => CompareOp::Lte,
+            CompareOp::Lt => CompareOp::Gt,
+            CompareOp::Lte => CompareOp::Gte,
+            op => op,
+        }
+    }
+}
+
+/// Something every value of a column has to satisfy.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    /// At most this many characters, from VARCHAR(n) or a CHECK on `length()`.
+    MaxLength(u32),
+    MinLength(u32),
+    /// `column op value` from a CHECK, e.g. `age >= 0` or `name <> ''`.
+    Compare(CompareOp, CheckValue),
+    /// `column IN (...)`.
+    OneOf(Vec<CheckValue>),
+    /// The column is named `email` or ends in `_email`.
+    Email,
+    /// The column is named `url`, `website` or ends in `_url`.
+    Url,
+}
+
+impl Rule {
+    /// What the field must be, as reported to the caller.
+    pub fn message(&self) -> String {
+        match self {
+            Rule::MaxLength(n) => format!("must be at most {} characters", n),
+            Rule::MinLength(n) => format!("must be at least {} characters", n),
+            Rule::Compare(CompareOp::Ne, CheckValue::Text(t)) if t.is_empty() => "must not be empty".to_string(),
+            Rule::Compare(op, value) => {
+                let relation = match op {
+                    CompareOp::Eq => "be",
+                    CompareOp::Ne => "not be",
+                    CompareOp::Gt => "be greater than",
+                    CompareOp::Gte => "be at least",
+                    CompareOp::Lt => "be less than",
+                    CompareOp::Lte => "be at most",
+                };
+                format!("must {} {}", relation, value.as_str())
+            }
+            Rule::OneOf(values) => {
+                let values: Vec<&str> = values.iter().map(|v| v.as_str()).collect();
+                format!("must be one of {}", values.join(", "))
+            }
+            Rule::Email => "must be an email address".to_string(),
+            Rule::Url => "must be an http or https URL".to_string(),
+        }
+    }
+
+    /// Whether the rule can be checked on values of `rust_type`. Rules that
+    /// can't, such as a range on a NUMERIC read as a String, are left to postgres.
+    fn fits(&self, rust_type: &str) -> bool {
+        let numeric = INTEGER_TYPES.contains(&rust_type) || FLOAT_TYPES.contains(&rust_type);
+        let fits_value = |value: &CheckValue| match value {
+            CheckValue::Number(n) => numeric && number_literal(n, rust_type).is_some(),
+            CheckValue::Text(_) => rust_type == "String",
+        };
+        match self {
+            Rule::MaxLength(_) | Rule::MinLength(_) | Rule::Email | Rule::Url => rust_type == "String",
+            Rule::Compare(op, value @ CheckValue::Text(_)) => {
+                matches!(op, CompareOp::Eq | CompareOp::Ne) && fits_value(value)
+            }
+            Rule::Compare(_, value) => fits_value(value),
+            Rule::OneOf(values) => !values.is_empty() && values.iter().all(fits_value),
+        }
+    }
+
+    /// An expression that is true when `value`, a `&T` of the column's
+    /// rust type, breaks the rule.
+    fn violated(&self, rust_type: &str) -> TokenStream {
+        match self {
+            Rule::MaxLength(n) => {
+                let n = Literal::u32_unsuffixed(*n);
+                quote! { value.chars().count() > #n }
+            }
+            Rule::MinLength(n) => {
+                let n = Literal::u32_unsuffixed(*n);
+                quote! { value.chars().count() < #n }
+            }
+            Rule::Compare(CompareOp::Ne, CheckValue::Text(t)) if t.is_empty() => quote! { value.is_empty() },
+            Rule::Compare(op, value) => {
+                let literal = value_tokens(value, rust_type);
+                match op {
+                    CompareOp::Eq => quote! { *value != #literal },
+                    CompareOp::Ne => quote! { *value == #literal },
+                    CompareOp::Gt => quote! { *value <= #literal },
+                    CompareOp::Gte => quote! { *value < #literal },
+                    CompareOp::Lt => quote! { *value >= #literal },
+                    CompareOp::Lte => quote! { *value > #literal },
+                }
+            }
+            Rule::OneOf(values) => {
+                let literals = values.iter().map(|value| value_tokens(value, rust_type));
+                match rust_type {
+                    "String" => quote! { ![#(#literals),*].contains(&value.as_str()) },
+                    _ => quote! { ![#(#literals),*].contains(value) },
+                }
+            }
+            Rule::Email => quote! {
+                !value.split_once('@').is_some_and(|(name, domain)| {
+                    !name.is_empty() && domain.contains('.') && !value.contains(char::is_whitespace)
+                })
+            },
+            Rule::Url => quote! { !(value.starts_with("http://") || value.starts_with("https://")) },
+        }
+    }
+}
+
+/// `text` as a literal of the integer or float type `rust_type`, or None
+/// when it isn't one, e.g. `1.5` for an i32 or a bound too big for an i16.
+fn number_literal(text: &str, rust_type: &str) -> Option<TokenStream> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let fits = match rust_type {
+        "i16" => text.parse::<i16>().is_ok(),
+        "i32" => text.parse::<i32>().is_ok(),
+        "i64" => text.parse::<i64>().is_ok(),
+        "f32" | "f64" => text.parse::<f64>().is_ok_and(f64::is_finite),
+        _ => false,
+    };
+    let literal = match rust_type {
+        "f32" | "f64" => Literal::f64_unsuffixed(digits.parse().ok().filter(|_| fits)?),
+        _ => Literal::u64_unsuffixed(digits.parse().ok().filter(|_| fits)?),
+    };
+    Some(if negative { quote! { -#literal } } else { quote! { #literal } })
+}
+
+fn value_tokens(value: &CheckValue, rust_type: &str) -> TokenStream {
+    match value {
+        CheckValue::Number(n) => number_literal(n, rust_type).unwrap_or_default(),
+        CheckValue::Text(t) => quote! { #t },
+    }
+}
+
+/// The rules for one column of `row` that its rust type can be checked against.
+pub fn column_rules(row: &Row, col: &Col, type_map: &HashMap<String, String>) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    if matches!(col.col_type.as_str(), "VARCHAR" | "CHAR" | "BPCHAR")
+        && let Some(length) = col.precision
+    {
+        rules.push(Rule::MaxLength(length));
+    }
+    let name = col.name.to_lowercase();
+    if name == "email" || name.ends_with("_email") {
+        rules.push(Rule::Email);
+    }
+    if name == "url" || name == "website" || name.ends_with("_url") {
+        rules.push(Rule::Url);
+    }
+    for check in &row.checks {
+        for (column, rule) in check_rules(check) {
+            if column.eq_ignore_ascii_case(&col.name) && !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+    }
+
+    let rust_type = col.rust_type(type_map);
+    rules.retain(|rule| rule.fits(&rust_type));
+    rules
+}
+
+/// The column rules in a CHECK expression. Conditions joined by a top-level
+/// AND are read one by one; anything other than a comparison with a literal,
+/// BETWEEN, IN or a bound on `length()` is skipped.
+pub fn check_rules(expr: &str) -> Vec<(String, Rule)> {
+    let Ok(tokens) = tokenize(expr) else { return Vec::new() };
+    let tokens = simplify(tokens);
+    let mut rules = Vec::new();
+    for condition in conjuncts(&tokens) {
+        rules.extend(condition_rules(condition));
+    }
+    rules
+}
+
+/// Drops `::type` casts and the parentheses around lone identifiers and
+/// literals, which postgres adds when it prints a CHECK back, e.g.
+/// `((status)::text = 'a'::text)` or `(score >= ('-1.5'::numeric)::double precision)`.
+/// A string cast to a number becomes that number.
+fn simplify(tokens: Vec<Token>) -> Vec<Token> {
+    let mut out: Vec<Token> = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        if token.kind == TokenKind::Operator("::".to_string()) {
+            if let Some(Token { kind: TokenKind::Word(name), .. }) = tokens.next() {
+                let name = name.to_lowercase();
+                // `character varying` and `double precision` are two words
+                if matches!(name.as_str(), "character" | "double" | "bit") {
+                    tokens.next_if(|t| matches!(&t.kind, TokenKind::Word(_)));
+                }
+                // array casts such as `::text[]`
+                if tokens.next_if(|t| t.is_punct('[')).is_some() {
+                    tokens.next_if(|t| t.is_punct(']'));
+                }
+                let numeric = ["numeric", "decimal", "int", "integer", "bigint", "smallint", "real", "double"]
+                    .contains(&name.as_str()) || name.starts_with("int") || name.starts_with("float");
+                if numeric && let Some(last) = out.last_mut() && let TokenKind::Str(text) = &last.kind {
+                    last.kind = TokenKind::Number(text.clone());
+                }
+            }
+            continue;
+        }
+        let n = out.len();
+        let lone = n >= 2
+            && token.is_punct(')')
+            && out[n - 2].is_punct('(')
+            && (column_name(&out[n - 1]).is_some() || literal(&out[n - 1..]).is_some())
+            // keep the parentheses of calls such as length(name)
+            && !(n >= 3 && matches!(out[n - 3].kind, TokenKind::Word(_) | TokenKind::QuotedIdent(_)));
+        if lone {
+            let inner = out.pop().expect("checked above");
+            out.pop();
+            out.push(inner);
+            continue;
+        }
+        out.push(token);
+    }
+    out
+}
+
+/// The conditions a top-level AND joins, without their outer parentheses.
+fn conjuncts(tokens: &[Token]) -> Vec<&[Token]> {
+    let tokens = strip_parens(tokens);
+    let mut parts = Vec::new();
+    let (mut depth, mut start, mut between) = (0, 0, false);
+    for (i, token) in tokens.iter().enumerate() {
+        if token.is_punct('(') || token.is_punct('[') {
+            depth += 1;
+        } else if token.is_punct(')') || token.is_punct(']') {
+            depth -= 1;
+        } else if depth == 0 && token.is_word("between") {
+            between = true;
+        } else if depth == 0 && token.is_word("and") {
+            // the AND of `BETWEEN a AND b` belongs to the BETWEEN
+            if between {
+                between = false;
+            } else {
+                parts.push(&tokens[start..i]);
+                start = i + 1;
+            }
+        }
+    }
+    if parts.is_empty() {
+        return vec![tokens];
+    }
+    parts.push(&tokens[start..]);
+    parts.into_iter().flat_map(conjuncts).collect()
+}
+
+fn strip_parens(mut tokens: &[Token]) -> &[Token] {
+    while tokens.len() >= 2 && tokens[0].is_punct('(') && tokens[tokens.len() - 1].is_punct(')') {
+        // `(a) AND (b)` starts and ends with parentheses that don't pair up
+        let mut depth = 0;
+        let closes_at_end = tokens.iter().position(|t| {
+            if t.is_punct('(') {
+                depth += 1;
+            } else if t.is_punct(')') {
+                depth -= 1;
+            }
+            depth == 0
+        }) == Some(tokens.len() - 1);
+        if !closes_at_end {
+            break;
+        }
+        tokens = &tokens[1..tokens.len() - 1];
+    }
+    tokens
+}
+
+fn column_name(token: &Token) -> Option<String> {
+    match &token.kind {
+        TokenKind::Word(w) if !is_keyword(w) => Some(w.to_lowercase()),
+        TokenKind::QuotedIdent(name) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    ["and", "or", "not", "null", "true", "false", "between", "in", "any", "array", "is"]
+        .iter()
+        .any(|k| k.eq_ignore_ascii_case(word))
+}
+
+/// A literal at the start of `tokens` and how many tokens it took.
+fn literal(tokens: &[Token]) -> Option<(CheckValue, usize)> {
+    match (&tokens.first()?.kind, tokens.get(1).map(|t| &t.kind)) {
+        (TokenKind::Number(n), _) => Some((CheckValue::Number(n.clone()), 1)),
+        (TokenKind::Str(s), _) => Some((CheckValue::Text(s.clone()), 1)),
+        (TokenKind::Operator(op), Some(TokenKind::Number(n))) if op == "-" => {
+            Some((CheckValue::Number(format!("-{}", n)), 2))
+        }
+        _ => None,
+    }
+}
+
+/// Comma separated literals followed by `close`, e.g. the `'a', 'b')` of an IN.
+fn literal_list(mut tokens: &[Token], close: char) -> Option<Vec<CheckValue>> {
+    let mut values = Vec::new();
+    loop {
+        let (value, used) = literal(tokens)?;
+        values.push(value);
+        let next = tokens.get(used)?;
+        tokens = &tokens[used + 1..];
+        if next.is_punct(close) {
+            return tokens.is_empty().then_some(values);
+        }
+        if !next.is_punct(',') {
+            return None;
+        }
+    }
+}
+
+fn operator(token: &Token) -> Option<CompareOp> {
+    match &token.kind {
+        TokenKind::Operator(op) => CompareOp::parse(op),
+        _ => None,
+    }
+}
+
+fn condition_rules(tokens: &[Token]) -> Vec<(String, Rule)> {
+    let tokens = strip_parens(tokens);
+    let Some(first) = tokens.first() else { return Vec::new() };
+
+    // length(name) >= 3
+    let length_fn = ["length", "char_length", "character_length"].iter().any(|f| first.is_word(f));
+    if length_fn && tokens.len() == 6 && tokens[1].is_punct('(') && tokens[3].is_punct(')') {
+        let (Some(column), Some(op)) = (column_name(&tokens[2]), operator(&tokens[4])) else { return Vec::new() };
+        let TokenKind::Number(n) = &tokens[5].kind else { return Vec::new() };
+        let Ok(n) = n.parse::<u32>() else { return Vec::new() };
+        let rules = match op {
+            CompareOp::Gte => vec![Rule::MinLength(n)],
+            CompareOp::Gt => vec![Rule::MinLength(n + 1)],
+            CompareOp::Lte => vec![Rule::MaxLength(n)],
+            CompareOp::Lt if n > 0 => vec![Rule::MaxLength(n - 1)],
+            CompareOp::Eq => vec![Rule::MinLength(n), Rule::MaxLength(n)],
+            _ => Vec::new(),
+        };
+        return rules.into_iter().map(|rule| (column.clone(), rule)).collect();
+    }
+
+    // 0 <= age
+    if let Some((value, used)) = literal(tokens) {
+        if tokens.len() == used + 2
+            && let (Some(op), Some(column)) = (operator(&tokens[used]), column_name(&tokens[used + 1]))
+        {
+            return vec![(column, Rule::Compare(op.flip(), value))];
+        }
+        return Vec::new();
+    }
+
+    let Some(column) = column_name(first) else { return Vec::new() };
+    let rest = &tokens[1..];
+    // age >= 0
+    if let Some(op) = rest.first().and_then(operator)
+        && let Some((value, used)) = literal(&rest[1..])
+        && rest.len() == used + 1
+    {
+        return vec![(column, Rule::Compare(op, value))];
+    }
+    // age BETWEEN 0 AND 150
+    if rest.first().is_some_and(|t| t.is_word("between"))
+        && let Some((low, used)) = literal(&rest[1..])
+        && rest.get(1 + used).is_some_and(|t| t.is_word("and"))
+        && let Some((high, high_used)) = literal(&rest[2 + used..])
+        && rest.len() == 2 + used + high_used
+    {
+        return vec![
+            (column.clone(), Rule::Compare(CompareOp::Gte, low)),
+            (column, Rule::Compare(CompareOp::Lte, high)),
+        ];
+    }
+    // status IN ('a', 'b')
+    if rest.len() > 2
+        && rest[0].is_word("in")
+        && rest[1].is_punct('(')
+        && let Some(values) = literal_list(&rest[2..], ')')
+    {
+        return vec![(column, Rule::OneOf(values))];
+    }
+    // status = ANY (ARRAY['a', 'b']), how postgres prints an IN back
+    if rest.len() > 2 && rest[0].kind == TokenKind::Operator("=".to_string()) && rest[1].is_word("any") {
+        let array = strip_parens(&rest[2..]);
+        if array.len() > 2
+            && array[0].is_word("array")
+            && array[1].is_punct('[')
+            && let Some(values) = literal_list(&array[2..], ']')
+        {
+            return vec![(column, Rule::OneOf(values))];
+        }
+    }
+    Vec::new()
+}
+
+/// Which payload a `validate()` is generated for, which decides how its
+/// fields are wrapped and which ones must be present.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PayloadKind {
+    /// Insert and PUT payloads: every field is an `Option` so a missing
+    /// required column is reported like any other bad field.
+    Full,
+    /// PATCH payloads: only the fields sent are checked, nullable columns
+    /// are double Options.
+    Partial,
+}
+
+/// Whether a full payload has to include `col`.
+pub fn is_required(col: &Col) -> bool {
+    !col.auto_gen && !col.is_nullable()
+}
+
+/// An `impl` giving `payload_struct` a `validate()` over `cols`, which
+/// collects a `FieldError` for every field that breaks a rule and fails
+/// with `AppError::Validation` when there are any.
+pub fn validate_impl(
+    row: &Row,
+    payload_struct: &proc_macro2::Ident,
+    cols: &[&Col],
+    kind: PayloadKind,
+    type_map: &HashMap<String, String>,
+) -> TokenStream {
+    let checks: Vec<TokenStream> = cols.iter().filter_map(|col| {
+        let (field, name) = (ident(&col.name), &col.name);
+        let rust_type = col.rust_type(type_map);
+        let violations: Vec<(TokenStream, TokenStream)> = column_rules(row, col, type_map).into_iter().map(|rule| {
+            let message = rule.message();
+            (rule.violated(&rust_type), quote! { errors.push(FieldError::new(#name, #message)) })
+        }).collect();
+        let rules: Vec<TokenStream> = violations.iter().map(|(violated, push)| quote! {
+            if #violated {
+                #push;
+            }
+        }).collect();
+        let required = kind == PayloadKind::Full && is_required(col);
+        if rules.is_empty() && !required {
+            return None;
+        }
+        let missing = quote! { errors.push(FieldError::new(#name, "is required")) };
+        Some(match kind {
+            PayloadKind::Full if rules.is_empty() => quote! {
+                if self.#field.is_none() {
+                    #missing;
+                }
+            },
+            PayloadKind::Full if required => quote! {
+                match &self.#field {
+                    Some(value) => {
+                        #(#rules)*
+                    }
+                    None => #missing,
+                }
+            },
+            // a single rule goes in the `if let` itself
+            _ => {
+                let pattern = match kind == PayloadKind::Partial && col.is_nullable() {
+                    true => quote! { Some(Some(value)) },
+                    false => quote! { Some(value) },
+                };
+                match violations.as_slice() {
+                    [(violated, push)] => quote! {
+                        if let #pattern = &self.#field && #violated {
+                            #push;
+                        }
+                    },
+                    _ => quote! {
+                        if let #pattern = &self.#field {
+                            #(#rules)*
+                        }
+                    },
+                }
+            }
+        })
+    }).collect();
+
+    let body = if checks.is_empty() {
+        quote! { Ok(()) }
+    } else {
+        quote! {
+            let mut errors = Vec::new();
+            #(#checks)*
+            match errors.is_empty() {
+                true => Ok(()),
+                false => Err(AppError::Validation(errors)),
+            }
+        }
+    };
+    quote! {
+        impl #payload_struct {
+            /// Checks the payload against the table's column types and CHECK
+            /// constraints, reporting every bad field at once.
+            pub fn validate(&self) -> Result<(), AppError> {
+                #body
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::render;
+    use crate::create_type_map;
+    use crate::schema::parse_rows;
+
+    /// The rules of every column of the table `sql` creates, by column name.
+    fn rules(sql: &str) -> Vec<(String, Vec<Rule>)> {
+        let row = parse_rows(sql).unwrap().remove(0);
+        let type_map = create_type_map();
+        row.cols.iter().map(|col| (col.name.clone(), column_rules(&row, col, &type_map))).collect()
+    }
+
+    fn number(n: &str) -> CheckValue {
+        CheckValue::Number(n.to_string())
+    }
+
+    fn text(t: &str) -> CheckValue {
+        CheckValue::Text(t.to_string())
+    }
+
+    #[test]
+    fn varchar_and_char_lengths_are_maximums() {
+        let rules = rules("CREATE TABLE t (name VARCHAR(40), code CHAR(3), body TEXT);");
+        assert_eq!(rules, [
+            ("name".to_string(), vec![Rule::MaxLength(40)]),
+            ("code".to_string(), vec![Rule::MaxLength(3)]),
+            ("body".to_string(), vec![]),
+        ]);
+    }
+
+    #[test]
+    fn not_null_columns_without_defaults_are_required() {
+        let row = parse_rows(
+            "CREATE TABLE t (id SERIAL PRIMARY KEY, name TEXT NOT NULL, note TEXT, created TIMESTAMPTZ NOT NULL DEFAULT now());",
+        ).unwrap().remove(0);
+        let required: Vec<&str> = row.cols.iter().filter(|col| is_required(col)).map(|col| col.name.as_str()).collect();
+        assert_eq!(required, ["name"]);
+
+        let cols: Vec<&Col> = row.cols.iter().filter(|col| !col.primary_key).collect();
+        let full = render(validate_impl(&row, &ident("tInsert"), &cols, PayloadKind::Full, &create_type_map())).unwrap();
+        assert!(full.contains("if self.name.is_none() {\n            errors.push(FieldError::new(\"name\", \"is required\"));"));
+        assert!(!full.contains("self.note"));
+        let partial = render(validate_impl(&row, &ident("tPatch"), &cols, PayloadKind::Partial, &create_type_map())).unwrap();
+        assert!(partial.contains("Ok(())"));
+        assert!(!partial.contains("is required"));
+    }
+
+    #[test]
+    fn ranges_become_comparisons() {
+        assert_eq!(check_rules("x >= 0 AND x <= 10"), [
+            ("x".to_string(), Rule::Compare(CompareOp::Gte, number("0"))),
+            ("x".to_string(), Rule::Compare(CompareOp::Lte, number("10"))),
+        ]);
+        assert_eq!(check_rules("0 < x"), [("x".to_string(), Rule::Compare(CompareOp::Gt, number("0")))]);
+        assert_eq!(check_rules("x BETWEEN -5 AND 5"), [
+            ("x".to_string(), Rule::Compare(CompareOp::Gte, number("-5"))),
+            ("x".to_string(), Rule::Compare(CompareOp::Lte, number("5"))),
+        ]);
+        // how postgres prints a CHECK back
+        assert_eq!(check_rules("((x >= 0) AND (x <= 10))"), check_rules("x >= 0 AND x <= 10"));
+    }
+
+    #[test]
+    fn length_bounds_become_length_rules() {
+        assert_eq!(check_rules("length(name) <= 20"), [("name".to_string(), Rule::MaxLength(20))]);
+        assert_eq!(check_rules("char_length(name) > 2"), [("name".to_string(), Rule::MinLength(3))]);
+        assert_eq!(check_rules("length(code) = 4"), [
+            ("code".to_string(), Rule::MinLength(4)),
+            ("code".to_string(), Rule::MaxLength(4)),
+        ]);
+    }
+
+    #[test]
+    fn in_lists_and_any_arrays_become_one_of() {
+        let one_of = vec![("status".to_string(), Rule::OneOf(vec![text("draft"), text("live")]))];
+        assert_eq!(check_rules("status IN ('draft', 'live')"), one_of);
+        assert_eq!(
+            check_rules("((status)::text = ANY ((ARRAY['draft'::character varying, 'live'::character varying])::text[]))"),
+            one_of
+        );
+    }
+
+    #[test]
+    fn email_and_url_columns_are_checked_by_name() {
+        let rules = rules("CREATE TABLE t (email TEXT, backup_email TEXT, website TEXT, avatar_url TEXT, emails TEXT);");
+        assert_eq!(rules, [
+            ("email".to_string(), vec![Rule::Email]),
+            ("backup_email".to_string(), vec![Rule::Email]),
+            ("website".to_string(), vec![Rule::Url]),
+            ("avatar_url".to_string(), vec![Rule::Url]),
+            ("emails".to_string(), vec![]),
+        ]);
+    }
+
+    #[test]
+    fn complex_checks_make_no_rules() {
+        assert!(check_rules("x > 0 OR y > 0").is_empty());
+        assert!(check_rules("x < y").is_empty());
+        assert!(check_rules("lower(name) = name").is_empty());
+        assert!(check_rules("x + 1 > 0").is_empty());
+        // only the part that can be read makes a rule
+        assert_eq!(check_rules("x >= 0 AND (x < y OR y IS NULL)"), [
+            ("x".to_string(), Rule::Compare(CompareOp::Gte, number("0"))),
+        ]);
+    }
+
+    #[test]
+    fn rules_that_do_not_fit_the_type_are_left_to_postgres() {
+        let rules = rules(
+            "CREATE TABLE t (age SMALLINT CHECK (age BETWEEN 0 AND 100000), price NUMERIC CHECK (price > 0), score INT CHECK (score > 1.5));",
+        );
+        // 100000 is out of an i16's range, NUMERIC is a Decimal and 1.5 isn't an integer
+        assert_eq!(rules, [
+            ("age".to_string(), vec![Rule::Compare(CompareOp::Gte, number("0"))]),
+            ("price".to_string(), vec![]),
+            ("score".to_string(), vec![]),
+        ]);
+    }
+}
@@ -0,0 +1,466 @@
+use std::error::Error;
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use ollama_rs::coordinator::Coordinator;
+use ollama_rs::generation::chat::ChatMessage;
+use ollama_rs::models::ModelOptions;
+use serde::{Deserialize, Serialize};
+
+use crate::base_structs::{NestedConfig, OnDelete, Relation, Row};
+use crate::cli::Component;
+use crate::codegen::is_ident;
+use crate::gen_sql::check_sql;
+use crate::llm_provider::LlmConfig;
+use crate::schema::{parse_rows, Col};
+use crate::schema_diff::migration_sql;
+
+/// Where an agent run's tool calls are logged in the project.
+pub const AGENT_LOG: &str = "agent-log.jsonl";
+
+const AGENT_PROMPT: &str = "You assemble the database and REST API of a service from a brief by calling tools.
+Call add_table for every table the brief needs, add_column for each of its columns and add_relation for each foreign key.
+Every table gets a UUID id primary key by itself, never add an id column.
+Column types are postgres types: TEXT, VARCHAR(n), INTEGER, BIGINT, BOOL, REAL, DOUBLE PRECISION, DATE, TIMESTAMPTZ, UUID, JSONB.
+Every table gets create, list, get, update and delete endpoints by itself.
+Call add_endpoint when the brief lists the rows of one table that belong to a row of another, such as a user's posts.
+Call enable_minio only when the brief involves uploading files, and generate_tests only when it asks for tests.
+A tool answers 'error: ...' when a call is wrong, fix the call and try again.
+When the service is complete, reply with a one paragraph summary of it.";
+
+/// One generator operation. The log of these is the whole outcome of an
+/// agent run, so replaying it rebuilds the project without the model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "tool", rename_all = "snake_case")]
+pub enum Step {
+    AddTable { name: String },
+    AddColumn {
+        table: String,
+        name: String,
+        sql_type: String,
+        not_null: bool,
+        unique: bool,
+        default: Option<String>,
+    },
+    AddRelation { table: String, column: String, ref_table: String, on_delete: Option<String> },
+    AddEndpoint { table: String, parent: String },
+    EnableMinio,
+    GenerateTests,
+}
+
+/// A line of `agent-log.jsonl`: the call and what the tool answered.
+#[derive(Debug, Serialize, Deserialize)]
+struct LoggedCall {
+    #[serde(flatten)]
+    step: Step,
+    result: String,
+}
+
+/// The project the steps so far describe.
+#[derive(Debug, Clone, Default)]
+pub struct Blueprint {
+    pub rows: Vec<Row>,
+    /// The nested lists add_endpoint asked for, as `parent.table`.
+    pub nested_lists: Vec<String>,
+    pub minio: bool,
+    pub tests: bool,
+}
+
+impl Blueprint {
+    /// Applies `step` if the schema it leads to passes the same checks as
+    /// LLM written SQL. The error says what to fix.
+    pub fn apply(&mut self, step: &Step) -> Result<String, String> {
+        let mut next = self.clone();
+        let message = next.apply_unchecked(step)?;
+        let problems = check_sql(&next.sql(), &[]);
+        if !problems.is_empty() {
+            return Err(problems.join("; "));
+        }
+        *self = next;
+        Ok(message)
+    }
+
+    fn apply_unchecked(&mut self, step: &Step) -> Result<String, String> {
+        match step {
+            Step::AddTable { name } => {
+                check_name(name)?;
+                if self.row(name).is_ok() {
+                    return Err(format!("table {} already exists", name));
+                }
+                self.rows.extend(parse(&format!(
+                    "CREATE TABLE IF NOT EXISTS {} (id UUID PRIMARY KEY DEFAULT gen_random_uuid());",
+                    name
+                ))?);
+                Ok(format!("added table {}", name))
+            }
+            Step::AddColumn { table, name, sql_type, not_null, unique, default } => {
+                check_name(name)?;
+                if self.row(table)?.cols.iter().any(|col| col.name == *name) {
+                    return Err(format!("column {}.{} already exists", table, name));
+                }
+                let mut definition = format!("{} {}", name, sql_type);
+                if *not_null {
+                    definition.push_str(" NOT NULL");
+                }
+                if *unique {
+                    definition.push_str(" UNIQUE");
+                }
+                if let Some(default) = default {
+                    definition.push_str(&format!(" DEFAULT {}", default));
+                }
+                let col = parse(&format!("CREATE TABLE t ({});", definition))?
+                    .pop()
+                    .and_then(|mut row| row.cols.pop())
+                    .ok_or_else(|| format!("'{}' is not a column definition", definition))?;
+                self.row_mut(table)?.cols.push(col);
+                Ok(format!("added column {}.{}", table, definition))
+            }
+            Step::AddRelation { table, column, ref_table, on_delete } => {
+                check_name(column)?;
+                let on_delete = match on_delete {
+                    Some(action) => OnDelete::from_sql(&action.to_uppercase())
+                        .ok_or_else(|| format!("'{}' is not an ON DELETE action", action))?,
+                    None => OnDelete::default(),
+                };
+                let ref_key = self.row(ref_table)?.primary_key()
+                    .ok_or_else(|| format!("{} has no primary key", ref_table))?
+                    .clone();
+                let row = self.row_mut(table)?;
+                match row.cols.iter().find(|col| col.name == *column) {
+                    Some(col) if col.cast_type() != ref_key.cast_type() => {
+                        return Err(format!(
+                            "{}.{} is {} but {}.{} is {}",
+                            table, column, col.col_type, ref_table, ref_key.name, ref_key.col_type
+                        ));
+                    }
+                    Some(_) => {}
+                    None => row.cols.push(Col {
+                        name: column.clone(),
+                        auto_gen: false,
+                        not_null: false,
+                        primary_key: false,
+                        unique: false,
+                        unique_name: None,
+                        default: None,
+                        ..ref_key.clone()
+                    }),
+                }
+                if row.relation_for(column).is_some() {
+                    return Err(format!("{}.{} already references a table", table, column));
+                }
+                row.relations.push(Relation {
+                    name: None,
+                    columns: vec![column.clone()],
+                    ref_table: ref_table.clone(),
+                    ref_columns: vec![ref_key.name],
+                    on_delete,
+                });
+                Ok(format!("{}.{} now references {}", table, column, ref_table))
+            }
+            // a table pointing at its parent twice is listed once per column
+            Step::AddEndpoint { table, parent } => {
+                self.row(table)?;
+                let children = self.row(parent)?.has_many(&self.rows);
+                let paths: Vec<String> = children.iter()
+                    .filter(|child| child.table == *table)
+                    .map(|child| match children.iter().filter(|c| c.table == child.table).count() {
+                        1 => format!("GET /{}/:id/{}", parent, table),
+                        _ => format!("GET /{}/:id/{}_by_{}", parent, table, child.columns[0]),
+                    })
+                    .collect();
+                if paths.is_empty() {
+                    return Err(format!("{} doesn't reference {}, call add_relation first", table, parent));
+                }
+                let list = format!("{}.{}", parent, table);
+                if self.nested_lists.contains(&list) {
+                    return Err(format!("{} are already listed under {}", table, parent));
+                }
+                self.nested_lists.push(list);
+                let verb = if paths.len() == 1 { "lists" } else { "list" };
+                Ok(format!("{} {} the {} of one {} row", paths.join(" and "), verb, table, parent))
+            }
+            Step::EnableMinio => {
+                self.minio = true;
+                Ok("file uploads through MinIO are enabled".to_string())
+            }
+            Step::GenerateTests => {
+                self.tests = true;
+                Ok("integration tests will be generated".to_string())
+            }
+        }
+    }
+
+    fn row(&self, name: &str) -> Result<&Row, String> {
+        self.rows.iter().find(|row| row.name == name).ok_or_else(|| format!("there is no table {}", name))
+    }
+
+    fn row_mut(&mut self, name: &str) -> Result<&mut Row, String> {
+        self.rows.iter_mut().find(|row| row.name == name).ok_or_else(|| format!("there is no table {}", name))
+    }
+
+    /// The migration creating every table.
+    pub fn sql(&self) -> String {
+        migration_sql(&[], &self.rows)
+    }
+
+    /// The nested lists to serve, only the ones add_endpoint asked for.
+    pub fn nested(&self) -> NestedConfig {
+        NestedConfig { lists: Some(self.nested_lists.clone()) }
+    }
+
+    /// The components the steps turned on.
+    pub fn components(&self) -> Vec<Component> {
+        let mut components = Vec::new();
+        if self.minio {
+            components.push(Component::Minio);
+        }
+        if self.tests {
+            components.push(Component::Tests);
+        }
+        components
+    }
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    let valid = name.chars().next().is_some_and(|c| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    match (valid, is_ident(name)) {
+        (true, true) => Ok(()),
+        (false, _) => Err(format!("'{}' is not a lower snake case name", name)),
+        (true, false) => Err(format!("'{}' can't be named in the generated rust", name)),
+    }
+}
+
+fn parse(sql: &str) -> Result<Vec<Row>, String> {
+    parse_rows(sql).map_err(|e| e.to_string())
+}
+
+/// A blueprint and the calls that built it.
+#[derive(Debug, Default)]
+pub struct Session {
+    pub blueprint: Blueprint,
+    log: Vec<LoggedCall>,
+}
+
+impl Session {
+    /// Runs one tool call and logs it, returning the answer for the model.
+    fn call(&mut self, step: Step) -> String {
+        let result = match self.blueprint.apply(&step) {
+            Ok(message) => format!("ok: {}", message),
+            Err(message) => format!("error: {}", message),
+        };
+        println!("{} -> {}", serde_json::to_string(&step).unwrap_or_default(), result);
+        self.log.push(LoggedCall { step, result: result.clone() });
+        result
+    }
+
+    /// Writes the calls as JSON lines, the format `replay` reads.
+    pub fn save_log(&self, path: &Path) -> Result<(), io::Error> {
+        let mut lines = String::new();
+        for call in &self.log {
+            lines.push_str(&serde_json::to_string(call).map_err(io::Error::other)?);
+            lines.push('\n');
+        }
+        crate::vfs::write(path, lines)
+    }
+}
+
+/// The session of the running agent. `#[ollama_rs::function]` tools are
+/// unit structs, so they reach it here.
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Session>> {
+    SESSION.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Runs a tool's step in the agent's session.
+fn call(step: Step) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut session = lock();
+    let session = session.as_mut().ok_or("no agent is running")?;
+    Ok(session.call(step))
+}
+
+/// Add a table. It gets a UUID id primary key, add its other columns with add_column.
+/// * name - The table name in lower snake case, e.g. blog_posts.
+#[ollama_rs::function]
+async fn add_table(name: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+    call(Step::AddTable { name })
+}
+
+/// Add a column to a table made with add_table.
+/// * table - The table to add the column to.
+/// * name - The column name in lower snake case.
+/// * sql_type - The postgres type, e.g. TEXT, VARCHAR(255), INTEGER, BOOL or TIMESTAMPTZ.
+/// * not_null - Whether every row must have a value.
+/// * unique - Whether no two rows may have the same value.
+/// * default - A default SQL expression such as now() or false, if any.
+#[ollama_rs::function]
+async fn add_column(
+    table: String,
+    name: String,
+    sql_type: String,
+    not_null: bool,
+    unique: bool,
+    default: Option<String>,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    call(Step::AddColumn { table, name, sql_type, not_null, unique, default })
+}
+
+/// Make a column reference another table's id, adding the column if it does not exist yet.
+/// * table - The table holding the reference, e.g. posts.
+/// * column - The referencing column, e.g. user_id.
+/// * ref_table - The referenced table, e.g. users.
+/// * on_delete - What happens to the row when the referenced row is deleted: CASCADE, SET NULL or RESTRICT. Leave out for NO ACTION.
+#[ollama_rs::function]
+async fn add_relation(
+    table: String,
+    column: String,
+    ref_table: String,
+    on_delete: Option<String>,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    call(Step::AddRelation { table, column, ref_table, on_delete })
+}
+
+/// Serve the rows of a table that reference one row of its parent, e.g. GET /users/:id/posts. The table needs a relation to the parent.
+/// * table - The table whose rows are listed, e.g. posts.
+/// * parent - The table they belong to, e.g. users.
+#[ollama_rs::function]
+async fn add_endpoint(table: String, parent: String) -> Result<String, Box<dyn Error + Send + Sync>> {
+    call(Step::AddEndpoint { table, parent })
+}
+
+/// Add file uploads: a signed url endpoint backed by MinIO.
+#[ollama_rs::function]
+async fn enable_minio() -> Result<String, Box<dyn Error + Send + Sync>> {
+    call(Step::EnableMinio)
+}
+
+/// Generate integration tests for every table's endpoints.
+#[ollama_rs::function]
+async fn generate_tests() -> Result<String, Box<dyn Error + Send + Sync>> {
+    call(Step::GenerateTests)
+}
+
+/// Has the LLM assemble a project from `brief` by calling the generator tools.
+pub async fn run(brief: &str, llm: &LlmConfig) -> Result<Session, Box<dyn Error + Send + Sync>> {
+    let (ollama, model) = llm.ollama()?;
+    *lock() = Some(Session::default());
+
+    let mut coordinator = Coordinator::new(ollama, model, vec![ChatMessage::system(AGENT_PROMPT.to_string())])
+        .options(ModelOptions::default().temperature(0.0))
+        .add_tool(add_table)
+        .add_tool(add_column)
+        .add_tool(add_relation)
+        .add_tool(add_endpoint)
+        .add_tool(enable_minio)
+        .add_tool(generate_tests);
+    let reply = coordinator.chat(vec![ChatMessage::user(brief.to_string())]).await;
+
+    let session = lock().take().unwrap_or_default();
+    println!("\n{}\n", reply?.message.content.trim());
+    Ok(session)
+}
+
+/// Runs the calls in an `agent-log.jsonl` again, without the model.
+pub fn replay(path: &Path) -> Result<Session, io::Error> {
+    let contents = crate::vfs::read_to_string(path)?;
+    let mut session = Session::default();
+    for (i, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let logged: LoggedCall = serde_json::from_str(line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), i + 1, e))
+        })?;
+        let result = session.call(logged.step);
+        if result != logged.result {
+            eprintln!("Warning: {}:{} was answered '{}' when it was logged", path.display(), i + 1, logged.result);
+        }
+    }
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::ProjectConfig;
+
+    // what a run answered, one tool call per line
+    const RECORDED: &str = r#"{"tool":"add_table","name":"authors","result":"ok: added table authors"}
+{"tool":"add_column","table":"authors","name":"name","sql_type":"TEXT","not_null":true,"unique":false,"default":null,"result":"ok: added column authors.name TEXT NOT NULL"}
+{"tool":"add_table","name":"books","result":"ok: added table books"}
+{"tool":"add_column","table":"books","name":"id","sql_type":"UUID","not_null":false,"unique":false,"default":null,"result":"error: column books.id already exists"}
+{"tool":"add_column","table":"books","name":"title","sql_type":"VARCHAR(200)","not_null":true,"unique":false,"default":null,"result":"ok: added column books.title VARCHAR(200) NOT NULL"}
+{"tool":"add_relation","table":"books","column":"author_id","ref_table":"authors","on_delete":"CASCADE","result":"ok: books.author_id now references authors"}
+{"tool":"add_endpoint","table":"authors","parent":"books","result":"error: authors doesn't reference books, call add_relation first"}
+{"tool":"add_endpoint","table":"books","parent":"authors","result":"ok: GET /authors/:id/books lists the books of one authors row"}
+{"tool":"enable_minio","result":"ok: file uploads through MinIO are enabled"}
+"#;
+
+    #[test]
+    fn replaying_a_log_rebuilds_the_project() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join(AGENT_LOG);
+        std::fs::write(&log, RECORDED).unwrap();
+
+        let session = replay(&log).unwrap();
+        let blueprint = &session.blueprint;
+        let tables: Vec<&str> = blueprint.rows.iter().map(|row| row.name.as_str()).collect();
+        assert_eq!(tables, ["authors", "books"]);
+        assert_eq!(blueprint.components(), [Component::Minio]);
+        let books = blueprint.row("books").unwrap();
+        assert_eq!(books.relation_for("author_id").unwrap().ref_table, "authors");
+
+        let project_dir = dir.path().join("library");
+        std::fs::create_dir_all(project_dir.join("src")).unwrap();
+        let config = ProjectConfig {
+            name: Some("library".to_string()),
+            nested: blueprint.nested(),
+            ..ProjectConfig::default()
+        };
+        let generated = crate::generate_agent_project(&project_dir, &config, &blueprint.components(), &session).unwrap();
+        assert_eq!(generated.tables, ["authors", "books"]);
+
+        for file in [
+            "auto-rust.toml",
+            "migrations/0001_agent.sql",
+            "src/models/authors.rs",
+            "src/handlers/books.rs",
+            "src/data/books.rs",
+            "src/handlers/minio.rs",
+        ] {
+            assert!(project_dir.join(file).is_file(), "{} was not generated", file);
+        }
+        assert!(!project_dir.join("tests").exists());
+        // the log is written back as it was recorded
+        assert_eq!(std::fs::read_to_string(project_dir.join(AGENT_LOG)).unwrap(), RECORDED);
+        let migration = std::fs::read_to_string(project_dir.join("migrations/0001_agent.sql")).unwrap();
+        assert!(migration.contains("REFERENCES authors"), "{}", migration);
+        let handlers = std::fs::read_to_string(project_dir.join("src/handlers/authors.rs")).unwrap();
+        assert!(handlers.contains("get_authors_books"), "{}", handlers);
+        // regenerating keeps serving only the lists the agent asked for
+        let saved = std::fs::read_to_string(project_dir.join("auto-rust.toml")).unwrap();
+        assert!(saved.contains("[nested]\nlists = [\"authors.books\"]"), "{}", saved);
+    }
+
+    #[test]
+    fn an_endpoint_names_the_column_when_a_table_references_its_parent_twice() {
+        let mut blueprint = Blueprint::default();
+        for step in [
+            Step::AddTable { name: "users".to_string() },
+            Step::AddTable { name: "transfers".to_string() },
+            Step::AddRelation { table: "transfers".to_string(), column: "sender_id".to_string(), ref_table: "users".to_string(), on_delete: None },
+            Step::AddRelation { table: "transfers".to_string(), column: "receiver_id".to_string(), ref_table: "users".to_string(), on_delete: None },
+        ] {
+            blueprint.apply(&step).unwrap();
+        }
+        let endpoint = Step::AddEndpoint { table: "transfers".to_string(), parent: "users".to_string() };
+        assert_eq!(
+            blueprint.apply(&endpoint).unwrap(),
+            "GET /users/:id/transfers_by_sender_id and GET /users/:id/transfers_by_receiver_id list the transfers of one users row"
+        );
+        assert_eq!(blueprint.apply(&endpoint).unwrap_err(), "transfers are already listed under users");
+        assert_eq!(blueprint.nested().lists.unwrap(), ["users.transfers"]);
+        let unknown = Step::AddEndpoint { table: "transfers".to_string(), parent: "accounts".to_string() };
+        assert_eq!(blueprint.apply(&unknown).unwrap_err(), "there is no table accounts");
+    }
+}
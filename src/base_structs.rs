@@ -1,10 +1,306 @@
 use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::openapi::Operation;
 use crate::schema::Col;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Row {
     pub name: String,
     pub cols: Vec<Col>,
+    /// Foreign keys from this table to other tables (the "many" side).
+    pub relations: Vec<Relation>,
+    /// CHECK constraint expressions as written, column and table level alike.
+    pub checks: Vec<String>,
+    /// The primary key constraint's name when it was given one, postgres
+    /// calls an unnamed one `{table}_pkey`.
+    pub primary_key_name: Option<String>,
+    /// The column that marks a row soft deleted, if the table has it.
+    pub soft_delete_column: String,
+    /// The tables listed under this one's rows, e.g. `posts` for
+    /// `/users/:id/posts`. `None` lists every table that references it.
+    pub nested_lists: Option<Vec<String>>,
+}
+
+/// What postgres does to the referencing rows when the referenced row is deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnDelete {
+    #[default]
+    NoAction,
+    Restrict,
+    Cascade,
+    SetNull,
+    SetDefault,
+}
+
+impl OnDelete {
+    pub fn from_sql(action: &str) -> Option<OnDelete> {
+        match action.to_uppercase().as_str() {
+            "NO ACTION" => Some(OnDelete::NoAction),
+            "RESTRICT" => Some(OnDelete::Restrict),
+            "CASCADE" => Some(OnDelete::Cascade),
+            "SET NULL" => Some(OnDelete::SetNull),
+            "SET DEFAULT" => Some(OnDelete::SetDefault),
+            _ => None,
+        }
+    }
+
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            OnDelete::NoAction => "NO ACTION",
+            OnDelete::Restrict => "RESTRICT",
+            OnDelete::Cascade => "CASCADE",
+            OnDelete::SetNull => "SET NULL",
+            OnDelete::SetDefault => "SET DEFAULT",
+        }
+    }
+}
+
+/// A foreign key, from an inline `REFERENCES` or a table level `FOREIGN KEY`.
+#[derive(Debug, Clone)]
+pub struct Relation {
+    /// The constraint's name when it was given one, see `constraint_name`.
+    pub name: Option<String>,
+    /// Columns on this table holding the key.
+    pub columns: Vec<String>,
+    pub ref_table: String,
+    /// Columns on the referenced table, the referenced primary key when
+    /// the DDL leaves them out.
+    pub ref_columns: Vec<String>,
+    pub on_delete: OnDelete,
+}
+
+/// Two keys are the same key whatever they are called, a database
+/// names the ones its migrations left unnamed.
+impl PartialEq for Relation {
+    fn eq(&self, other: &Relation) -> bool {
+        self.columns == other.columns
+            && self.ref_table == other.ref_table
+            && self.ref_columns == other.ref_columns
+            && self.on_delete == other.on_delete
+    }
+}
+
+impl Relation {
+    /// The name of the key's constraint on `table`: the one it was given,
+    /// or the one postgres gives an unnamed key, e.g. `runs_user_id_fkey`.
+    pub fn constraint_name(&self, table: &str) -> String {
+        self.name.clone().unwrap_or_else(|| format!("{}_{}_fkey", table, self.columns.join("_")))
+    }
+}
+
+/// The reverse of a `Relation`, seen from the referenced table: one row of
+/// `parent` has many rows in `table`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HasMany {
+    pub table: String,
+    /// Columns on `table` that point back at the parent.
+    pub columns: Vec<String>,
+    /// Columns on the parent that are referenced.
+    pub ref_columns: Vec<String>,
+    pub on_delete: OnDelete,
+}
+
+/// Tables with this column are soft deleted: DELETE stamps the column and
+/// reads and updates skip rows where it is set.
+pub const SOFT_DELETE_COLUMN: &str = "deleted_at";
+
+/// Columns that record when a row was created. With a default they are
+/// set once, on insert, and PUT and PATCH leave them alone.
+const CREATION_COLUMNS: &[&str] = &["created_at", "created_on", "inserted_at"];
+
+/// Whether `col` is a creation timestamp that no update may change.
+pub fn is_creation_stamp(col: &Col) -> bool {
+    col.auto_gen && CREATION_COLUMNS.contains(&col.name.as_str()) && is_time(col)
+}
+
+/// Whether `col` holds a point in time, a timestamp or a date.
+fn is_time(col: &Col) -> bool {
+    !col.array && matches!(col.col_type.as_str(), "TIMESTAMPTZ" | "TIMESTAMP" | "DATE")
+}
+
+/// What DELETE sets a soft delete column to: the time for timestamp and
+/// date columns and `true` for boolean flags. Columns of any other type
+/// can't mark a row deleted.
+pub fn deleted_value(col: &Col) -> Option<&'static str> {
+    match col.col_type.as_str() {
+        _ if col.array => None,
+        _ if is_time(col) => Some("now()"),
+        "BOOL" => Some("true"),
+        _ => None,
+    }
+}
+
+/// `[soft_delete]` in auto-rust.toml.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoftDeleteConfig {
+    /// The column that marks a row deleted, `deleted_at` by default. A
+    /// timestamp or date gets the time of the delete, a boolean `true`.
+    pub column: Option<String>,
+}
+
+impl SoftDeleteConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == SoftDeleteConfig::default()
+    }
+
+    pub fn column(&self) -> &str {
+        self.column.as_deref().unwrap_or(SOFT_DELETE_COLUMN)
+    }
+}
+
+/// `[nested]` in auto-rust.toml.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NestedConfig {
+    /// The nested list routes to serve as `parent.table`, e.g. `users.posts`
+    /// for `/users/:id/posts`. Every foreign key gets one when this is left out.
+    pub lists: Option<Vec<String>>,
+}
+
+impl NestedConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == NestedConfig::default()
+    }
+
+    /// The tables to list under `parent`'s rows, `None` for all of them.
+    pub fn lists_of(&self, parent: &str) -> Option<Vec<String>> {
+        let lists = self.lists.as_ref()?;
+        Some(lists.iter()
+            .filter_map(|list| list.strip_prefix(parent)?.strip_prefix('.'))
+            .map(String::from)
+            .collect())
+    }
+}
+
+/// A column with this name holds password hashes, which are never sent
+/// back in responses.
+pub const PASSWORD_HASH_COLUMN: &str = "password_hash";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpMethod {
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+impl HttpMethod {
+    /// Name of the `axum::routing` function for this method.
+    pub fn routing_fn(&self) -> &'static str {
+        match self {
+            HttpMethod::Get => "get",
+            HttpMethod::Post => "post",
+            HttpMethod::Put => "put",
+            HttpMethod::Patch => "patch",
+            HttpMethod::Delete => "delete",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Delete => "DELETE",
+        }
+    }
+}
+
+/// A generated handler and the route it is served on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub method: HttpMethod,
+    /// Axum route path, e.g. `/delete_users/:user_id`.
+    pub path: String,
+    pub handler: String,
+    /// Needs an access token, when the service has auth.
+    pub protected: bool,
+    /// How the handler is described in `openapi.json`.
+    pub operation: Operation,
+}
+
+impl Endpoint {
+    /// An endpoint served at `/{handler}`, the way most generated handlers are.
+    pub fn new(method: HttpMethod, handler: &str) -> Endpoint {
+        Endpoint {
+            method,
+            path: format!("/{}", handler),
+            handler: handler.to_string(),
+            protected: false,
+            operation: Operation::default(),
+        }
+    }
+
+    /// An endpoint served at `/{handler}/:{param}`.
+    pub fn with_path_param(method: HttpMethod, handler: &str, param: &str) -> Endpoint {
+        Endpoint {
+            method,
+            path: format!("/{}/:{}", handler, param),
+            handler: handler.to_string(),
+            protected: false,
+            operation: Operation::default(),
+        }
+    }
+
+    pub fn with_operation(self, operation: Operation) -> Endpoint {
+        Endpoint { operation, ..self }
+    }
+}
+
+impl Row {
+    pub fn primary_key(&self) -> Option<&Col> {
+        self.cols.iter().find(|col| col.primary_key)
+    }
+
+    /// The name of the primary key constraint, the one postgres gives an
+    /// unnamed key unless it was given another.
+    pub fn primary_key_constraint(&self) -> String {
+        self.primary_key_name.clone().unwrap_or_else(|| format!("{}_pkey", self.name))
+    }
+
+    /// The soft delete column, when the table has one.
+    pub fn soft_delete_col(&self) -> Option<&Col> {
+        self.cols.iter().find(|col| col.name == self.soft_delete_column)
+    }
+
+    /// The condition rows that haven't been soft deleted meet, with the
+    /// column qualified by `qualifier` when given, e.g. `runs.deleted_at IS NULL`.
+    /// A boolean flag that is NULL counts as not deleted.
+    pub fn live_condition(&self, qualifier: Option<&str>) -> Option<String> {
+        let col = self.soft_delete_col()?;
+        let column = match qualifier {
+            Some(qualifier) => format!("{}.{}", qualifier, col.name),
+            None => col.name.clone(),
+        };
+        match col.col_type.as_str() {
+            "BOOL" => Some(format!("{} IS NOT TRUE", column)),
+            _ => Some(format!("{} IS NULL", column)),
+        }
+    }
+
+    /// The relation that `col` takes part in, if it is a foreign key column.
+    pub fn relation_for(&self, col: &str) -> Option<&Relation> {
+        self.relations.iter().find(|rel| rel.columns.iter().any(|c| c == col))
+    }
+
+    /// Every table in `rows` with a foreign key pointing at this one.
+    pub fn has_many(&self, rows: &[Row]) -> Vec<HasMany> {
+        rows.iter()
+            .flat_map(|row| {
+                row.relations.iter()
+                    .filter(|rel| rel.ref_table == self.name)
+                    .map(|rel| HasMany {
+                        table: row.name.clone(),
+                        columns: rel.columns.clone(),
+                        ref_columns: rel.ref_columns.clone(),
+                        on_delete: rel.on_delete,
+                    })
+            })
+            .collect()
+    }
 }
 
 // Helper function to insert multiple PostgreSQL types for a single Rust type
@@ -18,13 +314,13 @@ pub fn create_type_map() -> HashMap<String, String> {
     let mut type_map = HashMap::new();
 
     insert_multiple(&mut type_map, "bool", &["BOOL"]);
-    insert_multiple(&mut type_map, "i8", &["CHAR"]);
+    insert_multiple(&mut type_map, "i8", &["\"char\""]);
     insert_multiple(&mut type_map, "i16", &["SMALLINT", "SMALLSERIAL", "INT2"]);
     insert_multiple(&mut type_map, "i32", &["INT", "SERIAL", "INT4", "INTEGER"]);
     insert_multiple(&mut type_map, "i64", &["BIGINT", "BIGSERIAL", "INT8"]);
     insert_multiple(&mut type_map, "f32", &["REAL", "FLOAT4"]);
     insert_multiple(&mut type_map, "f64", &["DOUBLE PRECISION", "FLOAT8"]);
-    insert_multiple(&mut type_map, "String", &["VARCHAR", "CHAR(N)", "TEXT", "NAME", "CITEXT"]);
+    insert_multiple(&mut type_map, "String", &["VARCHAR", "CHAR", "BPCHAR", "TEXT", "NAME", "CITEXT"]);
     insert_multiple(&mut type_map, "Vec<u8>", &["BYTEA"]);
     insert_multiple(&mut type_map, "()", &["VOID"]);
     // insert_multiple(&mut type_map, "PgInterval", &["INTERVAL"]);
@@ -43,7 +339,7 @@ pub fn create_type_map() -> HashMap<String, String> {
     // insert_multiple(&mut type_map, "PgHstore", &["HSTORE"]);
 
     // Add the new pairs
-    // type_map.insert("NUMERIC".to_string(), "bigdecimal::Decimal".to_string());
+    type_map.insert("NUMERIC".to_string(), "rust_decimal::Decimal".to_string());
     type_map.insert("TIMESTAMPTZ".to_string(), "chrono::DateTime<Utc>".to_string());
     type_map.insert("TIMESTAMP".to_string(), "chrono::NaiveDateTime".to_string());
     type_map.insert("DATE".to_string(), "chrono::NaiveDate".to_string());
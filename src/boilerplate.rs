@@ -1,124 +1,521 @@
-use std::fs::OpenOptions;
-use std::io::{self, Write};
+use std::io;
 
-pub fn add_top_boilerplate(file_path: &std::path::Path) -> Result<(), io::Error> {
-    // Ensure parent directories exist
-    if let Some(parent) = file_path.parent() {
-        std::fs::create_dir_all(parent)?;
-    }
-    
-    let mut file = OpenOptions::new()
-        .write(true) // Enable writing to the file.
-        .create(true) // Create the file if it doesn't exist.
-        .truncate(true) // Clear the file if it exists
-        .open(file_path)
-        .map_err(|e| {
-            eprintln!("Error opening file {}: {}", file_path.display(), e);
-            e
-        })?;
-    let top_boiler = r###"
-use axum::{                                                                                                                                                                      
-    extract::{self, Path, Query},  
-    routing::{get, post},                                                                                                                                                        
-    Json, Router,                        
-};       
-use minio_rsc::{Minio, provider::StaticProvider, client::PresignedArgs};
-use serde::{Deserialize, Serialize};                                                                                                                                                          
-use serde_json::{json, Value};                                                                                                                                                  
-use sqlx::PgPool;                                                                                                                                                               
-use sqlx::{postgres::PgPoolOptions, prelude::FromRow};                                                                                                                           
-use std::env;                                                                                                                                                                    
-use std::net::SocketAddr;                                                                                                                                                        
-use std::result::Result;                                                                                                                                                         
-use std::sync::Arc;                                                                                                                                                              
-use axum::http::StatusCode;                  
-use sqlx::types::chrono::Utc; 
-use std::collections::HashMap;
-use tower_http::cors::{AllowOrigin, CorsLayer};
-use axum::http::Method;
-use reqwest;
-
-
-"###;
-    file.write_all(top_boiler.as_bytes())?;
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
 
-    Ok(())
-} 
+use crate::add_pagination::DEFAULT_MAX_PAGE_SIZE;
+use crate::base_structs::Endpoint;
+use crate::codegen::{ident, write_mod_file, write_module};
+use crate::{manifest, vfs};
 
-pub fn add_axum_end(funcs: Vec<String>, file_path: &std::path::Path) -> Result<(), io::Error> {
-    // Ensure parent directories exist
-    if let Some(parent) = file_path.parent() {
-        std::fs::create_dir_all(parent)?;
+/// The layer directories under the generated `src/`, one file per table in each.
+const LAYER_DIRS: &[&str] = &["models", "handlers", "data"];
+
+/// Removes the model, handler and data modules of `tables`, e.g. ones a
+/// migration dropped. Modules the user edited are kept.
+pub fn remove_table_modules(src_dir: &std::path::Path, tables: &[String]) -> Result<(), io::Error> {
+    for table in tables {
+        for dir in LAYER_DIRS {
+            let file = src_dir.join(dir).join(format!("{}.rs", table));
+            if vfs::exists(&file) {
+                manifest::remove_generated(&file)?;
+            }
+        }
     }
-    
-    // Create tests directory structure
-    let project_root = file_path.parent().unwrap().parent().unwrap();
-    crate::add_tests::create_test_directory_structure(project_root)?;
-    crate::add_tests::add_test_dependencies_to_cargo_toml(project_root)?;
-    
-    // TODO: Pass table names to generate database-specific test utilities
-    // crate::add_tests::generate_database_test_utilities(project_root, &table_names)?;
-    // crate::add_tests::generate_crud_tests(project_root, &table_names)?;
-    // crate::add_tests::generate_query_param_tests(project_root, &table_names)?;
-    // crate::add_tests::generate_error_handling_tests(project_root, &table_names)?;
-    
-    let mut file = OpenOptions::new()
-        .write(true) // Enable writing to the file.
-        .create(true) // Create the file if it doesn't exist.
-        .append(true) // Set the append mode.
-        .open(file_path)
-        .map_err(|e| {
-            eprintln!("Error opening file {}: {}", file_path.display(), e);
-            e
-        })?;
-    let mut routs: String = funcs.iter().map(|func| {
-        let http_method = if func.starts_with("get") { "get" } else { "post" };
-        format!("\t.route(\"/{func}\", {http_method}({func}))\n").to_string()
-    }).collect::<String>();
-    routs.push_str("\t.route(\"/signed-urls/:video_path\", get(get_signed_url))\n");
-    let ending = format!(r###"
-async fn health() -> String {{"healthy".to_string() }}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {{
-    let db_url = env::var("DATABASE_URL")
-     .unwrap_or_else(|_| "postgres://dbuser:p@localhost:1111/data".to_string());
-    let pool = PgPoolOptions::new()
-        .max_connections(100)
-        .connect(&db_url)
-        .await?;
-
-    let migrate = sqlx::migrate!("./migrations").run(&pool).await;
-
-    match migrate {{
-        Ok(_) => println!("Migrations applied successfully."),
-        Err(e) => eprintln!("Error applying migrations: {{}}", e),
-    }};
-
-    let app = Router::new()
-    .route("/health", get(health))
-    {routs}
-    .route("/python", get(python))
-    .layer(
-        CorsLayer::new()
-            .allow_origin(AllowOrigin::list(vec![
-                "http://localhost:3000".parse().unwrap(),
-                "https://example.com".parse().unwrap(),
-            ]))
-            .allow_methods([Method::GET, Method::POST])
-            .allow_headers(tower_http::cors::Any)
-    )
-        .with_state(pool);
-
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8081").await.unwrap();
-
-    axum::serve(listener, app).await.unwrap();
     Ok(())
-}}
+}
 
+/// Starts the generated service's `src/`: clears the generated modules out
+/// of the layer directories from an earlier run, so tables that were
+/// dropped don't linger, and writes `config.rs` and `error.rs`. Modules the user edited or
+/// added stay. With `clear_layers` off the table modules from the earlier
+/// run are kept, for regenerating only some of them.
+pub fn add_top_boilerplate(src_dir: &std::path::Path, clear_layers: bool) -> Result<(), io::Error> {
+    for dir in LAYER_DIRS.iter().filter(|_| clear_layers) {
+        let dir = src_dir.join(dir);
+        if !vfs::exists(&dir) {
+            continue;
+        }
+        for file in vfs::read_dir(&dir)?.into_iter().filter(|file| file.extension().is_some_and(|ext| ext == "rs")) {
+            manifest::remove_generated(&file).map_err(|e| {
+                eprintln!("Error clearing {}: {}", file.display(), e);
+                e
+            })?;
+        }
+    }
 
-"###);  //https://tidelabs.github.io/tidechain/tower_http/cors/struct.CorsLayer.html (may help with auth) 
-    
-    file.write_all(ending.as_bytes())?; // comment for testing 
-    Ok(())
+    let default_max_page_size = Literal::i64_unsuffixed(DEFAULT_MAX_PAGE_SIZE);
+    let config = quote! {
+        /// Page size used when MAX_PAGE_SIZE is not set.
+        pub const DEFAULT_MAX_PAGE_SIZE: i64 = #default_max_page_size;
+
+        /// Settings read from the environment at startup.
+        pub struct Config {
+            pub database_url: String,
+            /// Address the server listens on, e.g. `0.0.0.0:8081`.
+            pub bind_address: String,
+        }
+
+        impl Config {
+            pub fn from_env() -> Config {
+                Config {
+                    database_url: env::var("DATABASE_URL")
+                        .unwrap_or_else(|_| "postgres://dbuser:p@localhost:1111/data".to_string()),
+                    bind_address: env::var("BIND_ADDRESS").unwrap_or_else(|_| "0.0.0.0:8081".to_string()),
+                }
+            }
+        }
+
+        /// Largest page a list endpoint will return, set with the MAX_PAGE_SIZE env var.
+        pub fn max_page_size() -> i64 {
+            env::var("MAX_PAGE_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|v: &i64| *v > 0)
+                .unwrap_or(DEFAULT_MAX_PAGE_SIZE)
+        }
+    };
+
+    write_module(&src_dir.join("config.rs"), config)?;
+    add_error_module(&src_dir.join("error.rs"))
+}
+
+/// Writes `error.rs`: the `AppError` every generated handler fails with,
+/// answered as an RFC 7807 problem+json body. Database errors the caller
+/// can fix get a 4xx status, anything else is logged and reported as a 500
+/// without the database's message. Payloads failing `validate()` list the
+/// bad fields in an extra `errors` member. The `Path`, `Query` and `Json`
+/// extractors the handlers use are axum's with their rejections turned into
+/// `AppError`s, so a malformed id, query string or body is a problem too.
+fn add_error_module(file_path: &std::path::Path) -> Result<(), io::Error> {
+    write_module(file_path, quote! {
+        use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+        use axum::extract::{FromRequest, FromRequestParts};
+        use axum::response::Response;
+        use sqlx::error::ErrorKind;
+
+        /// `axum::extract::Path`, rejecting with an `AppError`.
+        #[derive(FromRequestParts)]
+        #[from_request(via(axum::extract::Path), rejection(AppError))]
+        pub struct Path<T>(pub T);
+
+        /// `axum::extract::Query`, rejecting with an `AppError`.
+        #[derive(FromRequestParts)]
+        #[from_request(via(axum::extract::Query), rejection(AppError))]
+        pub struct Query<T>(pub T);
+
+        impl<T> std::ops::Deref for Query<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                &self.0
+            }
+        }
+
+        /// `axum::Json`, rejecting with an `AppError`. Responds like it too.
+        #[derive(FromRequest)]
+        #[from_request(via(axum::Json), rejection(AppError))]
+        pub struct Json<T>(pub T);
+
+        impl<T: Serialize> IntoResponse for Json<T> {
+            fn into_response(self) -> Response {
+                axum::Json(self.0).into_response()
+            }
+        }
+
+        /// A payload field and the rule it breaks, e.g. `age` "must be at least 0".
+        #[derive(Debug, Serialize)]
+        pub struct FieldError {
+            pub field: String,
+            pub message: String,
+        }
+
+        impl FieldError {
+            pub fn new(field: impl Into<String>, message: impl Into<String>) -> FieldError {
+                FieldError { field: field.into(), message: message.into() }
+            }
+        }
+
+        /// Why a request failed. The message is the problem's `detail`.
+        #[derive(Debug)]
+        pub enum AppError {
+            /// 400: the request can't be carried out as sent.
+            BadRequest(String),
+            /// 401: there is no valid access token.
+            Unauthorized(String),
+            /// 403: the caller's role may not do this.
+            Forbidden(String),
+            NotFound(String),
+            /// 409: a unique column already has the value.
+            Conflict(String),
+            /// 422: a foreign key points at a row that doesn't exist, or a row
+            /// that others point at can't go.
+            Unprocessable(String),
+            /// 422: payload fields break their columns' rules, listed in `errors`.
+            Validation(Vec<FieldError>),
+            /// The path, query string or body can't be read, with axum's
+            /// status and message, e.g. 400 for an id that isn't a UUID.
+            Rejected(StatusCode, String),
+            /// 500: the message is logged, the caller only learns that something went wrong.
+            Internal(String),
+        }
+
+        impl AppError {
+            pub fn status(&self) -> StatusCode {
+                match self {
+                    AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+                    AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+                    AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+                    AppError::NotFound(_) => StatusCode::NOT_FOUND,
+                    AppError::Conflict(_) => StatusCode::CONFLICT,
+                    AppError::Unprocessable(_) | AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+                    AppError::Rejected(status, _) => *status,
+                    AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                }
+            }
+        }
+
+        impl IntoResponse for AppError {
+            fn into_response(self) -> Response {
+                let status = self.status();
+                let mut errors = None;
+                let detail = match self {
+                    AppError::Internal(message) => {
+                        eprintln!("Internal error: {}", message);
+                        "The server could not complete the request".to_string()
+                    }
+                    AppError::Validation(fields) => {
+                        errors = Some(fields);
+                        "The payload has invalid fields".to_string()
+                    }
+                    AppError::BadRequest(detail)
+                    | AppError::Unauthorized(detail)
+                    | AppError::Forbidden(detail)
+                    | AppError::NotFound(detail)
+                    | AppError::Conflict(detail)
+                    | AppError::Unprocessable(detail)
+                    | AppError::Rejected(_, detail) => detail,
+                };
+                let mut problem = json!({
+                    "type": "about:blank",
+                    "title": status.canonical_reason().unwrap_or("Error"),
+                    "status": status.as_u16(),
+                    "detail": detail,
+                });
+                if let Some(errors) = errors {
+                    problem["errors"] = json!(errors);
+                }
+                (status, [(header::CONTENT_TYPE, "application/problem+json")], problem.to_string()).into_response()
+            }
+        }
+
+        impl From<PathRejection> for AppError {
+            fn from(rejection: PathRejection) -> Self {
+                AppError::Rejected(rejection.status(), rejection.body_text())
+            }
+        }
+
+        impl From<QueryRejection> for AppError {
+            fn from(rejection: QueryRejection) -> Self {
+                AppError::Rejected(rejection.status(), rejection.body_text())
+            }
+        }
+
+        impl From<JsonRejection> for AppError {
+            fn from(rejection: JsonRejection) -> Self {
+                AppError::Rejected(rejection.status(), rejection.body_text())
+            }
+        }
+
+        /// Constraint violations and bad values are the caller's to fix,
+        /// other database errors are a 500. The database's own message and
+        /// constraint names stay in the server's log.
+        impl From<sqlx::Error> for AppError {
+            fn from(e: sqlx::Error) -> Self {
+                let Some(db) = e.as_database_error() else {
+                    return match e {
+                        sqlx::Error::RowNotFound => AppError::NotFound("No matching record".to_string()),
+                        e => AppError::Internal(format!("Database error: {}", e)),
+                    };
+                };
+                let client_error = |error: AppError| {
+                    eprintln!("Rejected by the database: {}", db.message());
+                    error
+                };
+                match db.kind() {
+                    ErrorKind::UniqueViolation => client_error(AppError::Conflict("A record with this value already exists".to_string())),
+                    ErrorKind::ForeignKeyViolation if db.message().starts_with("update or delete") => {
+                        client_error(AppError::Unprocessable("Other records still reference this one".to_string()))
+                    }
+                    ErrorKind::ForeignKeyViolation => client_error(AppError::Unprocessable("A referenced record does not exist".to_string())),
+                    ErrorKind::CheckViolation => client_error(AppError::BadRequest("A value is not allowed".to_string())),
+                    ErrorKind::NotNullViolation => {
+                        let column = db.try_downcast_ref::<sqlx::postgres::PgDatabaseError>().and_then(|e| e.column());
+                        client_error(AppError::BadRequest(format!("{} must not be null", column.unwrap_or("A value"))))
+                    }
+                    // class 22 is bad input, such as text too long for its column
+                    _ if db.code().is_some_and(|code| code.starts_with("22")) => {
+                        client_error(AppError::BadRequest("A value does not fit its column".to_string()))
+                    }
+                    _ => AppError::Internal(format!("Database error: {}", e)),
+                }
+            }
+        }
+    })
+}
+
+/// Finishes the generated service once every handler is written: the
+/// `mod.rs` of each layer, `routes.rs` with a route per endpoint and `main.rs`.
+/// With `auth`, `main.rs` declares the `auth` module and protected endpoints
+/// need an access token. `models` goes into `models/mod.rs`.
+pub fn add_axum_end(endpoints: Vec<Endpoint>, src_dir: &std::path::Path, auth: bool, models: TokenStream) -> Result<(), io::Error> {
+    for dir in LAYER_DIRS {
+        vfs::create_dir_all(src_dir.join(dir))?;
+    }
+    write_mod_file(&src_dir.join("models"), quote! {
+        /// Lets PATCH payloads tell an explicit `null` (Some(None)) apart from a
+        /// missing key (None) for nullable columns.
+        #[allow(dead_code)]
+        pub fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+        where
+            T: Deserialize<'de>,
+            D: serde::Deserializer<'de>,
+        {
+            Option::<T>::deserialize(deserializer).map(Some)
+        }
+
+        #models
+    })?;
+    write_mod_file(&src_dir.join("handlers"), quote! {
+        pub async fn health() -> String { "healthy".to_string() }
+    })?;
+    write_mod_file(&src_dir.join("data"), quote! {})?;
+
+    let route = |endpoint: &&Endpoint| {
+        let path = &endpoint.path;
+        let method = ident(endpoint.method.routing_fn());
+        let handler = ident(&endpoint.handler);
+        quote! { .route(#path, #method(#handler)) }
+    };
+    let (protected, public): (Vec<&Endpoint>, Vec<&Endpoint>) = endpoints.iter().partition(|endpoint| endpoint.protected);
+    let routes = public.iter().map(route);
+    // the token is checked before any protected handler runs
+    let protected_routes = (!protected.is_empty()).then(|| {
+        let routes = protected.iter().map(route);
+        quote! {
+            .merge(
+                Router::new()
+                    #(#routes)*
+                    .route_layer(axum::middleware::from_extractor::<crate::auth::AuthUser>())
+            )
+        }
+    });
+    //https://tidelabs.github.io/tidechain/tower_http/cors/struct.CorsLayer.html (may help with auth)
+    write_module(&src_dir.join("routes.rs"), quote! {
+        use crate::handlers::*;
+
+        /// Every generated endpoint, sharing one connection pool.
+        pub fn router(pool: PgPool) -> Router {
+            Router::new()
+                .route("/health", get(health))
+                #(#routes)*
+                #protected_routes
+                .layer(
+                    CorsLayer::new()
+                        .allow_origin(AllowOrigin::list(vec![
+                            "http://localhost:3000".parse().unwrap(),
+                            "https://example.com".parse().unwrap(),
+                        ]))
+                        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+                        .allow_headers(tower_http::cors::Any)
+                )
+                .with_state(pool)
+        }
+    })?;
+
+    let auth_module = auth.then(|| quote! { mod auth; });
+    write_module(&src_dir.join("main.rs"), quote! {
+        #auth_module
+        mod config;
+        mod data;
+        mod error;
+        mod handlers;
+        mod models;
+        mod routes;
+
+        #[tokio::main]
+        async fn main() -> Result<(), Box<dyn std::error::Error>> {
+            let config = config::Config::from_env();
+            let pool = PgPoolOptions::new()
+                .max_connections(100)
+                .connect(&config.database_url)
+                .await?;
+
+            let migrate = sqlx::migrate!("./migrations").run(&pool).await;
+
+            match migrate {
+                Ok(_) => println!("Migrations applied successfully."),
+                Err(e) => eprintln!("Error applying migrations: {}", e),
+            };
+
+            let app = routes::router(pool);
+
+            let listener = tokio::net::TcpListener::bind(&config.bind_address).await.unwrap();
+
+            axum::serve(listener, app).await.unwrap();
+            Ok(())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+    use syn::visit::{self, Visit};
+
+    /// The generated `error.rs`, which has to parse as Rust.
+    fn error_module() -> syn::File {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("error.rs");
+        add_error_module(&file_path).unwrap();
+        syn::parse_file(&std::fs::read_to_string(&file_path).unwrap()).expect("error.rs parses")
+    }
+
+    /// The function `name` of the impl whose trait, or type without one, is `of`.
+    fn impl_fn<'a>(file: &'a syn::File, of: &str, name: &str) -> &'a syn::ImplItemFn {
+        file.items.iter()
+            .filter_map(|item| match item {
+                syn::Item::Impl(item) => Some(item),
+                _ => None,
+            })
+            .filter(|item| match &item.trait_ {
+                Some((_, path, _)) => path.to_token_stream().to_string() == of,
+                None => item.self_ty.to_token_stream().to_string() == of,
+            })
+            .flat_map(|item| &item.items)
+            .find_map(|item| match item {
+                syn::ImplItem::Fn(f) if f.sig.ident == name => Some(f),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("no {} in impl {}", name, of))
+    }
+
+    /// Every match arm in a function: its pattern and guard, the `AppError`
+    /// variant it builds first and the first string literal in it.
+    #[derive(Default)]
+    struct Arms(Vec<(String, Option<String>, Option<String>)>);
+
+    #[derive(Default)]
+    struct FirstBuilt {
+        variant: Option<String>,
+        detail: Option<String>,
+    }
+
+    impl<'ast> Visit<'ast> for FirstBuilt {
+        fn visit_path(&mut self, path: &'ast syn::Path) {
+            let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+            if let [error, variant] = segments.as_slice()
+                && error == "AppError"
+            {
+                self.variant.get_or_insert(variant.clone());
+            }
+            visit::visit_path(self, path);
+        }
+
+        fn visit_lit_str(&mut self, lit: &'ast syn::LitStr) {
+            self.detail.get_or_insert(lit.value());
+        }
+    }
+
+    impl<'ast> Visit<'ast> for Arms {
+        fn visit_arm(&mut self, arm: &'ast syn::Arm) {
+            let mut pattern = arm.pat.to_token_stream().to_string();
+            if let Some((_, guard)) = &arm.guard {
+                pattern.push_str(&format!(" if {}", guard.to_token_stream()));
+            }
+            let mut built = FirstBuilt::default();
+            built.visit_expr(&arm.body);
+            self.0.push((pattern, built.variant, built.detail));
+            visit::visit_arm(self, arm);
+        }
+    }
+
+    fn arms(f: &syn::ImplItemFn) -> Vec<(String, Option<String>, Option<String>)> {
+        let mut arms = Arms::default();
+        arms.visit_impl_item_fn(f);
+        arms.0
+    }
+
+    #[test]
+    fn database_errors_map_to_client_errors() {
+        let file = error_module();
+        let arms = arms(impl_fn(&file, "From < sqlx :: Error >", "from"));
+        // a guard is matched by how it starts
+        let mapped = |pattern: &str| {
+            let (_, variant, detail) = arms.iter()
+                .find(|(arm, ..)| arm == pattern || (pattern.contains(" if ") && arm.starts_with(pattern)))
+                .unwrap_or_else(|| panic!("{} is not mapped", pattern));
+            (variant.as_deref().unwrap_or("nothing"), detail.as_deref().unwrap_or(""))
+        };
+        assert_eq!(mapped("sqlx :: Error :: RowNotFound"), ("NotFound", "No matching record"));
+        assert_eq!(mapped("ErrorKind :: UniqueViolation"), ("Conflict", "A record with this value already exists"));
+        assert_eq!(
+            mapped("ErrorKind :: ForeignKeyViolation if db . message () . starts_with (\"update or delete\")"),
+            ("Unprocessable", "Other records still reference this one")
+        );
+        assert_eq!(mapped("ErrorKind :: ForeignKeyViolation"), ("Unprocessable", "A referenced record does not exist"));
+        assert_eq!(mapped("ErrorKind :: CheckViolation"), ("BadRequest", "A value is not allowed"));
+        assert_eq!(mapped("ErrorKind :: NotNullViolation").0, "BadRequest");
+        // class 22, bad input such as text too long for its column
+        assert_eq!(mapped("_ if db . code ()"), ("BadRequest", "A value does not fit its column"));
+        assert_eq!(mapped("_").0, "Internal");
+        assert_eq!(arms.last().unwrap().0, "_", "anything else is a 500");
+    }
+
+    #[test]
+    fn every_error_has_its_status() {
+        let file = error_module();
+        let statuses: Vec<(String, String)> = match &impl_fn(&file, "AppError", "status").block.stmts[..] {
+            [syn::Stmt::Expr(syn::Expr::Match(status), None)] => status.arms.iter()
+                .map(|arm| {
+                    let status = match &*arm.body {
+                        syn::Expr::Block(block) => block.block.stmts[0].to_token_stream(),
+                        body => body.to_token_stream(),
+                    };
+                    (arm.pat.to_token_stream().to_string(), status.to_string())
+                })
+                .collect(),
+            other => panic!("status() is not one match: {:?}", other.len()),
+        };
+        assert_eq!(statuses, [
+            ("AppError :: BadRequest (_)", "StatusCode :: BAD_REQUEST"),
+            ("AppError :: Unauthorized (_)", "StatusCode :: UNAUTHORIZED"),
+            ("AppError :: Forbidden (_)", "StatusCode :: FORBIDDEN"),
+            ("AppError :: NotFound (_)", "StatusCode :: NOT_FOUND"),
+            ("AppError :: Conflict (_)", "StatusCode :: CONFLICT"),
+            ("AppError :: Unprocessable (_) | AppError :: Validation (_)", "StatusCode :: UNPROCESSABLE_ENTITY"),
+            ("AppError :: Rejected (status , _)", "* status"),
+            ("AppError :: Internal (_)", "StatusCode :: INTERNAL_SERVER_ERROR"),
+        ].map(|(pattern, status)| (pattern.to_string(), status.to_string())));
+    }
+
+    #[test]
+    fn extractor_rejections_are_app_errors() {
+        let file = error_module();
+        for rejection in ["PathRejection", "QueryRejection", "JsonRejection"] {
+            let mut built = FirstBuilt::default();
+            built.visit_impl_item_fn(impl_fn(&file, &format!("From < {} >", rejection), "from"));
+            assert_eq!(built.variant.as_deref(), Some("Rejected"), "{}", rejection);
+        }
+        let extractors: Vec<String> = file.items.iter()
+            .filter_map(|item| match item {
+                syn::Item::Struct(item) => Some(item),
+                _ => None,
+            })
+            .filter(|item| item.attrs.iter().any(|attr| attr.to_token_stream().to_string().contains("rejection (AppError)")))
+            .map(|item| item.ident.to_string())
+            .collect();
+        assert_eq!(extractors, ["Path", "Query", "Json"]);
+    }
 }
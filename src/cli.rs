@@ -0,0 +1,241 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::add_auth::AuthConfig;
+use crate::base_structs::{NestedConfig, SoftDeleteConfig};
+use crate::llm_provider::{LlmArgs, LlmConfig};
+use crate::vfs;
+
+/// Name of the optional project config, read from the project directory.
+pub const CONFIG_FILE: &str = "auto-rust.toml";
+
+/// Generates an axum + sqlx API service from a Postgres schema.
+#[derive(Debug, Parser)]
+#[command(name = "auto-rust", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+    /// Work out every file, table, route and service the command would
+    /// produce and print a summary, without writing anything. The LLM and
+    /// `--database-url` are still asked for the schema.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Like `--dry-run`, but print a unified diff against the project on disk.
+    #[arg(long, global = true)]
+    pub diff: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Create a new cargo project and generate a service into it.
+    New {
+        /// Name of the project, also its directory and binary name.
+        name: String,
+        /// Directory the project is created in.
+        #[arg(short, long, default_value = ".")]
+        output_dir: PathBuf,
+        #[command(flatten)]
+        schema: SchemaArgs,
+        #[command(flatten)]
+        components: ComponentArgs,
+    },
+    /// Write the schema and generate the service into an existing project.
+    Generate {
+        #[command(flatten)]
+        project: ProjectArgs,
+        #[command(flatten)]
+        schema: SchemaArgs,
+        #[command(flatten)]
+        components: ComponentArgs,
+    },
+    /// Add tables to a project as a new migration, then regenerate it.
+    AddTable {
+        #[command(flatten)]
+        project: ProjectArgs,
+        #[command(flatten)]
+        schema: SchemaArgs,
+        /// Name used for the migration file, e.g. `0002_{name}.sql`.
+        #[arg(long, default_value = "add_table")]
+        name: String,
+    },
+    /// Move a project to a new schema: diff it against the current one,
+    /// write the ALTER statements as a new migration and regenerate the
+    /// tables that changed.
+    Migrate {
+        #[command(flatten)]
+        project: ProjectArgs,
+        /// The whole schema the project should have, from `--sql` or `--task`.
+        #[command(flatten)]
+        schema: SchemaArgs,
+        /// Name used for the migration file, e.g. `0002_{name}.sql`.
+        #[arg(long, default_value = "alter_schema")]
+        name: String,
+        /// Write drops and type changes without asking first.
+        #[arg(long)]
+        allow_destructive: bool,
+    },
+    /// Create a new project from a brief. The LLM builds it by calling
+    /// generator tools, and every call is logged to `agent-log.jsonl` so
+    /// the run can be replayed without the model.
+    Agent {
+        /// Name of the project, also its directory and binary name.
+        name: String,
+        /// Directory the project is created in.
+        #[arg(short, long, default_value = ".")]
+        output_dir: PathBuf,
+        /// What the service is for, e.g. "a blog with users, posts and comments".
+        #[arg(short, long, required_unless_present = "replay")]
+        brief: Option<String>,
+        /// An `agent-log.jsonl` from an earlier run to build from instead
+        /// of asking the LLM.
+        #[arg(long, conflicts_with = "brief")]
+        replay: Option<PathBuf>,
+        #[command(flatten)]
+        llm: LlmArgs,
+        #[command(flatten)]
+        components: ComponentArgs,
+    },
+    /// Regenerate the service from the project's existing migrations.
+    Regenerate {
+        #[command(flatten)]
+        project: ProjectArgs,
+        #[command(flatten)]
+        components: ComponentArgs,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ProjectArgs {
+    /// The project to work on.
+    #[arg(short, long, default_value = ".")]
+    pub project_dir: PathBuf,
+}
+
+/// Where the schema comes from: a prompt for the LLM, SQL we already have
+/// or a database that already exists.
+#[derive(Debug, Args)]
+pub struct SchemaArgs {
+    /// What the database should store, turned into SQL by the LLM.
+    #[arg(short, long, conflicts_with_all = ["sql", "database_url"])]
+    pub task: Option<String>,
+    /// Existing migrations to generate from instead of asking the LLM: `.sql`
+    /// files or directories of them, e.g. `--sql schema/ --sql extra.sql`.
+    #[arg(short, long, num_args = 1.., conflicts_with = "database_url")]
+    pub sql: Vec<PathBuf>,
+    /// Read the tables from a live database instead of migrations, e.g. a
+    /// legacy database whose DDL is spread over years of migrations.
+    #[arg(short, long)]
+    pub database_url: Option<String>,
+    /// Show the tables the LLM proposes for `--task` and ask it for changes
+    /// until you accept them, before anything is written.
+    #[arg(short, long, requires = "task")]
+    pub interactive: bool,
+    #[command(flatten)]
+    pub llm: LlmArgs,
+}
+
+#[derive(Debug, Default, Args)]
+pub struct ComponentArgs {
+    /// Components to include, e.g. `--components minio,nginx`. Defaults
+    /// to the project config, or everything but tests without one.
+    #[arg(short, long, value_delimiter = ',')]
+    pub components: Option<Vec<Component>>,
+}
+
+/// Optional parts of the generated project besides the API itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Component {
+    /// Signed url handler and a MinIO service in docker compose.
+    Minio,
+    /// The React frontend template.
+    React,
+    /// The FastAPI template service.
+    Fastapi,
+    /// The `/python` route proxying to the FastAPI service.
+    PythonProxy,
+    /// nginx in front of the API, frontend and FastAPI service.
+    Nginx,
+    /// Integration test scaffolding under `tests/`.
+    Tests,
+    /// `/openapi.json` with Swagger UI at `/docs` and ReDoc at `/redoc`.
+    Docs,
+    /// Register and login endpoints with JWTs, a token required on every
+    /// route that `[auth] public` doesn't open, and role checks that keep
+    /// users to their own rows.
+    Auth,
+}
+
+impl Component {
+    /// What a project gets when no components are picked. Tests are left
+    /// out, they add dev-dependencies most projects don't want yet.
+    pub const DEFAULT: &'static [Component] = &[
+        Component::Minio,
+        Component::React,
+        Component::Fastapi,
+        Component::PythonProxy,
+        Component::Nginx,
+        Component::Docs,
+    ];
+}
+
+/// `auto-rust.toml`, written by `new` and read by every other subcommand.
+/// Flags on the command line win over it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// The project's name, used for the binary in the Dockerfile.
+    pub name: Option<String>,
+    pub components: Option<Vec<Component>>,
+    /// Default prompt for `generate` when neither `--task` nor `--sql` is given.
+    pub task: Option<String>,
+    /// Default SQL files or directories for `generate`, relative to the
+    /// project directory.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sql: Vec<PathBuf>,
+    /// Database the tables are read from instead of `migrations/`.
+    pub database_url: Option<String>,
+    /// The LLM behind `--task`.
+    #[serde(skip_serializing_if = "LlmConfig::is_empty")]
+    pub llm: LlmConfig,
+    /// Which table holds accounts, which routes are public and what each
+    /// role may do, with the auth component.
+    #[serde(skip_serializing_if = "AuthConfig::is_empty")]
+    pub auth: AuthConfig,
+    /// Which column marks soft deleted rows.
+    #[serde(skip_serializing_if = "SoftDeleteConfig::is_empty")]
+    pub soft_delete: SoftDeleteConfig,
+    /// Which nested list routes are served.
+    #[serde(skip_serializing_if = "NestedConfig::is_empty")]
+    pub nested: NestedConfig,
+}
+
+impl ProjectConfig {
+    /// Reads `auto-rust.toml` from `project_dir`, or the defaults when there is none.
+    pub fn load(project_dir: &Path) -> Result<ProjectConfig, io::Error> {
+        let path = project_dir.join(CONFIG_FILE);
+        if !vfs::exists(&path) {
+            return Ok(ProjectConfig::default());
+        }
+        let contents = vfs::read_to_string(&path)?;
+        toml::from_str(&contents).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+        })
+    }
+
+    pub fn save(&self, project_dir: &Path) -> Result<(), io::Error> {
+        let contents = toml::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        vfs::write(project_dir.join(CONFIG_FILE), contents)
+    }
+
+    /// The components to generate: the flag, then the config, then the defaults.
+    pub fn components(&self, args: &ComponentArgs) -> Vec<Component> {
+        args.components.clone()
+            .or_else(|| self.components.clone())
+            .unwrap_or_else(|| Component::DEFAULT.to_vec())
+    }
+}
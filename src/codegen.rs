@@ -0,0 +1,350 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::Path;
+
+use convert_case::{Case, Casing};
+use proc_macro2::{Ident, Spacing, Span, TokenStream, TokenTree};
+use quote::quote;
+
+use crate::base_structs::Row;
+use crate::{manifest, vfs};
+
+/// Names the generated code uses unqualified, and where they come from.
+/// `write_module` imports the ones a module actually mentions.
+const EXTERNAL_USES: &[(&str, &str)] = &[
+    ("Router", "axum::Router"),
+    ("extract", "axum::extract"),
+    ("StatusCode", "axum::http::StatusCode"),
+    ("Method", "axum::http::Method"),
+    ("header", "axum::http::header"),
+    ("Html", "axum::response::Html"),
+    ("IntoResponse", "axum::response::IntoResponse"),
+    ("get", "axum::routing::get"),
+    ("post", "axum::routing::post"),
+    ("put", "axum::routing::put"),
+    ("patch", "axum::routing::patch"),
+    ("delete", "axum::routing::delete"),
+    ("Minio", "minio_rsc::Minio"),
+    ("PresignedArgs", "minio_rsc::client::PresignedArgs"),
+    ("StaticProvider", "minio_rsc::provider::StaticProvider"),
+    ("Deserialize", "serde::Deserialize"),
+    ("Serialize", "serde::Serialize"),
+    ("json", "serde_json::json"),
+    ("Value", "serde_json::Value"),
+    ("FromRow", "sqlx::FromRow"),
+    ("PgPool", "sqlx::PgPool"),
+    ("PgPoolOptions", "sqlx::postgres::PgPoolOptions"),
+    ("Utc", "sqlx::types::chrono::Utc"),
+    ("HashMap", "std::collections::HashMap"),
+    ("env", "std::env"),
+    ("AllowOrigin", "tower_http::cors::AllowOrigin"),
+    ("CorsLayer", "tower_http::cors::CorsLayer"),
+    ("AppError", "crate::error::AppError"),
+    ("FieldError", "crate::error::FieldError"),
+    // axum's extractors, wrapped so their rejections are `AppError`s
+    ("Json", "crate::error::Json"),
+    ("Path", "crate::error::Path"),
+    ("Query", "crate::error::Query"),
+];
+
+/// Parses generated tokens as a Rust file and pretty prints them. Tokens that
+/// don't form valid Rust are an `InvalidData` error, so a generator mistake
+/// shows up here instead of when the generated project is compiled.
+pub fn render(tokens: TokenStream) -> Result<String, io::Error> {
+    let file = syn::parse2::<syn::File>(tokens).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("generated code does not parse: {}", e))
+    })?;
+
+    // prettyplease puts items back to back, so each one is printed on its
+    // own and separated by a blank line, except within a run of `use`s or `mod`s
+    let mut source = String::new();
+    let mut previous: Option<std::mem::Discriminant<syn::Item>> = None;
+    for (i, item) in file.items.into_iter().enumerate() {
+        let kind = std::mem::discriminant(&item);
+        let grouped = matches!(item, syn::Item::Use(_) | syn::Item::Mod(_)) && previous == Some(kind);
+        if previous.is_some() && !grouped {
+            source.push('\n');
+        }
+        let attrs = if i == 0 { file.attrs.clone() } else { Vec::new() };
+        source.push_str(&prettyplease::unparse(&syn::File { shebang: None, attrs, items: vec![item] }));
+        previous = Some(kind);
+    }
+    Ok(source)
+}
+
+/// Writes one module of the generated project, replacing what was there
+/// unless the user has edited it.
+/// The `use` lines for axum, serde, sqlx and friends are worked out from the
+/// names `items` mentions, so each file only imports what it needs. Names
+/// the module defines itself are not imported.
+pub fn write_module(file_path: &Path, items: TokenStream) -> Result<(), io::Error> {
+    if let Some(parent) = file_path.parent() {
+        vfs::create_dir_all(parent)?;
+    }
+
+    let mut names = HashSet::new();
+    collect_names(items.clone(), &mut names);
+    if let Ok(file) = syn::parse2::<syn::File>(items.clone()) {
+        for item in &file.items {
+            let defined = match item {
+                syn::Item::Struct(item) => &item.ident,
+                syn::Item::Enum(item) => &item.ident,
+                syn::Item::Fn(item) => &item.sig.ident,
+                syn::Item::Const(item) => &item.ident,
+                syn::Item::Type(item) => &item.ident,
+                _ => continue,
+            };
+            names.remove(&defined.to_string());
+        }
+    }
+
+    // group by parent path so axum's names share one `use axum::{...}`
+    let mut grouped: BTreeMap<&str, Vec<Ident>> = BTreeMap::new();
+    for (name, path) in EXTERNAL_USES.iter().filter(|(name, _)| names.contains(*name)) {
+        let (parent, _) = path.rsplit_once("::").unwrap_or((path, name));
+        grouped.entry(parent).or_default().push(ident(name));
+    }
+    let uses = grouped.into_iter().map(|(parent, names)| {
+        let parent: syn::Path = syn::parse_str(parent).expect("EXTERNAL_USES holds valid paths");
+        match names.as_slice() {
+            [name] => quote! { use #parent::#name; },
+            _ => quote! { use #parent::{#(#names),*}; },
+        }
+    });
+
+    let source = render(quote! {
+        #(#uses)*
+        #items
+    })?;
+    manifest::write_generated(file_path, source)
+}
+
+/// Writes `{dir}/mod.rs` declaring every other `.rs` file in `dir` and
+/// re-exporting its items, followed by `items`.
+pub fn write_mod_file(dir: &Path, items: TokenStream) -> Result<(), io::Error> {
+    let mut modules = Vec::new();
+    for path in vfs::read_dir(dir)? {
+        match (path.file_stem().and_then(|s| s.to_str()), path.extension()) {
+            (Some(stem), Some(ext)) if ext == "rs" && stem != "mod" => modules.push(ident(stem)),
+            _ => {}
+        }
+    }
+    modules.sort_by_key(|module| module.to_string());
+
+    write_module(&dir.join("mod.rs"), quote! {
+        #(pub mod #modules;)*
+
+        #(pub use #modules::*;)*
+
+        #items
+    })
+}
+
+/// Identifiers that could refer to an imported name: ones that are not a
+/// path segment after `::` and not a field or method after `.`.
+fn collect_names(tokens: TokenStream, names: &mut HashSet<String>) {
+    let mut after_path_or_dot = false;
+    // the first half of a `::`
+    let mut joint_colon = false;
+    for token in tokens {
+        match token {
+            TokenTree::Group(group) => {
+                collect_names(group.stream(), names);
+                after_path_or_dot = false;
+                joint_colon = false;
+            }
+            TokenTree::Ident(ident) => {
+                if !after_path_or_dot {
+                    names.insert(ident.to_string());
+                }
+                after_path_or_dot = false;
+                joint_colon = false;
+            }
+            TokenTree::Punct(punct) => {
+                after_path_or_dot = punct.as_char() == '.' || (punct.as_char() == ':' && joint_colon);
+                joint_colon = punct.as_char() == ':' && punct.spacing() == Spacing::Joint;
+            }
+            TokenTree::Literal(_) => {
+                after_path_or_dot = false;
+                joint_colon = false;
+            }
+        }
+    }
+}
+
+/// The code generated for one table, split the way the generated service
+/// is: structs in `models/`, API layer handlers in `handlers/` and data
+/// layer functions in `data/`.
+#[derive(Default)]
+pub struct TableModule {
+    pub models: TokenStream,
+    pub handlers: TokenStream,
+    pub data: TokenStream,
+}
+
+impl TableModule {
+    /// Writes `models/{table}.rs`, `handlers/{table}.rs` and `data/{table}.rs` under `src_dir`.
+    pub fn write(self, src_dir: &Path, table: &str) -> Result<(), io::Error> {
+        let file_name = format!("{}.rs", table);
+        let TableModule { models, handlers, data } = self;
+        write_module(&src_dir.join("models").join(&file_name), models)?;
+        write_module(&src_dir.join("handlers").join(&file_name), quote! {
+            use crate::data::*;
+            use crate::models::*;
+
+            #handlers
+        })?;
+        write_module(&src_dir.join("data").join(&file_name), quote! {
+            use super::*;
+            use crate::models::*;
+
+            #data
+        })
+    }
+}
+
+/// Keywords that can't be raw identifiers either.
+const NOT_RAW: &[&str] = &["self", "Self", "super", "crate", "_"];
+
+/// Whether `ident` can make an identifier of `name`, as is or as a raw one.
+pub fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && !NOT_RAW.contains(&name)
+}
+
+/// Checks that the tables and columns in `rows` can be named in the
+/// generated code, so `ident` never meets a name it can't use. The error
+/// is `InvalidData` and names the first one that can't.
+pub fn check_idents(rows: &[Row]) -> Result<(), io::Error> {
+    let refuse = |what: String| {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} can't be named in the generated rust, rename it", what),
+        ))
+    };
+    for row in rows {
+        if !is_ident(&row.name) || !is_ident(&row.name.to_case(Case::Pascal)) {
+            return refuse(format!("table \"{}\"", row.name));
+        }
+        if let Some(col) = row.cols.iter().find(|col| !is_ident(&col.name)) {
+            return refuse(format!("column {}.\"{}\"", row.name, col.name));
+        }
+        // enum types and their labels become a rust enum and its variants
+        for col in row.cols.iter().filter(|col| !col.enum_labels.is_empty()) {
+            if !is_ident(&col.col_type.to_case(Case::Pascal)) {
+                return refuse(format!("enum type \"{}\"", col.col_type));
+            }
+            if let Some(label) = col.enum_labels.iter().find(|label| !is_ident(&label.to_case(Case::Pascal))) {
+                return refuse(format!("label '{}' of enum type {}", label, col.col_type));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An identifier for a generated name. Column names that are Rust keywords
+/// (`type`, `match`, ...) become raw identifiers. Names `is_ident` refuses
+/// are kept out by `check_idents`.
+pub fn ident(name: &str) -> Ident {
+    match syn::parse_str::<Ident>(name) {
+        Ok(ident) => ident,
+        Err(_) => Ident::new_raw(name, Span::call_site()),
+    }
+}
+
+/// Parses a Rust type from the type map, e.g. `chrono::DateTime<Utc>`.
+pub fn rust_type(name: &str) -> Result<syn::Type, io::Error> {
+    syn::parse_str(name).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("'{}' is not a rust type: {}", name, e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_that_are_not_rust_are_refused() {
+        let error = render(quote! { pub fn broken() -> }).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("generated code does not parse: "));
+    }
+
+    #[test]
+    fn items_are_spaced_except_runs_of_uses() {
+        let source = render(quote! {
+            use a::b;
+            use c::d;
+            pub struct A { x: i32 }
+            pub fn f() -> i32 { 1 }
+        }).unwrap();
+        assert_eq!(source, "use a::b;\nuse c::d;\n\npub struct A {\n    x: i32,\n}\n\npub fn f() -> i32 {\n    1\n}\n");
+    }
+
+    #[test]
+    fn modules_import_only_the_names_they_use() {
+        let _serial = manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("handlers/runs.rs");
+        write_module(&path, quote! {
+            pub struct Query;
+
+            pub async fn list(extract::State(pool): extract::State<PgPool>, Path(id): Path<i32>) -> Result<Json<Value>, AppError> {
+                // `.json` and `serde_json::json` are not the json! macro
+                let body = response.json();
+                let value = serde_json::json!({});
+                Ok(Json(json!({ "id": id, "value": value, "body": body, "pool": pool.size(), "q": Query })))
+            }
+        }).unwrap();
+        let source = std::fs::read_to_string(&path).unwrap();
+        let uses: Vec<&str> = source.lines().take_while(|line| line.starts_with("use ")).collect();
+        // Query is defined here, not axum's
+        assert_eq!(uses, [
+            "use axum::extract;",
+            "use crate::error::{AppError, Json, Path};",
+            "use serde_json::{json, Value};",
+            "use sqlx::PgPool;",
+        ]);
+    }
+
+    #[test]
+    fn mod_files_declare_and_reexport_every_module() {
+        let _serial = manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        for file in ["users.rs", "runs.rs", "mod.rs", "notes.txt"] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        write_mod_file(dir.path(), quote! {}).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("mod.rs")).unwrap(),
+            "pub mod runs;\npub mod users;\n\npub use runs::*;\npub use users::*;\n"
+        );
+    }
+
+    #[test]
+    fn names_that_cant_be_identifiers_are_refused() {
+        for name in ["self", "crate", "\"Self\"", "\"First Name\""] {
+            let rows = crate::schema::parse_rows(&format!("CREATE TABLE t (id INT PRIMARY KEY, {} TEXT);", name)).unwrap();
+            let error = check_idents(&rows).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(
+                error.to_string(),
+                format!("column t.\"{}\" can't be named in the generated rust, rename it", name.trim_matches('"'))
+            );
+        }
+        let rows = crate::schema::parse_rows("CREATE TABLE super (id INT PRIMARY KEY);").unwrap();
+        assert!(check_idents(&rows).unwrap_err().to_string().starts_with("table \"super\""));
+        let rows = crate::schema::parse_rows("CREATE TABLE runs (id INT PRIMARY KEY, type TEXT, self_id INT);").unwrap();
+        assert!(check_idents(&rows).is_ok());
+    }
+
+    #[test]
+    fn keywords_become_raw_identifiers() {
+        assert_eq!(ident("type").to_string(), "r#type");
+        assert_eq!(ident("user_id").to_string(), "user_id");
+        assert!(rust_type("Option<chrono::DateTime<Utc>>").is_ok());
+        assert_eq!(rust_type("Vec<").err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+
+/// An error found while reading DDL, pointing at the line and column
+/// (both 1-based) where the problem starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(line: usize, col: usize, message: impl Into<String>) -> Self {
+        ParseError { line, col, message: message.into() }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.col, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// A bare word, either a keyword or an unquoted identifier.
+    Word(String),
+    /// A double quoted identifier, with the quotes removed.
+    QuotedIdent(String),
+    /// A single quoted (or dollar quoted) string literal, with the quotes removed.
+    Str(String),
+    Number(String),
+    /// Single character punctuation: `( ) , ; . [ ]`
+    Punct(char),
+    /// Anything else, e.g. `>=`, `::`, `||`, `+`.
+    Operator(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,
+    pub col: usize,
+    /// Byte range of the token in the source, used to slice out raw
+    /// expressions such as DEFAULT and CHECK bodies.
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Token {
+    /// True when the token is the given keyword, ignoring case.
+    pub fn is_word(&self, keyword: &str) -> bool {
+        matches!(&self.kind, TokenKind::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    pub fn is_punct(&self, c: char) -> bool {
+        self.kind == TokenKind::Punct(c)
+    }
+
+    /// Short human readable form used in error messages.
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            TokenKind::Word(w) => format!("'{}'", w),
+            TokenKind::QuotedIdent(w) => format!("'\"{}\"'", w),
+            TokenKind::Str(s) => format!("string '{}'", s),
+            TokenKind::Number(n) => format!("number {}", n),
+            TokenKind::Punct(c) => format!("'{}'", c),
+            TokenKind::Operator(o) => format!("'{}'", o),
+        }
+    }
+}
+
+const OPERATOR_CHARS: &str = "+-*/<>=~!@#%^&|`?:";
+
+struct Cursor<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+    col: usize,
+}
+
+impl Cursor<'_> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.src[self.pos..].chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError::new(self.line, self.col, message)
+    }
+}
+
+/// Splits SQL source into tokens, dropping whitespace and `--` / `/* */` comments.
+pub fn tokenize(src: &str) -> Result<Vec<Token>, ParseError> {
+    let mut cur = Cursor { src, pos: 0, line: 1, col: 1 };
+    let mut tokens = Vec::new();
+
+    while let Some(c) = cur.peek() {
+        let (start, line, col) = (cur.pos, cur.line, cur.col);
+
+        if c.is_whitespace() {
+            cur.bump();
+            continue;
+        }
+
+        if c == '-' && cur.peek_at(1) == Some('-') {
+            while let Some(c) = cur.peek() {
+                if c == '\n' {
+                    break;
+                }
+                cur.bump();
+            }
+            continue;
+        }
+
+        if c == '/' && cur.peek_at(1) == Some('*') {
+            cur.bump();
+            cur.bump();
+            // postgres allows nested block comments
+            let mut depth = 1;
+            while depth > 0 {
+                match cur.bump() {
+                    Some('*') if cur.peek() == Some('/') => {
+                        cur.bump();
+                        depth -= 1;
+                    }
+                    Some('/') if cur.peek() == Some('*') => {
+                        cur.bump();
+                        depth += 1;
+                    }
+                    Some(_) => {}
+                    None => return Err(ParseError::new(line, col, "unterminated block comment")),
+                }
+            }
+            continue;
+        }
+
+        let kind = if c.is_alphabetic() || c == '_' {
+            let mut word = String::new();
+            while let Some(c) = cur.peek() {
+                if c.is_alphanumeric() || c == '_' || c == '$' {
+                    word.push(c);
+                    cur.bump();
+                } else {
+                    break;
+                }
+            }
+            TokenKind::Word(word)
+        } else if c.is_ascii_digit() || (c == '.' && cur.peek_at(1).is_some_and(|n| n.is_ascii_digit())) {
+            let mut num = String::new();
+            while let Some(c) = cur.peek() {
+                let exponent_sign = (c == '+' || c == '-') && num.ends_with(['e', 'E']);
+                if c.is_ascii_alphanumeric() || c == '.' || exponent_sign {
+                    num.push(c);
+                    cur.bump();
+                } else {
+                    break;
+                }
+            }
+            TokenKind::Number(num)
+        } else if c == '"' {
+            cur.bump();
+            TokenKind::QuotedIdent(read_quoted(&mut cur, '"', line, col, "quoted identifier")?)
+        } else if c == '\'' {
+            cur.bump();
+            TokenKind::Str(read_quoted(&mut cur, '\'', line, col, "string literal")?)
+        } else if c == '$' && is_dollar_tag_start(&cur) {
+            TokenKind::Str(read_dollar_quoted(&mut cur, line, col)?)
+        } else if "(),;.[]".contains(c) {
+            cur.bump();
+            TokenKind::Punct(c)
+        } else if OPERATOR_CHARS.contains(c) {
+            let mut op = String::new();
+            while let Some(c) = cur.peek() {
+                // keep `--` and `/*` from being swallowed into an operator
+                let comment_start = (c == '-' && cur.peek_at(1) == Some('-'))
+                    || (c == '/' && cur.peek_at(1) == Some('*'));
+                if OPERATOR_CHARS.contains(c) && !comment_start {
+                    op.push(c);
+                    cur.bump();
+                } else {
+                    break;
+                }
+            }
+            TokenKind::Operator(op)
+        } else if c == '$' {
+            // positional parameter such as $1
+            cur.bump();
+            let mut param = String::from("$");
+            while let Some(c) = cur.peek().filter(|c| c.is_ascii_digit()) {
+                param.push(c);
+                cur.bump();
+            }
+            TokenKind::Operator(param)
+        } else {
+            return Err(cur.error(format!("unexpected character '{}'", c)));
+        };
+
+        tokens.push(Token { kind, line, col, start, end: cur.pos });
+    }
+
+    Ok(tokens)
+}
+
+/// Reads up to the closing quote, treating a doubled quote as an escaped one.
+fn read_quoted(cur: &mut Cursor, quote: char, line: usize, col: usize, what: &str) -> Result<String, ParseError> {
+    let mut value = String::new();
+    loop {
+        match cur.bump() {
+            Some(c) if c == quote => {
+                if cur.peek() == Some(quote) {
+                    cur.bump();
+                    value.push(quote);
+                } else {
+                    return Ok(value);
+                }
+            }
+            Some(c) => value.push(c),
+            None => return Err(ParseError::new(line, col, format!("unterminated {}", what))),
+        }
+    }
+}
+
+fn is_dollar_tag_start(cur: &Cursor) -> bool {
+    let rest = &cur.src[cur.pos + 1..];
+    let tag_len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+    rest[tag_len..].starts_with('$') && !rest.starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn read_dollar_quoted(cur: &mut Cursor, line: usize, col: usize) -> Result<String, ParseError> {
+    let mut tag = String::new();
+    tag.push(cur.bump().unwrap_or('$'));
+    while let Some(c) = cur.bump() {
+        tag.push(c);
+        if c == '$' {
+            break;
+        }
+    }
+    let body_start = cur.pos;
+    match cur.src[body_start..].find(&tag) {
+        Some(offset) => {
+            while cur.pos < body_start + offset + tag.len() {
+                cur.bump();
+            }
+            Ok(cur.src[body_start..body_start + offset].to_string())
+        }
+        None => Err(ParseError::new(line, col, "unterminated dollar quoted string")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<TokenKind> {
+        tokenize(src).unwrap().into_iter().map(|t| t.kind).collect()
+    }
+
+    #[test]
+    fn drops_line_and_block_comments() {
+        let src = "a -- to the end of the line\nb /* block /* nested */ still */ c";
+        assert_eq!(
+            kinds(src),
+            vec![TokenKind::Word("a".into()), TokenKind::Word("b".into()), TokenKind::Word("c".into())]
+        );
+    }
+
+    #[test]
+    fn comment_markers_are_not_operators() {
+        assert_eq!(kinds("x>--y\n1"), vec![
+            TokenKind::Word("x".into()),
+            TokenKind::Operator(">".into()),
+            TokenKind::Number("1".into()),
+        ]);
+    }
+
+    #[test]
+    fn quoted_identifiers_keep_case_and_doubled_quotes() {
+        assert_eq!(kinds(r#""User" "say ""hi""""#), vec![
+            TokenKind::QuotedIdent("User".into()),
+            TokenKind::QuotedIdent(r#"say "hi""#.into()),
+        ]);
+    }
+
+    #[test]
+    fn strings_and_dollar_quotes() {
+        assert_eq!(kinds("'it''s' $$a 'b'$$ $tag$x$tag$"), vec![
+            TokenKind::Str("it's".into()),
+            TokenKind::Str("a 'b'".into()),
+            TokenKind::Str("x".into()),
+        ]);
+    }
+
+    #[test]
+    fn tokens_know_their_line_and_column() {
+        let tokens = tokenize("a\n  /* c */ b").unwrap();
+        assert_eq!((tokens[0].line, tokens[0].col), (1, 1));
+        assert_eq!((tokens[1].line, tokens[1].col), (2, 11));
+    }
+
+    #[test]
+    fn unterminated_input_points_at_where_it_started() {
+        let error = tokenize("a\n  'open").unwrap_err();
+        assert_eq!((error.line, error.col), (2, 3));
+        assert_eq!(error.to_string(), "line 2, column 3: unterminated string literal");
+
+        let error = tokenize("/* never closed").unwrap_err();
+        assert_eq!(error.message, "unterminated block comment");
+    }
+}
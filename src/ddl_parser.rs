@@ -0,0 +1,941 @@
+use crate::ddl_lexer::{tokenize, ParseError, Token, TokenKind};
+
+/// A parsed `CREATE TABLE` statement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableDef {
+    pub name: String,
+    /// The table name as written, e.g. `public."user"` for a table named `user`.
+    pub written_name: String,
+    pub if_not_exists: bool,
+    pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub data_type: DataType,
+    pub constraints: Vec<ColumnConstraint>,
+    pub line: usize,
+}
+
+/// A column type, normalised to upper case with multi word spellings
+/// folded into the short postgres name (`CHARACTER VARYING` -> `VARCHAR`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataType {
+    pub name: String,
+    /// Type modifiers such as the `255` in `VARCHAR(255)` or `10, 2` in `NUMERIC(10, 2)`.
+    pub args: Vec<String>,
+    pub array: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKeyRef {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub on_delete: Option<String>,
+    pub on_update: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnConstraint {
+    NotNull,
+    Null,
+    PrimaryKey,
+    Unique,
+    /// The raw default expression, e.g. `gen_random_uuid()`.
+    Default(String),
+    Check(String),
+    References(ForeignKeyRef),
+    /// `GENERATED ... AS IDENTITY` or `GENERATED ALWAYS AS (expr) STORED`.
+    Generated,
+    Collate(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+    PrimaryKey { name: Option<String>, columns: Vec<String> },
+    Unique { name: Option<String>, columns: Vec<String> },
+    Check { name: Option<String>, expr: String },
+    ForeignKey { name: Option<String>, columns: Vec<String>, references: ForeignKeyRef },
+    /// Constraints we accept but do not model, such as `EXCLUDE`.
+    Other { name: Option<String>, text: String },
+}
+
+/// A statement that changes which tables exist or what they look like.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    /// `CREATE TABLE`
+    Create(TableDef),
+    /// `ALTER TABLE`
+    Alter { name: String, actions: Vec<AlterAction>, line: usize },
+    /// `DROP TABLE`, possibly of several tables
+    Drop { names: Vec<String>, line: usize },
+}
+
+/// One action of an `ALTER TABLE`. Actions that don't change the schema we
+/// model (`OWNER TO`, `ENABLE TRIGGER`, ...) are skipped by the parser.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterAction {
+    AddColumn(ColumnDef),
+    DropColumn(String),
+    AlterType { column: String, data_type: DataType },
+    SetNotNull(String),
+    DropNotNull(String),
+    SetDefault { column: String, expr: String },
+    DropDefault(String),
+    AddConstraint(TableConstraint),
+    DropConstraint(String),
+    RenameColumn { from: String, to: String },
+    RenameTable(String),
+}
+
+/// Parses every `CREATE TABLE` statement in `src`. Other statements
+/// (`ALTER TABLE`, `CREATE INDEX`, inserts, ...) are skipped.
+pub fn parse_tables(src: &str) -> Result<Vec<TableDef>, ParseError> {
+    let tables = parse_statements(src)?.into_iter()
+        .filter_map(|statement| match statement {
+            Statement::Create(table) => Some(table),
+            _ => None,
+        })
+        .collect();
+    Ok(tables)
+}
+
+/// Parses the `CREATE TABLE`, `ALTER TABLE` and `DROP TABLE` statements in
+/// `src`, in order. Other statements are skipped.
+pub fn parse_statements(src: &str) -> Result<Vec<Statement>, ParseError> {
+    let tokens = tokenize(src)?;
+    let mut parser = Parser { src, tokens: &tokens, pos: 0 };
+    let mut statements = Vec::new();
+
+    while parser.peek().is_some() {
+        if parser.eat_punct(';') {
+            continue;
+        }
+        if parser.at_create_table() {
+            statements.push(Statement::Create(parser.create_table()?));
+        } else if parser.peek_is_word("ALTER") && parser.peek_nth(1).is_some_and(|t| t.is_word("TABLE")) {
+            statements.push(parser.alter_table()?);
+        } else if parser.peek_is_word("DROP") && parser.peek_nth(1).is_some_and(|t| t.is_word("TABLE")) {
+            statements.push(parser.drop_table()?);
+        } else {
+            parser.skip_statement()?;
+        }
+    }
+
+    Ok(statements)
+}
+
+/// Keywords that end a DEFAULT expression inside a column definition.
+const COLUMN_CONSTRAINT_KEYWORDS: &[&str] = &[
+    "NOT", "NULL", "PRIMARY", "UNIQUE", "DEFAULT", "CHECK", "REFERENCES",
+    "CONSTRAINT", "COLLATE", "GENERATED", "DEFERRABLE", "INITIALLY",
+];
+
+struct Parser<'a> {
+    src: &'a str,
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&'a Token> {
+        self.tokens.get(self.pos + n)
+    }
+
+    fn peek_is_word(&self, keyword: &str) -> bool {
+        self.peek().is_some_and(|t| t.is_word(keyword))
+    }
+
+    fn peek_is_punct(&self, c: char) -> bool {
+        self.peek().is_some_and(|t| t.is_punct(c))
+    }
+
+    fn eat_word(&mut self, keyword: &str) -> bool {
+        if self.peek_is_word(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_words(&mut self, keywords: &[&str]) -> bool {
+        let matches = keywords
+            .iter()
+            .enumerate()
+            .all(|(i, k)| self.peek_nth(i).is_some_and(|t| t.is_word(k)));
+        if matches {
+            self.pos += keywords.len();
+        }
+        matches
+    }
+
+    fn eat_punct(&mut self, c: char) -> bool {
+        if self.peek_is_punct(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Builds an error at the current token, or at the end of the last one.
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        match self.peek().or(self.tokens.last()) {
+            Some(t) if self.peek().is_some() => ParseError::new(t.line, t.col, message),
+            Some(t) => {
+                let (line, col) = position_after(self.src, t.end);
+                ParseError::new(line, col, message)
+            }
+            None => ParseError::new(1, 1, message),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(t) => self.error(format!("expected {} but found {}", expected, t.describe())),
+            None => self.error(format!("expected {} but reached the end of the input", expected)),
+        }
+    }
+
+    fn expect_word(&mut self, keyword: &str) -> Result<(), ParseError> {
+        if self.eat_word(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", keyword)))
+        }
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), ParseError> {
+        if self.eat_punct(c) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", c)))
+        }
+    }
+
+    /// Reads an identifier. Unquoted names are folded to lower case like postgres does.
+    fn ident(&mut self, what: &str) -> Result<String, ParseError> {
+        match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Word(w)) => {
+                self.pos += 1;
+                Ok(w.to_lowercase())
+            }
+            Some(TokenKind::QuotedIdent(w)) => {
+                self.pos += 1;
+                Ok(w.clone())
+            }
+            _ => Err(self.unexpected(what)),
+        }
+    }
+
+    /// Reads a possibly schema qualified name and keeps only the last part,
+    /// so `public."user"` becomes `user`.
+    fn qualified_name(&mut self, what: &str) -> Result<String, ParseError> {
+        let mut name = self.ident(what)?;
+        while self.eat_punct('.') {
+            name = self.ident(what)?;
+        }
+        Ok(name)
+    }
+
+    fn ident_list(&mut self) -> Result<Vec<String>, ParseError> {
+        self.expect_punct('(')?;
+        let mut names = vec![self.ident("a column name")?];
+        while self.eat_punct(',') {
+            names.push(self.ident("a column name")?);
+        }
+        self.expect_punct(')')?;
+        Ok(names)
+    }
+
+    fn at_create_table(&self) -> bool {
+        if !self.peek_is_word("CREATE") {
+            return false;
+        }
+        let mut n = 1;
+        while self.peek_nth(n).is_some_and(|t| {
+            ["GLOBAL", "LOCAL", "TEMP", "TEMPORARY", "UNLOGGED"].iter().any(|k| t.is_word(k))
+        }) {
+            n += 1;
+        }
+        self.peek_nth(n).is_some_and(|t| t.is_word("TABLE"))
+    }
+
+    /// Skips a statement we do not care about, up to and including its `;`.
+    fn skip_statement(&mut self) -> Result<(), ParseError> {
+        let mut depth = 0usize;
+        while let Some(t) = self.peek() {
+            if t.is_punct('(') {
+                depth += 1;
+            } else if t.is_punct(')') {
+                if depth == 0 {
+                    return Err(self.error("unbalanced ')'"));
+                }
+                depth -= 1;
+            } else if t.is_punct(';') && depth == 0 {
+                self.pos += 1;
+                return Ok(());
+            }
+            self.pos += 1;
+        }
+        if depth > 0 {
+            return Err(self.unexpected("')'"));
+        }
+        Ok(())
+    }
+
+    fn create_table(&mut self) -> Result<TableDef, ParseError> {
+        let line = self.peek().map(|t| t.line).unwrap_or(1);
+        self.expect_word("CREATE")?;
+        while ["GLOBAL", "LOCAL", "TEMP", "TEMPORARY", "UNLOGGED"].iter().any(|k| self.eat_word(k)) {}
+        self.expect_word("TABLE")?;
+        let if_not_exists = self.eat_words(&["IF", "NOT", "EXISTS"]);
+        let name_start = self.peek().map(|t| t.start);
+        let name = self.qualified_name("a table name")?;
+        let written_name = match (name_start, self.tokens.get(self.pos - 1)) {
+            (Some(start), Some(last)) => self.src[start..last.end].to_string(),
+            _ => name.clone(),
+        };
+        self.expect_punct('(')?;
+
+        let mut columns = Vec::new();
+        let mut constraints = Vec::new();
+        if !self.peek_is_punct(')') {
+            loop {
+                if self.at_table_constraint() {
+                    constraints.push(self.table_constraint()?);
+                } else if self.eat_word("LIKE") {
+                    self.qualified_name("a table name")?;
+                    self.skip_until_element_end();
+                } else {
+                    let (column, named) = self.column_def()?;
+                    columns.push(column);
+                    constraints.extend(named);
+                }
+                if self.eat_punct(',') {
+                    continue;
+                }
+                if self.peek_is_punct(')') {
+                    break;
+                }
+                return Err(self.unexpected("',' or ')'"));
+            }
+        }
+        self.expect_punct(')')?;
+
+        // table options such as INHERITS (...), WITH (...) or TABLESPACE
+        while self.peek().is_some() && !self.peek_is_punct(';') {
+            if self.peek_is_word("CREATE") {
+                return Err(self.unexpected(&format!("';' after table '{}'", name)));
+            }
+            if self.peek_is_punct('(') {
+                self.balanced_text()?;
+            } else {
+                self.pos += 1;
+            }
+        }
+        self.eat_punct(';');
+
+        Ok(TableDef { name, written_name, if_not_exists, columns, constraints, line })
+    }
+
+    fn alter_table(&mut self) -> Result<Statement, ParseError> {
+        let line = self.peek().map(|t| t.line).unwrap_or(1);
+        self.expect_word("ALTER")?;
+        self.expect_word("TABLE")?;
+        self.eat_words(&["IF", "EXISTS"]);
+        self.eat_word("ONLY");
+        let name = self.qualified_name("a table name")?;
+
+        let mut actions = Vec::new();
+        loop {
+            actions.extend(self.alter_action()?);
+            if self.eat_punct(',') {
+                continue;
+            }
+            if self.peek().is_none() || self.eat_punct(';') {
+                break;
+            }
+            return Err(self.unexpected("',' or ';'"));
+        }
+        Ok(Statement::Alter { name, actions, line })
+    }
+
+    /// One `ALTER TABLE` action, none for one we skip. Adding a column with
+    /// named constraints adds them as table constraints after it.
+    fn alter_action(&mut self) -> Result<Vec<AlterAction>, ParseError> {
+        let action = if self.eat_word("ADD") {
+            if self.at_table_constraint() {
+                AlterAction::AddConstraint(self.table_constraint()?)
+            } else {
+                self.eat_word("COLUMN");
+                self.eat_words(&["IF", "NOT", "EXISTS"]);
+                let (column, named) = self.column_def()?;
+                self.skip_until_element_end();
+                let mut actions = vec![AlterAction::AddColumn(column)];
+                actions.extend(named.into_iter().map(AlterAction::AddConstraint));
+                return Ok(actions);
+            }
+        } else if self.eat_word("DROP") {
+            if self.eat_word("CONSTRAINT") {
+                self.eat_words(&["IF", "EXISTS"]);
+                AlterAction::DropConstraint(self.ident("a constraint name")?)
+            } else {
+                self.eat_word("COLUMN");
+                self.eat_words(&["IF", "EXISTS"]);
+                AlterAction::DropColumn(self.ident("a column name")?)
+            }
+        } else if self.eat_word("ALTER") {
+            self.eat_word("COLUMN");
+            let column = self.ident("a column name")?;
+            if self.eat_word("TYPE") || self.eat_words(&["SET", "DATA", "TYPE"]) {
+                AlterAction::AlterType { column, data_type: self.data_type()? }
+            } else if self.eat_words(&["SET", "NOT", "NULL"]) {
+                AlterAction::SetNotNull(column)
+            } else if self.eat_words(&["DROP", "NOT", "NULL"]) {
+                AlterAction::DropNotNull(column)
+            } else if self.eat_words(&["SET", "DEFAULT"]) {
+                AlterAction::SetDefault { column, expr: self.default_expr()? }
+            } else if self.eat_words(&["DROP", "DEFAULT"]) {
+                AlterAction::DropDefault(column)
+            } else {
+                self.skip_until_element_end();
+                return Ok(Vec::new());
+            }
+        } else if self.eat_word("RENAME") {
+            if self.eat_word("TO") {
+                AlterAction::RenameTable(self.ident("a table name")?)
+            } else if self.eat_word("CONSTRAINT") {
+                self.skip_until_element_end();
+                return Ok(Vec::new());
+            } else {
+                self.eat_word("COLUMN");
+                let from = self.ident("a column name")?;
+                self.expect_word("TO")?;
+                AlterAction::RenameColumn { from, to: self.ident("a column name")? }
+            }
+        } else {
+            self.skip_until_element_end();
+            return Ok(Vec::new());
+        };
+
+        // COLLATE, USING, CASCADE and the like after the part we model
+        self.skip_until_element_end();
+        Ok(vec![action])
+    }
+
+    fn drop_table(&mut self) -> Result<Statement, ParseError> {
+        let line = self.peek().map(|t| t.line).unwrap_or(1);
+        self.expect_word("DROP")?;
+        self.expect_word("TABLE")?;
+        self.eat_words(&["IF", "EXISTS"]);
+        let mut names = vec![self.qualified_name("a table name")?];
+        while self.eat_punct(',') {
+            names.push(self.qualified_name("a table name")?);
+        }
+        self.skip_statement()?;
+        Ok(Statement::Drop { names, line })
+    }
+
+    fn at_table_constraint(&self) -> bool {
+        ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN", "EXCLUDE"]
+            .iter()
+            .any(|k| self.peek_is_word(k))
+    }
+
+    fn table_constraint(&mut self) -> Result<TableConstraint, ParseError> {
+        let name = if self.eat_word("CONSTRAINT") {
+            Some(self.ident("a constraint name")?)
+        } else {
+            None
+        };
+
+        let constraint = if self.eat_words(&["PRIMARY", "KEY"]) {
+            TableConstraint::PrimaryKey { name, columns: self.ident_list()? }
+        } else if self.eat_word("UNIQUE") {
+            self.nulls_distinct();
+            TableConstraint::Unique { name, columns: self.ident_list()? }
+        } else if self.eat_word("CHECK") {
+            TableConstraint::Check { name, expr: self.balanced_text()? }
+        } else if self.eat_words(&["FOREIGN", "KEY"]) {
+            let columns = self.ident_list()?;
+            self.expect_word("REFERENCES")?;
+            let references = self.references()?;
+            TableConstraint::ForeignKey { name, columns, references }
+        } else if self.peek_is_word("EXCLUDE") {
+            let start = self.pos;
+            self.skip_until_element_end();
+            TableConstraint::Other { name, text: self.text_between(start, self.pos) }
+        } else {
+            return Err(self.unexpected("a table constraint"));
+        };
+
+        self.constraint_attributes();
+        // index parameters: INCLUDE (...), WITH (...), USING INDEX TABLESPACE x
+        if matches!(constraint, TableConstraint::PrimaryKey { .. } | TableConstraint::Unique { .. }) {
+            self.skip_until_element_end();
+        }
+        Ok(constraint)
+    }
+
+    /// A column definition. Constraints given a name with `CONSTRAINT` come
+    /// back as the table constraints they are, which have a name to keep.
+    fn column_def(&mut self) -> Result<(ColumnDef, Vec<TableConstraint>), ParseError> {
+        let line = self.peek().map(|t| t.line).unwrap_or(1);
+        let name = self.ident("a column name")?;
+        let data_type = self.data_type()?;
+        let mut constraints = Vec::new();
+        let mut named = Vec::new();
+        let mut constraint_name = None;
+
+        while let Some(t) = self.peek() {
+            if t.is_punct(',') || t.is_punct(')') || t.is_punct(';') {
+                break;
+            }
+            if self.eat_word("CONSTRAINT") {
+                constraint_name = Some(self.ident("a constraint name")?);
+                continue;
+            }
+            let given = constraint_name.take();
+            if given.is_some() && ["PRIMARY", "UNIQUE", "CHECK", "REFERENCES"].iter().any(|k| self.peek_is_word(k)) {
+                let columns = vec![name.clone()];
+                let constraint = if self.eat_words(&["PRIMARY", "KEY"]) {
+                    TableConstraint::PrimaryKey { name: given, columns }
+                } else if self.eat_word("UNIQUE") {
+                    self.nulls_distinct();
+                    TableConstraint::Unique { name: given, columns }
+                } else if self.eat_word("CHECK") {
+                    let expr = self.balanced_text()?;
+                    self.eat_words(&["NO", "INHERIT"]);
+                    TableConstraint::Check { name: given, expr }
+                } else {
+                    self.expect_word("REFERENCES")?;
+                    TableConstraint::ForeignKey { name: given, columns, references: self.references()? }
+                };
+                named.push(constraint);
+                continue;
+            }
+            if self.eat_words(&["NOT", "NULL"]) {
+                constraints.push(ColumnConstraint::NotNull);
+            } else if self.eat_word("NULL") {
+                constraints.push(ColumnConstraint::Null);
+            } else if self.eat_words(&["PRIMARY", "KEY"]) {
+                constraints.push(ColumnConstraint::PrimaryKey);
+            } else if self.eat_word("UNIQUE") {
+                self.nulls_distinct();
+                constraints.push(ColumnConstraint::Unique);
+            } else if self.eat_word("DEFAULT") {
+                constraints.push(ColumnConstraint::Default(self.default_expr()?));
+            } else if self.eat_word("CHECK") {
+                constraints.push(ColumnConstraint::Check(self.balanced_text()?));
+                self.eat_words(&["NO", "INHERIT"]);
+            } else if self.eat_word("REFERENCES") {
+                constraints.push(ColumnConstraint::References(self.references()?));
+            } else if self.eat_word("GENERATED") {
+                self.generated()?;
+                constraints.push(ColumnConstraint::Generated);
+            } else if self.eat_word("COLLATE") {
+                let collation = self.qualified_name("a collation name")?;
+                constraints.push(ColumnConstraint::Collate(collation));
+            } else if !self.constraint_attributes() {
+                return Err(self.unexpected(&format!("a constraint for column '{}'", name)));
+            }
+        }
+
+        Ok((ColumnDef { name, data_type, constraints, line }, named))
+    }
+
+    fn data_type(&mut self) -> Result<DataType, ParseError> {
+        let first = match self.peek().map(|t| &t.kind) {
+            // `"char"` is postgres' one byte type, not CHAR(n)
+            Some(TokenKind::QuotedIdent(w)) if w == "char" => "\"char\"".to_string(),
+            Some(TokenKind::Word(w)) | Some(TokenKind::QuotedIdent(w)) => w.to_uppercase(),
+            _ => return Err(self.unexpected("a column type")),
+        };
+        self.pos += 1;
+
+        let mut name = match first.as_str() {
+            "DOUBLE" => {
+                self.expect_word("PRECISION")?;
+                "DOUBLE PRECISION".to_string()
+            }
+            "CHARACTER" if self.eat_word("VARYING") => "VARCHAR".to_string(),
+            "CHARACTER" => "CHAR".to_string(),
+            "BIT" if self.eat_word("VARYING") => "VARBIT".to_string(),
+            "BOOLEAN" => "BOOL".to_string(),
+            "DECIMAL" => "NUMERIC".to_string(),
+            "FLOAT" => "FLOAT8".to_string(),
+            _ => first,
+        };
+
+        // user defined types may be schema qualified
+        while self.eat_punct('.') {
+            name = self.ident("a type name")?.to_uppercase();
+        }
+
+        let mut args = Vec::new();
+        if self.peek_is_punct('(') {
+            let inner = self.balanced_text()?;
+            args = inner.split(',').map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect();
+        }
+
+        if name == "TIMESTAMP" || name == "TIME" {
+            if self.eat_words(&["WITH", "TIME", "ZONE"]) {
+                name.push_str("TZ");
+            } else {
+                self.eat_words(&["WITHOUT", "TIME", "ZONE"]);
+            }
+        }
+
+        let mut array = false;
+        loop {
+            if self.eat_punct('[') {
+                if let Some(Token { kind: TokenKind::Number(_), .. }) = self.peek() {
+                    self.pos += 1;
+                }
+                self.expect_punct(']')?;
+                array = true;
+            } else if self.eat_word("ARRAY") {
+                array = true;
+            } else {
+                break;
+            }
+        }
+
+        Ok(DataType { name, args, array })
+    }
+
+    fn references(&mut self) -> Result<ForeignKeyRef, ParseError> {
+        let table = self.qualified_name("a referenced table name")?;
+        let columns = if self.peek_is_punct('(') { self.ident_list()? } else { Vec::new() };
+        let mut on_delete = None;
+        let mut on_update = None;
+
+        loop {
+            if self.eat_word("MATCH") {
+                self.ident("FULL, PARTIAL or SIMPLE")?;
+            } else if self.eat_words(&["ON", "DELETE"]) {
+                on_delete = Some(self.referential_action()?);
+            } else if self.eat_words(&["ON", "UPDATE"]) {
+                on_update = Some(self.referential_action()?);
+            } else {
+                break;
+            }
+        }
+
+        Ok(ForeignKeyRef { table, columns, on_delete, on_update })
+    }
+
+    fn referential_action(&mut self) -> Result<String, ParseError> {
+        let action = if self.eat_words(&["NO", "ACTION"]) {
+            "NO ACTION"
+        } else if self.eat_word("RESTRICT") {
+            "RESTRICT"
+        } else if self.eat_word("CASCADE") {
+            "CASCADE"
+        } else if self.eat_words(&["SET", "NULL"]) {
+            "SET NULL"
+        } else if self.eat_words(&["SET", "DEFAULT"]) {
+            "SET DEFAULT"
+        } else {
+            return Err(self.unexpected("a referential action"));
+        };
+        // postgres 15 allows SET NULL (cols)
+        if action.starts_with("SET") && self.peek_is_punct('(') {
+            self.ident_list()?;
+        }
+        Ok(action.to_string())
+    }
+
+    fn generated(&mut self) -> Result<(), ParseError> {
+        if !self.eat_word("ALWAYS") {
+            self.expect_word("BY")?;
+            self.expect_word("DEFAULT")?;
+        }
+        self.expect_word("AS")?;
+        if self.eat_word("IDENTITY") {
+            if self.peek_is_punct('(') {
+                self.balanced_text()?;
+            }
+        } else {
+            self.balanced_text()?;
+            self.expect_word("STORED")?;
+        }
+        Ok(())
+    }
+
+    /// Skips `NULLS [NOT] DISTINCT` after UNIQUE.
+    fn nulls_distinct(&mut self) {
+        if !self.eat_words(&["NULLS", "DISTINCT"]) {
+            self.eat_words(&["NULLS", "NOT", "DISTINCT"]);
+        }
+    }
+
+    /// Skips DEFERRABLE / INITIALLY attributes. Returns true if any were found.
+    fn constraint_attributes(&mut self) -> bool {
+        let mut found = false;
+        loop {
+            if self.eat_word("DEFERRABLE") || self.eat_words(&["NOT", "DEFERRABLE"]) {
+                found = true;
+            } else if self.eat_word("INITIALLY") {
+                self.pos += 1;
+                found = true;
+            } else {
+                return found;
+            }
+        }
+    }
+
+    /// Reads an expression up to the next constraint keyword, `,` or `)` at depth zero.
+    fn default_expr(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        let mut depth = 0usize;
+        while let Some(t) = self.peek() {
+            if depth == 0 {
+                let at_keyword = self.pos > start
+                    && COLUMN_CONSTRAINT_KEYWORDS.iter().any(|k| t.is_word(k));
+                if t.is_punct(',') || t.is_punct(')') || t.is_punct(';') || at_keyword {
+                    break;
+                }
+            }
+            if t.is_punct('(') {
+                depth += 1;
+            } else if t.is_punct(')') {
+                depth -= 1;
+            }
+            self.pos += 1;
+        }
+        if depth > 0 {
+            return Err(self.unexpected("')'"));
+        }
+        if self.pos == start {
+            return Err(self.unexpected("a default expression"));
+        }
+        Ok(self.text_between(start, self.pos))
+    }
+
+    /// Reads a parenthesised group and returns the source text between the parens.
+    fn balanced_text(&mut self) -> Result<String, ParseError> {
+        self.expect_punct('(')?;
+        let start = self.pos;
+        let mut depth = 1usize;
+        while let Some(t) = self.peek() {
+            if t.is_punct('(') {
+                depth += 1;
+            } else if t.is_punct(')') {
+                depth -= 1;
+                if depth == 0 {
+                    let text = self.text_between(start, self.pos);
+                    self.pos += 1;
+                    return Ok(text);
+                }
+            } else if t.is_punct(';') {
+                break;
+            }
+            self.pos += 1;
+        }
+        Err(self.unexpected("')'"))
+    }
+
+    /// Skips to the `,` or `)` that ends the current table element.
+    fn skip_until_element_end(&mut self) {
+        let mut depth = 0usize;
+        while let Some(t) = self.peek() {
+            if depth == 0 && (t.is_punct(',') || t.is_punct(')') || t.is_punct(';')) {
+                return;
+            }
+            if t.is_punct('(') {
+                depth += 1;
+            } else if t.is_punct(')') {
+                depth -= 1;
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// The original source text of tokens `start..end`.
+    fn text_between(&self, start: usize, end: usize) -> String {
+        if start >= end {
+            return String::new();
+        }
+        self.src[self.tokens[start].start..self.tokens[end - 1].end].trim().to_string()
+    }
+}
+
+/// Line and column of the byte offset `pos` in `src`.
+fn position_after(src: &str, pos: usize) -> (usize, usize) {
+    let before = &src[..pos];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().map(|l| l.chars().count()).unwrap_or(0) + 1;
+    (line, col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(src: &str) -> TableDef {
+        let mut tables = parse_tables(src).unwrap();
+        assert_eq!(tables.len(), 1);
+        tables.remove(0)
+    }
+
+    fn column<'a>(table: &'a TableDef, name: &str) -> &'a ColumnDef {
+        table.columns.iter().find(|col| col.name == name).unwrap()
+    }
+
+    #[test]
+    fn numeric_keeps_precision_and_scale() {
+        let t = table("CREATE TABLE t (price NUMERIC(10,2), total DECIMAL(12, 4), n NUMERIC);");
+        assert_eq!(column(&t, "price").data_type, DataType { name: "NUMERIC".into(), args: vec!["10".into(), "2".into()], array: false });
+        assert_eq!(column(&t, "total").data_type.name, "NUMERIC");
+        assert_eq!(column(&t, "total").data_type.args, vec!["12", "4"]);
+        assert!(column(&t, "n").data_type.args.is_empty());
+    }
+
+    #[test]
+    fn multi_word_types_and_arrays() {
+        let t = table(
+            "CREATE TABLE t (a CHARACTER VARYING(20), b DOUBLE PRECISION, c TIMESTAMP WITH TIME ZONE, d TEXT[], e INT ARRAY, f INTEGER[3][3])",
+        );
+        assert_eq!(column(&t, "a").data_type.name, "VARCHAR");
+        assert_eq!(column(&t, "a").data_type.args, vec!["20"]);
+        assert_eq!(column(&t, "b").data_type.name, "DOUBLE PRECISION");
+        assert_eq!(column(&t, "c").data_type.name, "TIMESTAMPTZ");
+        assert!(!column(&t, "c").data_type.array);
+        for name in ["d", "e", "f"] {
+            assert!(column(&t, name).data_type.array, "{} is an array", name);
+        }
+    }
+
+    #[test]
+    fn column_and_table_checks() {
+        let t = table(
+            "CREATE TABLE t (
+                age INT CHECK (age >= 0 AND (age < 150)) NOT NULL,
+                lo INT,
+                hi INT,
+                CONSTRAINT ordered CHECK (lo <= hi)
+            )",
+        );
+        assert_eq!(
+            column(&t, "age").constraints,
+            vec![ColumnConstraint::Check("age >= 0 AND (age < 150)".into()), ColumnConstraint::NotNull]
+        );
+        assert_eq!(t.constraints, vec![TableConstraint::Check { name: Some("ordered".into()), expr: "lo <= hi".into() }]);
+    }
+
+    #[test]
+    fn comments_anywhere_in_the_statement() {
+        let t = table(
+            "-- users of the app
+            CREATE TABLE /* inline */ users (
+                id SERIAL PRIMARY KEY, -- the key
+                /* a block
+                   over lines */
+                name TEXT DEFAULT 'a -- not a comment'
+            );",
+        );
+        assert_eq!(t.name, "users");
+        assert_eq!(t.columns.len(), 2);
+        assert_eq!(column(&t, "name").constraints, vec![ColumnConstraint::Default("'a -- not a comment'".into())]);
+    }
+
+    #[test]
+    fn quoted_identifiers_keep_their_case() {
+        let t = table(r#"CREATE TABLE public."User" ("Id" INT PRIMARY KEY, Name TEXT, "order" INT)"#);
+        assert_eq!(t.name, "User");
+        assert_eq!(t.written_name, r#"public."User""#);
+        let names: Vec<&str> = t.columns.iter().map(|col| col.name.as_str()).collect();
+        assert_eq!(names, vec!["Id", "name", "order"]);
+    }
+
+    #[test]
+    fn composite_primary_and_foreign_keys() {
+        let t = table(
+            "CREATE TABLE enrollments (
+                student_id INT,
+                course_id INT,
+                term TEXT,
+                PRIMARY KEY (student_id, course_id),
+                FOREIGN KEY (course_id, term) REFERENCES courses (id, term) ON DELETE CASCADE
+            )",
+        );
+        assert_eq!(t.constraints, vec![
+            TableConstraint::PrimaryKey { name: None, columns: vec!["student_id".into(), "course_id".into()] },
+            TableConstraint::ForeignKey {
+                name: None,
+                columns: vec!["course_id".into(), "term".into()],
+                references: ForeignKeyRef {
+                    table: "courses".into(),
+                    columns: vec!["id".into(), "term".into()],
+                    on_delete: Some("CASCADE".into()),
+                    on_update: None,
+                },
+            },
+        ]);
+    }
+
+    #[test]
+    fn named_column_constraints_become_table_constraints() {
+        let t = table("CREATE TABLE runs (user_id INT NOT NULL CONSTRAINT fk_runs_owner REFERENCES users, code TEXT CONSTRAINT runs_code UNIQUE)");
+        assert_eq!(column(&t, "user_id").constraints, vec![ColumnConstraint::NotNull]);
+        assert!(column(&t, "code").constraints.is_empty());
+        assert_eq!(t.constraints, vec![
+            TableConstraint::ForeignKey {
+                name: Some("fk_runs_owner".into()),
+                columns: vec!["user_id".into()],
+                references: ForeignKeyRef { table: "users".into(), columns: vec![], on_delete: None, on_update: None },
+            },
+            TableConstraint::Unique { name: Some("runs_code".into()), columns: vec!["code".into()] },
+        ]);
+
+        let statements = parse_statements("ALTER TABLE runs ADD COLUMN coach_id INT CONSTRAINT fk_runs_coach REFERENCES users;").unwrap();
+        match &statements[0] {
+            Statement::Alter { actions, .. } => {
+                assert!(matches!(&actions[0], AlterAction::AddColumn(col) if col.name == "coach_id"));
+                assert!(matches!(&actions[1], AlterAction::AddConstraint(TableConstraint::ForeignKey { name: Some(name), .. }) if name == "fk_runs_coach"));
+            }
+            other => panic!("expected an ALTER TABLE, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_statements_are_skipped_and_alters_kept() {
+        let statements = parse_statements(
+            "CREATE INDEX i ON t (a);
+            INSERT INTO t VALUES (1, ';');
+            ALTER TABLE t ADD COLUMN b TEXT, DROP COLUMN c;
+            DROP TABLE IF EXISTS x, y CASCADE;",
+        ).unwrap();
+        assert_eq!(statements.len(), 2);
+        match &statements[0] {
+            Statement::Alter { name, actions, line } => {
+                assert_eq!((name.as_str(), *line), ("t", 3));
+                assert!(matches!(&actions[0], AlterAction::AddColumn(col) if col.name == "b"));
+                assert_eq!(actions[1], AlterAction::DropColumn("c".into()));
+            }
+            other => panic!("expected an ALTER TABLE, got {:?}", other),
+        }
+        assert_eq!(statements[1], Statement::Drop { names: vec!["x".into(), "y".into()], line: 4 });
+    }
+
+    #[test]
+    fn errors_point_at_the_line_and_column() {
+        let error = parse_tables("CREATE TABLE t (\n  id INT\n  name TEXT\n);").unwrap_err();
+        assert_eq!((error.line, error.col), (3, 3));
+        assert_eq!(error.to_string(), "line 3, column 3: expected a constraint for column 'id' but found 'name'");
+
+        // past the last token the error is right after it
+        let error = parse_tables("CREATE TABLE t (id INT").unwrap_err();
+        assert_eq!((error.line, error.col), (1, 23));
+    }
+}
@@ -1,8 +1,9 @@
-use std::{fs::OpenOptions, io::Write, fs::File};
+use crate::manifest;
 
 
-pub fn gen_docker(path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    print!("{}\n", path);
+/// Writes the Dockerfile for the binary `path` into `project_dir`.
+pub fn gen_docker(project_dir: &std::path::Path, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", path);
 let docker = format!(
 "
 # -----------------------------------------------------------------------------
@@ -69,11 +70,10 @@ CMD [\"/app/{path}\"]
   // Create the directory if it doesn't exist
   //std::fs::create_dir_all(path)?;
   
-  let dockerfile_path = format!("../{}/Dockerfile", path);
-  let mut file = File::create(&dockerfile_path)?;
-  file.write_all(docker.as_bytes())?;
+  let dockerfile_path = project_dir.join("Dockerfile");
+  manifest::write_generated(&dockerfile_path, docker)?;
   
-  println!("Dockerfile created at {}", dockerfile_path);
+  println!("Dockerfile created at {}", dockerfile_path.display());
   Ok(())
 }
 
@@ -1,50 +1,85 @@
 
-use std::{fmt::format, io::Write};
 
-pub fn gen_examples(path: &str, func_names: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+use crate::base_structs::{Endpoint, HttpMethod};
+
+pub fn gen_examples(project_dir: &std::path::Path, endpoints: Vec<Endpoint>) -> Result<(), Box<dyn std::error::Error>> {
     // write a stirng to a file
 
-    println!("function name in examples are {:?}", func_names);
     let mut example = String::new();
+    // protected routes want the access token from /auth/login or /auth/register
+    if endpoints.iter().any(|endpoint| endpoint.protected) {
+        example.push_str("
+            const accessToken = \"<access_token from /auth/login>\";
+            ");
+    }
     
     
-    
-    for func_name in func_names {
-        // the if only has two paths because right now there is only add and get functions 
-        // will need to fix for update and delete
-        if func_name.contains("add") {
-            // print struct 
-            println!("struct is {}", "struct name");
-            example.push_str(format!("
-            fetch(\"http://localhost:3002/api/{}\", {{
-                method: 'POST',
-                headers: {{
+    for endpoint in endpoints {
+        // path params such as /:user_id are filled in from a js variable of the same name
+        let url = endpoint.path.split('/')
+            .map(|part| match part.strip_prefix(':') {
+                Some(param) => format!("\" + {} + \"", param),
+                None => part.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let bearer = "'Authorization': 'Bearer ' + accessToken";
+        let (auth_header, auth_headers, auth_options) = match endpoint.protected {
+            true => (
+                format!("\n                    {},", bearer),
+                format!(", headers: {{ {} }}", bearer),
+                format!(", {{ headers: {{ {} }} }}", bearer),
+            ),
+            false => Default::default(),
+        };
+        // only list endpoints take a cursor
+        let paginated = endpoint.operation.params.iter().any(|param| param.name == "cursor");
+        match endpoint.method {
+            HttpMethod::Post | HttpMethod::Put | HttpMethod::Patch => {
+                // the same sample body openapi.json shows for the payload struct
+                let body = endpoint.operation.example.as_ref()
+                    .and_then(|example| serde_json::to_string_pretty(example).ok())
+                    .unwrap_or_else(|| "{}".to_string())
+                    .replace('\n', "\n                ");
+                example.push_str(format!("
+            fetch(\"http://localhost:3002/api{}\", {{
+                method: '{}',
+                headers: {{{}
                     'Content-Type': 'application/json'
                 }},
-                body: JSON.stringify({{
-                    {}
-                }})
+                body: JSON.stringify({})
             }}).then(response => response.json()).then(data => console.log(data)); 
-            ", func_name, String::from("[ add object of key values based on the struct ]")).as_str());
-        } else {
-            example.push_str(format!("
-            fetch(\"http://localhost:3002/api/{}\").then(response => response.json()).then(data => console.log(data));
-            ", func_name).as_str());
+            ", url, endpoint.method.as_str(), auth_header, body).as_str());
+            }
+            HttpMethod::Delete => {
+                example.push_str(format!("
+            fetch(\"http://localhost:3002/api{}\", {{ method: 'DELETE'{} }}).then(response => response.json()).then(data => console.log(data));
+            ", url, auth_headers).as_str());
+            }
+            // list endpoints are paginated, so show how to follow next_cursor
+            HttpMethod::Get if paginated => {
+                example.push_str(format!("
+            const getPage = (cursor) => fetch(\"http://localhost:3002/api{0}?limit=20\" + (cursor ? \"&cursor=\" + cursor : \"\"){1})
+                .then(response => response.json())
+                .then(page => {{
+                    console.log(page.payload, page.total_count);
+                    if (page.next_cursor) {{
+                        return getPage(page.next_cursor);
+                    }}
+                }});
+            getPage();
+            ", url, auth_options).as_str());
+            }
+            HttpMethod::Get => {
+                example.push_str(format!("
+            fetch(\"http://localhost:3002/api{}\"{}).then(response => response.json()).then(data => console.log(data));
+            ", url, auth_options).as_str());
+            }
         }
-        example.push_str("\n");
+        example.push('\n');
     }
 
-    println!("");
-    println!("");
-    println!("example string is {}", example);
-    println!("");
-    println!("");
-    println!("attempting to write to {}\n", "../".to_owned() + path + "/examples.js");
-    println!("");
-    println!("");
-    let mut file = std::fs::File::create("../".to_owned() + path + "/examples.js")?;
-    file.write_all(example.as_bytes())?;
-    println!("");
-    println!("");
+    let examples_path = project_dir.join("examples.js");
+    crate::manifest::write_generated(examples_path, example)?;
     Ok(())
 }
\ No newline at end of file
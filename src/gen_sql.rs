@@ -1,28 +1,218 @@
-use std::{fs::{self, File}, io::Write, process::Command};
+use crate::ddl_parser::{parse_tables, ColumnConstraint, TableConstraint, TableDef};
+use crate::llm_provider::{LlmProvider, Message};
 
-use ollama_rs::{coordinator::Coordinator, generation::chat::ChatMessage, Ollama};
+/// How many times the model gets its problems back before we give up, unless configured.
+pub const DEFAULT_REPAIR_ROUNDS: u32 = 3;
 
-/// Interacts with the Ollama LLM to process SQL generation requests.
+/// The column types `SQL_PROMPT` allows, as the DDL parser names them.
+const ALLOWED_TYPES: &[&str] = &[
+    "BOOL", "CHAR", "SMALLINT", "SMALLSERIAL", "INT2", "INT", "INTEGER", "SERIAL", "INT4", "BIGINT",
+    "BIGSERIAL", "INT8", "REAL", "FLOAT4", "DOUBLE PRECISION", "FLOAT8", "VARCHAR",
+    "TEXT", "NAME", "CITEXT", "BYTEA", "VOID", "INTERVAL",
+    "INT8RANGE", "INT4RANGE", "TSRANGE", "TSTZRANGE", "DATERANGE",
+    "TIMESTAMPTZ", "TIMESTAMP", "DATE", "TIME", "TIMETZ",
+    "UUID", "INET", "CIDR", "MACADDR", "BIT", "VARBIT", "JSON", "JSONB",
+];
+
+/// Asks the LLM for the SQL that defines a database.
 ///
-/// This function sets up a coordinator with the Ollama model and
-/// generates SQL based on the provided prompt.
+/// The rules and examples go in as the system message and `sql_task` as
+/// the user's, so any provider can answer it. Each reply is checked against
+/// those rules with `check_sql`; when it breaks them the problems are sent
+/// back for up to `repair_rounds` more tries.
 ///
 /// # Arguments
 ///
-/// * `project_dir` - The directory where the project is located
-/// * `file_name` - The name of the project
+/// * `llm` - The model to ask
+/// * `sql_task` - What the database should store
+/// * `known_tables` - Tables that already exist and may be referenced
+/// * `repair_rounds` - How many times to ask for a fix
 ///
 /// # Returns
 ///
-/// Returns a `Result` containing the generated SQL as a string, or an error if the operation fails.
-pub async fn gen_sql(project_dir: std::path::PathBuf, file_name: String, sql_task: String) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let model = "llama3.2:latest".to_string();
-    
-    let ollama = Ollama::default();
-    let history = vec![];
-    let mut coordinator = Coordinator::new(ollama, model, history);
-        
-    let prompt = format!(r#"you are a postgresSQL database designer. Here is how you should write postgres SQL code to define a database.
+/// Returns a `Result` containing the generated SQL as a string, or an error listing what was
+/// still wrong with the last reply. Writing it to a migration is left to the caller.
+pub async fn gen_sql(
+    llm: &dyn LlmProvider,
+    sql_task: String,
+    known_tables: &[String],
+    repair_rounds: u32,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    SqlSession::new(llm, known_tables, repair_rounds).ask(format!("now {}", sql_task)).await
+}
+
+/// A conversation about one schema. Each request builds on the replies
+/// before it, so "add a phone column to hosts" changes the last schema
+/// instead of starting over.
+pub struct SqlSession<'a> {
+    llm: &'a dyn LlmProvider,
+    known_tables: &'a [String],
+    repair_rounds: u32,
+    messages: Vec<Message>,
+}
+
+impl<'a> SqlSession<'a> {
+    pub fn new(llm: &'a dyn LlmProvider, known_tables: &'a [String], repair_rounds: u32) -> SqlSession<'a> {
+        SqlSession { llm, known_tables, repair_rounds, messages: vec![Message::system(SQL_PROMPT)] }
+    }
+
+    /// Sends `request` and returns the SQL from the first reply that passes
+    /// `check_sql`. When none does the conversation is put back the way it
+    /// was, so the previous schema still stands.
+    pub async fn ask(&mut self, request: String) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let history_len = self.messages.len();
+        self.messages.push(Message::user(request));
+
+        for round in 0..=self.repair_rounds {
+            let reply = self.llm.complete(&self.messages).await?;
+            let sql = extract_sql(&reply);
+            let problems = check_sql(&sql, self.known_tables);
+            if problems.is_empty() {
+                println!("Generated SQL: {}", sql);
+                self.messages.push(Message::assistant(reply));
+                return Ok(sql);
+            }
+
+            let problems = problems.iter().map(|problem| format!("- {}", problem)).collect::<Vec<_>>().join("\n");
+            eprintln!("Generated SQL was rejected (round {} of {}):\n{}", round + 1, self.repair_rounds + 1, problems);
+            if round == self.repair_rounds {
+                self.messages.truncate(history_len);
+                return Err(Box::new(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("{} did not produce valid SQL after {} repair rounds:\n{}", self.llm.describe(), self.repair_rounds, problems)
+                )));
+            }
+            self.messages.push(Message::assistant(reply));
+            self.messages.push(Message::user(format!(
+                "That SQL breaks the rules:\n{}\nReply with the whole corrected SQL and nothing else.",
+                problems
+            )));
+        }
+        unreachable!("the last round returns")
+    }
+}
+
+/// The SQL in a reply. Models like to wrap their answer in ```sql blocks
+/// even when told not to, and to talk around them; when there are fenced
+/// blocks only their contents count.
+pub fn extract_sql(reply: &str) -> String {
+    let mut blocks = Vec::new();
+    let mut rest = reply;
+    while let Some(start) = rest.find("```") {
+        let body = &rest[start + 3..];
+        // skip the language tag on the opening fence
+        let body = body.split_once('\n').map_or("", |(_, body)| body);
+        let Some(end) = body.find("```") else {
+            blocks.push(body);
+            break;
+        };
+        blocks.push(&body[..end]);
+        rest = &body[end + 3..];
+    }
+
+    if blocks.is_empty() {
+        reply.trim().to_string()
+    } else {
+        blocks.iter().map(|block| block.trim()).collect::<Vec<_>>().join("\n\n")
+    }
+}
+
+/// Everything wrong with `sql` by the rules in `SQL_PROMPT`, worded so the
+/// model can act on it. Empty when the SQL is fine.
+pub fn check_sql(sql: &str, known_tables: &[String]) -> Vec<String> {
+    let tables = match parse_tables(sql) {
+        Ok(tables) => tables,
+        Err(e) => return vec![format!("the SQL does not parse: {}", e)],
+    };
+    if tables.is_empty() {
+        return vec!["there are no CREATE TABLE statements".to_string()];
+    }
+
+    let mut problems = Vec::new();
+    for table in &tables {
+        check_table(table, &mut problems);
+        for (column, referenced) in references(table) {
+            let exists = tables.iter().any(|t| t.name == referenced) || known_tables.contains(&referenced);
+            if !exists {
+                problems.push(format!(
+                    "line {}: {}.{} references the table {}, which is not defined",
+                    table.line, table.name, column, referenced
+                ));
+            }
+        }
+    }
+    problems
+}
+
+fn check_table(table: &TableDef, problems: &mut Vec<String>) {
+    let name = &table.name;
+    if !table.if_not_exists {
+        problems.push(format!("line {}: use CREATE TABLE IF NOT EXISTS for {}", table.line, name));
+    }
+    if table.written_name != *name {
+        problems.push(format!(
+            "line {}: name the table {} instead of {}, without a schema or quotes",
+            table.line, name, table.written_name
+        ));
+    }
+
+    for column in &table.columns {
+        let type_name = column.data_type.name.as_str();
+        if type_name == "NUMERIC" {
+            problems.push(format!(
+                "line {}: {}.{} is NUMERIC, use INT or FLOAT instead",
+                column.line, name, column.name
+            ));
+        } else if !ALLOWED_TYPES.contains(&type_name) {
+            problems.push(format!(
+                "line {}: {}.{} has the type {}, which is not one of the allowed types",
+                column.line, name, column.name, type_name
+            ));
+        }
+    }
+
+    let mut primary_key: Vec<&str> = table.columns.iter()
+        .filter(|column| column.constraints.contains(&ColumnConstraint::PrimaryKey))
+        .map(|column| column.name.as_str())
+        .collect();
+    for constraint in &table.constraints {
+        if let TableConstraint::PrimaryKey { columns, .. } = constraint {
+            primary_key.extend(columns.iter().map(String::as_str));
+        }
+    }
+    let uuid_key = match primary_key.as_slice() {
+        [key] => table.columns.iter().find(|column| column.name == *key),
+        _ => None,
+    };
+    let generated = uuid_key.is_some_and(|column| {
+        column.data_type.name == "UUID" && column.constraints.iter().any(|c| {
+            matches!(c, ColumnConstraint::Default(expr) if expr.to_lowercase().contains("gen_random_uuid"))
+        })
+    });
+    if !generated {
+        problems.push(format!(
+            "line {}: {} needs a single UUID PRIMARY KEY column with DEFAULT gen_random_uuid()",
+            table.line, name
+        ));
+    }
+}
+
+/// (column, referenced table) for each foreign key in `table`.
+fn references(table: &TableDef) -> Vec<(String, String)> {
+    let inline = table.columns.iter().flat_map(|column| {
+        column.constraints.iter().filter_map(|c| match c {
+            ColumnConstraint::References(references) => Some((column.name.clone(), references.table.clone())),
+            _ => None,
+        })
+    });
+    let table_level = table.constraints.iter().filter_map(|c| match c {
+        TableConstraint::ForeignKey { columns, references, .. } => Some((columns.join(", "), references.table.clone())),
+        _ => None,
+    });
+    inline.chain(table_level).collect()
+}
+
+const SQL_PROMPT: &str = r#"you are a postgresSQL database designer. Here is how you should write postgres SQL code to define a database.
     
     Tables should be defined with CREATE TABLE IF NOT EXISTS. 
     Only use these datatypes: 
@@ -86,60 +276,93 @@ pub async fn gen_sql(project_dir: std::path::PathBuf, file_name: String, sql_tas
         duration_seconds INT NOT NULL
     );
 
-    
+"#;
 
-    now {}"#, sql_task);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_provider::{FixtureProvider, Role};
 
-    // test python process 
-    let output = Command::new("./.venv/bin/python3")
-        .arg("llm.py")
-        .arg(prompt.clone())
-        .output()
-        .expect("Failed to execute Python command");
+    const GOOD: &str = "CREATE TABLE IF NOT EXISTS hosts (
+    host_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL
+);";
 
-    // Check if Python script executed successfully
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        eprintln!("Python script failed with error: {}", error_msg);
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("Python script execution failed: {}", error_msg)
-        )));
+    #[test]
+    fn fenced_blocks_are_the_sql() {
+        let tagged = format!("Here is the schema:\n```sql\n{}\n```\nEnjoy!", GOOD);
+        assert_eq!(extract_sql(&tagged), GOOD);
+        let untagged = format!("```\n{}\n```", GOOD);
+        assert_eq!(extract_sql(&untagged), GOOD);
+        // a reply cut off before its closing fence still has its SQL
+        let unclosed = format!("```sql\n{}\n", GOOD);
+        assert_eq!(extract_sql(&unclosed), GOOD);
+        let two = "```sql\nCREATE TABLE a ();\n```\nand\n```sql\nCREATE TABLE b ();\n```";
+        assert_eq!(extract_sql(two), "CREATE TABLE a ();\n\nCREATE TABLE b ();");
+        assert_eq!(extract_sql(&format!("\n{}\n\n", GOOD)), GOOD);
     }
 
-    let sql = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    
-    if sql.is_empty() {
-        return Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::InvalidData,
-            "Python script returned empty SQL"
-        )));
+    #[test]
+    fn good_sql_has_no_problems() {
+        assert!(check_sql(GOOD, &[]).is_empty());
+        let child = "CREATE TABLE IF NOT EXISTS visits (
+    visit_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    host_id UUID REFERENCES hosts(host_id)
+);";
+        assert!(check_sql(child, &["hosts".to_string()]).is_empty());
     }
 
-    println!("Generated SQL: {}", sql);
+    #[test]
+    fn broken_rules_are_worded_for_the_model() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let sql = "CREATE TABLE hosts (
+    host_id SERIAL PRIMARY KEY,
+    rate NUMERIC(5, 2),
+    region_id UUID REFERENCES regions(region_id)
+);";
+        assert_eq!(check_sql(sql, &[]), [
+            "line 1: use CREATE TABLE IF NOT EXISTS for hosts",
+            "line 3: hosts.rate is NUMERIC, use INT or FLOAT instead",
+            "line 1: hosts needs a single UUID PRIMARY KEY column with DEFAULT gen_random_uuid()",
+            "line 1: hosts.region_id references the table regions, which is not defined",
+        ]);
+        assert_eq!(check_sql("SELECT 1;", &[]), ["there are no CREATE TABLE statements"]);
+        assert!(check_sql("CREATE TABLE (", &[])[0].starts_with("the SQL does not parse: "));
+    }
 
-    let migrations_dir = project_dir.join("migrations");
-    let sql_path = migrations_dir.join("0001_data.sql");
-    
-    println!("Creating SQL file at: {}", sql_path.display());
-    
-    // Create parent directories
-    println!("Creating directory: {}", migrations_dir.display());
-    fs::create_dir_all(&migrations_dir).map_err(|e| {
-        eprintln!("Error creating directory: {}", e);
-        e
-    })?;
-    
-    // Create and write to the file
-    println!("Creating file: {}", sql_path.display());
-    let mut file = File::create(&sql_path).map_err(|e| {
-        eprintln!("Error creating file: {}", e);
-        e
-    })?;
-
-    file.write_all(sql.as_bytes()).map_err(|e| {
-        eprintln!("Error writing to file: {}", e);
-        e
-    })?;
-    Ok("success".to_string())
+    fn replies(replies: &[&str]) -> (tempfile::TempDir, FixtureProvider) {
+        let dir = tempfile::tempdir().unwrap();
+        for (i, reply) in replies.iter().enumerate() {
+            std::fs::write(dir.path().join(format!("{:02}.txt", i)), reply).unwrap();
+        }
+        let provider = FixtureProvider::load(dir.path()).unwrap();
+        (dir, provider)
+    }
+
+    #[tokio::test]
+    async fn rejected_sql_goes_back_to_be_fixed() {
+        let bad = "CREATE TABLE hosts (host_id UUID PRIMARY KEY DEFAULT gen_random_uuid());";
+        let (_dir, llm) = replies(&[bad, &format!("```sql\n{}\n```", GOOD)]);
+        let mut session = SqlSession::new(&llm, &[], 1);
+        assert_eq!(session.ask("now store hosts".to_string()).await.unwrap(), GOOD);
+
+        let roles: Vec<Role> = session.messages.iter().map(|message| message.role).collect();
+        assert_eq!(roles, [Role::System, Role::User, Role::Assistant, Role::User, Role::Assistant]);
+        assert_eq!(session.messages[2].content, bad);
+        assert!(session.messages[3].content.contains("use CREATE TABLE IF NOT EXISTS for hosts"));
+    }
+
+    #[tokio::test]
+    async fn a_failed_request_leaves_the_conversation_as_it_was() {
+        let bad = "CREATE TABLE IF NOT EXISTS hosts (host_id SERIAL PRIMARY KEY);";
+        let (_dir, llm) = replies(&[GOOD, bad]);
+        let mut session = SqlSession::new(&llm, &[], 2);
+        session.ask("now store hosts".to_string()).await.unwrap();
+        let before = session.messages.clone();
+
+        let error = session.ask("give hosts a serial key".to_string()).await.unwrap_err();
+        assert!(error.to_string().contains("did not produce valid SQL after 2 repair rounds"), "{}", error);
+        assert!(error.to_string().contains("hosts needs a single UUID PRIMARY KEY"), "{}", error);
+        assert_eq!(session.messages, before);
+    }
 }
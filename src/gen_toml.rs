@@ -1,48 +1,144 @@
 
 
-use std::{fs::{File, OpenOptions}, io::Write};
+use std::io;
+use std::path::Path;
+
+use toml_edit::DocumentMut;
+
+use crate::vfs;
 
 /// Generates a TOML configuration file for a Rust project.
 ///
-/// This function creates a TOML file with specific dependencies for a Rust project 
-/// located at the given `project_dir` with the specified `file_name`.
+/// This function merges the generated service's dependencies into the
+/// Cargo.toml of the project located at the given `project_dir`.
 ///
 /// # Arguments
 ///
 /// * `project_dir` - The directory where the project is located.
-/// * `file_name` - The name of the project.
 ///
 /// # Returns
 ///
 /// Returns a `Result` containing the generated TOML content as a string, 
 /// or an error if the operation fails.
-pub async fn gen_toml(project_dir: &std::path::PathBuf) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+pub async fn gen_toml(project_dir: &std::path::Path) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
     let deps = "
-    axum = { version = \"0.7\", features = [\"macros\"] }
+axum = { version = \"0.7\", features = [\"macros\"] }
 tokio = { version = \"1\", features = [\"full\", \"time\"] }
 serde = { version = \"1\", features = [\"derive\"] }
 serde_json = \"1\"
-sqlx = { version = \"0.7\", features = [\"runtime-tokio-rustls\", \"postgres\", \"chrono\", \"uuid\"] } # Added \"uuid\" feature as it's often used with database interactions.
+sqlx = { version = \"0.7\", features = [\"runtime-tokio-rustls\", \"postgres\", \"chrono\", \"uuid\", \"rust_decimal\"] } # Added \"uuid\" feature as it's often used with database interactions.
 dotenv = \"0.15\" # Useful for loading environment variables like your database URL
 tower-http = { version = \"0.5\", features = [\"cors\"] } # For CorsLayer
 chrono = { version = \"0.4\", features = [\"serde\"] } # For Utc
 uuid = { version = \"1\", features = [\"serde\", \"v4\"] } # For UUID generation and serialization
+rust_decimal = { version = \"1\", features = [\"serde\"] } # NUMERIC columns, serialized as strings
 tempfile = \"3.3\"
 anyhow = \"1.0\"
 minio-rsc = \"0.2.6\"
 reqwest = { version = \"0.11\", features = [\"json\"] }
-    ";
- 
-    let mut file = OpenOptions::new()
-        .write(true) // Enable writing to the file.
-        .append(true) // Set the append mode.  Crucially, this makes it append.
-        .create(true) // Create the file if it doesn't exist.
-        .open(project_dir.join("Cargo.toml"))?; // Open the file, returning a Result.
-
-    file.write_all(deps.as_bytes()).map_err(|e| {
+";
+
+    let added = merge_dependencies(&project_dir.join("Cargo.toml"), "dependencies", deps).map_err(|e| {
         eprintln!("Error writing to file: {}", e);
         e
     })?;
+    if added.is_empty() {
+        println!("Cargo.toml already has the generated dependencies");
+    }
     Ok(deps.to_string())
 }
-    
+
+/// Adds the crates in `deps` that `[{section}]` of `cargo_toml` doesn't
+/// list yet, keeping the rest of the file as it is. A crate the project
+/// already has keeps its version and features. Returns the crates added.
+pub fn merge_dependencies(cargo_toml: &Path, section: &str, deps: &str) -> Result<Vec<String>, io::Error> {
+    let invalid = |e: toml_edit::TomlError| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", cargo_toml.display(), e))
+    };
+    let mut doc = vfs::read_to_string(cargo_toml)?.parse::<DocumentMut>().map_err(invalid)?;
+    let deps = deps.parse::<DocumentMut>().expect("the generated dependencies are valid TOML");
+
+    let table = doc.entry(section).or_insert(toml_edit::table()).as_table_like_mut().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("[{}] in {} is not a table", section, cargo_toml.display()))
+    })?;
+    let mut added = Vec::new();
+    for (name, item) in deps.iter() {
+        if !table.contains_key(name) {
+            table.insert(name, item.clone());
+            added.push(name.to_string());
+        }
+    }
+    if !added.is_empty() {
+        vfs::write(cargo_toml, doc.to_string())?;
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARGO_TOML: &str = r#"[package]
+name = "service"
+version = "0.1.0"
+
+# pinned for the deploy image
+[dependencies]
+serde = { version = "1.0.100", features = ["derive", "rc"] }
+anyhow = "1"
+"#;
+
+    #[test]
+    fn adds_missing_crates_and_keeps_the_rest() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let cargo_toml = dir.path().join("Cargo.toml");
+        std::fs::write(&cargo_toml, CARGO_TOML).unwrap();
+
+        let added = merge_dependencies(
+            &cargo_toml,
+            "dependencies",
+            "serde = { version = \"1.0\", features = [\"derive\"] }\naxum = \"0.7\"\n",
+        ).unwrap();
+        assert_eq!(added, vec!["axum"]);
+
+        let merged = std::fs::read_to_string(&cargo_toml).unwrap();
+        assert!(merged.starts_with(CARGO_TOML));
+        assert!(merged.ends_with("axum = \"0.7\"\n"));
+    }
+
+    #[test]
+    fn creates_a_missing_section() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let cargo_toml = dir.path().join("Cargo.toml");
+        std::fs::write(&cargo_toml, CARGO_TOML).unwrap();
+
+        let added = merge_dependencies(&cargo_toml, "dev-dependencies", "tokio = \"1\"\n").unwrap();
+        assert_eq!(added, vec!["tokio"]);
+        let merged = std::fs::read_to_string(&cargo_toml).unwrap();
+        assert!(merged.ends_with("[dev-dependencies]\ntokio = \"1\"\n"));
+    }
+
+    #[test]
+    fn leaves_a_complete_file_untouched() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let cargo_toml = dir.path().join("Cargo.toml");
+        std::fs::write(&cargo_toml, CARGO_TOML).unwrap();
+
+        assert!(merge_dependencies(&cargo_toml, "dependencies", "anyhow = \"1.0.80\"\n").unwrap().is_empty());
+        assert_eq!(std::fs::read_to_string(&cargo_toml).unwrap(), CARGO_TOML);
+    }
+
+    #[test]
+    fn rejects_a_broken_file() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let cargo_toml = dir.path().join("Cargo.toml");
+        std::fs::write(&cargo_toml, "[dependencies\n").unwrap();
+
+        let err = merge_dependencies(&cargo_toml, "dependencies", "axum = \"0.7\"\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,415 @@
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+use clap::{Args, ValueEnum};
+use ollama_rs::generation::chat::request::ChatMessageRequest;
+use ollama_rs::generation::chat::ChatMessage;
+use ollama_rs::models::ModelOptions;
+use ollama_rs::Ollama;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+const DEFAULT_OLLAMA_MODEL: &str = "llama3.2:latest";
+const DEFAULT_OPENAI_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_OPENAI_MODEL: &str = "gpt-4o-mini";
+const DEFAULT_API_KEY_ENV: &str = "OPENAI_API_KEY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+/// One turn of a conversation with the model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    pub role: Role,
+    pub content: String,
+}
+
+impl Message {
+    pub fn system(content: impl Into<String>) -> Message {
+        Message { role: Role::System, content: content.into() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Message {
+        Message { role: Role::User, content: content.into() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Message {
+        Message { role: Role::Assistant, content: content.into() }
+    }
+}
+
+/// A model that answers a conversation. Generators only talk to this trait,
+/// so which model runs where is down to the project config.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Which provider and model this is, for log messages.
+    fn describe(&self) -> String;
+
+    /// The model's reply to `messages`.
+    async fn complete(&self, messages: &[Message]) -> Result<String, Box<dyn Error + Send + Sync>>;
+}
+
+/// A model served by Ollama.
+pub struct OllamaProvider {
+    ollama: Ollama,
+    model: String,
+}
+
+impl OllamaProvider {
+    pub fn new(base_url: &str, model: &str) -> Result<OllamaProvider, io::Error> {
+        Ok(OllamaProvider { ollama: ollama_client(base_url)?, model: model.to_string() })
+    }
+}
+
+fn ollama_client(base_url: &str) -> Result<Ollama, io::Error> {
+    Ollama::try_new(base_url).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("'{}' is not an Ollama url: {}", base_url, e))
+    })
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn describe(&self) -> String {
+        format!("ollama ({})", self.model)
+    }
+
+    async fn complete(&self, messages: &[Message]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let messages = messages.iter()
+            .map(|message| match message.role {
+                Role::System => ChatMessage::system(message.content.clone()),
+                Role::User => ChatMessage::user(message.content.clone()),
+                Role::Assistant => ChatMessage::assistant(message.content.clone()),
+            })
+            .collect();
+        // schema generation wants the same answer for the same question
+        let request = ChatMessageRequest::new(self.model.clone(), messages)
+            .options(ModelOptions::default().temperature(0.0));
+        let response = self.ollama.send_chat_messages(request).await?;
+        Ok(response.message.content)
+    }
+}
+
+/// Any server speaking OpenAI's `/chat/completions`: OpenAI itself,
+/// OpenRouter, vLLM or a llama.cpp server.
+pub struct OpenAiProvider {
+    client: reqwest::Client,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    temperature: f32,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatChoiceMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatChoiceMessage {
+    content: Option<String>,
+}
+
+impl OpenAiProvider {
+    pub fn new(base_url: &str, model: &str, api_key: Option<String>) -> OpenAiProvider {
+        OpenAiProvider {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            api_key,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn describe(&self) -> String {
+        format!("{} ({})", self.base_url, self.model)
+    }
+
+    async fn complete(&self, messages: &[Message]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let mut request = self.client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&ChatRequest { model: &self.model, messages, temperature: 0.0 });
+        // local servers such as llama.cpp don't need a key
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("{} answered {}: {}", self.base_url, status, body).into());
+        }
+        let response: ChatResponse = response.json().await?;
+        response.choices.into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| format!("{} returned no message", self.base_url).into())
+    }
+}
+
+/// Canned replies read from disk, so runs and tests are repeatable without
+/// a model. A file is the reply to every call; a directory holds one reply
+/// per file, handed out in name order, with the last one repeated.
+pub struct FixtureProvider {
+    replies: Vec<String>,
+    next: AtomicUsize,
+}
+
+impl FixtureProvider {
+    pub fn load(path: &Path) -> Result<FixtureProvider, io::Error> {
+        let files = if path.is_dir() {
+            let mut files = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            files.retain(|file| file.is_file());
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let replies = files.iter()
+            .map(|file| std::fs::read_to_string(file).map_err(|e| {
+                io::Error::new(e.kind(), format!("Cannot read fixture {}: {}", file.display(), e))
+            }))
+            .collect::<Result<Vec<_>, _>>()?;
+        if replies.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no fixtures", path.display())));
+        }
+        Ok(FixtureProvider { replies, next: AtomicUsize::new(0) })
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FixtureProvider {
+    fn describe(&self) -> String {
+        format!("fixture ({} replies)", self.replies.len())
+    }
+
+    async fn complete(&self, _messages: &[Message]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let i = self.next.fetch_add(1, Ordering::Relaxed).min(self.replies.len() - 1);
+        Ok(self.replies[i].clone())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// A local or remote Ollama server.
+    Ollama,
+    /// An OpenAI compatible `/chat/completions` endpoint.
+    Openai,
+    /// Replies read from `fixture`, for tests and repeatable runs.
+    Fixture,
+}
+
+/// The LLM flags, which win over `[llm]` in auto-rust.toml.
+#[derive(Debug, Default, Args)]
+pub struct LlmArgs {
+    /// Which kind of LLM writes the SQL. Defaults to ollama.
+    #[arg(long)]
+    pub provider: Option<ProviderKind>,
+    /// Model name, e.g. `llama3.2:latest` or `gpt-4o-mini`.
+    #[arg(long)]
+    pub model: Option<String>,
+    /// Where the provider is served, e.g. `https://openrouter.ai/api/v1`.
+    #[arg(long)]
+    pub base_url: Option<String>,
+    /// A reply file, or a directory of them, for the fixture provider.
+    #[arg(long)]
+    pub fixture: Option<PathBuf>,
+    /// How many times rejected SQL goes back to the model to be fixed.
+    #[arg(long)]
+    pub repair_rounds: Option<u32>,
+}
+
+/// `[llm]` in auto-rust.toml.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlmConfig {
+    pub provider: Option<ProviderKind>,
+    pub model: Option<String>,
+    pub base_url: Option<String>,
+    /// Environment variable holding the API key for `openai`. Defaults to
+    /// `OPENAI_API_KEY`; the key itself never goes in the config.
+    pub api_key_env: Option<String>,
+    /// Relative to the project directory.
+    pub fixture: Option<PathBuf>,
+    pub repair_rounds: Option<u32>,
+}
+
+impl LlmConfig {
+    /// These settings with the flags in `args` laid over them. Paths from
+    /// the config are made relative to `project_dir`.
+    pub fn with_args(&self, project_dir: &Path, args: &LlmArgs) -> LlmConfig {
+        LlmConfig {
+            provider: args.provider.or(self.provider),
+            model: args.model.clone().or_else(|| self.model.clone()),
+            base_url: args.base_url.clone().or_else(|| self.base_url.clone()),
+            api_key_env: self.api_key_env.clone(),
+            repair_rounds: args.repair_rounds.or(self.repair_rounds),
+            // a flag is relative to where we were run, so it still works once saved
+            fixture: args.fixture.as_deref().map(|fixture| std::path::absolute(fixture).unwrap_or(fixture.to_path_buf()))
+                .or_else(|| self.fixture.as_ref().map(|fixture| project_dir.join(fixture))),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == LlmConfig::default()
+    }
+
+    /// The Ollama client and model name, for what only Ollama does here:
+    /// tool calling through its coordinator.
+    pub fn ollama(&self) -> Result<(Ollama, String), io::Error> {
+        if self.provider.is_some_and(|provider| provider != ProviderKind::Ollama) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Tool calling needs the ollama provider"));
+        }
+        let ollama = ollama_client(self.base_url.as_deref().unwrap_or(DEFAULT_OLLAMA_URL))?;
+        let model = self.model.clone().unwrap_or_else(|| DEFAULT_OLLAMA_MODEL.to_string());
+        println!("Using LLM ollama ({})", model);
+        Ok((ollama, model))
+    }
+
+    /// Builds the configured provider, filling in each one's defaults.
+    pub fn provider(&self) -> Result<Box<dyn LlmProvider>, io::Error> {
+        let provider: Box<dyn LlmProvider> = match self.provider.unwrap_or(ProviderKind::Ollama) {
+            ProviderKind::Ollama => Box::new(OllamaProvider::new(
+                self.base_url.as_deref().unwrap_or(DEFAULT_OLLAMA_URL),
+                self.model.as_deref().unwrap_or(DEFAULT_OLLAMA_MODEL),
+            )?),
+            ProviderKind::Openai => {
+                let api_key_env = self.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_ENV);
+                let api_key = std::env::var(api_key_env).ok();
+                if api_key.is_none() && self.base_url.is_none() {
+                    eprintln!("Warning: {} is not set, the request will likely be refused", api_key_env);
+                }
+                Box::new(OpenAiProvider::new(
+                    self.base_url.as_deref().unwrap_or(DEFAULT_OPENAI_URL),
+                    self.model.as_deref().unwrap_or(DEFAULT_OPENAI_MODEL),
+                    api_key,
+                ))
+            }
+            ProviderKind::Fixture => {
+                let fixture = self.fixture.as_deref().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "The fixture provider needs --fixture or llm.fixture")
+                })?;
+                Box::new(FixtureProvider::load(fixture)?)
+            }
+        };
+        println!("Using LLM {}", provider.describe());
+        Ok(provider)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixtures(replies: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, reply) in replies {
+            std::fs::write(dir.path().join(name), reply).unwrap();
+        }
+        dir
+    }
+
+    async fn replies(provider: &FixtureProvider, n: usize) -> Vec<String> {
+        let mut replies = Vec::new();
+        for _ in 0..n {
+            replies.push(provider.complete(&[Message::user("schema?")]).await.unwrap());
+        }
+        replies
+    }
+
+    #[tokio::test]
+    async fn a_directory_hands_out_replies_in_name_order_and_repeats_the_last() {
+        let dir = fixtures(&[("02-fixed.sql", "second"), ("01-broken.sql", "first"), ("10-final.sql", "third")]);
+        std::fs::create_dir(dir.path().join("00-not-a-reply")).unwrap();
+        let provider = FixtureProvider::load(dir.path()).unwrap();
+        assert_eq!(provider.describe(), "fixture (3 replies)");
+        assert_eq!(replies(&provider, 5).await, ["first", "second", "third", "third", "third"]);
+    }
+
+    #[tokio::test]
+    async fn a_file_is_the_reply_to_every_call() {
+        let dir = fixtures(&[("reply.sql", "CREATE TABLE t ();")]);
+        let provider = FixtureProvider::load(&dir.path().join("reply.sql")).unwrap();
+        assert_eq!(replies(&provider, 2).await, ["CREATE TABLE t ();", "CREATE TABLE t ();"]);
+    }
+
+    #[test]
+    fn missing_or_empty_fixtures_are_errors() {
+        let dir = fixtures(&[]);
+        let empty = FixtureProvider::load(dir.path()).err().unwrap();
+        assert_eq!(empty.kind(), io::ErrorKind::InvalidInput);
+        let missing = FixtureProvider::load(&dir.path().join("missing.sql")).err().unwrap();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn flags_win_over_the_config() {
+        let config = LlmConfig {
+            provider: Some(ProviderKind::Openai),
+            model: Some("gpt-4o-mini".to_string()),
+            base_url: Some("http://localhost:8000/v1".to_string()),
+            api_key_env: Some("ROUTER_KEY".to_string()),
+            fixture: Some(PathBuf::from("fixtures/schema.sql")),
+            repair_rounds: Some(1),
+        };
+        let project_dir = Path::new("/projects/shop");
+
+        // without flags the config stands, with its fixture under the project
+        let unchanged = config.with_args(project_dir, &LlmArgs::default());
+        assert_eq!(unchanged, LlmConfig { fixture: Some(project_dir.join("fixtures/schema.sql")), ..config.clone() });
+
+        let args = LlmArgs {
+            provider: Some(ProviderKind::Fixture),
+            model: Some("llama3.2:latest".to_string()),
+            base_url: None,
+            fixture: Some(PathBuf::from("/tmp/replies")),
+            repair_rounds: Some(0),
+        };
+        assert_eq!(config.with_args(project_dir, &args), LlmConfig {
+            provider: Some(ProviderKind::Fixture),
+            model: Some("llama3.2:latest".to_string()),
+            base_url: Some("http://localhost:8000/v1".to_string()),
+            api_key_env: Some("ROUTER_KEY".to_string()),
+            fixture: Some(PathBuf::from("/tmp/replies")),
+            repair_rounds: Some(0),
+        });
+
+        // a relative flag is taken from where auto-rust runs, not the project
+        let relative = LlmArgs { fixture: Some(PathBuf::from("replies")), ..LlmArgs::default() };
+        let fixture = LlmConfig::default().with_args(project_dir, &relative).fixture.unwrap();
+        assert_eq!(fixture, std::env::current_dir().unwrap().join("replies"));
+    }
+
+    #[test]
+    fn tool_calling_needs_ollama() {
+        let openai = LlmConfig { provider: Some(ProviderKind::Openai), ..LlmConfig::default() };
+        assert_eq!(openai.ollama().err().unwrap().kind(), io::ErrorKind::InvalidInput);
+        let (_, model) = LlmConfig::default().ollama().unwrap();
+        assert_eq!(model, DEFAULT_OLLAMA_MODEL);
+    }
+}
@@ -1,5 +1,6 @@
 
-mod llm;
+mod ddl_lexer;
+mod ddl_parser;
 mod schema;
 mod gen_docker;
 mod gen_sql;
@@ -16,62 +17,63 @@ mod add_react;
 mod gen_examples;
 mod add_fastapi;
 mod add_python;
-
+mod add_pagination;
+mod add_filters;
+mod add_joins;
+mod codegen;
+mod cli;
+mod migrations;
+mod introspect;
+mod llm_provider;
+mod schema_diff;
+mod refine;
+mod agent;
+mod vfs;
+mod plan;
+mod manifest;
+mod openapi;
+mod add_auth;
+mod add_validation;
+
+use add_auth::{add_auth, Authz};
 use add_python::add_python_func;
+use add_tests::add_tests;
+use add_pagination::add_pagination_helpers;
+use add_filters::add_filter_helpers;
 use add_fastapi::add_fastapi;
 use gen_examples::gen_examples;
 use add_react::create_react_app;
 use gen_toml::gen_toml;
 use add_minio::add_minio;
-use add_object::add_object;
 use add_compose::add_compose;
-use gen_sql::gen_sql;
-use std::collections::HashMap;
-use std::fmt::format;
-use std::fs::OpenOptions;
-use std::io::{self, BufWriter};
-use convert_case::{Case, Casing};
-use serde::de::value::{self, Error};
-use sqlx::FromRow;
+use gen_sql::{gen_sql, SqlSession};
+use std::io;
+use std::path::Path;
+use clap::Parser;
+use cli::{Cli, Command as CliCommand, Component, ProjectConfig, SchemaArgs};
+use llm_provider::LlmConfig;
+use plan::Generated;
+use schema_diff::{affected_tables, diff_rows, SchemaChange};
 use std::io::Write;
-pub use schema::{extract_column_info, extract_table_schemas, extract_table_names, Col};
-use std::process::{Command, Output};
+pub use schema::{extract_rows, extract_rows_from_files, Col};
+use std::process::Command;
 use gen_docker::gen_docker;
-use boilerplate::{add_axum_end, add_top_boilerplate};
+use boilerplate::{add_axum_end, add_top_boilerplate, remove_table_modules};
 pub use base_structs::{Row, create_type_map};
-pub use sql_funcs::add_basic_sql_funcs;
+use base_structs::deleted_value;
+pub use sql_funcs::{add_basic_sql_funcs, enum_types};
 use std::net::{TcpListener, SocketAddr};
 
 
-// This function is now in base_structs.rs
-fn create_rows_from_sql(file_path: &std::path::Path) -> Result<Vec<Row>, io::Error> {
-    let table_names = extract_table_names(&file_path.display().to_string())?;
-    let schemas = extract_table_schemas(&file_path.display().to_string())?;
-    let mut rows: Vec<Row> = Vec::new();
-
-    if table_names.len() != schemas.len() {
-        eprintln!("Warning: Number of table names and schemas do not match!");
-    }
-
-    for (table_name, schema) in table_names.iter().zip(schemas.iter()) {
-        let cleaned_name = table_name
-            .split('.')
-            .last()
-            .unwrap_or(&table_name)
-            .trim_matches('"')
-            .to_string();
-        let cols = extract_column_info(schema);
-        //let cols = c.into_iter().filter(|col| {
-            //  !col.auto_gen
-        //}).collect::<Vec<_>>();
-        let row = Row {
-            name: cleaned_name,
-            cols,
-        };
-        rows.push(row);
+/// Parses every migration in `migrations_dir`, in the order they are applied.
+fn create_rows_from_migrations(migrations_dir: &Path) -> Result<Vec<Row>, io::Error> {
+    let files = migrations::migration_files(migrations_dir).map_err(|e| {
+        io::Error::new(e.kind(), format!("Cannot read migrations in {}: {}", migrations_dir.display(), e))
+    })?;
+    let rows = extract_rows_from_files(&files)?;
+    if rows.is_empty() {
+        eprintln!("Warning: no CREATE TABLE statements found in {}", migrations_dir.display());
     }
-
-
     Ok(rows)
 }
 
@@ -81,142 +83,563 @@ fn create_rows_from_sql(file_path: &std::path::Path) -> Result<Vec<Row>, io::Err
 // docker run --name some-postgres -e POSTGRES_USER=dbuser -e POSTGRES_PASSWORD=p -e POSTGRES_DB=work -p 1111:5432 -d postgres
 #[tokio::main]
 async fn main() -> Result<(), std::io::Error> {
-    let mut file_name = String::new();
-    println!("Enter project name: ");
-    io::stdin().read_line(&mut file_name)?;
-    let file_name = file_name.trim().to_string();
-    
-    let parent_dir = std::env::current_dir()?.parent()
-        .ok_or_else(|| std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Cannot get parent directory"
-        ))?.to_path_buf();
-    
-    let project_dir = parent_dir.join(&file_name);
+    let cli = Cli::parse();
+    let dry_run = cli.dry_run || cli.diff;
+    if dry_run {
+        vfs::start_plan();
+    }
+    let (project_dir, generated) = match cli.command {
+        CliCommand::New { name, output_dir, schema, components } => {
+            let project_dir = output_dir.join(&name);
+
+            // later subcommands pick the components and database back up from auto-rust.toml
+            let config = ProjectConfig {
+                name: Some(name.clone()),
+                components: Some(ProjectConfig::default().components(&components)),
+                database_url: schema.database_url.clone(),
+                llm: LlmConfig::default().with_args(&project_dir, &schema.llm),
+                ..ProjectConfig::default()
+            };
+
+            // settle the schema with the LLM first, so one that is turned
+            // down doesn't leave a half made project behind
+            let task_sql = match &schema.task {
+                Some(task) => Some(llm_sql(&project_dir, &schema, &config, task.clone(), &[]).await?),
+                None => None,
+            };
+
+            create_project(&output_dir, &name)?;
+            config.save(&project_dir)?;
+
+            let database_url = database_url(&schema, &config);
+            match task_sql {
+                Some(sql) => migrations::write_migration(&project_dir.join("migrations/0001_data.sql"), &sql)?,
+                None => write_schema(&project_dir, &schema, &config, database_url.is_some()).await?,
+            }
+            add_dependencies(&project_dir).await;
+            let rows = load_rows(&project_dir, database_url.as_deref()).await?;
+            let generated = generate_service(&project_dir, &config, &config.components(&components), rows, None)?;
+            (project_dir, generated)
+        }
+        CliCommand::Generate { project, schema, components } => {
+            let config = ProjectConfig::load(&project.project_dir)?;
+            let database_url = database_url(&schema, &config);
+            write_schema(&project.project_dir, &schema, &config, database_url.is_some()).await?;
+            add_dependencies(&project.project_dir).await;
+            let rows = load_rows(&project.project_dir, database_url.as_deref()).await?;
+            let generated = generate_service(&project.project_dir, &config, &config.components(&components), rows, None)?;
+            (project.project_dir, generated)
+        }
+        CliCommand::AddTable { project, schema, name } => {
+            let config = ProjectConfig::load(&project.project_dir)?;
+            add_table(&project.project_dir, &schema, &config, &name).await?;
+            if let Some(url) = &config.database_url {
+                eprintln!("Warning: tables are read from {}, apply the new migration there before they are generated", url);
+            }
+            let rows = load_rows(&project.project_dir, config.database_url.as_deref()).await?;
+            let generated = generate_service(&project.project_dir, &config, &config.components(&Default::default()), rows, None)?;
+            (project.project_dir, generated)
+        }
+        CliCommand::Migrate { project, schema, name, allow_destructive } => {
+            let config = ProjectConfig::load(&project.project_dir)?;
+            // nothing is written in a dry run, so there is nothing to confirm
+            let generated = migrate(&project.project_dir, &schema, &config, &name, allow_destructive || dry_run).await?;
+            (project.project_dir, generated)
+        }
+        CliCommand::Agent { name, output_dir, brief, replay, llm, components } => {
+            let project_dir = output_dir.join(&name);
+            let llm = LlmConfig::default().with_args(&project_dir, &llm);
+            let session = match (replay, brief) {
+                (Some(log), _) => agent::replay(&log)?,
+                (None, Some(brief)) => agent::run(&brief, &llm).await.map_err(|e| {
+                    io::Error::other(format!("The agent failed: {}", e))
+                })?,
+                (None, None) => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Pass --brief or --replay")),
+            };
+            let blueprint = &session.blueprint;
+            if blueprint.rows.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "The agent added no tables"));
+            }
+
+            let config = ProjectConfig {
+                name: Some(name.clone()),
+                components: Some(components.components.clone().unwrap_or_else(|| blueprint.components())),
+                llm,
+                nested: blueprint.nested(),
+                ..ProjectConfig::default()
+            };
+            create_project(&output_dir, &name)?;
+            add_dependencies(&project_dir).await;
+            let generated = generate_agent_project(&project_dir, &config, &config.components(&components), &session)?;
+            (project_dir, generated)
+        }
+        CliCommand::Regenerate { project, components } => {
+            let config = ProjectConfig::load(&project.project_dir)?;
+            let rows = load_rows(&project.project_dir, config.database_url.as_deref()).await?;
+            let generated = generate_service(&project.project_dir, &config, &config.components(&components), rows, None)?;
+            (project.project_dir, generated)
+        }
+    };
+
+    if dry_run {
+        let changes = vfs::finish_plan();
+        if cli.diff {
+            plan::print_diff(&project_dir, &changes);
+        } else {
+            plan::print_summary(&project_dir, &generated, &changes);
+        }
+        return Ok(());
+    }
+    check_port();
+    Ok(())
+}
+
+/// Runs `cargo new {name}` in `output_dir`. A dry run plans the files
+/// `cargo new` would write instead.
+fn create_project(output_dir: &Path, name: &str) -> Result<(), io::Error> {
+    let project_dir = output_dir.join(name);
     println!("Project directory: {}", project_dir.display());
-    println!("Parent directory: {}", parent_dir.display());
+    if vfs::planning() {
+        if vfs::exists(&project_dir) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", project_dir.display())));
+        }
+        vfs::write(project_dir.join("Cargo.toml"), format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2024\"\n\n[dependencies]\n", name
+        ))?;
+        vfs::write(project_dir.join(".gitignore"), "/target\n")?;
+        return vfs::write(project_dir.join("src/main.rs"), "fn main() {\n    println!(\"Hello, world!\");\n}\n");
+    }
+    std::fs::create_dir_all(output_dir)?;
 
-    
     // Create new cargo project
     let output = Command::new("cargo")
-        .current_dir(&parent_dir)
+        .current_dir(output_dir)
         .arg("new")
-        .arg(&file_name)
+        .arg(name)
         .output()?;
 
- 
     if !output.status.success() {
         eprintln!("Failed to create new project: {}", String::from_utf8_lossy(&output.stderr));
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
+        return Err(std::io::Error::other(
             format!("Failed to create new project: {}", String::from_utf8_lossy(&output.stderr))
         ));
     }
+    Ok(())
+}
 
-
-    let gen_toml_res = gen_toml::gen_toml(&project_dir).await;
+async fn add_dependencies(project_dir: &Path) {
+    let gen_toml_res = gen_toml(project_dir).await;
     match gen_toml_res {
         Ok(_) => println!("Successfully generated TOML"),
         Err(e) => eprintln!("Failed to generate TOML: {}", e),
     };
-           
-    // Generate SQL and create necessary files
-    let mut sql_task = String::new();
-    println!("Enter the specific task for the SQL database (e.g., 'make SQL to store users and their favored food'): ");
-    io::stdin().read_line(&mut sql_task)?;
-    let mut sql_task = sql_task.trim().to_string();
-    if sql_task == "" {
-        sql_task = "make a database to track infomation about hosts and renters for an airBnB like aplication. there are hosts that have a zip code, name, email, and password hash. there are also renters that have all the same colums expet the zip code.".to_string();
-        println!("using default test string");
-    }
-
-    match gen_sql::gen_sql(project_dir.clone(), file_name.clone(), sql_task).await {
-        Ok(content) => {
-            println!("Successfully generated SQL ({} bytes)", content.len());
-        },
-        Err(e) => {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to generate SQL: {}", e)
-            ));
+}
+
+/// The database to read the tables from: `--database-url`, or the one in
+/// auto-rust.toml when no other schema source is on the command line.
+fn database_url(schema: &SchemaArgs, config: &ProjectConfig) -> Option<String> {
+    if schema.task.is_some() || !schema.sql.is_empty() {
+        return None;
+    }
+    schema.database_url.clone().or_else(|| config.database_url.clone())
+}
+
+/// Fills `migrations/` from `--sql`, copying the existing migrations over
+/// without involving the LLM, or writes `0001_data.sql` from the LLM given
+/// `--task`. Falls back to the `sql` and `task` in auto-rust.toml. A schema
+/// read from a database has no migrations to write.
+async fn write_schema(project_dir: &Path, schema: &SchemaArgs, config: &ProjectConfig, from_database: bool) -> Result<(), io::Error> {
+    let migrations_dir = project_dir.join("migrations");
+    if from_database {
+        // the generated service still embeds migrations/, even with none in it
+        return vfs::create_dir_all(&migrations_dir);
+    }
+    let sql_paths = if schema.sql.is_empty() {
+        config.sql.iter().map(|sql| project_dir.join(sql)).collect()
+    } else {
+        schema.sql.clone()
+    };
+    let task = schema.task.clone().or_else(|| config.task.clone());
+
+    match (sql_paths.is_empty(), task) {
+        // an explicit --task wins over sql files from the config
+        (false, Some(task)) if schema.task.is_some() => {
+            migrations::write_migration(&migrations_dir.join("0001_data.sql"), &llm_sql(project_dir, schema, config, task, &[]).await?)
+        }
+        (false, _) => {
+            let files = migrations::sql_files(&sql_paths)?;
+            // check the whole schema parses before any of it lands in the project
+            let rows = extract_rows_from_files(&files)?;
+            if rows.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "No CREATE TABLE statements in --sql"));
+            }
+            migrations::import_migrations(&files, &migrations_dir)?;
+            Ok(())
+        }
+        (true, Some(task)) => {
+            migrations::write_migration(&migrations_dir.join("0001_data.sql"), &llm_sql(project_dir, schema, config, task, &[]).await?)
         }
+        (true, None) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Pass --task, --sql or --database-url, or set one of them in auto-rust.toml",
+        )),
     }
-    
-    // Process the generated SQL file
-    let sql_path = project_dir.join("migrations/0001_data.sql");
-    println!("Attempting to read SQL file from: {}", sql_path.display());
-    
-    // Verify file exists
-    if !std::path::Path::new(&sql_path).exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("SQL file does not exist at: {}", sql_path.display())
-        ));
+}
+
+/// Asks the LLM set up by `--provider` and friends, or `[llm]` in
+/// auto-rust.toml, for the SQL behind `task`. New tables may reference
+/// `known_tables`. With `--interactive` the user refines the schema with
+/// the model until they accept it.
+async fn llm_sql(project_dir: &Path, schema: &SchemaArgs, config: &ProjectConfig, task: String, known_tables: &[String]) -> Result<String, io::Error> {
+    let llm_config = config.llm.with_args(project_dir, &schema.llm);
+    let llm = llm_config.provider()?;
+    let repair_rounds = llm_config.repair_rounds.unwrap_or(gen_sql::DEFAULT_REPAIR_ROUNDS);
+    let to_io_error = |e: Box<dyn std::error::Error + Send + Sync>| {
+        std::io::Error::other(format!("Failed to generate SQL: {}", e))
+    };
+
+    if !schema.interactive {
+        return gen_sql(llm.as_ref(), task, known_tables, repair_rounds).await.map_err(to_io_error);
     }
-    
-    let r = create_rows_from_sql(&sql_path);
-    let rows = match r {
+    let mut session = SqlSession::new(llm.as_ref(), known_tables, repair_rounds);
+    let sql = session.ask(format!("now {}", task)).await.map_err(to_io_error)?;
+    refine::refine_sql(&mut session, sql).await
+}
+
+/// Writes the tables from `--sql` or `--task` as the project's next migration.
+/// The LLM is shown the existing schema so new tables can reference it.
+async fn add_table(project_dir: &Path, schema: &SchemaArgs, config: &ProjectConfig, name: &str) -> Result<(), io::Error> {
+    let migrations_dir = project_dir.join("migrations");
+    if schema.database_url.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "add-table writes a migration, pass --task or --sql"));
+    }
+    let sql = match (schema.sql.is_empty(), &schema.task) {
+        (false, _) => {
+            let mut sql = String::new();
+            for file in migrations::sql_files(&schema.sql)? {
+                sql.push_str(&std::fs::read_to_string(file)?);
+                sql.push('\n');
+            }
+            sql
+        }
+        (true, Some(task)) => {
+            let files = migrations::migration_files(&migrations_dir)?;
+            let mut existing = String::new();
+            for file in &files {
+                existing.push_str(&vfs::read_to_string(file)?);
+                existing.push('\n');
+            }
+            let known_tables: Vec<String> = extract_rows_from_files(&files)?.into_iter().map(|row| row.name).collect();
+            llm_sql(project_dir, schema, config, format!(
+                "{}\n\nThe database already has these tables, output only the new ones:\n{}",
+                task, existing
+            ), &known_tables).await?
+        }
+        (true, None) => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Pass --task or --sql"));
+        }
+    };
+
+    // don't leave a migration behind that the generators can't read
+    let rows = schema::parse_rows(&sql).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if rows.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "No CREATE TABLE statements to add"));
+    }
+    let path = migrations::next_migration_path(&migrations_dir, name)?;
+    migrations::write_migration(&path, &sql)
+}
+
+/// Diffs the schema in `--sql` or from `--task` against the project's,
+/// writes the difference as the next migration and regenerates the tables
+/// it touches. Changes that lose data are confirmed on stdin first unless
+/// `allow_destructive` is set.
+async fn migrate(project_dir: &Path, schema: &SchemaArgs, config: &ProjectConfig, name: &str, allow_destructive: bool) -> Result<Generated, io::Error> {
+    if schema.database_url.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "migrate writes a migration, pass --task or --sql"));
+    }
+    let old_rows = load_rows(project_dir, config.database_url.as_deref()).await?;
+
+    let sql = match (schema.sql.is_empty(), &schema.task) {
+        (false, _) => {
+            let mut sql = String::new();
+            for file in migrations::sql_files(&schema.sql)? {
+                sql.push_str(&std::fs::read_to_string(file)?);
+                sql.push('\n');
+            }
+            sql
+        }
+        (true, Some(task)) => {
+            let existing: Vec<String> = old_rows.iter().map(schema_diff::create_table_sql).collect();
+            llm_sql(project_dir, schema, config, format!(
+                "{}\n\nThe database has these tables now. Output the whole schema after the change, \
+                 including the tables that stay the same:\n{}",
+                task, existing.join("\n\n")
+            ), &[]).await?
+        }
+        (true, None) => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Pass --task or --sql"));
+        }
+    };
+    let new_rows = schema::parse_rows(&sql).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if new_rows.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "No CREATE TABLE statements in the new schema"));
+    }
+
+    let changes = diff_rows(&old_rows, &new_rows);
+    if changes.is_empty() {
+        println!("The schema is unchanged, no migration written");
+        return Ok(Generated::default());
+    }
+    println!("\nChanges:\n");
+    for change in &changes {
+        println!("{}", change);
+    }
+    println!();
+    if !allow_destructive {
+        confirm_destructive(&changes)?;
+    }
+
+    let migrations_dir = project_dir.join("migrations");
+    let migration = schema_diff::migration_sql(&old_rows, &new_rows);
+    // the next run replays the migrations, don't write one it would have to skip
+    let replayed = match &config.database_url {
+        Some(_) => None,
+        None => {
+            let files = migrations::migration_files(&migrations_dir)?;
+            Some(schema::replay_migration(&files, &migration).map_err(|e| io::Error::new(
+                e.kind(),
+                format!("No migration written, it doesn't apply to the project's migrations: {}", e),
+            ))?)
+        }
+    };
+    let path = migrations::next_migration_path(&migrations_dir, name)?;
+    migrations::write_migration(&path, &migration)?;
+
+    let affected = affected_tables(&changes, &old_rows, &new_rows);
+    let dropped: Vec<String> = changes.iter()
+        .filter_map(|change| match change {
+            SchemaChange::DropTable(row) => Some(row.name.clone()),
+            _ => None,
+        })
+        .collect();
+    manifest::track(project_dir)?;
+    remove_table_modules(&project_dir.join("src"), &dropped)?;
+
+    let rows = match (&config.database_url, replayed) {
+        (Some(url), _) => {
+            eprintln!("Warning: tables are read from {}, the service applies the migration there when it starts", url);
+            new_rows
+        }
+        // what was written read back, so the code matches the migrations exactly
+        (None, replayed) => replayed.unwrap_or(new_rows),
+    };
+    println!("Regenerating {}", affected.join(", "));
+    generate_service(project_dir, config, &config.components(&Default::default()), rows, Some(&affected))
+}
+
+/// Lists the changes that can lose data and asks whether to go ahead.
+fn confirm_destructive(changes: &[SchemaChange]) -> Result<(), io::Error> {
+    let destructive: Vec<&SchemaChange> = changes.iter().filter(|change| change.is_destructive()).collect();
+    if destructive.is_empty() {
+        return Ok(());
+    }
+    println!("These changes can lose data already in the database:\n");
+    for change in &destructive {
+        println!("{}", change);
+    }
+    print!("\nWrite the migration anyway? [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "No migration written, pass --allow-destructive to write it without asking",
+        )),
+    }
+}
+
+/// The tables to generate for: read from `database_url` when there is one,
+/// otherwise parsed from the project's migrations.
+async fn load_rows(project_dir: &Path, database_url: Option<&str>) -> Result<Vec<Row>, io::Error> {
+    if let Some(database_url) = database_url {
+        let rows = introspect::introspect_rows(database_url, "public").await?;
+        println!("Read {} tables from the database", rows.len());
+        return Ok(rows);
+    }
+
+    let migrations_dir = project_dir.join("migrations");
+    let r = create_rows_from_migrations(&migrations_dir);
+    match r {
         Ok(rows) => {
             println!("Successfully parsed {} table definitions from SQL", rows.len());
-            rows
+            Ok(rows)
         },
         Err(e) => {
-            return Err(std::io::Error::new(
+            Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidData,
-                format!("Error parsing SQL file at {}: {}", sql_path.display(), e)
+                format!("Error parsing SQL files in {}: {}", migrations_dir.display(), e)
+            ))
+        }
+    }
+}
+
+/// Writes what an agent session built into the project `create_project`
+/// made: the config, the call log, the migration and the service.
+fn generate_agent_project(project_dir: &Path, config: &ProjectConfig, components: &[Component], session: &agent::Session) -> Result<Generated, io::Error> {
+    config.save(project_dir)?;
+    session.save_log(&project_dir.join(agent::AGENT_LOG))?;
+    migrations::write_migration(&project_dir.join("migrations/0001_agent.sql"), &session.blueprint.sql())?;
+    let rows = create_rows_from_migrations(&project_dir.join("migrations"))?;
+    generate_service(project_dir, config, components, rows, None)
+}
+
+/// Runs every generator over `rows`. With `only_tables` only those tables'
+/// modules are rewritten, along with the files that list every table
+/// (routes, mod files, examples); the rest of the project is left alone.
+fn generate_service(project_dir: &Path, config: &ProjectConfig, components: &[Component], mut rows: Vec<Row>, only_tables: Option<&[String]>) -> Result<Generated, io::Error> {
+    // the binary is named after the package, which `new` names after the directory
+    let name = config.name.clone()
+        .or_else(|| project_dir.canonicalize().ok()?.file_name()?.to_str().map(String::from))
+        .unwrap_or_else(|| "app".to_string());
+
+    codegen::check_idents(&rows)?;
+    for row in rows.iter_mut() {
+        row.soft_delete_column = config.soft_delete.column().to_string();
+        let Some(col) = row.soft_delete_col().filter(|col| deleted_value(col).is_none()) else { continue };
+        let problem = format!(
+            "{}.{} is {}, a soft delete column must be a timestamp, date or boolean",
+            row.name, col.name, col.col_type
+        );
+        // a configured column is meant to be one, a column that only has the default name may not be
+        if config.soft_delete.column.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("[soft_delete] column: {}", problem)));
+        }
+        eprintln!("Warning: {}, so {} rows are deleted for good.", problem, row.name);
+        row.soft_delete_column = String::new();
+    }
+    for list in config.nested.lists.iter().flatten() {
+        let served = list.split_once('.').is_some_and(|(parent, table)| {
+            rows.iter().any(|row| row.name == table && row.relations.iter().any(|rel| rel.ref_table == parent))
+        });
+        if !served {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("[nested] lists: {} is not a parent.table pair, e.g. users.posts, where table references parent", list),
             ));
         }
-    };
+    }
+    for row in rows.iter_mut() {
+        row.nested_lists = config.nested.lists_of(&row.name);
+    }
 
-    let path = project_dir.join("src/main.rs");
-    let mut func_names = Vec::new();
-    add_top_boilerplate(&path)?;
+    let src_dir = project_dir.join("src");
+    let mut endpoints = Vec::new();
+    manifest::track(project_dir)?;
+    add_top_boilerplate(&src_dir, only_tables.is_none())?;
+    add_pagination_helpers(&src_dir.join("data/pagination.rs"))?;
+    add_filter_helpers(&src_dir.join("data/filters.rs"))?;
     
 
+    let table_names: Vec<String> = rows.iter().map(|row| row.name.clone()).collect();
+    // the data layer needs to know about auth before any table is written
+    let authz = components.contains(&Component::Auth).then(|| Authz::new(&config.auth, &rows)).flatten();
     // TODO: rename, this creates select all, select one, and add functions. 
-    add_basic_sql_funcs(rows, &path , &mut func_names)?;
-    println!("function names after basic sql are {:?}", func_names);
-    add_python_func(&path)?;
+    add_basic_sql_funcs(&rows, &src_dir, &mut endpoints, only_tables, authz.as_ref())?;
+    println!("endpoints after basic sql are {:?}", endpoints);
+    if components.contains(&Component::PythonProxy) {
+        endpoints.push(add_python_func(&src_dir.join("handlers/python.rs"))?);
+    }
 
     // TODO: this looks like a dublicat of the add_minio function 
     // add_object(&path);
-    add_axum_end(func_names.clone(), &path)?;
-    let docker_res = gen_docker(project_dir.file_name().expect("Failed to get file name").to_str().unwrap());
-    match docker_res {
-        Ok(_) => println!("Dockerfile created at {}", project_dir.to_str().unwrap().to_owned()),
-        Err(e) => eprintln!("Error creating Dockerfile: {}", e),
+    if components.contains(&Component::Minio) {
+        let minio = add_minio(&src_dir.join("handlers/minio.rs"));
+        match minio {
+            Ok(endpoint) => {
+                println!("Minio added at {}", project_dir.display());
+                endpoints.push(endpoint);
+            }
+            Err(e) => eprintln!("Error adding Minio: {}", e),
+        }
+    }
+    if let Some(authz) = &authz {
+        add_auth(project_dir, authz, &mut endpoints)?;
     }
-    println!("function names after axum end are {:?}", func_names);
-    let compose = add_compose(project_dir.file_name().expect("Failed to get file name").to_str().unwrap());
-    match compose {
-        Ok(_) => println!("Docker compose created at {}", project_dir.to_str().unwrap().to_owned()),
-        Err(e) => eprintln!("Error creating Docker compose: {}", e),
+    let auth = authz.is_some();
+    let auth_module = src_dir.join("auth.rs");
+    if !auth && vfs::exists(&auth_module) {
+        manifest::remove_generated(&auth_module)?;
     }
-    let minio = add_minio(&project_dir.join("src/main.rs"));
-    match minio {
-        Ok(_) => println!("Minio added at {}", project_dir.to_str().unwrap().to_owned()),
-        Err(e) => eprintln!("Error adding Minio: {}", e),
+
+    // the document covers the API, not the routes serving the document
+    let document = openapi::openapi_document(&name, &rows, &endpoints, authz.as_ref(), components.contains(&Component::Nginx));
+    openapi::write_openapi(project_dir, &document)?;
+    let mut routes = endpoints.clone();
+    if components.contains(&Component::Docs) {
+        routes.extend(openapi::add_docs(&src_dir.join("handlers/docs.rs"))?);
+    }
+
+    // routes and the layer mod.rs files cover every handler written above
+    add_axum_end(routes.clone(), &src_dir, auth, enum_types(&rows))?;
+    // the docker files and templates don't depend on the tables
+    let whole_project = only_tables.is_none();
+    let mut services = Vec::new();
+    if whole_project {
+        let docker_res = gen_docker(project_dir, &name);
+        match docker_res {
+            Ok(_) => println!("Dockerfile created at {}", project_dir.display()),
+            Err(e) => eprintln!("Error creating Dockerfile: {}", e),
+        }
+        let compose = add_compose(project_dir, components);
+        match compose {
+            Ok(names) => {
+                println!("Docker compose created at {}", project_dir.display());
+                services = names;
+            }
+            Err(e) => eprintln!("Error creating Docker compose: {}", e),
+        }
     }
 
-    let _ = create_react_app("../".to_owned() + project_dir.file_name().expect("Failed to get file name").to_str().unwrap());
+    if whole_project && components.contains(&Component::React) {
+        let _ = create_react_app(project_dir);
+    }
 
-    let gen_examples_res = gen_examples(&project_dir.file_name().expect("Failed to get file name").to_str().unwrap(), func_names.clone());
-    println!("function names after gen examples are {:?}", func_names); 
+    let gen_examples_res = gen_examples(project_dir, endpoints.clone());
     match gen_examples_res {
-        Ok(_) => println!("Examples generated at {}", project_dir.to_str().unwrap().to_owned()),
+        Ok(_) => println!("Examples generated at {}", project_dir.display()),
         Err(e) => eprintln!("Error generating examples: {}", e),
     }
 
 
-    let fastapi_res = add_fastapi(&project_dir.file_name().expect("faild to get the file name for fast api func").to_str().unwrap());
+    if whole_project && components.contains(&Component::Tests) {
+        add_tests(project_dir, &rows)?;
+    }
 
-    match fastapi_res {
-        Ok(_) => println!("added the fastapi folder "),
-        Err(e) => eprintln!("error while adding the fastapi folder: {}", e)
+    if whole_project && components.contains(&Component::Fastapi) {
+        let fastapi_res = add_fastapi(project_dir);
+
+        match fastapi_res {
+            Ok(_) => println!("added the fastapi folder "),
+            Err(e) => eprintln!("error while adding the fastapi folder: {}", e)
+        }
     }
 
+    let kept = manifest::finish()?;
+    if !kept.is_empty() {
+        println!("\nKept {} files you edited, new versions are next to them as .{} files:", kept.len(), manifest::GENERATED_SUFFIX);
+        for file in &kept {
+            println!("  {}", file.display());
+        }
+    }
+    Ok(Generated { tables: table_names, routes, services })
+}
+
+/// Tells the user whether the generated service's port is free to run it on.
+fn check_port() {
     let addr: SocketAddr = "0.0.0.0:8081".parse().unwrap();
-    match TcpListener::bind(&addr) {
+    match TcpListener::bind(addr) {
         // If the bind operation is successful, it means the port was available.
         Ok(listener) => {
             println!("✅ Port 8081 is NOT in use.");
@@ -252,7 +675,6 @@ async fn main() -> Result<(), std::io::Error> {
 
         }
     }
-    Ok(())
 }
 
 
@@ -0,0 +1,257 @@
+//! Keeps regeneration from overwriting files the user has edited. Every
+//! generated file's hash is recorded in `.auto-rust/manifest.toml`; on the
+//! next run a file whose contents no longer match its hash is left alone
+//! and the new version is written next to it as `{file}.generated`.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::vfs;
+
+/// Where the manifest lives, relative to the project directory.
+pub const MANIFEST_FILE: &str = ".auto-rust/manifest.toml";
+
+/// Suffix of the new version of a file the user edited.
+pub const GENERATED_SUFFIX: &str = "generated";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Manifest {
+    /// Hash of each file as it was last generated, keyed by its path
+    /// relative to the project, e.g. `src/data/users.rs`.
+    files: BTreeMap<String, String>,
+}
+
+struct Tracking {
+    project_dir: PathBuf,
+    manifest: Manifest,
+    /// No manifest yet: the project predates it, or `new` just made it,
+    /// so every file is taken as generated.
+    untracked: bool,
+    /// Files left alone because the user changed them.
+    kept: Vec<PathBuf>,
+}
+
+static TRACKING: Mutex<Option<Tracking>> = Mutex::new(None);
+
+/// Tracking and dry run plans are global, so tests that write files take turns.
+#[cfg(test)]
+pub static SERIAL: Mutex<()> = Mutex::new(());
+
+fn lock() -> std::sync::MutexGuard<'static, Option<Tracking>> {
+    TRACKING.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Starts tracking the files generated into `project_dir` against its
+/// manifest, if it isn't tracked already.
+pub fn track(project_dir: &Path) -> Result<(), io::Error> {
+    if lock().as_ref().is_some_and(|tracking| tracking.project_dir == project_dir) {
+        return Ok(());
+    }
+    let path = project_dir.join(MANIFEST_FILE);
+    let (manifest, untracked) = match vfs::read_to_string(&path) {
+        Ok(contents) => {
+            let manifest = toml::from_str(&contents).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+            })?;
+            (manifest, false)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (Manifest::default(), true),
+        Err(e) => return Err(e),
+    };
+    *lock() = Some(Tracking { project_dir: project_dir.to_path_buf(), manifest, untracked, kept: Vec::new() });
+    Ok(())
+}
+
+/// Saves the manifest and stops tracking. Returns the files that were
+/// kept because the user edited them.
+pub fn finish() -> Result<Vec<PathBuf>, io::Error> {
+    let Some(mut tracking) = lock().take() else {
+        return Ok(Vec::new());
+    };
+    tracking.kept.sort();
+    tracking.kept.dedup();
+    let contents = toml::to_string_pretty(&tracking.manifest)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let path = tracking.project_dir.join(MANIFEST_FILE);
+    if let Some(dir) = path.parent() {
+        vfs::create_dir_all(dir)?;
+    }
+    vfs::write(path, contents)?;
+    Ok(tracking.kept)
+}
+
+/// Writes a generated file, unless the user has changed the one that is
+/// there. Then the file is kept and `contents` go to `{path}.generated`
+/// for them to merge.
+pub fn write_generated(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<(), io::Error> {
+    let (path, contents) = (path.as_ref(), contents.as_ref());
+    let mut guard = lock();
+    let Some(tracking) = guard.as_mut() else {
+        return vfs::write(path, contents);
+    };
+    let Some(key) = tracking.key(path) else {
+        return vfs::write(path, contents);
+    };
+
+    let current = match vfs::read(path) {
+        Ok(current) => Some(current),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let side_file = generated_path(path);
+    if current.is_some() && !tracking.is_generated(&key, current.as_deref()) {
+        if current.as_deref() == Some(contents) {
+            // the user took the new version, so it's ours again
+            tracking.manifest.files.insert(key, hash(contents));
+        } else {
+            eprintln!(
+                "Warning: {} was edited, the new version is in {}",
+                path.display(), side_file.display()
+            );
+            tracking.kept.push(path.to_path_buf());
+            return vfs::write(&side_file, contents);
+        }
+    } else {
+        tracking.manifest.files.insert(key, hash(contents));
+        if current.as_deref() != Some(contents) {
+            vfs::write(path, contents)?;
+        }
+    }
+    if vfs::exists(&side_file) {
+        vfs::remove_file(&side_file)?;
+    }
+    Ok(())
+}
+
+/// Removes a generated file the user hasn't changed, e.g. the module of a
+/// dropped table. Edited files stay, with a warning, and files that were
+/// never generated are not touched.
+pub fn remove_generated(path: impl AsRef<Path>) -> Result<(), io::Error> {
+    let path = path.as_ref();
+    let mut guard = lock();
+    let key = guard.as_ref().and_then(|tracking| tracking.key(path));
+    let (Some(tracking), Some(key)) = (guard.as_mut(), key) else {
+        return vfs::remove_file(path);
+    };
+    if !tracking.untracked && !tracking.manifest.files.contains_key(&key) {
+        return Ok(());
+    }
+    let current = vfs::read(path)?;
+    if !tracking.is_generated(&key, Some(&current)) {
+        eprintln!("Warning: {} was edited, so it is not removed", path.display());
+        tracking.kept.push(path.to_path_buf());
+        return Ok(());
+    }
+    tracking.manifest.files.remove(&key);
+    vfs::remove_file(path)
+}
+
+impl Tracking {
+    /// `path` relative to the project with `/` separators, when it's in it.
+    fn key(&self, path: &Path) -> Option<String> {
+        let project_dir = std::path::absolute(&self.project_dir).ok()?;
+        let path = std::path::absolute(path).ok()?;
+        let relative = path.strip_prefix(project_dir).ok()?;
+        let parts: Vec<_> = relative.components().map(|part| part.as_os_str().to_string_lossy()).collect();
+        Some(parts.join("/"))
+    }
+
+    /// Whether `current` is what was last generated for `key`, so it can
+    /// be replaced.
+    fn is_generated(&self, key: &str, current: Option<&[u8]>) -> bool {
+        match (current, self.manifest.files.get(key)) {
+            (None, _) => true,
+            (Some(current), Some(recorded)) => hash(current) == *recorded,
+            (Some(_), None) => self.untracked,
+        }
+    }
+}
+
+fn generated_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(GENERATED_SUFFIX);
+    path.with_file_name(name)
+}
+
+fn hash(contents: &[u8]) -> String {
+    Sha256::digest(contents).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(project_dir: &Path, files: &[(&str, &str)]) -> Vec<PathBuf> {
+        track(project_dir).unwrap();
+        for (path, contents) in files {
+            write_generated(project_dir.join(path), contents).unwrap();
+        }
+        finish().unwrap()
+    }
+
+    fn read(project_dir: &Path, path: &str) -> String {
+        std::fs::read_to_string(project_dir.join(path)).unwrap()
+    }
+
+    #[test]
+    fn edited_files_are_kept() {
+        let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let project_dir = dir.path();
+        std::fs::create_dir(project_dir.join("src")).unwrap();
+
+        assert!(generate(project_dir, &[("src/a.rs", "a1"), ("src/b.rs", "b1")]).is_empty());
+        assert!(read(project_dir, MANIFEST_FILE).contains("\"src/a.rs\""));
+
+        std::fs::write(project_dir.join("src/a.rs"), "a1 // mine").unwrap();
+        let kept = generate(project_dir, &[("src/a.rs", "a2"), ("src/b.rs", "b2")]);
+        assert_eq!(kept, vec![project_dir.join("src/a.rs")]);
+        assert_eq!(read(project_dir, "src/a.rs"), "a1 // mine");
+        assert_eq!(read(project_dir, "src/a.rs.generated"), "a2");
+        assert_eq!(read(project_dir, "src/b.rs"), "b2");
+
+        // taking the new version hands the file back to the generator
+        std::fs::write(project_dir.join("src/a.rs"), "a2").unwrap();
+        assert!(generate(project_dir, &[("src/a.rs", "a2")]).is_empty());
+        assert!(!project_dir.join("src/a.rs.generated").exists());
+        assert!(generate(project_dir, &[("src/a.rs", "a3")]).is_empty());
+        assert_eq!(read(project_dir, "src/a.rs"), "a3");
+    }
+
+    #[test]
+    fn projects_without_a_manifest_are_overwritten() {
+        let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let project_dir = dir.path();
+        std::fs::write(project_dir.join("main.rs"), "old").unwrap();
+
+        assert!(generate(project_dir, &[("main.rs", "new")]).is_empty());
+        assert_eq!(read(project_dir, "main.rs"), "new");
+    }
+
+    #[test]
+    fn only_unedited_files_are_removed() {
+        let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let project_dir = dir.path();
+        generate(project_dir, &[("a.rs", "a"), ("b.rs", "b")]);
+        std::fs::write(project_dir.join("b.rs"), "b // mine").unwrap();
+        std::fs::write(project_dir.join("c.rs"), "c").unwrap();
+
+        track(project_dir).unwrap();
+        for path in ["a.rs", "b.rs", "c.rs"] {
+            remove_generated(project_dir.join(path)).unwrap();
+        }
+        assert_eq!(finish().unwrap(), vec![project_dir.join("b.rs")]);
+        assert!(!project_dir.join("a.rs").exists());
+        assert!(project_dir.join("b.rs").exists());
+        assert!(project_dir.join("c.rs").exists());
+    }
+}
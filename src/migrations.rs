@@ -0,0 +1,189 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::vfs;
+
+/// The `.sql` files in `migrations_dir`, in the order sqlx applies them.
+pub fn migration_files(migrations_dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut files = Vec::new();
+    for path in vfs::read_dir(migrations_dir)? {
+        if path.extension().is_some_and(|ext| ext == "sql") {
+            files.push(path);
+        }
+    }
+    files.sort_by_key(|path| (migration_version(path), path.clone()));
+    Ok(files)
+}
+
+/// The version sqlx reads from the front of a migration's file name, e.g. 2
+/// for `0002_add_runs.sql`.
+fn migration_version(path: &Path) -> Option<i64> {
+    let name = path.file_name()?.to_str()?;
+    name.split('_').next()?.parse().ok()
+}
+
+/// Where the next migration goes: `{version}_{name}.sql`, one past the highest
+/// version in `migrations_dir`.
+pub fn next_migration_path(migrations_dir: &Path, name: &str) -> Result<PathBuf, io::Error> {
+    let latest = if vfs::exists(migrations_dir) {
+        migration_files(migrations_dir)?.iter().filter_map(|path| migration_version(path)).max()
+    } else {
+        None
+    };
+    let version = latest.unwrap_or(0) + 1;
+    Ok(migrations_dir.join(format!("{:04}_{}.sql", version, name)))
+}
+
+/// Writes `sql` as a migration, creating the migrations directory if needed.
+pub fn write_migration(path: &Path, sql: &str) -> Result<(), io::Error> {
+    if let Some(dir) = path.parent() {
+        vfs::create_dir_all(dir)?;
+    }
+    println!("Creating SQL file at: {}", path.display());
+    vfs::write(path, sql)
+}
+
+/// The `.sql` files behind `--sql`: files are taken as they are and
+/// directories contribute their `.sql` files in migration order.
+pub fn sql_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, io::Error> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(migration_files(path)?);
+        } else if path.exists() {
+            files.push(path.clone());
+        } else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display())));
+        }
+    }
+    if files.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "No .sql files found in --sql"));
+    }
+    Ok(files)
+}
+
+/// Copies existing migrations into `migrations_dir`. Files that already carry
+/// a version (`0003_runs.sql`, `20240101_runs.sql`) keep their names; when
+/// any of them doesn't, all are numbered in the order given, e.g.
+/// `schema.sql` becomes `0001_schema.sql`. Files already in `migrations_dir`
+/// are left where they are.
+pub fn import_migrations(files: &[PathBuf], migrations_dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    vfs::create_dir_all(migrations_dir)?;
+    // during a dry run the directory may only exist in the plan
+    let target_dir = migrations_dir.canonicalize().or_else(|_| std::path::absolute(migrations_dir))?;
+    let versioned = files.iter().all(|file| migration_version(file).is_some());
+
+    let mut imported = Vec::new();
+    for (i, file) in files.iter().enumerate() {
+        let file_name = file.file_name().and_then(|name| name.to_str()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{} is not a file name", file.display()))
+        })?;
+        let target = if versioned {
+            migrations_dir.join(file_name)
+        } else {
+            let stem = file_name.trim_end_matches(".sql");
+            let stem = match migration_version(file) {
+                Some(_) => stem.split_once('_').map_or(stem, |(_, rest)| rest),
+                None => stem,
+            };
+            migrations_dir.join(format!("{:04}_{}.sql", i + 1, stem))
+        };
+
+        let in_place = file.parent().and_then(|dir| dir.canonicalize().ok()) == Some(target_dir.clone())
+            && target.file_name() == file.file_name();
+        if !in_place {
+            println!("Copying {} to {}", file.display(), target.display());
+            vfs::copy(file, &target)?;
+        }
+        imported.push(target);
+    }
+
+    // left over migrations are still applied and parsed along with the new ones
+    for stale in migration_files(migrations_dir)?.iter().filter(|path| !imported.contains(path)) {
+        eprintln!("Warning: {} is not one of the --sql files but is still a migration", stale.display());
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(dir: &Path, names: &[&str]) {
+        for name in names {
+            std::fs::write(dir.join(name), format!("-- {}", name)).unwrap();
+        }
+    }
+
+    fn names(paths: &[PathBuf]) -> Vec<String> {
+        paths.iter().map(|path| path.file_name().unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn migrations_are_ordered_by_version() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["10_tags.sql", "2_runs.sql", "0001_users.sql", "README.md"]);
+        assert_eq!(names(&migration_files(dir.path()).unwrap()), ["0001_users.sql", "2_runs.sql", "10_tags.sql"]);
+        assert_eq!(next_migration_path(dir.path(), "add_notes").unwrap(), dir.path().join("0011_add_notes.sql"));
+        assert_eq!(next_migration_path(&dir.path().join("missing"), "init").unwrap(), dir.path().join("missing/0001_init.sql"));
+    }
+
+    #[test]
+    fn sql_paths_are_files_or_directories() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("ddl")).unwrap();
+        touch(&dir.path().join("ddl"), &["0002_b.sql", "0001_a.sql", "notes.txt"]);
+        touch(dir.path(), &["extra.sql"]);
+
+        let files = sql_files(&[dir.path().join("ddl"), dir.path().join("extra.sql")]).unwrap();
+        assert_eq!(names(&files), ["0001_a.sql", "0002_b.sql", "extra.sql"]);
+
+        let missing = sql_files(&[dir.path().join("nope.sql")]).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+        std::fs::create_dir(dir.path().join("empty")).unwrap();
+        let empty = sql_files(&[dir.path().join("empty")]).unwrap_err();
+        assert_eq!(empty.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn versioned_files_keep_their_names() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["0003_runs.sql", "20240101_tags.sql"]);
+        let migrations_dir = dir.path().join("project/migrations");
+        let files = [dir.path().join("0003_runs.sql"), dir.path().join("20240101_tags.sql")];
+
+        let imported = import_migrations(&files, &migrations_dir).unwrap();
+        assert_eq!(names(&imported), ["0003_runs.sql", "20240101_tags.sql"]);
+        assert_eq!(std::fs::read_to_string(migrations_dir.join("0003_runs.sql")).unwrap(), "-- 0003_runs.sql");
+    }
+
+    #[test]
+    fn unversioned_files_are_numbered_in_order() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        touch(dir.path(), &["schema.sql", "0007_seed.sql"]);
+        let migrations_dir = dir.path().join("migrations");
+        let files = [dir.path().join("schema.sql"), dir.path().join("0007_seed.sql")];
+
+        let imported = import_migrations(&files, &migrations_dir).unwrap();
+        assert_eq!(names(&imported), ["0001_schema.sql", "0002_seed.sql"]);
+        assert_eq!(std::fs::read_to_string(migrations_dir.join("0002_seed.sql")).unwrap(), "-- 0007_seed.sql");
+    }
+
+    #[test]
+    fn files_already_in_place_are_not_copied() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let migrations_dir = dir.path().join("migrations");
+        std::fs::create_dir(&migrations_dir).unwrap();
+        touch(&migrations_dir, &["0001_users.sql", "0002_old.sql"]);
+
+        let imported = import_migrations(&[migrations_dir.join("0001_users.sql")], &migrations_dir).unwrap();
+        assert_eq!(imported, [migrations_dir.join("0001_users.sql")]);
+        // the other migration is left alone, with a warning
+        assert_eq!(names(&migration_files(&migrations_dir).unwrap()), ["0001_users.sql", "0002_old.sql"]);
+    }
+}
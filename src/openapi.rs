@@ -0,0 +1,751 @@
+//! `openapi.json` for the generated service. Each generator describes the
+//! handler it writes with an `Operation` on its `Endpoint`, and the document
+//! puts those together with a schema per struct derived from the `Row` IR.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use convert_case::{Case, Casing};
+use quote::quote;
+use serde_json::{json, Map, Value};
+
+use crate::add_validation::{column_rules, CheckValue, CompareOp, Rule};
+use crate::add_auth::{is_hidden, Authz};
+use crate::add_functions::payload_cols;
+use crate::base_structs::{create_type_map, Endpoint, HttpMethod, Row};
+use crate::codegen::write_module;
+use crate::manifest;
+use crate::schema::Col;
+
+/// Where the document is written, relative to the project directory.
+pub const OPENAPI_FILE: &str = "openapi.json";
+
+/// What a generated handler takes and returns, for its entry in the document.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Operation {
+    pub summary: String,
+    pub description: Option<String>,
+    /// Groups operations in the docs, the table name for table endpoints.
+    pub tag: Option<String>,
+    pub params: Vec<Param>,
+    /// Schema of the JSON body, usually a `schema_ref` such as `NewUsers`.
+    pub body: Option<Value>,
+    /// A body to show in the docs and in `examples.js`.
+    pub example: Option<Value>,
+    /// Schema of the 200 response, any JSON when null.
+    pub response: Value,
+    /// The 200 response is plain text instead of JSON.
+    pub text_response: bool,
+    /// Error statuses, each answered with a plain text message.
+    pub errors: Vec<u16>,
+}
+
+/// A path or query parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    /// `path` or `query`.
+    pub location: &'static str,
+    pub required: bool,
+    pub schema: Value,
+    pub description: Option<String>,
+}
+
+impl Param {
+    pub fn path(name: &str, schema: Value) -> Param {
+        Param { name: name.to_string(), location: "path", required: true, schema, description: None }
+    }
+
+    pub fn query(name: &str, schema: Value, description: &str) -> Param {
+        Param {
+            name: name.to_string(),
+            location: "query",
+            required: false,
+            schema,
+            description: Some(description.to_string()),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut param = json!({
+            "name": self.name,
+            "in": self.location,
+            "required": self.required,
+            "schema": self.schema,
+        });
+        if let Some(description) = &self.description {
+            param["description"] = json!(description);
+        }
+        param
+    }
+}
+
+impl Operation {
+    fn to_json(&self, handler: &str, path_params: &[&str]) -> Value {
+        let mut operation = json!({
+            "operationId": handler,
+            "summary": if self.summary.is_empty() { handler } else { &self.summary },
+        });
+        if let Some(description) = &self.description {
+            operation["description"] = json!(description);
+        }
+        if let Some(tag) = &self.tag {
+            operation["tags"] = json!([tag]);
+        }
+
+        // path parameters the generator didn't describe are taken as text
+        let mut params: Vec<Value> = self.params.iter().map(Param::to_json).collect();
+        for name in path_params {
+            if !self.params.iter().any(|param| param.location == "path" && param.name == *name) {
+                params.push(Param::path(name, json!({"type": "string"})).to_json());
+            }
+        }
+        if !params.is_empty() {
+            operation["parameters"] = Value::Array(params);
+        }
+
+        if let Some(body) = &self.body {
+            let mut content = json!({"schema": body});
+            if let Some(example) = &self.example {
+                content["example"] = example.clone();
+            }
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": {"application/json": content},
+            });
+        }
+
+        let mut responses = Map::new();
+        let ok = match (self.text_response, &self.response) {
+            (true, _) => json!({"text/plain": {"schema": {"type": "string"}}}),
+            (false, Value::Null) => json!({"application/json": {"schema": {}}}),
+            (false, schema) => json!({"application/json": {"schema": schema}}),
+        };
+        responses.insert("200".to_string(), json!({"description": "OK", "content": ok}));
+        for status in &self.errors {
+            responses.insert(status.to_string(), error_response(*status));
+        }
+        operation["responses"] = Value::Object(responses);
+        operation
+    }
+}
+
+fn error_description(status: u16) -> &'static str {
+    match status {
+        400 => "The request is invalid, the detail says why",
+        401 => "A valid access token is needed",
+        403 => "The caller's role may not do this",
+        404 => "No matching row",
+        409 => "A unique column already has the value",
+        422 => "Fields break their columns' rules, listed in errors, or a foreign key doesn't match a row",
+        500 => "Database or server error",
+        _ => "Error",
+    }
+}
+
+/// The RFC 7807 body every generated error is answered with.
+const PROBLEM_SCHEMA: &str = "Problem";
+
+fn error_response(status: u16) -> Value {
+    json!({
+        "description": error_description(status),
+        "content": {"application/problem+json": {"schema": schema_ref(PROBLEM_SCHEMA)}},
+    })
+}
+
+/// A reference to a schema in `components/schemas`.
+pub fn schema_ref(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{}", name)})
+}
+
+fn pascal(row: &Row) -> String {
+    row.name.to_case(Case::Pascal)
+}
+
+/// The JSON schema of a column's values, leaving out NULL.
+fn col_schema(row: &Row, col: &Col, type_map: &std::collections::HashMap<String, String>) -> Value {
+    let mut schema = match col.element_type(type_map).as_str() {
+        "bool" => json!({"type": "boolean"}),
+        "i8" | "i16" | "i32" => json!({"type": "integer", "format": "int32"}),
+        "i64" => json!({"type": "integer", "format": "int64"}),
+        "f32" => json!({"type": "number", "format": "float"}),
+        "f64" => json!({"type": "number", "format": "double"}),
+        // serialized as a string so no digits are lost
+        "rust_decimal::Decimal" => json!({"type": "string", "format": "decimal", "examples": ["9.99"]}),
+        "uuid::Uuid" => json!({"type": "string", "format": "uuid"}),
+        "chrono::DateTime<Utc>" => json!({"type": "string", "format": "date-time"}),
+        "chrono::NaiveDateTime" => json!({"type": "string", "examples": ["2024-01-31T12:00:00"]}),
+        "chrono::NaiveDate" => json!({"type": "string", "format": "date"}),
+        "chrono::NaiveTime" => json!({"type": "string", "examples": ["12:00:00"]}),
+        "serde_json::Value" => json!({}),
+        "Vec<u8>" => json!({"type": "array", "items": {"type": "integer", "minimum": 0, "maximum": 255}}),
+        _ if !col.enum_labels.is_empty() => json!({"type": "string", "enum": col.enum_labels}),
+        _ => json!({"type": "string"}),
+    };
+    for rule in column_rules(row, col, type_map) {
+        let number = |value: &CheckValue| match value {
+            CheckValue::Number(n) => match (n.parse::<i64>(), n.parse::<f64>()) {
+                (Ok(i), _) => json!(i),
+                (_, Ok(f)) => json!(f),
+                _ => json!(n),
+            },
+            CheckValue::Text(t) => json!(t),
+        };
+        match rule {
+            Rule::MaxLength(n) => schema["maxLength"] = json!(n),
+            Rule::MinLength(n) => schema["minLength"] = json!(n),
+            Rule::Compare(CompareOp::Gte, value) => schema["minimum"] = number(&value),
+            Rule::Compare(CompareOp::Gt, value) => schema["exclusiveMinimum"] = number(&value),
+            Rule::Compare(CompareOp::Lte, value) => schema["maximum"] = number(&value),
+            Rule::Compare(CompareOp::Lt, value) => schema["exclusiveMaximum"] = number(&value),
+            Rule::Compare(CompareOp::Eq, value) => schema["const"] = number(&value),
+            Rule::Compare(CompareOp::Ne, value) => schema["not"] = json!({"const": number(&value)}),
+            Rule::OneOf(values) => schema["enum"] = json!(values.iter().map(number).collect::<Vec<_>>()),
+            Rule::Email => schema["format"] = json!("email"),
+            Rule::Url => schema["format"] = json!("uri"),
+        }
+    }
+    if col.array {
+        schema = json!({"type": "array", "items": schema});
+    }
+
+    let mut notes = Vec::new();
+    if let Some(rel) = row.relation_for(&col.name) {
+        notes.push(format!("references {}({})", rel.ref_table, rel.ref_columns.join(", ")));
+    }
+    if let Some(default) = &col.default {
+        notes.push(format!("defaults to {}", default));
+    }
+    if !notes.is_empty() {
+        schema["description"] = json!(notes.join(", "));
+    }
+    schema
+}
+
+/// Lets `schema` also be null.
+fn nullable(mut schema: Value) -> Value {
+    if let Some(Value::Array(values)) = schema.get_mut("enum") {
+        values.push(Value::Null);
+    }
+    match schema.get("type").cloned() {
+        Some(Value::String(t)) => schema["type"] = json!([t, "null"]),
+        // no type already takes anything
+        None => {}
+        Some(_) => return json!({"anyOf": [schema, {"type": "null"}]}),
+    }
+    schema
+}
+
+/// An object schema over `cols`. `optional` tells which may be left out,
+/// and those may also be null, as serde reads a null as a missing value.
+pub fn object_schema(row: &Row, cols: &[&Col], optional: impl Fn(&Col) -> bool) -> Value {
+    let type_map = create_type_map();
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for col in cols {
+        let schema = col_schema(row, col, &type_map);
+        if optional(col) || col.is_nullable() {
+            properties.insert(col.name.clone(), nullable(schema));
+        } else {
+            properties.insert(col.name.clone(), schema);
+        }
+        if !optional(col) {
+            required.push(col.name.clone());
+        }
+    }
+    json!({"type": "object", "properties": properties, "required": required})
+}
+
+/// The component schemas for one table: the row itself, the insert, PUT
+/// and PATCH payloads, and a page of the list endpoint.
+fn row_schemas(row: &Row, authz: Option<&Authz>) -> Vec<(String, Value)> {
+    let name = pascal(row);
+    let all = payload_cols(row, authz);
+    let fields: Vec<&Col> = all.iter().copied().filter(|col| !col.primary_key).collect();
+    let returned: Vec<&Col> = row.cols.iter().filter(|col| !is_hidden(authz, row, col)).collect();
+    vec![
+        // every column is serialized, NULL or not, except hidden ones
+        (name.clone(), object_schema(row, &returned, |_| false)),
+        (format!("New{}", name), object_schema(row, &all, |col| col.auto_gen || col.is_nullable())),
+        (format!("Update{}", name), object_schema(row, &fields, |col| col.auto_gen || col.is_nullable())),
+        (format!("Patch{}", name), object_schema(row, &fields, |_| true)),
+        (format!("{}Page", name), json!({
+            "type": "object",
+            "properties": {
+                "payload": {"type": "array", "items": schema_ref(&name)},
+                "next_cursor": {
+                    "type": ["string", "null"],
+                    "description": "pass as cursor to get the next page, null on the last one",
+                },
+                "has_more": {"type": "boolean"},
+                "total_count": {"type": "integer", "format": "int64", "description": "rows matching the filters, across every page"},
+                "limit": {"type": "integer", "format": "int64"},
+                "offset": {"type": "integer", "format": "int64"},
+            },
+            "required": ["payload", "next_cursor", "has_more", "total_count", "limit", "offset"],
+        })),
+    ]
+}
+
+/// A sample value for a column, for example bodies.
+fn example_value(col: &Col, type_map: &std::collections::HashMap<String, String>) -> Value {
+    let value = match col.element_type(type_map).as_str() {
+        "bool" => json!(true),
+        "i8" | "i16" | "i32" | "i64" => json!(1),
+        "f32" | "f64" => json!(1.5),
+        "rust_decimal::Decimal" => json!("9.99"),
+        "uuid::Uuid" => json!("00000000-0000-0000-0000-000000000000"),
+        "chrono::DateTime<Utc>" => json!("2024-01-31T12:00:00Z"),
+        "chrono::NaiveDateTime" => json!("2024-01-31T12:00:00"),
+        "chrono::NaiveDate" => json!("2024-01-31"),
+        "chrono::NaiveTime" => json!("12:00:00"),
+        "serde_json::Value" => json!({}),
+        "Vec<u8>" => json!([]),
+        _ if !col.enum_labels.is_empty() => json!(col.enum_labels[0]),
+        _ if col.name.contains("email") => json!("user@example.com"),
+        _ if col.name.contains("url") => json!("https://example.com"),
+        _ => json!(format!("example {}", col.name)),
+    };
+    match col.array {
+        true => json!([value]),
+        false => value,
+    }
+}
+
+/// A body with a sample value for each of `cols`.
+fn example_body<'a>(cols: impl IntoIterator<Item = &'a Col>) -> Value {
+    let type_map = create_type_map();
+    let body: Map<String, Value> = cols.into_iter()
+        .map(|col| (col.name.clone(), example_value(col, &type_map)))
+        .collect();
+    Value::Object(body)
+}
+
+/// `{"payload": <row>}`, what the single row endpoints answer with.
+fn payload_response(row: &Row) -> Value {
+    json!({
+        "type": "object",
+        "properties": {"payload": schema_ref(&pascal(row))},
+        "required": ["payload"],
+    })
+}
+
+fn pk_param(row: &Row, pk: &Col) -> Param {
+    Param::path(&pk.name, col_schema(row, pk, &create_type_map()))
+}
+
+/// The query parameters of `row`'s list endpoint, `{table}QueryParams`.
+/// `cols` are the columns it filters and sorts by and `expansions` the
+/// names `?expand=` takes.
+fn list_params(row: &Row, cols: &[&Col], expansions: &[String]) -> Vec<Param> {
+    let type_map = create_type_map();
+    let columns: Vec<&str> = cols.iter().map(|col| col.name.as_str()).collect();
+    let mut params = vec![
+        Param::query("order_by", json!({"type": "string", "enum": columns}), "column to sort by, the primary key by default"),
+        Param::query("direction", json!({"type": "string", "enum": ["asc", "desc"]}), "sort direction, asc by default"),
+        Param::query("limit", json!({"type": "integer", "minimum": 1}), "page size, capped by MAX_PAGE_SIZE"),
+        Param::query("offset", json!({"type": "integer", "minimum": 0}), "rows to skip, not together with cursor"),
+        Param::query("cursor", json!({"type": "string"}), "next_cursor of the previous page"),
+    ];
+    if !expansions.is_empty() {
+        params.push(Param::query(
+            "expand",
+            json!({"type": "string", "enum": expansions}),
+            "a foreign key whose row is embedded under this name",
+        ));
+    }
+    params.extend(cols.iter().map(|col| {
+        let description = format!("only rows whose {} equals this, see the description for other operators", col.name);
+        Param::query(&col.name, col_schema(row, col, &type_map), &description)
+    }));
+    params
+}
+
+const FILTER_DESCRIPTION: &str = "Any column is a filter, `column=value` for equality or \
+`column[op]=value` with op one of eq, ne, gt, gte, lt, lte, like, ilike, in, nin, is and between. \
+`in`, `nin` and `between` take comma separated values and `is` takes null or not_null. \
+Pages are ordered by order_by, follow next_cursor or use offset to get the next one.";
+
+/// `GET /get_{table}`
+pub fn list_operation(row: &Row, cols: &[&Col], expansions: &[String]) -> Operation {
+    Operation {
+        summary: format!("List {} rows", row.name),
+        description: Some(FILTER_DESCRIPTION.to_string()),
+        tag: Some(row.name.clone()),
+        params: list_params(row, cols, expansions),
+        response: schema_ref(&format!("{}Page", pascal(row))),
+        errors: vec![400, 500],
+        ..Operation::default()
+    }
+}
+
+/// `GET /{parent}/:id/{child}`, the child's list narrowed to one parent row.
+pub fn nested_list_operation(parent: &Row, ref_col: &Col, child: &Row, cols: &[&Col], expansions: &[String]) -> Operation {
+    let mut params = vec![Param::path("id", col_schema(parent, ref_col, &create_type_map()))];
+    params.extend(list_params(child, cols, expansions));
+    Operation {
+        summary: format!("List the {} rows of one {} row", child.name, parent.name),
+        description: Some(FILTER_DESCRIPTION.to_string()),
+        tag: Some(parent.name.clone()),
+        params,
+        response: schema_ref(&format!("{}Page", pascal(child))),
+        errors: vec![400, 404, 500],
+        ..Operation::default()
+    }
+}
+
+/// `POST /add_{table}`, taking `fields`. It always answers 200, with `res`
+/// saying whether the insert worked.
+pub fn insert_operation(row: &Row, fields: &[&Col]) -> Operation {
+    Operation {
+        summary: format!("Insert a {} row", row.name),
+        tag: Some(row.name.clone()),
+        body: Some(schema_ref(&format!("New{}", pascal(row)))),
+        example: Some(example_body(fields.iter().copied().filter(|col| !col.auto_gen))),
+        response: payload_response(row),
+        errors: vec![400, 409, 422, 500],
+        ..Operation::default()
+    }
+}
+
+/// `GET /get_one_{table}{col}?{col}=`
+pub fn get_one_operation(row: &Row, col: &Col) -> Operation {
+    let mut param = Param::query(&col.name, col_schema(row, col, &create_type_map()), "value to look up");
+    param.required = true;
+    Operation {
+        summary: format!("Get the first {} row by {}", row.name, col.name),
+        tag: Some(row.name.clone()),
+        params: vec![param],
+        response: payload_response(row),
+        errors: vec![400, 404, 500],
+        ..Operation::default()
+    }
+}
+
+/// `PUT /update_{table}/:pk`, taking `fields`.
+pub fn update_operation(row: &Row, pk: &Col, fields: &[&Col]) -> Operation {
+    Operation {
+        summary: format!("Replace a {} row", row.name),
+        description: Some("Omitted nullable columns are set to NULL and omitted columns with a default go back to it.".to_string()),
+        tag: Some(row.name.clone()),
+        params: vec![pk_param(row, pk)],
+        body: Some(schema_ref(&format!("Update{}", pascal(row)))),
+        example: Some(example_body(fields.iter().copied().filter(|col| !col.auto_gen))),
+        response: payload_response(row),
+        errors: vec![400, 404, 409, 422, 500],
+        ..Operation::default()
+    }
+}
+
+/// `PATCH /patch_{table}/:pk`, taking any of `fields`.
+pub fn patch_operation(row: &Row, pk: &Col, fields: &[&Col]) -> Operation {
+    Operation {
+        summary: format!("Update some columns of a {} row", row.name),
+        description: Some("Only the columns in the body change. null clears a nullable column.".to_string()),
+        tag: Some(row.name.clone()),
+        params: vec![pk_param(row, pk)],
+        body: Some(schema_ref(&format!("Patch{}", pascal(row)))),
+        example: Some(example_body(fields.iter().copied().filter(|col| !col.auto_gen).take(1))),
+        response: payload_response(row),
+        errors: vec![400, 404, 409, 422, 500],
+        ..Operation::default()
+    }
+}
+
+/// `DELETE /delete_{table}/:pk`
+pub fn delete_operation(row: &Row, pk: &Col) -> Operation {
+    let description = row.soft_delete_col()
+        .map(|col| format!("The row is kept with {} set, and no longer listed.", col.name));
+    Operation {
+        summary: format!("Delete a {} row", row.name),
+        description,
+        tag: Some(row.name.clone()),
+        params: vec![pk_param(row, pk)],
+        response: payload_response(row),
+        errors: vec![400, 404, 422, 500],
+        ..Operation::default()
+    }
+}
+
+/// The OpenAPI 3.1 document for `endpoints`. `behind_nginx` adds the
+/// nginx address the API is also served on.
+pub fn openapi_document(title: &str, rows: &[Row], endpoints: &[Endpoint], authz: Option<&Authz>, behind_nginx: bool) -> Value {
+    let mut paths: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+    paths.entry("/health".to_string()).or_default().insert("get".to_string(), Operation {
+        summary: "Health check".to_string(),
+        text_response: true,
+        ..Operation::default()
+    }.to_json("health", &[]));
+    for endpoint in endpoints {
+        // axum's `:param` is `{param}` in OpenAPI
+        let path_params: Vec<&str> = endpoint.path.split('/').filter_map(|part| part.strip_prefix(':')).collect();
+        let path = endpoint.path.split('/')
+            .map(|part| match part.strip_prefix(':') {
+                Some(param) => format!("{{{}}}", param),
+                None => part.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let mut operation = endpoint.operation.to_json(&endpoint.handler, &path_params);
+        if endpoint.protected {
+            operation["security"] = json!([{"bearerAuth": []}]);
+            operation["responses"]["401"] = error_response(401);
+        }
+        paths.entry(path).or_default().insert(endpoint.method.routing_fn().to_string(), operation);
+    }
+
+    // the insert, PUT and PATCH payloads only exist when their endpoints do
+    let bodies: BTreeSet<&str> = endpoints.iter()
+        .filter_map(|endpoint| endpoint.operation.body.as_ref()?.get("$ref")?.as_str()?.rsplit('/').next())
+        .collect();
+    let mut schemas = Map::new();
+    for row in rows {
+        let payloads = [format!("New{}", pascal(row)), format!("Update{}", pascal(row)), format!("Patch{}", pascal(row))];
+        for (name, schema) in row_schemas(row, authz) {
+            if !payloads.contains(&name) || bodies.contains(name.as_str()) {
+                schemas.insert(name, schema);
+            }
+        }
+    }
+    schemas.insert(PROBLEM_SCHEMA.to_string(), json!({
+        "type": "object",
+        "properties": {
+            "type": {"type": "string", "description": "always about:blank"},
+            "title": {"type": "string", "description": "the status's reason phrase"},
+            "status": {"type": "integer"},
+            "detail": {"type": "string", "description": "what went wrong"},
+            "errors": {
+                "type": "array",
+                "description": "the invalid payload fields, on a 422 from validation",
+                "items": {
+                    "type": "object",
+                    "properties": {"field": {"type": "string"}, "message": {"type": "string"}},
+                    "required": ["field", "message"],
+                },
+            },
+        },
+        "required": ["type", "title", "status", "detail"],
+    }));
+
+    let mut servers = vec![json!({"url": "http://localhost:8081", "description": "the API itself"})];
+    if behind_nginx {
+        servers.push(json!({"url": "http://localhost:3002/api", "description": "through nginx"}));
+    }
+    let mut components = json!({"schemas": schemas});
+    if endpoints.iter().any(|endpoint| endpoint.protected) {
+        components["securitySchemes"] = json!({
+            "bearerAuth": {"type": "http", "scheme": "bearer", "bearerFormat": "JWT"},
+        });
+    }
+    json!({
+        "openapi": "3.1.0",
+        "info": {"title": title, "version": "0.1.0"},
+        "servers": servers,
+        "paths": paths,
+        "components": components,
+    })
+}
+
+/// Writes `openapi.json` into the project directory.
+pub fn write_openapi(project_dir: &std::path::Path, document: &Value) -> Result<(), io::Error> {
+    let mut contents = serde_json::to_string_pretty(document)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    contents.push('\n');
+    manifest::write_generated(project_dir.join(OPENAPI_FILE), contents)
+}
+
+/// Writes the handlers serving `openapi.json`, Swagger UI at `/docs` and
+/// ReDoc at `/redoc`. The document is built into the binary, and both UIs
+/// load from a CDN.
+pub fn add_docs(file_path: &std::path::Path) -> Result<Vec<Endpoint>, io::Error> {
+    let swagger_ui = r##"<!doctype html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>API docs</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+      window.ui = SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });
+    </script>
+  </body>
+</html>
+"##;
+    let redoc = r#"<!doctype html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>API docs</title>
+  </head>
+  <body>
+    <redoc spec-url="openapi.json"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/latest/bundles/redoc.standalone.js"></script>
+  </body>
+</html>
+"#;
+    let openapi_path = format!("../../{}", OPENAPI_FILE);
+    // raw strings keep the pages readable in the generated file
+    let raw = |html: &str| format!("r##\"{}\"##", html).parse::<proc_macro2::Literal>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+    let (swagger_ui, redoc) = (raw(swagger_ui)?, raw(redoc)?);
+
+    write_module(file_path, quote! {
+        /// The OpenAPI document, built into the binary when it is compiled.
+        const OPENAPI_JSON: &str = include_str!(#openapi_path);
+        const SWAGGER_UI: &str = #swagger_ui;
+        const REDOC: &str = #redoc;
+
+        /// Serves openapi.json
+        pub async fn openapi_json() -> impl IntoResponse {
+            ([(header::CONTENT_TYPE, "application/json")], OPENAPI_JSON)
+        }
+
+        /// Swagger UI for openapi.json
+        pub async fn swagger_ui() -> Html<&'static str> {
+            Html(SWAGGER_UI)
+        }
+
+        /// ReDoc for openapi.json
+        pub async fn redoc() -> Html<&'static str> {
+            Html(REDOC)
+        }
+    })?;
+
+    let endpoint = |path: &str, handler: &str| Endpoint {
+        method: HttpMethod::Get,
+        path: path.to_string(),
+        handler: handler.to_string(),
+        protected: false,
+        operation: Operation::default(),
+    };
+    Ok(vec![
+        endpoint("/openapi.json", "openapi_json"),
+        endpoint("/docs", "swagger_ui"),
+        endpoint("/redoc", "redoc"),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::add_auth::AuthConfig;
+    use crate::schema::parse_rows;
+    use crate::sql_funcs::table_module;
+
+    const SCHEMA: &str = "CREATE TABLE users (
+            user_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            email VARCHAR(255) NOT NULL UNIQUE
+        );
+        CREATE TABLE runs (
+            run_id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(user_id),
+            km DOUBLE PRECISION CHECK (km > 0),
+            note TEXT
+        );";
+
+    fn document(schema: &str, auth: bool) -> Value {
+        let rows = parse_rows(schema).unwrap();
+        let config = AuthConfig::default();
+        let authz = auth.then(|| Authz::new(&config, &rows)).flatten();
+        let mut endpoints = Vec::new();
+        for row in &rows {
+            table_module(row, &rows, authz.as_ref(), &mut endpoints).unwrap();
+        }
+        // what add_auth does to the routes it doesn't open
+        for endpoint in endpoints.iter_mut() {
+            endpoint.protected = auth && !config.is_public(endpoint);
+        }
+        openapi_document("runs", &rows, &endpoints, authz.as_ref(), false)
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        value.as_object().unwrap().keys().map(String::as_str).collect()
+    }
+
+    fn names(params: &Value) -> Vec<&str> {
+        params.as_array().unwrap().iter().map(|param| param["name"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn every_route_and_struct_is_documented() {
+        let document = document(SCHEMA, false);
+        assert_eq!(document["openapi"], "3.1.0");
+        assert_eq!(keys(&document["paths"]), [
+            "/add_runs", "/add_users", "/delete_runs/{run_id}", "/delete_users/{user_id}",
+            "/get_one_runskm", "/get_one_runsnote", "/get_one_runsrun_id", "/get_one_runsuser_id",
+            "/get_one_usersemail", "/get_one_usersuser_id", "/get_runs", "/get_users", "/health",
+            "/patch_runs/{run_id}", "/patch_users/{user_id}", "/update_runs/{run_id}", "/update_users/{user_id}",
+            "/users/{id}/runs",
+        ]);
+        assert_eq!(keys(&document["components"]["schemas"]), [
+            "NewRuns", "NewUsers", "PatchRuns", "PatchUsers", "Problem", "Runs", "RunsPage",
+            "UpdateRuns", "UpdateUsers", "Users", "UsersPage",
+        ]);
+        assert!(document["components"].get("securitySchemes").is_none());
+    }
+
+    #[test]
+    fn operations_have_their_params_bodies_and_errors() {
+        let document = document(SCHEMA, false);
+        let paths = &document["paths"];
+
+        let list = &paths["/get_runs"]["get"];
+        assert_eq!(names(&list["parameters"]), [
+            "order_by", "direction", "limit", "offset", "cursor", "expand", "run_id", "user_id", "km", "note",
+        ]);
+        assert_eq!(list["parameters"][5]["schema"]["enum"], json!(["user"]));
+        assert_eq!(list["responses"]["200"]["content"]["application/json"]["schema"], schema_ref("RunsPage"));
+
+        let patch = &paths["/patch_runs/{run_id}"]["patch"];
+        assert_eq!(patch["parameters"][0]["in"], "path");
+        assert_eq!(patch["parameters"][0]["schema"]["format"], "uuid");
+        assert_eq!(patch["requestBody"]["content"]["application/json"]["schema"], schema_ref("PatchRuns"));
+        assert_eq!(keys(&patch["responses"]), ["200", "400", "404", "409", "422", "500"]);
+        assert_eq!(
+            patch["responses"]["404"]["content"]["application/problem+json"]["schema"],
+            schema_ref(PROBLEM_SCHEMA)
+        );
+
+        let nested = &paths["/users/{id}/runs"]["get"];
+        assert_eq!(nested["tags"], json!(["users"]));
+        assert_eq!(names(&nested["parameters"])[0], "id");
+        assert_eq!(nested["responses"]["200"]["content"]["application/json"]["schema"], schema_ref("RunsPage"));
+    }
+
+    #[test]
+    fn schemas_follow_the_columns() {
+        let document = document(SCHEMA, false);
+        let schemas = &document["components"]["schemas"];
+        assert_eq!(schemas["Runs"]["required"], json!(["run_id", "user_id", "km", "note"]));
+        // the key has a default and the rest may be NULL
+        assert_eq!(schemas["NewRuns"]["required"], json!(["user_id"]));
+        assert_eq!(schemas["PatchRuns"]["required"], json!([]));
+        assert!(schemas["UpdateRuns"]["properties"].get("run_id").is_none());
+
+        let km = &schemas["Runs"]["properties"]["km"];
+        assert_eq!(km["type"], json!(["number", "null"]));
+        assert_eq!(km["exclusiveMinimum"], 0);
+        let email = &schemas["Users"]["properties"]["email"];
+        assert_eq!((&email["format"], &email["maxLength"]), (&json!("email"), &json!(255)));
+        assert_eq!(schemas["Runs"]["properties"]["user_id"]["description"], "references users(user_id)");
+    }
+
+    #[test]
+    fn protected_routes_need_a_token() {
+        let document = document(
+            "CREATE TABLE users (id SERIAL PRIMARY KEY, email TEXT NOT NULL, password_hash TEXT NOT NULL);
+            CREATE TABLE runs (id SERIAL PRIMARY KEY, user_id INT REFERENCES users(id));",
+            true,
+        );
+        assert_eq!(document["components"]["securitySchemes"]["bearerAuth"]["scheme"], "bearer");
+        let list = &document["paths"]["/get_runs"]["get"];
+        assert_eq!(list["security"], json!([{"bearerAuth": []}]));
+        assert_eq!(list["responses"]["401"]["description"], error_description(401));
+        assert_eq!(list["responses"]["403"]["description"], error_description(403));
+        // the hash is never sent back, so it isn't documented either
+        assert!(document["components"]["schemas"]["Users"]["properties"].get("password_hash").is_none());
+    }
+}
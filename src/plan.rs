@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use similar::{ChangeTag, TextDiff};
+
+use crate::base_structs::Endpoint;
+use crate::vfs::FileChange;
+
+/// What `generate_service` produced, for the `--dry-run` summary.
+#[derive(Debug, Default)]
+pub struct Generated {
+    pub tables: Vec<String>,
+    pub routes: Vec<Endpoint>,
+    /// The docker compose services, empty when compose wasn't written.
+    pub services: Vec<String>,
+}
+
+/// Prints what a dry run would have done to `project_dir`: the files with
+/// their line counts, then the tables, routes and services behind them.
+pub fn print_summary(project_dir: &Path, generated: &Generated, changes: &[FileChange]) {
+    println!("\nDry run, nothing was written. {} would get:\n", project_dir.display());
+    if changes.is_empty() {
+        println!("No file changes");
+    }
+    for change in changes {
+        let lines = match (text(&change.before), text(&change.after)) {
+            (Some(before), Some(after)) => {
+                let (added, removed) = line_counts(before, after);
+                format!("+{} -{}", added, removed)
+            }
+            (None, Some(after)) if change.before.is_none() => format!("+{}", after.lines().count()),
+            (Some(before), None) if change.after.is_none() => format!("-{}", before.lines().count()),
+            _ => "binary".to_string(),
+        };
+        println!("  {:<7} {} ({})", change.kind(), relative(project_dir, &change.path).display(), lines);
+    }
+
+    if !generated.tables.is_empty() {
+        println!("\nTables: {}", generated.tables.join(", "));
+    }
+    if !generated.routes.is_empty() {
+        println!("\nRoutes:");
+        for route in &generated.routes {
+            println!("  {:<7} {}", route.method.as_str(), route.path);
+        }
+    }
+    if !generated.services.is_empty() {
+        println!("\nServices: {}", generated.services.join(", "));
+    }
+}
+
+/// Prints the dry run as a unified diff against the files in `project_dir`,
+/// paths relative to it the way `git diff` shows them.
+pub fn print_diff(project_dir: &Path, changes: &[FileChange]) {
+    print!("{}", unified_diff(project_dir, changes));
+}
+
+fn unified_diff(project_dir: &Path, changes: &[FileChange]) -> String {
+    let mut diff = String::new();
+    for change in changes {
+        let path = relative(project_dir, &change.path).display().to_string();
+        let (Some(before), Some(after)) = (
+            text(&change.before).or(change.before.is_none().then_some("")),
+            text(&change.after).or(change.after.is_none().then_some("")),
+        ) else {
+            diff.push_str(&format!("Binary file {} differs\n", path));
+            continue;
+        };
+        let old_name = match change.before {
+            Some(_) => format!("a/{}", path),
+            None => "/dev/null".to_string(),
+        };
+        let new_name = match change.after {
+            Some(_) => format!("b/{}", path),
+            None => "/dev/null".to_string(),
+        };
+        diff.push_str(&TextDiff::from_lines(before, after).unified_diff().header(&old_name, &new_name).to_string());
+    }
+    diff
+}
+
+fn text(contents: &Option<Vec<u8>>) -> Option<&str> {
+    std::str::from_utf8(contents.as_deref()?).ok()
+}
+
+fn line_counts(before: &str, after: &str) -> (usize, usize) {
+    let diff = TextDiff::from_lines(before, after);
+    diff.iter_all_changes().fold((0, 0), |(added, removed), change| match change.tag() {
+        ChangeTag::Insert => (added + 1, removed),
+        ChangeTag::Delete => (added, removed + 1),
+        ChangeTag::Equal => (added, removed),
+    })
+}
+
+/// `path` relative to the project, when it is inside it.
+fn relative<'a>(project_dir: &Path, path: &'a Path) -> &'a Path {
+    std::path::absolute(project_dir).ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::SERIAL;
+    use crate::vfs;
+
+    #[test]
+    fn the_diff_is_against_the_files_on_disk() {
+        let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let project_dir = dir.path();
+        std::fs::create_dir(project_dir.join("src")).unwrap();
+        std::fs::write(project_dir.join("src/main.rs"), "mod models;\nfn main() {}\n").unwrap();
+        std::fs::write(project_dir.join("logo.png"), [0x89, 0xff]).unwrap();
+
+        vfs::start_plan();
+        vfs::write(project_dir.join("src/main.rs"), "mod handlers;\nmod models;\nfn main() {}\n").unwrap();
+        vfs::write(project_dir.join("src/handlers.rs"), "pub mod runs;\n").unwrap();
+        vfs::write(project_dir.join("logo.png"), [0x89, 0xfe]).unwrap();
+        let changes = vfs::finish_plan();
+
+        assert_eq!(
+            unified_diff(project_dir, &changes),
+            "Binary file logo.png differs\n\
+             --- /dev/null\n\
+             +++ b/src/handlers.rs\n\
+             @@ -0,0 +1 @@\n\
+             +pub mod runs;\n\
+             --- a/src/main.rs\n\
+             +++ b/src/main.rs\n\
+             @@ -1,2 +1,3 @@\n\
+             +mod handlers;\n \
+             mod models;\n \
+             fn main() {}\n"
+        );
+    }
+}
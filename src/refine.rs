@@ -0,0 +1,66 @@
+use std::io::{self, Write};
+
+use crate::base_structs::Row;
+use crate::gen_sql::SqlSession;
+use crate::schema::parse_rows;
+use crate::schema_diff::{describe_table, diff_rows};
+
+/// Shows the tables in `sql` and takes change requests from stdin, such as
+/// "add a phone column to hosts", until the user accepts the schema. Every
+/// change is shown as a diff against the schema before it, and nothing is
+/// written until the schema is accepted.
+pub async fn refine_sql(session: &mut SqlSession<'_>, mut sql: String) -> Result<String, io::Error> {
+    let mut rows = parse(&sql)?;
+    println!("\nProposed schema:\n");
+    for row in &rows {
+        println!("{}", describe_table(row));
+    }
+
+    loop {
+        print!("Describe a change, press enter to accept the schema, or type quit: ");
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Stdin closed before the schema was accepted"));
+        }
+
+        let request = line.trim();
+        match request.to_lowercase().as_str() {
+            "" | "y" | "yes" => return Ok(sql),
+            "q" | "quit" | "exit" => {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "The schema was not accepted"));
+            }
+            _ => {}
+        }
+
+        let asked = session.ask(format!(
+            "Change the schema: {}\nReply with the whole schema, not only what changed.",
+            request
+        )).await;
+        let new_sql = match asked {
+            Ok(new_sql) => new_sql,
+            Err(e) => {
+                eprintln!("{}\nThe schema is unchanged.", e);
+                continue;
+            }
+        };
+
+        let new_rows = parse(&new_sql)?;
+        let changes = diff_rows(&rows, &new_rows);
+        if changes.is_empty() {
+            println!("\nThe tables did not change.\n");
+        } else {
+            println!("\nChanges:\n");
+            for change in &changes {
+                println!("{}", change);
+            }
+            println!();
+        }
+        sql = new_sql;
+        rows = new_rows;
+    }
+}
+
+fn parse(sql: &str) -> Result<Vec<Row>, io::Error> {
+    parse_rows(sql).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
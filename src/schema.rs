@@ -1,107 +1,634 @@
+use std::collections::HashMap;
 use std::io;
-use std::fs;
-
-pub fn extract_table_schemas(file_path: &str) -> Result<Vec<String>, io::Error> {
-    let contents = fs::read_to_string(file_path)?;
-    let mut schemas = Vec::new();
-    let lower_contents = contents.to_lowercase();
-    let mut start_index = 0;
-
-    while let Some(create_index) = lower_contents[start_index..].find("create table") {
-        let start = start_index + create_index;
-        if let Some(open_paren_index) = contents[start..].find('(') {
-            let schema_start = start + open_paren_index + 1;
-            if let Some(close_paren_index) = contents[schema_start..].find(");") {
-                let schema_end = schema_start + close_paren_index;
-                let schema = contents[schema_start..schema_end].trim().to_string();
-                schemas.push(schema);
-                start_index = schema_end + 2; // Move past ");"
-            } else if let Some(close_paren_index) = contents[schema_start..].find(')') {
-                let schema_end = schema_start + close_paren_index;
-                let schema = contents[schema_start..schema_end].trim().to_string();
-                schemas.push(schema);
-                start_index = schema_end + 1; // Move past ")"
-            } else {
-                break; // Handle potential errors if closing parenthesis isn't found
-            }
-        } else {
-            break; // Handle potential errors if opening parenthesis isn't found
-        }
-    }
 
-    Ok(schemas)
-}
+use convert_case::{Case, Casing};
 
-#[derive(Debug)]
+use crate::base_structs::{OnDelete, Relation, Row, SOFT_DELETE_COLUMN};
+use crate::vfs;
+use crate::ddl_lexer::ParseError;
+use crate::ddl_parser::{
+    parse_statements, AlterAction, ColumnConstraint, ColumnDef, ForeignKeyRef, Statement, TableConstraint, TableDef,
+};
+
+#[derive(Debug, Clone)]
 pub struct Col {
     pub name: String,
     pub col_type: String,
-    pub auto_gen: bool
-}
-
-pub fn extract_column_info(schema: &str) -> Vec<Col> {
-    let column_definitions: Vec<&str> = schema.split(',')
-    .map(|s| s.trim())
-    .filter(|&s| !s.contains("FOREIGN")).collect();
-    let mut columns_info = Vec::new();
-    
-    for definition in column_definitions {
-        let parts: Vec<&str> = definition.split_whitespace().collect();
-        let auto_gen = if parts.contains(&"DEFAULT") {
-            true
-        } else {
-            false
+    pub auto_gen: bool,
+    pub not_null: bool,
+    pub primary_key: bool,
+    pub unique: bool,
+    /// The UNIQUE constraint's name when it was given one, postgres calls
+    /// an unnamed one `{table}_{column}_key`.
+    pub unique_name: Option<String>,
+    /// The DEFAULT expression as written in the DDL, e.g. `gen_random_uuid()`.
+    pub default: Option<String>,
+    /// Length for character and bit types (`VARCHAR(255)`), precision for
+    /// `NUMERIC(10, 2)` and the time types.
+    pub precision: Option<u32>,
+    /// Digits after the decimal point for `NUMERIC(p, s)`.
+    pub scale: Option<u32>,
+    /// `TEXT[]` and the like: the column holds an array of `col_type`.
+    pub array: bool,
+    /// The labels of the enum type `col_type` names, in order. Empty for
+    /// other types.
+    pub enum_labels: Vec<String>,
+}
+
+impl Col {
+    /// A column can hold NULL unless it is NOT NULL or part of the primary key.
+    pub fn is_nullable(&self) -> bool {
+        !self.not_null && !self.primary_key
+    }
+
+    /// The rust type for this column, without any `Option` wrapper. Array
+    /// columns are a `Vec` of their element type.
+    pub fn rust_type(&self, type_map: &HashMap<String, String>) -> String {
+        match self.array {
+            true => format!("Vec<{}>", self.element_type(type_map)),
+            false => self.element_type(type_map),
+        }
+    }
+
+    /// The rust type of one value of this column, the element type for arrays.
+    /// Enum columns get the enum `enum_types` generates into the models.
+    pub fn element_type(&self, type_map: &HashMap<String, String>) -> String {
+        if !self.enum_labels.is_empty() {
+            return format!("crate::models::{}", self.col_type.to_case(Case::Pascal));
+        }
+        type_map.get(&self.col_type)
+            .cloned()
+            .unwrap_or_else(|| "String".to_string()) // Default to String if type not found
+    }
+
+    /// The type to CAST a text parameter to when comparing against this
+    /// column. Serial types only exist in DDL so they map to their integer type.
+    pub fn cast_type(&self) -> String {
+        let element = match self.col_type.as_str() {
+            "SMALLSERIAL" => "SMALLINT",
+            "SERIAL" => "INTEGER",
+            "BIGSERIAL" => "BIGINT",
+            other => other,
+        };
+        match self.array {
+            true => format!("{}[]", element),
+            false => element.to_string(),
+        }
+    }
+
+    /// `cast_type` with the length, precision and scale the column was
+    /// declared with, e.g. `CHAR(3)` or `NUMERIC(10, 2)[]`. Postgres reads a
+    /// bare `CHAR` or `BIT` as one character or bit long and cuts a value
+    /// cast to it short.
+    pub fn declared_cast_type(&self) -> String {
+        let cast_type = self.cast_type();
+        let element = cast_type.trim_end_matches("[]");
+        let element = match (self.precision, self.scale) {
+            (Some(precision), Some(scale)) => format!("{}({}, {})", element, precision, scale),
+            (Some(precision), None) => format!("{}({})", element, precision),
+            _ => element.to_string(),
         };
-        if parts.len() >= 2 {
-            let name = parts[0].to_string();
-            let mut col_type = parts[1].to_string();
-            if col_type == "DOUBLE" {
-                col_type.push(' ');
-                col_type.push_str(parts[2])
+        match self.array {
+            true => format!("{}[]", element),
+            false => element,
+        }
+    }
+}
+
+/// Serial types get their value from a sequence, the same as a DEFAULT would.
+const SERIAL_TYPES: &[&str] = &["SMALLSERIAL", "SERIAL", "BIGSERIAL"];
+
+fn col_from_def(def: &ColumnDef, table: &TableDef) -> Col {
+    let mut col = Col {
+        name: def.name.clone(),
+        col_type: def.data_type.name.clone(),
+        auto_gen: SERIAL_TYPES.contains(&def.data_type.name.as_str()),
+        not_null: false,
+        primary_key: false,
+        unique: false,
+        unique_name: None,
+        default: None,
+        precision: def.data_type.args.first().and_then(|a| a.parse().ok()),
+        scale: def.data_type.args.get(1).and_then(|a| a.parse().ok()),
+        array: def.data_type.array,
+        enum_labels: Vec::new(),
+    };
+
+    for constraint in &def.constraints {
+        match constraint {
+            ColumnConstraint::NotNull => col.not_null = true,
+            ColumnConstraint::Null => col.not_null = false,
+            ColumnConstraint::PrimaryKey => col.primary_key = true,
+            ColumnConstraint::Unique => col.unique = true,
+            ColumnConstraint::Default(expr) => {
+                col.default = Some(expr.clone());
+                col.auto_gen = true;
+            }
+            ColumnConstraint::Generated => col.auto_gen = true,
+            _ => {}
+        }
+    }
+
+    for constraint in &table.constraints {
+        match constraint {
+            TableConstraint::PrimaryKey { columns, .. } if columns.contains(&col.name) => {
+                col.primary_key = true;
+            }
+            // a multi column UNIQUE does not make each column unique on its own
+            TableConstraint::Unique { name, columns } if columns.len() == 1 && columns[0] == col.name => {
+                col.unique = true;
+                col.unique_name = name.clone();
             }
-            columns_info.push(Col { name, col_type, auto_gen});
-        } else if parts.len() == 1 {
-            // Handle cases with only a name (e.g., constraints)
-            let name = parts[0].to_string();
-            columns_info.push(Col { name, col_type: "".to_string(), auto_gen});
+            _ => {}
         }
     }
 
-    columns_info
+    if col.primary_key {
+        col.not_null = true;
+    }
+    col
+}
+
+fn relation_from_ref(name: Option<String>, columns: Vec<String>, references: &ForeignKeyRef) -> Relation {
+    Relation {
+        name,
+        columns,
+        ref_table: references.table.clone(),
+        ref_columns: references.columns.clone(),
+        on_delete: references.on_delete.as_deref()
+            .and_then(OnDelete::from_sql)
+            .unwrap_or_default(),
+    }
+}
+
+fn row_from_table(table: &TableDef) -> Row {
+    let inline = table.columns.iter().flat_map(|def| {
+        def.constraints.iter().filter_map(|c| match c {
+            ColumnConstraint::References(references) => {
+                Some(relation_from_ref(None, vec![def.name.clone()], references))
+            }
+            _ => None,
+        })
+    });
+    let table_level = table.constraints.iter().filter_map(|c| match c {
+        TableConstraint::ForeignKey { name, columns, references } => {
+            Some(relation_from_ref(name.clone(), columns.clone(), references))
+        }
+        _ => None,
+    });
+
+    let column_checks = table.columns.iter().flat_map(|def| {
+        def.constraints.iter().filter_map(|c| match c {
+            ColumnConstraint::Check(expr) => Some(expr.clone()),
+            _ => None,
+        })
+    });
+    let table_checks = table.constraints.iter().filter_map(|c| match c {
+        TableConstraint::Check { expr, .. } => Some(expr.clone()),
+        _ => None,
+    });
+
+    Row {
+        name: table.name.clone(),
+        cols: table.columns.iter().map(|def| col_from_def(def, table)).collect(),
+        relations: inline.chain(table_level).collect(),
+        checks: column_checks.chain(table_checks).collect(),
+        primary_key_name: table.constraints.iter().find_map(|c| match c {
+            TableConstraint::PrimaryKey { name, .. } => name.clone(),
+            _ => None,
+        }),
+        soft_delete_column: SOFT_DELETE_COLUMN.to_string(),
+        nested_lists: None,
+    }
 }
 
+/// `REFERENCES users` without a column list points at the primary key of `users`.
+fn resolve_ref_columns(rows: &mut [Row]) {
+    let primary_keys: HashMap<String, Vec<String>> = rows.iter()
+        .map(|row| {
+            let pk = row.cols.iter().filter(|c| c.primary_key).map(|c| c.name.clone()).collect();
+            (row.name.clone(), pk)
+        })
+        .collect();
 
-pub fn extract_table_names(file_path: &str) -> Result<Vec<String>, io::Error> {
-    let contents = fs::read_to_string(file_path)?;
-    let mut table_names = Vec::new();
-    let lower_contents = contents.to_lowercase();
-    let mut start_index = 0;
+    for row in rows.iter_mut() {
+        for rel in row.relations.iter_mut().filter(|rel| rel.ref_columns.is_empty()) {
+            match primary_keys.get(&rel.ref_table) {
+                Some(pk) if !pk.is_empty() => rel.ref_columns = pk.clone(),
+                _ => eprintln!(
+                    "Warning: {} references {} but its primary key is unknown",
+                    row.name, rel.ref_table
+                ),
+            }
+        }
+    }
+}
 
-    while let Some(create_index) = lower_contents[start_index..].find("create table if not exists") {
-        let start = start_index + create_index + "create table if not exists".len();
-        // Find the start of the table name
-        let name_start = contents[start..].trim_start();
-        // Extract the table name
-        let mut table_name = String::new();
-        for c in name_start.chars() {
-            if c == '(' || c == ' ' || c == '\n' || c == '\r' {
-                break;
+/// Plays `statements` over `tables` the way postgres would run them, so a
+/// schema built up by several migrations ends up as its final tables.
+/// Statements postgres would reject are skipped, each one is described,
+/// naming `source`, in what is returned.
+fn apply_statements(tables: &mut Vec<TableDef>, statements: Vec<Statement>, source: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    for statement in statements {
+        match statement {
+            Statement::Create(table) => {
+                match tables.iter_mut().find(|t| t.name == table.name) {
+                    None => tables.push(table),
+                    Some(_) if table.if_not_exists => {}
+                    Some(existing) => {
+                        problems.push(format!(
+                            "{}:{}: table {} is created twice, the later definition is used",
+                            source, table.line, table.name
+                        ));
+                        *existing = table;
+                    }
+                }
+            }
+            Statement::Drop { names, .. } => {
+                tables.retain(|t| !names.contains(&t.name));
+                // only DROP ... CASCADE gets this far with a foreign key still pointing here
+                for table in tables.iter_mut() {
+                    for name in &names {
+                        drop_references(table, name, None);
+                    }
+                }
             }
-            if table_name == "foreign"{
-                break;
+            Statement::Alter { name, actions, line } => {
+                for action in actions {
+                    if let Err(message) = alter_table(tables, &name, action) {
+                        problems.push(format!("{}:{}: {}, the change is skipped", source, line, message));
+                    }
+                }
             }
-            table_name.push(c);
         }
+    }
+    problems
+}
 
-        table_names.push(table_name);
+fn warn(problems: Vec<String>) {
+    for problem in problems {
+        eprintln!("Warning: {}", problem);
+    }
+}
 
-        // Find the end of the current CREATE TABLE statement (look for ");")
-        if let Some(end_index) = contents[start..].find(");") {
-            start_index = start + end_index + 2; // Move past ");"
-        } else {
-            break; // Handle case where ");" is not found (malformed SQL)
+fn alter_table(tables: &mut [TableDef], name: &str, action: AlterAction) -> Result<(), String> {
+    let i = tables.iter().position(|t| t.name == name)
+        .ok_or_else(|| format!("table {} does not exist", name))?;
+
+    match action {
+        AlterAction::AddColumn(def) => {
+            if tables[i].columns.iter().any(|c| c.name == def.name) {
+                return Err(format!("column {}.{} already exists", name, def.name));
+            }
+            tables[i].columns.push(def);
+        }
+        AlterAction::DropColumn(column) => {
+            column_mut(&mut tables[i], &column)?;
+            tables[i].columns.retain(|c| c.name != column);
+            // postgres drops the indexes and constraints the column was part of
+            tables[i].constraints.retain(|c| !constraint_columns(c).contains(&column));
+            for table in tables.iter_mut() {
+                drop_references(table, name, Some(&column));
+            }
+        }
+        AlterAction::AlterType { column, data_type } => {
+            column_mut(&mut tables[i], &column)?.data_type = data_type;
+        }
+        AlterAction::SetNotNull(column) => {
+            let def = column_mut(&mut tables[i], &column)?;
+            def.constraints.retain(|c| !matches!(c, ColumnConstraint::NotNull | ColumnConstraint::Null));
+            def.constraints.push(ColumnConstraint::NotNull);
+        }
+        AlterAction::DropNotNull(column) => {
+            column_mut(&mut tables[i], &column)?.constraints.retain(|c| *c != ColumnConstraint::NotNull);
+        }
+        AlterAction::SetDefault { column, expr } => {
+            let def = column_mut(&mut tables[i], &column)?;
+            def.constraints.retain(|c| !matches!(c, ColumnConstraint::Default(_)));
+            def.constraints.push(ColumnConstraint::Default(expr));
+        }
+        AlterAction::DropDefault(column) => {
+            column_mut(&mut tables[i], &column)?.constraints.retain(|c| !matches!(c, ColumnConstraint::Default(_)));
+        }
+        AlterAction::AddConstraint(constraint) => tables[i].constraints.push(constraint),
+        AlterAction::DropConstraint(constraint) => drop_constraint(&mut tables[i], &constraint)?,
+        AlterAction::RenameColumn { from, to } => {
+            column_mut(&mut tables[i], &from)?;
+            pin_constraint_names(&mut tables[i], Some(&from));
+            column_mut(&mut tables[i], &from)?.name = to.clone();
+            let rename = |columns: &mut Vec<String>| {
+                columns.iter_mut().filter(|c| **c == from).for_each(|c| *c = to.clone());
+            };
+            for constraint in tables[i].constraints.iter_mut() {
+                match constraint {
+                    TableConstraint::PrimaryKey { columns, .. }
+                    | TableConstraint::Unique { columns, .. }
+                    | TableConstraint::ForeignKey { columns, .. } => rename(columns),
+                    _ => {}
+                }
+            }
+            for table in tables.iter_mut() {
+                for_each_reference(table, |references| {
+                    if references.table == name {
+                        rename(&mut references.columns);
+                    }
+                });
+            }
+        }
+        AlterAction::RenameTable(new_name) => {
+            pin_constraint_names(&mut tables[i], None);
+            tables[i].name = new_name.clone();
+            tables[i].written_name = new_name.clone();
+            for table in tables.iter_mut() {
+                for_each_reference(table, |references| {
+                    if references.table == name {
+                        references.table = new_name.clone();
+                    }
+                });
+            }
         }
     }
+    Ok(())
+}
+
+fn column_mut<'a>(table: &'a mut TableDef, column: &str) -> Result<&'a mut ColumnDef, String> {
+    let name = &table.name;
+    table.columns.iter_mut()
+        .find(|c| c.name == column)
+        .ok_or_else(|| format!("column {}.{} does not exist", name, column))
+}
+
+fn constraint_columns(constraint: &TableConstraint) -> &[String] {
+    match constraint {
+        TableConstraint::PrimaryKey { columns, .. }
+        | TableConstraint::Unique { columns, .. }
+        | TableConstraint::ForeignKey { columns, .. } => columns,
+        _ => &[],
+    }
+}
+
+fn for_each_reference(table: &mut TableDef, mut f: impl FnMut(&mut ForeignKeyRef)) {
+    for def in table.columns.iter_mut() {
+        for constraint in def.constraints.iter_mut() {
+            if let ColumnConstraint::References(references) = constraint {
+                f(references);
+            }
+        }
+    }
+    for constraint in table.constraints.iter_mut() {
+        if let TableConstraint::ForeignKey { references, .. } = constraint {
+            f(references);
+        }
+    }
+}
+
+/// Removes the foreign keys in `table` pointing at `ref_table`, or only the
+/// ones using `ref_column` of it.
+fn drop_references(table: &mut TableDef, ref_table: &str, ref_column: Option<&str>) {
+    let dropped = |references: &ForeignKeyRef| {
+        references.table == ref_table
+            && ref_column.is_none_or(|column| references.columns.iter().any(|c| c == column))
+    };
+    for def in table.columns.iter_mut() {
+        def.constraints.retain(|c| !matches!(c, ColumnConstraint::References(r) if dropped(r)));
+    }
+    table.constraints.retain(|c| !matches!(c, TableConstraint::ForeignKey { references, .. } if dropped(references)));
+}
 
-    Ok(table_names)
+/// Removes the constraint `name` from `table`, matching unnamed constraints
+/// by the name postgres would have given them.
+fn drop_constraint(table: &mut TableDef, name: &str) -> Result<(), String> {
+    let table_name = table.name.clone();
+    let constraints = table.constraints.len();
+    table.constraints.retain(|c| {
+        let given = match c {
+            TableConstraint::PrimaryKey { name, .. }
+            | TableConstraint::Unique { name, .. }
+            | TableConstraint::Check { name, .. }
+            | TableConstraint::ForeignKey { name, .. }
+            | TableConstraint::Other { name, .. } => name.clone(),
+        };
+        given.or_else(|| table_constraint_name(&table_name, c)).as_deref() != Some(name)
+    });
+    if table.constraints.len() < constraints {
+        return Ok(());
+    }
+
+    for def in table.columns.iter_mut() {
+        let before = def.constraints.len();
+        def.constraints.retain(|c| column_constraint_name(&table_name, &def.name, c).as_deref() != Some(name));
+        if def.constraints.len() < before {
+            return Ok(());
+        }
+    }
+    Err(format!("constraint {} does not exist on {}", name, table_name))
+}
+
+/// Gives the unnamed constraints on `table`, or only the ones using
+/// `column`, the names postgres gave them. A rename keeps those names, so
+/// they have to stop following the table and column names. Constraints
+/// written on a column move to the table to have somewhere to keep a name.
+fn pin_constraint_names(table: &mut TableDef, column: Option<&str>) {
+    let table_name = table.name.clone();
+    for constraint in table.constraints.iter_mut() {
+        let uses_column = match constraint {
+            TableConstraint::Check { .. } | TableConstraint::Other { .. } => column.is_none(),
+            _ => column.is_none_or(|column| constraint_columns(constraint).iter().any(|c| c == column)),
+        };
+        let default_name = table_constraint_name(&table_name, constraint);
+        if let TableConstraint::PrimaryKey { name, .. }
+        | TableConstraint::Unique { name, .. }
+        | TableConstraint::Check { name, .. }
+        | TableConstraint::ForeignKey { name, .. } = constraint
+            && uses_column
+            && name.is_none()
+        {
+            *name = default_name;
+        }
+    }
+
+    for def in table.columns.iter_mut().filter(|def| column.is_none_or(|column| def.name == column)) {
+        let columns = vec![def.name.clone()];
+        let mut kept = Vec::new();
+        for constraint in std::mem::take(&mut def.constraints) {
+            let name = column_constraint_name(&table_name, &def.name, &constraint);
+            let moved = match constraint {
+                ColumnConstraint::PrimaryKey => TableConstraint::PrimaryKey { name, columns: columns.clone() },
+                ColumnConstraint::Unique => TableConstraint::Unique { name, columns: columns.clone() },
+                ColumnConstraint::Check(expr) => TableConstraint::Check { name, expr },
+                ColumnConstraint::References(references) => {
+                    TableConstraint::ForeignKey { name, columns: columns.clone(), references }
+                }
+                other => {
+                    kept.push(other);
+                    continue;
+                }
+            };
+            table.constraints.push(moved);
+        }
+        def.constraints = kept;
+    }
+}
+
+/// The name postgres gives an unnamed table constraint.
+fn table_constraint_name(table: &str, constraint: &TableConstraint) -> Option<String> {
+    match constraint {
+        TableConstraint::PrimaryKey { .. } => Some(format!("{}_pkey", table)),
+        TableConstraint::Unique { columns, .. } => Some(format!("{}_{}_key", table, columns.join("_"))),
+        TableConstraint::ForeignKey { columns, .. } => Some(format!("{}_{}_fkey", table, columns.join("_"))),
+        TableConstraint::Check { .. } => Some(format!("{}_check", table)),
+        TableConstraint::Other { .. } => None,
+    }
+}
+
+/// The name postgres gives a constraint written on the column itself.
+fn column_constraint_name(table: &str, column: &str, constraint: &ColumnConstraint) -> Option<String> {
+    match constraint {
+        ColumnConstraint::PrimaryKey => Some(format!("{}_pkey", table)),
+        ColumnConstraint::Unique => Some(format!("{}_{}_key", table, column)),
+        ColumnConstraint::References(_) => Some(format!("{}_{}_fkey", table, column)),
+        ColumnConstraint::Check(_) => Some(format!("{}_{}_check", table, column)),
+        _ => None,
+    }
+}
+
+fn rows_from_tables(tables: &[TableDef]) -> Vec<Row> {
+    let mut rows: Vec<Row> = tables.iter().map(row_from_table).collect();
+    resolve_ref_columns(&mut rows);
+    rows
+}
+
+/// Parses the `CREATE TABLE`, `ALTER TABLE` and `DROP TABLE` statements in
+/// `sql` into the rows they leave behind.
+pub fn parse_rows(sql: &str) -> Result<Vec<Row>, ParseError> {
+    let mut tables = Vec::new();
+    warn(apply_statements(&mut tables, parse_statements(sql)?, "sql"));
+    Ok(rows_from_tables(&tables))
+}
+
+/// Reads and parses a SQL file. Parse errors are returned as `InvalidData`
+/// with the file name, line and column in the message.
+pub fn extract_rows(file_path: &std::path::Path) -> Result<Vec<Row>, io::Error> {
+    let contents = vfs::read_to_string(file_path)?;
+    parse_rows(&contents).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file_path.display(), e))
+    })
+}
+
+/// Reads and parses several SQL files as one schema, in order, so a foreign
+/// key can point at a table created in an earlier file and a later
+/// migration can alter or drop it.
+pub fn extract_rows_from_files(file_paths: &[std::path::PathBuf]) -> Result<Vec<Row>, io::Error> {
+    Ok(rows_from_tables(&tables_from_files(file_paths)?))
+}
+
+fn tables_from_files(file_paths: &[std::path::PathBuf]) -> Result<Vec<TableDef>, io::Error> {
+    let mut tables = Vec::new();
+    for file_path in file_paths {
+        let contents = vfs::read_to_string(file_path)?;
+        let statements = parse_statements(&contents).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", file_path.display(), e))
+        })?;
+        warn(apply_statements(&mut tables, statements, &file_path.display().to_string()));
+    }
+    Ok(tables)
+}
+
+/// Reads the schema `file_paths` leave behind and plays `migration` over
+/// it, the way the next run will. A statement of `migration` that would be
+/// skipped is an `InvalidData` error, postgres would reject it too.
+pub fn replay_migration(file_paths: &[std::path::PathBuf], migration: &str) -> Result<Vec<Row>, io::Error> {
+    let mut tables = tables_from_files(file_paths)?;
+    let statements = parse_statements(migration)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("migration: {}", e)))?;
+    let problems = apply_statements(&mut tables, statements, "migration");
+    if !problems.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, problems.join("; ")));
+    }
+    Ok(rows_from_tables(&tables))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_structs::create_type_map;
+
+    fn rust_types(sql: &str) -> Vec<String> {
+        let type_map = create_type_map();
+        parse_rows(sql).unwrap().remove(0).cols.iter().map(|col| col.rust_type(&type_map)).collect()
+    }
+
+    #[test]
+    fn fixed_length_text_is_a_string() {
+        assert_eq!(
+            rust_types("CREATE TABLE t (code CHAR(3), kind CHARACTER(1), flag \"char\", name CHARACTER VARYING(20));"),
+            ["String", "String", "i8", "String"]
+        );
+    }
+
+    #[test]
+    fn numeric_is_a_decimal() {
+        assert_eq!(
+            rust_types("CREATE TABLE t (price NUMERIC(10,2), total DECIMAL, ratio DOUBLE PRECISION);"),
+            ["rust_decimal::Decimal", "rust_decimal::Decimal", "f64"]
+        );
+    }
+
+    #[test]
+    fn arrays_are_vecs_of_their_element() {
+        let type_map = create_type_map();
+        let row = parse_rows("CREATE TABLE t (tags TEXT[] NOT NULL, scores INT[], n SERIAL);").unwrap().remove(0);
+        assert_eq!(row.cols[0].rust_type(&type_map), "Vec<String>");
+        assert_eq!(row.cols[1].element_type(&type_map), "i32");
+        assert_eq!(row.cols[1].cast_type(), "INT[]");
+        assert_eq!(row.cols[2].cast_type(), "INTEGER");
+        assert!(row.cols[1].is_nullable());
+        assert!(!row.cols[0].is_nullable());
+    }
+
+    #[test]
+    fn foreign_keys_become_relations() {
+        let rows = parse_rows(
+            "CREATE TABLE users (user_id UUID PRIMARY KEY);
+            CREATE TABLE teams (team_id INT, season INT, PRIMARY KEY (team_id, season));
+            CREATE TABLE runs (
+                run_id UUID PRIMARY KEY,
+                user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+                coach_id UUID REFERENCES users,
+                team_id INT,
+                season INT,
+                FOREIGN KEY (team_id, season) REFERENCES teams (team_id, season) ON DELETE SET NULL
+            );",
+        ).unwrap();
+        let runs = &rows[2];
+        let relations: Vec<(String, &str, String, OnDelete)> = runs.relations.iter()
+            .map(|rel| (rel.columns.join(", "), rel.ref_table.as_str(), rel.ref_columns.join(", "), rel.on_delete))
+            .collect();
+        assert_eq!(relations, [
+            ("user_id".to_string(), "users", "user_id".to_string(), OnDelete::Cascade),
+            // without columns the referenced primary key is meant
+            ("coach_id".to_string(), "users", "user_id".to_string(), OnDelete::NoAction),
+            ("team_id, season".to_string(), "teams", "team_id, season".to_string(), OnDelete::SetNull),
+        ]);
+        assert_eq!(runs.relation_for("season").unwrap().ref_table, "teams");
+        assert!(runs.relation_for("run_id").is_none());
+    }
+
+    #[test]
+    fn has_many_is_the_reverse_of_the_relations() {
+        let rows = parse_rows(
+            "CREATE TABLE users (user_id UUID PRIMARY KEY);
+            CREATE TABLE runs (run_id UUID PRIMARY KEY, user_id UUID REFERENCES users ON DELETE CASCADE);
+            CREATE TABLE follows (follower UUID REFERENCES users, followed UUID REFERENCES users);",
+        ).unwrap();
+        let children = rows[0].has_many(&rows);
+        let tables: Vec<(&str, &str)> = children.iter()
+            .map(|child| (child.table.as_str(), child.columns[0].as_str()))
+            .collect();
+        assert_eq!(tables, [("runs", "user_id"), ("follows", "follower"), ("follows", "followed")]);
+        assert_eq!(children[0].ref_columns, ["user_id"]);
+        assert_eq!(children[0].on_delete, OnDelete::Cascade);
+        assert!(rows[1].has_many(&rows).is_empty());
+    }
 }
@@ -0,0 +1,599 @@
+use std::fmt;
+
+use crate::base_structs::{OnDelete, Relation, Row};
+use crate::schema::Col;
+
+/// One difference between two versions of a schema.
+#[derive(Debug)]
+pub enum SchemaChange<'a> {
+    CreateTable(&'a Row),
+    DropTable(&'a Row),
+    AddColumn { table: &'a str, col: &'a Col },
+    DropColumn { table: &'a str, col: &'a Col },
+    AlterColumn { table: &'a str, old: &'a Col, new: &'a Col },
+    AddRelation { table: &'a str, relation: &'a Relation },
+    DropRelation { table: &'a str, relation: &'a Relation },
+}
+
+/// What it takes to get from `old` to `new`, table by table in the order
+/// the tables appear. A renamed table or column shows up as a drop and an add.
+pub fn diff_rows<'a>(old: &'a [Row], new: &'a [Row]) -> Vec<SchemaChange<'a>> {
+    let mut changes = Vec::new();
+
+    for old_row in old.iter().filter(|row| !new.iter().any(|r| r.name == row.name)) {
+        changes.push(SchemaChange::DropTable(old_row));
+    }
+
+    for new_row in new {
+        let Some(old_row) = old.iter().find(|row| row.name == new_row.name) else {
+            changes.push(SchemaChange::CreateTable(new_row));
+            continue;
+        };
+        let table = new_row.name.as_str();
+
+        for col in old_row.cols.iter().filter(|col| find_col(new_row, &col.name).is_none()) {
+            changes.push(SchemaChange::DropColumn { table, col });
+        }
+        for col in &new_row.cols {
+            match find_col(old_row, &col.name) {
+                None => changes.push(SchemaChange::AddColumn { table, col }),
+                Some(old_col) if !same_col(old_col, col) => {
+                    changes.push(SchemaChange::AlterColumn { table, old: old_col, new: col });
+                }
+                Some(_) => {}
+            }
+        }
+
+        for relation in old_row.relations.iter().filter(|rel| !new_row.relations.contains(rel)) {
+            changes.push(SchemaChange::DropRelation { table, relation });
+        }
+        for relation in new_row.relations.iter().filter(|rel| !old_row.relations.contains(rel)) {
+            changes.push(SchemaChange::AddRelation { table, relation });
+        }
+    }
+    changes
+}
+
+fn find_col<'a>(row: &'a Row, name: &str) -> Option<&'a Col> {
+    row.cols.iter().find(|col| col.name == name)
+}
+
+fn same_col(a: &Col, b: &Col) -> bool {
+    canonical_type(&a.col_type) == canonical_type(&b.col_type)
+        && a.array == b.array
+        && a.precision == b.precision
+        && a.scale == b.scale
+        && a.not_null == b.not_null
+        && a.primary_key == b.primary_key
+        && a.unique == b.unique
+        && a.default.as_deref().map(normalize_default) == b.default.as_deref().map(normalize_default)
+}
+
+/// A default expression the way it compares: postgres hands back
+/// `'anon'::text` for `'anon'`, so casts are dropped and, outside string
+/// literals, case is ignored.
+fn normalize_default(expr: &str) -> String {
+    let mut normalized = String::new();
+    let mut chars = expr.trim().chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            in_string = !in_string;
+            normalized.push(c);
+        } else if in_string {
+            normalized.push(c);
+        } else if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            // the type name, including multi word ones such as `character varying`
+            while chars.peek().is_some_and(|c| c.is_alphanumeric() || matches!(c, '_' | ' ' | '"')) {
+                chars.next();
+            }
+        } else {
+            normalized.extend(c.to_lowercase());
+        }
+    }
+    normalized
+}
+
+/// The column's type as it would be written in DDL, e.g. `NUMERIC(10, 2)`
+/// or `TEXT[]`.
+pub fn sql_type(col: &Col) -> String {
+    let element = match (col.precision, col.scale) {
+        (Some(precision), Some(scale)) => format!("{}({}, {})", col.col_type, precision, scale),
+        (Some(precision), None) => format!("{}({})", col.col_type, precision),
+        _ => col.col_type.clone(),
+    };
+    match col.array {
+        true => format!("{}[]", element),
+        false => element,
+    }
+}
+
+/// A column definition as it would appear in a `CREATE TABLE`.
+pub fn column_definition(col: &Col) -> String {
+    let mut definition = format!("{} {}", col.name, sql_type(col));
+    if col.primary_key {
+        definition.push_str(" PRIMARY KEY");
+    } else if col.not_null {
+        definition.push_str(" NOT NULL");
+    }
+    if col.unique {
+        definition.push_str(" UNIQUE");
+    }
+    if let Some(default) = &col.default {
+        definition.push_str(&format!(" DEFAULT {}", default));
+    }
+    definition
+}
+
+/// One spelling per type, so `INT` written in a migration and the
+/// `INTEGER` a database reports are the same type.
+fn canonical_type(name: &str) -> &str {
+    match name {
+        "INT" | "INT4" => "INTEGER",
+        "INT2" => "SMALLINT",
+        "INT8" => "BIGINT",
+        "SERIAL2" => "SMALLSERIAL",
+        "SERIAL4" => "SERIAL",
+        "SERIAL8" => "BIGSERIAL",
+        "FLOAT4" => "REAL",
+        "FLOAT8" => "DOUBLE PRECISION",
+        "BOOLEAN" => "BOOL",
+        "BPCHAR" => "CHAR",
+        other => other,
+    }
+}
+
+/// The column's type for `ALTER COLUMN ... TYPE`, where the serial types
+/// don't exist. An array's precision goes on its element, `VARCHAR(40)[]`.
+fn alter_type(col: &Col) -> String {
+    let cast_type = col.cast_type();
+    let name = canonical_type(cast_type.trim_end_matches("[]"));
+    let element = match (col.precision, col.scale) {
+        (Some(precision), Some(scale)) => format!("{}({}, {})", name, precision, scale),
+        (Some(precision), None) => format!("{}({})", name, precision),
+        _ => name.to_string(),
+    };
+    match col.array {
+        true => format!("{}[]", element),
+        false => element,
+    }
+}
+
+fn primary_key(row: &Row) -> Vec<&str> {
+    row.cols.iter().filter(|col| col.primary_key).map(|col| col.name.as_str()).collect()
+}
+
+fn describe_relation(relation: &Relation) -> String {
+    let mut description = format!(
+        "({}) -> {}({})",
+        relation.columns.join(", "),
+        relation.ref_table,
+        relation.ref_columns.join(", ")
+    );
+    if relation.on_delete != OnDelete::NoAction {
+        description.push_str(&format!(" ON DELETE {}", relation.on_delete.as_sql()));
+    }
+    description
+}
+
+/// A table's columns and foreign keys, one per line, for showing a schema
+/// to someone deciding whether it is right.
+pub fn describe_table(row: &Row) -> String {
+    let mut description = format!("{}\n", row.name);
+    for col in &row.cols {
+        description.push_str(&format!("    {}\n", column_definition(col)));
+    }
+    for relation in &row.relations {
+        description.push_str(&format!("    {}\n", describe_relation(relation)));
+    }
+    description
+}
+
+impl fmt::Display for SchemaChange<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaChange::CreateTable(row) => {
+                write!(f, "+ table {}", describe_table(row).trim_end().replace('\n', "\n+ "))
+            }
+            SchemaChange::DropTable(row) => write!(f, "- table {}", row.name),
+            SchemaChange::AddColumn { table, col } => {
+                write!(f, "+ {}.{}", table, column_definition(col))
+            }
+            SchemaChange::DropColumn { table, col } => write!(f, "- {}.{}", table, col.name),
+            SchemaChange::AlterColumn { table, old, new } => write!(
+                f,
+                "~ {}.{} -> {}",
+                table,
+                column_definition(old),
+                column_definition(new)
+            ),
+            SchemaChange::AddRelation { table, relation } => {
+                write!(f, "+ {} {}", table, describe_relation(relation))
+            }
+            SchemaChange::DropRelation { table, relation } => {
+                write!(f, "- {} {}", table, describe_relation(relation))
+            }
+        }
+    }
+}
+
+impl SchemaChange<'_> {
+    /// The table the change is made to.
+    pub fn table(&self) -> &str {
+        match self {
+            SchemaChange::CreateTable(row) | SchemaChange::DropTable(row) => &row.name,
+            SchemaChange::AddColumn { table, .. }
+            | SchemaChange::DropColumn { table, .. }
+            | SchemaChange::AlterColumn { table, .. }
+            | SchemaChange::AddRelation { table, .. }
+            | SchemaChange::DropRelation { table, .. } => table,
+        }
+    }
+
+    /// Whether running the change can lose data already in the database.
+    /// A new type may not hold every old value, so any type change counts.
+    pub fn is_destructive(&self) -> bool {
+        match self {
+            SchemaChange::DropTable(_) | SchemaChange::DropColumn { .. } => true,
+            SchemaChange::AlterColumn { old, new, .. } => alter_type(old) != alter_type(new),
+            _ => false,
+        }
+    }
+}
+
+/// The tables whose generated code `changes` touch: the changed tables and
+/// the tables they reference, whose nested list handlers return their rows.
+/// Dropped tables are left out, they have nothing left to generate.
+pub fn affected_tables(changes: &[SchemaChange], old: &[Row], new: &[Row]) -> Vec<String> {
+    let changed: Vec<&str> = changes.iter().map(|change| change.table()).collect();
+    let parents: Vec<&str> = old.iter().chain(new)
+        .filter(|row| changed.contains(&row.name.as_str()))
+        .flat_map(|row| row.relations.iter().map(|relation| relation.ref_table.as_str()))
+        .collect();
+    new.iter()
+        .filter(|row| changed.contains(&row.name.as_str()) || parents.contains(&row.name.as_str()))
+        .map(|row| row.name.clone())
+        .collect()
+}
+
+/// A `CREATE TABLE` for `row`, with its foreign keys.
+pub fn create_table_sql(row: &Row) -> String {
+    create_table_with(row, &row.relations.iter().collect::<Vec<_>>())
+}
+
+/// A `CREATE TABLE` for `row` with only the foreign keys in `relations`.
+fn create_table_with(row: &Row, relations: &[&Relation]) -> String {
+    let primary_key = primary_key(row);
+    let mut lines: Vec<String> = row.cols.iter()
+        .map(|col| if primary_key.len() == 1 { column_definition(col) } else { column_sql(col) })
+        .collect();
+    if primary_key.len() > 1 {
+        lines.push(format!("PRIMARY KEY ({})", primary_key.join(", ")));
+    }
+    lines.extend(relations.iter().map(|relation| foreign_key_sql(relation)));
+    lines.extend(row.checks.iter().map(|check| format!("CHECK ({})", check)));
+    format!("CREATE TABLE IF NOT EXISTS {} (\n    {}\n);", row.name, lines.join(",\n    "))
+}
+
+/// A column definition without PRIMARY KEY, which is added on its own.
+fn column_sql(col: &Col) -> String {
+    let mut definition = format!("{} {}", col.name, sql_type(col));
+    if col.not_null {
+        definition.push_str(" NOT NULL");
+    }
+    if col.unique {
+        definition.push_str(" UNIQUE");
+    }
+    if let Some(default) = &col.default {
+        definition.push_str(&format!(" DEFAULT {}", default));
+    }
+    definition
+}
+
+fn foreign_key_sql(relation: &Relation) -> String {
+    let mut sql = format!(
+        "FOREIGN KEY ({}) REFERENCES {}({})",
+        relation.columns.join(", "),
+        relation.ref_table,
+        relation.ref_columns.join(", ")
+    );
+    if relation.on_delete != OnDelete::NoAction {
+        sql.push_str(&format!(" ON DELETE {}", relation.on_delete.as_sql()));
+    }
+    sql
+}
+
+// Where a migration statement goes in `migration_sql`
+const DROP_KEYS: usize = 0;
+const CREATE: usize = 1;
+const ALTER: usize = 2;
+const ADD_KEYS: usize = 3;
+const DROP: usize = 4;
+const PHASES: usize = 5;
+
+/// The SQL that takes a database from `old` to `new`. Statements are
+/// ordered so each one only needs the ones before it: keys are dropped
+/// first and added last, and columns and tables are dropped at the end.
+/// Constraints are dropped by the names they were given, or the ones
+/// postgres gives unnamed constraints, e.g. `runs_user_id_fkey`.
+/// Statements that lose data get a comment saying so.
+pub fn migration_sql(old: &[Row], new: &[Row]) -> String {
+    let mut phases: [Vec<String>; PHASES] = Default::default();
+
+    let changes = diff_rows(old, new);
+    let created: Vec<&str> = changes.iter()
+        .filter(|change| matches!(change, SchemaChange::CreateTable(_)))
+        .map(|change| change.table())
+        .collect();
+    for change in &changes {
+        let (phase, statement) = match change {
+            SchemaChange::CreateTable(row) => {
+                // a key to a table created further down is added once that table exists
+                let later = &created[created.iter().position(|name| *name == row.name).unwrap_or(0) + 1..];
+                let (deferred, inline): (Vec<&Relation>, Vec<&Relation>) =
+                    row.relations.iter().partition(|relation| later.contains(&relation.ref_table.as_str()));
+                for relation in deferred {
+                    phases[ADD_KEYS].push(format!("ALTER TABLE {} ADD {};", row.name, foreign_key_sql(relation)));
+                }
+                (CREATE, create_table_with(row, &inline))
+            }
+            SchemaChange::DropTable(row) => {
+                (DROP, format!("-- Destroys data: every row of {}\nDROP TABLE {};", row.name, row.name))
+            }
+            SchemaChange::AddColumn { table, col } => {
+                (ALTER, format!("ALTER TABLE {} ADD COLUMN {};", table, column_sql(col)))
+            }
+            SchemaChange::DropColumn { table, col } => (DROP, format!(
+                "-- Destroys data: every value of {}.{}\nALTER TABLE {} DROP COLUMN {};",
+                table, col.name, table, col.name
+            )),
+            SchemaChange::AlterColumn { table, old, new } => {
+                alter_column_sql(table, old, new, &mut phases);
+                continue;
+            }
+            SchemaChange::AddRelation { table, relation } => {
+                (ADD_KEYS, format!("ALTER TABLE {} ADD {};", table, foreign_key_sql(relation)))
+            }
+            SchemaChange::DropRelation { table, relation } => (DROP_KEYS, format!(
+                "ALTER TABLE {} DROP CONSTRAINT {};",
+                table,
+                relation.constraint_name(table)
+            )),
+        };
+        phases[phase].push(statement);
+    }
+
+    for new_row in new {
+        let Some(old_row) = old.iter().find(|row| row.name == new_row.name) else { continue };
+        let (old_key, new_key) = (primary_key(old_row), primary_key(new_row));
+        if old_key == new_key {
+            continue;
+        }
+        if !old_key.is_empty() {
+            phases[DROP_KEYS].push(format!("ALTER TABLE {} DROP CONSTRAINT {};", new_row.name, old_row.primary_key_constraint()));
+        }
+        if !new_key.is_empty() {
+            phases[ADD_KEYS].insert(0, format!("ALTER TABLE {} ADD PRIMARY KEY ({});", new_row.name, new_key.join(", ")));
+        }
+    }
+
+    // children usually come after their parents, so drop them first
+    phases[DROP].reverse();
+    let statements: Vec<String> = phases.into_iter().flatten().collect();
+    format!("-- Generated by auto-rust from the schema diff.\n\n{}\n", statements.join("\n\n"))
+}
+
+/// The statements for one changed column. Keys go in their phase so a
+/// foreign key is never left pointing at a dropped UNIQUE.
+fn alter_column_sql(table: &str, old: &Col, new: &Col, phases: &mut [Vec<String>; PHASES]) {
+    let alter = format!("ALTER TABLE {} ALTER COLUMN {}", table, new.name);
+    let statements = &mut phases[ALTER];
+    if alter_type(old) != alter_type(new) {
+        statements.push(format!(
+            "-- Destroys data: values that don't fit {} fail the migration\n{} TYPE {} USING {}::{};",
+            alter_type(new), alter, alter_type(new), new.name, alter_type(new)
+        ));
+    }
+    if old.default != new.default {
+        match &new.default {
+            Some(default) => statements.push(format!("{} SET DEFAULT {};", alter, default)),
+            None => statements.push(format!("{} DROP DEFAULT;", alter)),
+        }
+    }
+    if old.not_null != new.not_null {
+        if new.not_null && new.default.is_some() {
+            // existing NULLs would fail SET NOT NULL, give them the default
+            statements.push(format!("UPDATE {} SET {} = DEFAULT WHERE {} IS NULL;", table, new.name, new.name));
+        }
+        let action = if new.not_null { "SET" } else { "DROP" };
+        statements.push(format!("{} {} NOT NULL;", alter, action));
+    }
+    if old.unique != new.unique {
+        if new.unique {
+            phases[ADD_KEYS].push(format!("ALTER TABLE {} ADD CONSTRAINT {}_{}_key UNIQUE ({});", table, table, new.name, new.name));
+        } else {
+            let name = old.unique_name.clone().unwrap_or_else(|| format!("{}_{}_key", table, old.name));
+            phases[DROP_KEYS].push(format!("ALTER TABLE {} DROP CONSTRAINT {};", table, name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{parse_rows, replay_migration};
+
+    const USERS: &str = "CREATE TABLE users (id SERIAL PRIMARY KEY, email TEXT NOT NULL UNIQUE, name TEXT);";
+
+    fn rows(sql: &str) -> Vec<Row> {
+        parse_rows(sql).unwrap()
+    }
+
+    fn described(old: &[Row], new: &[Row]) -> Vec<String> {
+        diff_rows(old, new).iter().map(|change| change.to_string()).collect()
+    }
+
+    #[test]
+    fn same_schema_has_no_changes() {
+        assert!(diff_rows(&rows(USERS), &rows(USERS)).is_empty());
+    }
+
+    #[test]
+    fn columns_are_added_dropped_and_altered() {
+        let old = rows(USERS);
+        let new = rows("CREATE TABLE users (id SERIAL PRIMARY KEY, email VARCHAR(200) NOT NULL UNIQUE, age INT DEFAULT 0);");
+        assert_eq!(described(&old, &new), vec![
+            "- users.name",
+            "~ users.email TEXT NOT NULL UNIQUE -> email VARCHAR(200) NOT NULL UNIQUE",
+            "+ users.age INT DEFAULT 0",
+        ]);
+        let changes = diff_rows(&old, &new);
+        assert!(changes[0].is_destructive());
+        assert!(changes[1].is_destructive());
+        assert!(!changes[2].is_destructive());
+    }
+
+    #[test]
+    fn int_spellings_and_default_casts_compare_equal() {
+        let old = rows("CREATE TABLE t (id INT PRIMARY KEY, role TEXT DEFAULT 'anon', ok BOOLEAN);");
+        let new = rows("CREATE TABLE t (id INTEGER PRIMARY KEY, role TEXT DEFAULT 'anon'::text, ok BOOL);");
+        assert!(diff_rows(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn array_flag_is_a_change() {
+        let old = rows("CREATE TABLE t (id INT PRIMARY KEY, tags TEXT);");
+        let new = rows("CREATE TABLE t (id INT PRIMARY KEY, tags TEXT[]);");
+        assert_eq!(described(&old, &new), vec!["~ t.tags TEXT -> tags TEXT[]"]);
+        assert_eq!(sql_type(&new[0].cols[1]), "TEXT[]");
+    }
+
+    #[test]
+    fn array_types_are_altered_with_the_precision_on_the_element() {
+        let old = "CREATE TABLE t (id INT PRIMARY KEY, labels VARCHAR(20)[], scores INT[]);";
+        let (before, not_null) = (rows(old), rows("CREATE TABLE t (id INT PRIMARY KEY, labels VARCHAR(20)[], scores INTEGER[] NOT NULL);"));
+        let changes = diff_rows(&before, &not_null);
+        assert_eq!(changes.len(), 1);
+        assert!(!changes[0].is_destructive());
+
+        let new = "CREATE TABLE t (id INT PRIMARY KEY, labels VARCHAR(40)[], scores INTEGER[]);";
+        assert_eq!(statements(old, new), vec![
+            "-- Destroys data: values that don't fit VARCHAR(40)[] fail the migration\nALTER TABLE t ALTER COLUMN labels TYPE VARCHAR(40)[] USING labels::VARCHAR(40)[];",
+        ]);
+    }
+
+    #[test]
+    fn numeric_type_keeps_precision_and_scale() {
+        let row = &rows("CREATE TABLE t (price NUMERIC(10,2), code VARCHAR(8));")[0];
+        assert_eq!(sql_type(&row.cols[0]), "NUMERIC(10, 2)");
+        assert_eq!(sql_type(&row.cols[1]), "VARCHAR(8)");
+    }
+
+    #[test]
+    fn tables_and_relations() {
+        let old = rows(&format!("{} CREATE TABLE gone (id INT PRIMARY KEY);", USERS));
+        let new = rows(&format!(
+            "{} CREATE TABLE runs (id SERIAL PRIMARY KEY, user_id INT REFERENCES users(id) ON DELETE CASCADE);",
+            USERS
+        ));
+        let changes = diff_rows(&old, &new);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(changes[0], SchemaChange::DropTable(row) if row.name == "gone"));
+        assert!(matches!(changes[1], SchemaChange::CreateTable(row) if row.name == "runs"));
+        assert_eq!(changes[1].to_string(), "+ table runs\n+     id SERIAL PRIMARY KEY\n+     user_id INT\n+     (user_id) -> users(id) ON DELETE CASCADE");
+        assert_eq!(affected_tables(&changes, &old, &new), vec!["users", "runs"]);
+
+        let linked = rows(&format!("{} CREATE TABLE runs (id SERIAL PRIMARY KEY, user_id INT);", USERS));
+        assert_eq!(described(&linked, &new), vec!["+ runs (user_id) -> users(id) ON DELETE CASCADE"]);
+        assert_eq!(described(&new, &linked), vec!["- runs (user_id) -> users(id) ON DELETE CASCADE"]);
+    }
+
+    fn statements(old: &str, new: &str) -> Vec<String> {
+        let sql = migration_sql(&rows(old), &rows(new));
+        sql.split("\n\n").skip(1).map(|statement| statement.trim().to_string()).collect()
+    }
+
+    #[test]
+    fn created_table_keeps_its_checks_and_keys() {
+        let sql = create_table_sql(&rows(
+            "CREATE TABLE items (order_id INT, line INT, qty INT CHECK (qty > 0), PRIMARY KEY (order_id, line), FOREIGN KEY (order_id) REFERENCES orders(id));",
+        )[0]);
+        assert_eq!(sql, "CREATE TABLE IF NOT EXISTS items (\n    order_id INT NOT NULL,\n    line INT NOT NULL,\n    qty INT,\n    PRIMARY KEY (order_id, line),\n    FOREIGN KEY (order_id) REFERENCES orders(id),\n    CHECK (qty > 0)\n);");
+    }
+
+    #[test]
+    fn column_changes_become_alters() {
+        let old = "CREATE TABLE t (id INT PRIMARY KEY, name TEXT, score INT, code TEXT UNIQUE);";
+        let new = "CREATE TABLE t (id INT PRIMARY KEY, score BIGINT NOT NULL DEFAULT 0, code TEXT, added DATE);";
+        assert_eq!(statements(old, new), vec![
+            "ALTER TABLE t DROP CONSTRAINT t_code_key;",
+            "-- Destroys data: values that don't fit BIGINT fail the migration\nALTER TABLE t ALTER COLUMN score TYPE BIGINT USING score::BIGINT;",
+            "ALTER TABLE t ALTER COLUMN score SET DEFAULT 0;",
+            "UPDATE t SET score = DEFAULT WHERE score IS NULL;",
+            "ALTER TABLE t ALTER COLUMN score SET NOT NULL;",
+            "ALTER TABLE t ADD COLUMN added DATE;",
+            "-- Destroys data: every value of t.name\nALTER TABLE t DROP COLUMN name;",
+        ]);
+    }
+
+    #[test]
+    fn keys_are_dropped_first_and_added_last() {
+        let old = "CREATE TABLE a (id INT PRIMARY KEY); CREATE TABLE b (id INT PRIMARY KEY, a_id INT REFERENCES a(id));";
+        let new = "CREATE TABLE a (id INT PRIMARY KEY); CREATE TABLE b (id INT, a_id INT, c_id INT REFERENCES c(id), PRIMARY KEY (id, a_id)); CREATE TABLE c (id INT PRIMARY KEY);";
+        assert_eq!(statements(old, new), vec![
+            "ALTER TABLE b DROP CONSTRAINT b_a_id_fkey;",
+            "ALTER TABLE b DROP CONSTRAINT b_pkey;",
+            "CREATE TABLE IF NOT EXISTS c (\n    id INT PRIMARY KEY\n);",
+            "ALTER TABLE b ALTER COLUMN a_id SET NOT NULL;",
+            "ALTER TABLE b ADD COLUMN c_id INT;",
+            "ALTER TABLE b ADD PRIMARY KEY (id, a_id);",
+            "ALTER TABLE b ADD FOREIGN KEY (c_id) REFERENCES c(id);",
+        ]);
+    }
+
+    #[test]
+    fn named_constraints_are_dropped_by_their_names() {
+        let old = "CREATE TABLE users (id INT PRIMARY KEY);
+            CREATE TABLE runs (
+                id INT, user_id INT, coach_id INT REFERENCES users, code TEXT CONSTRAINT runs_code UNIQUE,
+                CONSTRAINT runs_id PRIMARY KEY (id),
+                CONSTRAINT fk_runs_owner FOREIGN KEY (user_id) REFERENCES users(id)
+            );";
+        let new = "CREATE TABLE users (id INT PRIMARY KEY); CREATE TABLE runs (id INT, user_id INT, coach_id INT, code TEXT);";
+        assert_eq!(statements(old, new), vec![
+            "ALTER TABLE runs DROP CONSTRAINT runs_code;",
+            "ALTER TABLE runs DROP CONSTRAINT runs_coach_id_fkey;",
+            "ALTER TABLE runs DROP CONSTRAINT fk_runs_owner;",
+            "ALTER TABLE runs DROP CONSTRAINT runs_id;",
+            "ALTER TABLE runs ALTER COLUMN id DROP NOT NULL;",
+        ]);
+    }
+
+    #[test]
+    fn a_migration_replays_over_the_one_before() {
+        let _serial = crate::manifest::SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("0001_data.sql");
+        let old = "CREATE TABLE users (id INT PRIMARY KEY);
+            CREATE TABLE runs (id INT PRIMARY KEY, user_id INT, CONSTRAINT fk_runs_owner FOREIGN KEY (user_id) REFERENCES users(id));";
+        std::fs::write(&first, old).unwrap();
+        let new = rows("CREATE TABLE users (id INT PRIMARY KEY); CREATE TABLE runs (id INT PRIMARY KEY, user_id INT);");
+
+        let replayed = replay_migration(std::slice::from_ref(&first), &migration_sql(&rows(old), &new)).unwrap();
+        assert!(diff_rows(&replayed, &new).is_empty());
+
+        let error = replay_migration(&[first], "ALTER TABLE runs DROP CONSTRAINT runs_user_id_fkey;").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("constraint runs_user_id_fkey does not exist on runs"));
+    }
+
+    #[test]
+    fn tables_are_dropped_children_first() {
+        let old = "CREATE TABLE a (id INT PRIMARY KEY); CREATE TABLE b (id INT PRIMARY KEY, a_id INT REFERENCES a(id));";
+        assert_eq!(statements(old, ""), vec![
+            "-- Destroys data: every row of b\nDROP TABLE b;",
+            "-- Destroys data: every row of a\nDROP TABLE a;",
+        ]);
+    }
+}
@@ -1,65 +1,227 @@
 
-use crate::base_structs::Row;
-use std::{fs::OpenOptions, io::{self, Write}};
+use crate::base_structs::{Endpoint, Row};
+use std::io;
+use std::collections::BTreeMap;
+use proc_macro2::TokenStream;
+use quote::quote;
+use crate::codegen::{ident, rust_type, TableModule};
+use crate::add_auth::{is_hidden, Authz};
 use crate::add_functions;
+use crate::add_joins;
 use crate::base_structs::create_type_map;
 use convert_case::{Case, Casing};
 
-fn generate_struct(row: &Row, file_path: &std::path::Path) -> Result<(), std::io::Error> {
-    // Ensure parent directories exist
-    if let Some(parent) = file_path.parent() {
-        std::fs::create_dir_all(parent)?;
-    }
-    
+fn generate_struct(row: &Row, authz: Option<&Authz>, module: &mut TableModule) -> Result<(), std::io::Error> {
     let type_map = create_type_map();
-    let struct_name = row.name.to_case(Case::Pascal); // Convert table name to PascalCase
-    let mut struct_string = format!("#[derive(Debug, Serialize, Deserialize, FromRow)]\nstruct {} {{\n", struct_name);
-
-    for col in &row.cols {
-        let field_name = col.name.clone();
-        let rust_type = type_map.get(&col.col_type)
-            .map(|s| s.as_str())
-            .unwrap_or("String"); // Default to String if type not found
-        if col.auto_gen {
-            struct_string.push_str(&format!("    {}: Option<{}>,\n",field_name, rust_type));
+    let struct_name = ident(&row.name.to_case(Case::Pascal)); // Convert table name to PascalCase
+
+    let fields = row.cols.iter().map(|col| {
+        let field_name = ident(&col.name);
+        let rust_type = rust_type(&col.rust_type(&type_map))?;
+        let doc = row.relation_for(&col.name)
+            .map(|rel| format!(" references {}({})", rel.ref_table, rel.ref_columns.join(", ")))
+            .map(|doc| quote! { #[doc = #doc] });
+        let skip = is_hidden(authz, row, col).then(|| quote! { #[serde(skip_serializing)] });
+        // only nullable columns can come back from the database as NULL
+        if col.is_nullable() {
+            Ok(quote! { #doc #skip pub #field_name: Option<#rust_type> })
         } else {
-            struct_string.push_str(&format!("    {}: {},\n", field_name, rust_type));
+            Ok(quote! { #doc #skip pub #field_name: #rust_type })
         }
-    }
+    }).collect::<Result<Vec<_>, io::Error>>()?;
 
-    struct_string.push_str("}\n");
+    module.models.extend(quote! {
+        #[derive(Debug, Serialize, Deserialize, FromRow)]
+        pub struct #struct_name {
+            #(#fields),*
+        }
+    });
+    Ok(())
+}
 
-    // Write the struct to the file
-    // fs::write(file_path, struct_string)?;
+/// A rust enum for each postgres enum type the columns of `rows` use, for
+/// `models/mod.rs` since tables share them.
+pub fn enum_types(rows: &[Row]) -> TokenStream {
+    let mut types = BTreeMap::new();
+    for col in rows.iter().flat_map(|row| &row.cols).filter(|col| !col.enum_labels.is_empty()) {
+        types.insert(&col.col_type, &col.enum_labels);
+    }
+    let enums = types.into_iter().map(|(type_name, labels)| {
+        let name = ident(&type_name.to_case(Case::Pascal));
+        let variants = labels.iter().map(|label| {
+            let variant = ident(&label.to_case(Case::Pascal));
+            quote! {
+                #[serde(rename = #label)]
+                #[sqlx(rename = #label)]
+                #variant
+            }
+        });
+        // the derive doesn't cover array columns, postgres names the array type `_{type}`
+        let array_type_name = format!("_{}", type_name);
+        quote! {
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+            #[sqlx(type_name = #type_name)]
+            pub enum #name {
+                #(#variants),*
+            }
 
-    let mut file = OpenOptions::new()
-        .write(true) // Enable writing to the file.
-        .append(true) // Set the append mode.  Crucially, this makes it append.
-        .create(true) // Create the file if it doesn't exist.
-        .open(file_path)?; // Open the file, returning a Result.
+            impl sqlx::postgres::PgHasArrayType for #name {
+                fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+                    sqlx::postgres::PgTypeInfo::with_name(#array_type_name)
+                }
+            }
+        }
+    });
+    quote! { #(#enums)* }
+}
 
-    // Write the data to the file.
-    file.write_all(struct_string.as_bytes())?; // comment for testing 
+/// Generates the models, handlers and data layer for every table, one
+/// file per table in each of `src_dir`'s `models/`, `handlers/` and `data/`.
+/// With `authz` the data layer checks the caller's role and keeps them to
+/// their own rows.
+pub fn add_basic_sql_funcs(
+    rows: &[Row],
+    src_dir: &std::path::Path,
+    endpoints: &mut Vec<Endpoint>,
+    only_tables: Option<&[String]>,
+    authz: Option<&Authz>,
+) -> Result<(), io::Error> {
+    for row in rows {
+        let module = table_module(row, rows, authz, endpoints)?;
+        // routes need every table's endpoints, but only these tables' files changed
+        if only_tables.is_none_or(|tables| tables.contains(&row.name)) {
+            module.write(src_dir, &row.name)?;
+        }
+    }
     Ok(())
 }
 
+/// The code for one table of `rows`, adding the endpoints it serves to `endpoints`.
+pub fn table_module(
+    row: &Row,
+    rows: &[Row],
+    authz: Option<&Authz>,
+    endpoints: &mut Vec<Endpoint>,
+) -> Result<TableModule, io::Error> {
+    // re do each one to have layers and return the endpoint layer (api layer)  
+    let mut module = TableModule::default();
+    generate_struct(row, authz, &mut module)?;
+    endpoints.extend(add_functions::add_insert_func(row, authz, &mut module)?);
+    // chould api caller pass optional param to set order by?
+    endpoints.push(add_functions::add_get_all_func(row, rows, authz, &mut module)?);
+    endpoints.extend(add_functions::add_update_func(row, authz, &mut module)?);
+    endpoints.extend(add_functions::add_delete_func(row, authz, &mut module)?);
+    // soft deleted rows are hidden, so looking one up by deletion time never
+    // matches, rows aren't looked up by a column they hide, and a query
+    // string value can't hold a whole array
+    let lookups = row.cols.iter()
+        .filter(|col| col.name != row.soft_delete_column && !is_hidden(authz, row, col) && !col.array);
+    for col in lookups {
+        endpoints.push(add_functions::add_get_one_func(row, col, authz, &mut module)?);
+    }
 
-pub fn add_basic_sql_funcs(
-    rows: Vec<Row>,
-    path: &std::path::Path,
-    func_names: &mut Vec<String>
-) -> Result<(), io::Error> {
+    // nested lists such as /users/:id/runs, one per foreign key unless the
+    // project picked some
+    let children = row.has_many(rows);
+    let listed = children.iter()
+        .filter(|child| row.nested_lists.as_ref().is_none_or(|lists| lists.contains(&child.table)));
+    for child in listed {
+        // a table pointing at the same parent twice needs the column in the path
+        let suffix = if children.iter().filter(|c| c.table == child.table).count() > 1 {
+            child.columns.first().map(|c| c.as_str())
+        } else {
+            None
+        };
+        endpoints.extend(add_joins::add_nested_list_func(row, child, rows, suffix, authz, &mut module)?);
+    }
+    Ok(module)
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::render;
+    use crate::schema::parse_rows;
 
-   // re do each one to have layers and return the endpoint layer (api layer)  
-    for row in rows {
-        generate_struct(&row, &path)?;
-        func_names.push(add_functions::add_insert_func(&row, &path)?);
-        // chould api caller pass optional param to set order by?
-        func_names.push(add_functions::add_get_all_func(&row, &path)?);
-        for col in &row.cols {
-            func_names.push(add_functions::add_get_one_func(&row, col, &path)?);
+    const RUNS: &str = "CREATE TABLE runs (id SERIAL PRIMARY KEY, name TEXT NOT NULL, note TEXT, tags TEXT[] NOT NULL, scores INT[]);";
+
+    #[test]
+    fn nullable_columns_are_options_and_arrays_vecs() {
+        let row = parse_rows(RUNS).unwrap().remove(0);
+        let mut module = TableModule::default();
+        generate_struct(&row, None, &mut module).unwrap();
+        let models = render(module.models).unwrap();
+        assert!(models.contains("pub id: i32,"));
+        assert!(models.contains("pub name: String,"));
+        assert!(models.contains("pub note: Option<String>,"));
+        assert!(models.contains("pub tags: Vec<String>,"));
+        assert!(models.contains("pub scores: Option<Vec<i32>>,"));
+    }
+
+    #[test]
+    fn lookups_take_the_column_type() {
+        let row = parse_rows(RUNS).unwrap().remove(0);
+        let mut module = TableModule::default();
+        add_functions::add_get_one_func(&row, &row.cols[0], None, &mut module).unwrap();
+        add_functions::add_get_one_func(&row, &row.cols[2], None, &mut module).unwrap();
+        let models = render(module.models).unwrap();
+        assert!(models.contains("pub struct runsidQuery {\n    pub id: i32,\n}"));
+        assert!(models.contains("pub struct runsnoteQuery {\n    pub note: Option<String>,\n}"));
+    }
+
+    #[test]
+    fn only_the_picked_nested_lists_are_served() {
+        let mut rows = parse_rows(
+            "CREATE TABLE users (id SERIAL PRIMARY KEY);
+            CREATE TABLE runs (id SERIAL PRIMARY KEY, user_id INT REFERENCES users);
+            CREATE TABLE notes (id SERIAL PRIMARY KEY, user_id INT REFERENCES users);",
+        ).unwrap();
+        let nested_paths = |rows: &[Row]| {
+            let mut endpoints = Vec::new();
+            table_module(&rows[0], rows, None, &mut endpoints).unwrap();
+            endpoints.into_iter().map(|e| e.path).filter(|path| path.starts_with("/users/")).collect::<Vec<_>>()
+        };
+        assert_eq!(nested_paths(&rows), ["/users/:id/runs", "/users/:id/notes"]);
+
+        rows[0].nested_lists = Some(vec!["notes".to_string()]);
+        assert_eq!(nested_paths(&rows), ["/users/:id/notes"]);
+        rows[0].nested_lists = Some(Vec::new());
+        assert!(nested_paths(&rows).is_empty());
+    }
+
+
+    #[test]
+    fn enum_columns_are_rust_enums_sqlx_can_bind() {
+        let mut rows = parse_rows("CREATE TABLE tickets (id SERIAL PRIMARY KEY, status TEXT NOT NULL, history TEXT[]);").unwrap();
+        // what introspecting a column of CREATE TYPE ticket_status AS ENUM (...) gives
+        for col in &mut rows[0].cols[1..] {
+            col.col_type = "ticket_status".to_string();
+            col.enum_labels = vec!["open".to_string(), "in progress".to_string()];
         }
+        crate::codegen::check_idents(&rows).unwrap();
+
+        let mut module = TableModule::default();
+        generate_struct(&rows[0], None, &mut module).unwrap();
+        let models = render(module.models).unwrap();
+        assert!(models.contains("pub status: crate::models::TicketStatus,"), "{}", models);
+        assert!(models.contains("pub history: Option<Vec<crate::models::TicketStatus>>,"), "{}", models);
+
+        // one enum for both columns
+        let enums = render(enum_types(&rows)).unwrap();
+        assert_eq!(enums.matches("pub enum TicketStatus").count(), 1, "{}", enums);
+        assert!(enums.contains("sqlx::Type"), "{}", enums);
+        assert!(enums.contains("#[sqlx(type_name = \"ticket_status\")]"), "{}", enums);
+        assert!(enums.contains("PgTypeInfo::with_name(\"_ticket_status\")"), "{}", enums);
+        assert!(enums.contains("#[serde(rename = \"in progress\")]\n    #[sqlx(rename = \"in progress\")]\n    InProgress,"), "{}", enums);
+
+        let schema = crate::openapi::object_schema(&rows[0], &[&rows[0].cols[1]], |_| false);
+        assert_eq!(schema["properties"]["status"], serde_json::json!({"type": "string", "enum": ["open", "in progress"]}));
+
+        rows[0].cols[1].enum_labels.push("1st".to_string());
+        assert_eq!(
+            crate::codegen::check_idents(&rows).unwrap_err().to_string(),
+            "label '1st' of enum type ticket_status can't be named in the generated rust, rename it"
+        );
     }
-    Ok(())
-}
\ No newline at end of file
+
+}
@@ -0,0 +1,308 @@
+//! The filesystem every generator writes through. Calls go straight to
+//! disk, except while a plan is running (`--dry-run`): then writes and
+//! removals are held in memory on top of the disk, reads see them as if
+//! they had happened, and nothing in the project changes.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// What a plan has done to one file.
+#[derive(Debug, Clone)]
+enum Planned {
+    Written(Vec<u8>),
+    Removed,
+}
+
+#[derive(Debug, Default)]
+struct Plan {
+    files: BTreeMap<PathBuf, Planned>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+static PLAN: Mutex<Option<Plan>> = Mutex::new(None);
+
+/// A file a plan would create, change or remove.
+#[derive(Debug)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// The file on disk now, `None` when it would be created.
+    pub before: Option<Vec<u8>>,
+    /// The file after the plan, `None` when it would be removed.
+    pub after: Option<Vec<u8>>,
+}
+
+impl FileChange {
+    pub fn kind(&self) -> &'static str {
+        match (&self.before, &self.after) {
+            (None, _) => "create",
+            (Some(_), None) => "remove",
+            (Some(_), Some(_)) => "modify",
+        }
+    }
+}
+
+/// Starts holding writes in memory instead of making them.
+pub fn start_plan() {
+    *lock() = Some(Plan::default());
+}
+
+pub fn planning() -> bool {
+    lock().is_some()
+}
+
+/// Ends the plan and returns the files it would change, compared with
+/// what is on disk. Files written with the contents they already have are
+/// left out.
+pub fn finish_plan() -> Vec<FileChange> {
+    let Some(plan) = lock().take() else {
+        return Vec::new();
+    };
+    plan.files.into_iter()
+        .filter_map(|(path, planned)| {
+            let before = std::fs::read(&path).ok();
+            let after = match planned {
+                Planned::Written(contents) => Some(contents),
+                Planned::Removed => None,
+            };
+            (before != after).then_some(FileChange { path, before, after })
+        })
+        .collect()
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<Plan>> {
+    PLAN.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The key a path is planned under, so `./out/src` and `out/src` are the same file.
+fn key(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<(), io::Error> {
+    let path = path.as_ref();
+    match lock().as_mut() {
+        Some(plan) => {
+            plan.files.insert(key(path), Planned::Written(contents.as_ref().to_vec()));
+            Ok(())
+        }
+        None => std::fs::write(path, contents),
+    }
+}
+
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<u8>, io::Error> {
+    let path = path.as_ref();
+    if let Some(plan) = lock().as_ref() {
+        match plan.files.get(&key(path)) {
+            Some(Planned::Written(contents)) => return Ok(contents.clone()),
+            Some(Planned::Removed) => return Err(not_found(path)),
+            None => {}
+        }
+    }
+    std::fs::read(path)
+}
+
+pub fn read_to_string(path: impl AsRef<Path>) -> Result<String, io::Error> {
+    let contents = read(path.as_ref())?;
+    String::from_utf8(contents).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.as_ref().display(), e))
+    })
+}
+
+/// Whether `path` is a file or directory, counting the plan's changes.
+pub fn exists(path: impl AsRef<Path>) -> bool {
+    match lock().as_ref() {
+        Some(plan) => exists_in(plan, path.as_ref()),
+        None => path.as_ref().exists(),
+    }
+}
+
+pub fn create_dir_all(path: impl AsRef<Path>) -> Result<(), io::Error> {
+    let path = path.as_ref();
+    match lock().as_mut() {
+        Some(plan) => {
+            plan.dirs.insert(key(path));
+            Ok(())
+        }
+        None => std::fs::create_dir_all(path),
+    }
+}
+
+pub fn remove_file(path: impl AsRef<Path>) -> Result<(), io::Error> {
+    let path = path.as_ref();
+    match lock().as_mut() {
+        Some(plan) => {
+            plan.files.insert(key(path), Planned::Removed);
+            Ok(())
+        }
+        None => std::fs::remove_file(path),
+    }
+}
+
+/// The files and directories directly in `dir`, sorted.
+pub fn read_dir(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, io::Error> {
+    let dir = dir.as_ref();
+    let mut entries = BTreeSet::new();
+    let guard = lock();
+    match (std::fs::read_dir(dir), guard.as_ref()) {
+        (Ok(read_dir), _) => {
+            for entry in read_dir {
+                entries.insert(entry?.path());
+            }
+        }
+        // the plan may have made the directory
+        (Err(e), Some(_)) if e.kind() == io::ErrorKind::NotFound => {}
+        (Err(e), _) => return Err(e),
+    }
+    let Some(plan) = guard.as_ref() else {
+        return Ok(entries.into_iter().collect());
+    };
+
+    let dir_key = key(dir);
+    for (file, planned) in &plan.files {
+        let first = file.strip_prefix(&dir_key).ok().and_then(|relative| relative.components().next());
+        if let (Some(first), Planned::Written(_)) = (first, planned) {
+            entries.insert(dir.join(first));
+        }
+    }
+    entries.retain(|entry| exists_in(plan, entry));
+    if entries.is_empty() && !exists_in(plan, dir) {
+        return Err(not_found(dir));
+    }
+    Ok(entries.into_iter().collect())
+}
+
+/// Copies the directory `from` into `to`, as `to/{name of from}`.
+pub fn copy_dir(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), io::Error> {
+    let from = from.as_ref();
+    let name = from.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("{} has no directory name", from.display()))
+    })?;
+    let target = to.as_ref().join(name);
+    for file in disk_files(from) {
+        let relative = file.strip_prefix(from).expect("disk_files lists files under the directory");
+        let path = target.join(relative);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(&path, std::fs::read(&file)?)?;
+    }
+    Ok(())
+}
+
+pub fn copy(from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<(), io::Error> {
+    write(to, read(from)?)
+}
+
+fn exists_in(plan: &Plan, path: &Path) -> bool {
+    let path_key = key(path);
+    match plan.files.get(&path_key) {
+        Some(Planned::Written(_)) => return true,
+        Some(Planned::Removed) => return false,
+        None => {}
+    }
+    if plan.dirs.contains(&path_key) || planned_files_under(plan, &path_key).next().is_some() {
+        return true;
+    }
+    if path.is_dir() {
+        // a directory goes with the last of its files
+        let files = disk_files(path);
+        return files.is_empty()
+            || files.iter().any(|file| !matches!(plan.files.get(&key(file)), Some(Planned::Removed)));
+    }
+    path.exists()
+}
+
+fn planned_files_under<'a>(plan: &'a Plan, dir: &'a Path) -> impl Iterator<Item = &'a PathBuf> {
+    plan.files.iter()
+        .filter(move |(file, planned)| file.starts_with(dir) && matches!(planned, Planned::Written(_)))
+        .map(|(file, _)| file)
+}
+
+/// Every file under `dir` on disk, however deep.
+fn disk_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return files;
+    };
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            files.extend(disk_files(&path));
+        } else {
+            files.push(path);
+        }
+    }
+    files
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::SERIAL;
+
+    #[test]
+    fn a_plan_holds_writes_and_reads_them_back() {
+        let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let kept = dir.path().join("kept.txt");
+        let changed = dir.path().join("changed.txt");
+        let removed = dir.path().join("removed.txt");
+        let created = dir.path().join("src/new.rs");
+        std::fs::write(&kept, "kept\n").unwrap();
+        std::fs::write(&changed, "before\n").unwrap();
+        std::fs::write(&removed, "removed\n").unwrap();
+
+        start_plan();
+        create_dir_all(created.parent().unwrap()).unwrap();
+        write(&created, "fn main() {}\n").unwrap();
+        write(&changed, "after\n").unwrap();
+        write(&kept, "kept\n").unwrap();
+        remove_file(&removed).unwrap();
+
+        // reads see the plan, the disk doesn't
+        assert_eq!(read_to_string(&created).unwrap(), "fn main() {}\n");
+        assert_eq!(read_to_string(&changed).unwrap(), "after\n");
+        assert_eq!(read(&removed).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(exists(&created) && !exists(&removed));
+        assert_eq!(read_dir(dir.path()).unwrap(), [changed.clone(), kept.clone(), dir.path().join("src")]);
+        assert!(!created.exists() && !dir.path().join("src").exists());
+        assert_eq!(std::fs::read_to_string(&changed).unwrap(), "before\n");
+        assert!(removed.exists());
+
+        let changes = finish_plan();
+        assert!(!planning());
+        let kinds: Vec<(&Path, &str)> = changes.iter().map(|change| (change.path.as_path(), change.kind())).collect();
+        // the file written with what it already had is left out
+        assert_eq!(kinds, [(changed.as_path(), "modify"), (removed.as_path(), "remove"), (created.as_path(), "create")]);
+        assert_eq!(changes[0].before.as_deref(), Some(&b"before\n"[..]));
+        assert_eq!(changes[0].after.as_deref(), Some(&b"after\n"[..]));
+    }
+
+    #[test]
+    fn copying_a_directory_under_a_plan_copies_nothing() {
+        let _serial = SERIAL.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("migrations");
+        std::fs::create_dir_all(from.join("seed")).unwrap();
+        std::fs::write(from.join("0001_init.sql"), "CREATE TABLE runs (id INT);\n").unwrap();
+        std::fs::write(from.join("seed/runs.sql"), "INSERT INTO runs VALUES (1);\n").unwrap();
+        let to = dir.path().join("out");
+
+        start_plan();
+        copy_dir(&from, &to).unwrap();
+        assert_eq!(read_to_string(to.join("migrations/seed/runs.sql")).unwrap(), "INSERT INTO runs VALUES (1);\n");
+        assert_eq!(read_dir(to.join("migrations")).unwrap(), [to.join("migrations/0001_init.sql"), to.join("migrations/seed")]);
+        let changes = finish_plan();
+
+        assert!(!to.exists());
+        let mut created: Vec<&Path> = changes.iter().filter(|c| c.kind() == "create").map(|c| c.path.as_path()).collect();
+        created.sort();
+        assert_eq!(created, [to.join("migrations/0001_init.sql"), to.join("migrations/seed/runs.sql")]);
+    }
+}